@@ -0,0 +1,122 @@
+//! Helpers for running the [externref transformer](externref_transformer) as part of a build.
+//!
+//! Cargo has no post-build step, and a build script runs before the crate it belongs to is
+//! compiled, so it only ever sees the artifact of the previous build, which cargo then replaces.
+//! These helpers are meant for steps that run once `cargo build` finished instead, e.g. a cargo
+//! runner, an `xtask` or a script wrapping the build, which tell them where the artifact is.
+//!
+//! [wasm_artifacts] reads the paths of the `.wasm` files a build produced from cargo's
+//! `--message-format=json` output, which is the stable way to find them:
+//!
+//! ```rust,no_run
+//! use std::process::Command;
+//!
+//! use externref_transformer::Config;
+//!
+//! fn main() -> anyhow::Result<()> {
+//!     let output = Command::new("cargo")
+//!         .args(["build", "--release", "--target", "wasm32-unknown-unknown"])
+//!         .arg("--message-format=json")
+//!         .output()?;
+//!     for path in externref_build::wasm_artifacts(&output.stdout)? {
+//!         externref_build::transform_in_place(&path, &Config::default())?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+//!
+//! [transform_artifact] takes the profile directory instead, e.g.
+//! `target/wasm32-unknown-unknown/release`, and transforms `name.wasm` in it, where `name` is the
+//! binary's name, or the library's with `-` replaced by `_`. The layout of cargo's target
+//! directory isn't stable, so this may not find the artifact in future versions of cargo.
+//!
+//! Transforming an artifact twice is harmless, as the first transform removes the sections
+//! describing the functions and the second has nothing left to rewrite.
+#![forbid(missing_docs)]
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use externref_transformer::{Config, Report};
+use serde::Deserialize;
+
+/// Transforms the artifact named `name` in a profile directory in place with the default
+/// [Config], returning its path.
+pub fn transform_artifact(profile_dir: &Path, name: &str) -> Result<PathBuf> {
+    transform_artifact_with_config(profile_dir, name, &Config::default())
+}
+
+/// Transforms the artifact named `name` in a profile directory in place, returning its path.
+///
+/// Every warning of the transform is printed to stderr.
+pub fn transform_artifact_with_config(
+    profile_dir: &Path,
+    name: &str,
+    config: &Config,
+) -> Result<PathBuf> {
+    let path = locate_artifact(profile_dir, name)?;
+    let report = transform_in_place(&path, config)?;
+    for warning in report.warnings {
+        eprintln!("warning: {warning}");
+    }
+    Ok(path)
+}
+
+/// Finds the artifact named `name`, e.g. `my_crate`, in a profile directory like
+/// `target/wasm32-unknown-unknown/release`.
+pub fn locate_artifact(profile_dir: &Path, name: &str) -> Result<PathBuf> {
+    let path = profile_dir.join(format!("{name}.wasm"));
+    if !path.is_file() {
+        bail!(
+            "no artifact at {}, the crate must be built for a wasm target first",
+            path.display()
+        );
+    }
+    Ok(path)
+}
+
+/// A message cargo prints with `--message-format=json`, of which only artifacts are read.
+#[derive(Deserialize)]
+struct Message {
+    reason: String,
+    #[serde(default)]
+    filenames: Vec<PathBuf>,
+}
+
+/// Reads the `.wasm` files of every artifact in the output of `cargo build
+/// --message-format=json`, in the order cargo built them.
+pub fn wasm_artifacts(messages: &[u8]) -> Result<Vec<PathBuf>> {
+    let mut artifacts = Vec::new();
+    for (index, line) in messages.split(|byte| *byte == b'\n').enumerate() {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let message: Message = serde_json::from_slice(line)
+            .with_context(|| format!("invalid cargo message on line {}", index + 1))?;
+        if message.reason != "compiler-artifact" {
+            continue;
+        }
+        artifacts.extend(message.filenames.into_iter().filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "wasm")
+        }));
+    }
+    Ok(artifacts)
+}
+
+/// Transforms the module at `path`, replacing it once the transform succeeded.
+pub fn transform_in_place(path: &Path, config: &Config) -> Result<Report> {
+    let wasm = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let (transformed, report) = externref_transformer::transform_with_report(&wasm, config)
+        .with_context(|| format!("failed to transform {}", path.display()))?;
+
+    // The module is written next to the artifact first, so a failed write doesn't leave it
+    // truncated.
+    let temp = path.with_extension("wasm.tmp");
+    fs::write(&temp, transformed).with_context(|| format!("failed to write {}", temp.display()))?;
+    fs::rename(&temp, path).with_context(|| format!("failed to replace {}", path.display()))?;
+    Ok(report)
+}
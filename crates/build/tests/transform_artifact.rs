@@ -0,0 +1,62 @@
+use std::{env, fs};
+
+use anyhow::Result;
+use externref_build::{locate_artifact, transform_artifact, wasm_artifacts};
+
+const MODULE: &str = r#"
+    (module
+        (import "console" "log" (func $log (param i32)))
+        (func (export "identity") (param i32) (result i32)
+            local.get 0
+            call $log
+            local.get 0)
+        (@custom "__extern_ref_data_console_log"
+            "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}")
+        (@custom "__extern_ref_data_identity"
+            "{\"name\":\"identity\",\"argIndicies\":[0],\"retIsExternRef\":true}"))
+"#;
+
+#[test]
+fn locate_and_transform() -> Result<()> {
+    let target = env::temp_dir().join(format!("externref-build-{}", std::process::id()));
+    let profile = target.join("wasm32-unknown-unknown").join("release");
+    fs::create_dir_all(&profile)?;
+
+    assert!(locate_artifact(&profile, "externref_build").is_err());
+
+    let artifact = profile.join("externref_build.wasm");
+    fs::write(&artifact, wat::parse_str(MODULE)?)?;
+    assert_eq!(locate_artifact(&profile, "externref_build")?, artifact);
+
+    let transformed = transform_artifact(&profile, "externref_build")?;
+    assert_eq!(transformed, artifact);
+
+    let wasm = fs::read(&artifact)?;
+    assert!(externref_transformer::read_metadata(&wasm)?.is_empty());
+    assert!(!artifact.with_extension("wasm.tmp").exists());
+
+    fs::remove_dir_all(&target)?;
+    Ok(())
+}
+
+#[test]
+fn artifacts_from_messages() -> Result<()> {
+    // Trimmed messages of `cargo build --message-format=json` for a library with a build script.
+    let messages = br#"
+{"reason":"build-script-executed","package_id":"app 0.1.0","out_dir":"/target/release/build/app-0123abcd/out"}
+{"reason":"compiler-artifact","package_id":"app 0.1.0","filenames":["/target/release/build/app-4567ef01/build-script-build"],"executable":null,"fresh":false}
+{"reason":"compiler-artifact","package_id":"app 0.1.0","filenames":["/target/wasm32-unknown-unknown/release/libapp.rlib","/target/wasm32-unknown-unknown/release/app.wasm"],"executable":null,"fresh":false}
+{"reason":"build-finished","success":true}
+"#;
+    assert_eq!(
+        wasm_artifacts(messages)?,
+        [std::path::Path::new(
+            "/target/wasm32-unknown-unknown/release/app.wasm"
+        )]
+    );
+
+    let err = wasm_artifacts(b"{\"reason\":\"build-finished\"}\n   Compiling app\n").unwrap_err();
+    assert_eq!(err.to_string(), "invalid cargo message on line 2");
+
+    Ok(())
+}
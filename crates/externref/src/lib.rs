@@ -1,12 +1,92 @@
 //! A crate that allows you to use `externref`s with your Wasm modules.
+//!
+//! # Features
+//!
+//! - std (default): Enables helpers that need the standard library. Without it the crate is
+//!   `no_std` and [ExternRef] only depends on `core`.
+//! - host: Makes the parts of the [ExternRef] API that don't need the transformer available on
+//!   non-wasm targets, e.g. for testing code that uses it on the host. With std it also adds
+//!   `ExternRefTable` to play the host's side of the table, and `ExternRefList` for passing many
+//!   references at once. On non-wasm targets references are also ordered by their handle, so they
+//!   can key a `BTreeMap`.
+//! - manifest: Describes every annotated function in a single `__extern_ref_manifest` custom
+//!   section holding a JSON array, instead of a section per function. The array is written by
+//!   invoking [manifest!] at the end of the crate root, which expands to nothing without the
+//!   feature. This crate writes its own for the intrinsics it imports, and the linker concatenates
+//!   the arrays of every crate. Tools can find all of the
+//!   metadata in one place, and the transformer reads either form.
+//! - compression: Allows the macro's `encoding = "gzip"` option, which compresses the metadata of
+//!   each function with gzip. The transformer reads it with its own `compression` feature, which
+//!   is enabled by default.
+//! - wasm-bindgen: Adds `unsafe` conversions between [ExternRef] and `wasm_bindgen::JsValue` on
+//!   `wasm32`, which are only sound when `wasm-bindgen` and the transformer share one table.
+//! - js-sys: Adds `unsafe` conversions between [ExternRef] and the types of `js-sys` and `web-sys`
+//!   on `wasm32`, and enables wasm-bindgen. It needs `js-sys` 0.3.61 or later, the release of the
+//!   oldest supported `wasm-bindgen` 0.2.84. Cargo picks the `js-sys` release matching the
+//!   resolved `wasm-bindgen`, and `web-sys` types convert through the same `JsCast` based
+//!   methods without a dependency on it. The handles are shared as is, so like the `JsValue`
+//!   conversions they're only sound when `wasm-bindgen` and the transformer use the same table.
+//! - refcount: Tells the host whenever a non-null [ExternRef] is cloned or dropped on wasm targets,
+//!   so it can count the references the module holds. See [ExternRef] for the details and costs.
+#![cfg_attr(not(feature = "std"), no_std)]
 #![forbid(missing_docs)]
 
-pub use externref_macros::externref;
+pub use externref_macros::{externref, externref_type, manifest};
+
+/// A type that is an [ExternRef] at the ABI level, so functions using it can be treated as taking
+/// or returning an `externref`.
+///
+/// This is implemented by the [externref_type] macro, which also registers the type with the
+/// [externref] macro.
+///
+/// # Safety
+/// The type must be a `#[repr(transparent)]` wrapper around an [ExternRef].
+pub unsafe trait IsExternRef {}
+
+// SAFETY: An `ExternRef` trivially has its own layout.
+unsafe impl IsExternRef for ExternRef {}
+
+/// Returns an [ExternRef] to one of the module's own exported functions, e.g. to hand the host a
+/// callback it can call like any other export.
+///
+/// This expands to an intrinsic import that the transformer replaces with a `ref.func` of the
+/// export, after the export itself has been rewritten. A `ref.func` is a `funcref`, not an
+/// `externref`, so the transformer converts it with a `__externref.from_funcref` import the host
+/// must provide, which takes a `funcref` and returns it as an `externref`. In JavaScript this is
+/// the identity function, `(f) => f`, and the host receives the exported function object.
+///
+/// The name is the name of the export after any `name` option, not the Rust function name.
+///
+/// ```rust,ignore
+/// #[externref(name = "onEvent")]
+/// pub fn on_event(event: ExternRef) {}
+///
+/// let handler: ExternRef = externref::export_ref!("onEvent");
+/// ```
+#[macro_export]
+macro_rules! export_ref {
+    ($name:tt) => {{
+        use $crate::{externref, ExternRef};
+
+        #[externref(name = "__externref_export_ref")]
+        extern "C" {
+            #[externref(name = $name, export_ref = $name)]
+            fn export_ref() -> ExternRef;
+        }
+
+        // SAFETY: The transformer replaces the import with a function returning the reference.
+        unsafe { export_ref() }
+    }};
+}
 
 /// A struct acting as a Rust interpretation of an `externref` that will get modified after compile
 /// time. Because Rust itself doesn't have a concept of `externref` we need to transform the output
 /// wasm module after compilating to match it's import/export usages.
 ///
+/// The handle is a `usize`, so it follows the pointer width of the target: an `i32` on `wasm32`
+/// and an `i64` on `wasm64`. The transformer can't parse modules with a 64-bit memory yet, so only
+/// `wasm32` modules can be transformed.
+///
 /// Example:
 ///
 /// ```rust,ignore
@@ -25,26 +105,112 @@ pub use externref_macros::externref;
 ///
 /// assert_eq!(unreffed, VALUE);
 /// ```
+///
+/// # Reference counting
+/// With the `refcount` feature on wasm targets, an [ExternRef] isn't `Copy`. Cloning a non-null
+/// reference calls the `__externref.clone` import and dropping one calls `__externref.drop`, both
+/// of which receive the reference itself once the module is transformed. A clone is stored in a
+/// slot of the table of its own, and dropping a reference clears its slot so the table hands it
+/// out again rather than growing. References passed to or returned from the host move across
+/// the boundary without either call, so the host owns the count of a reference it passes in and
+/// gives one up for each reference it receives. Converting a reference into its `usize` handle
+/// also doesn't drop it.
+///
+/// Every clone and drop is a call into the host, and types wrapping an [ExternRef] can't be `Copy`
+/// either, so the feature is opt-in.
 #[repr(transparent)]
-#[derive(Debug, Copy, Clone)]
+#[cfg_attr(
+    not(all(
+        feature = "refcount",
+        any(target_arch = "wasm32", target_arch = "wasm64")
+    )),
+    derive(Copy, Clone)
+)]
 pub struct ExternRef {
     inner: usize,
 }
 
-#[cfg(target_arch = "wasm32")]
+/// The handle of the null reference, the transformer reserves the first slot of the table for it.
+const NULL_HANDLE: usize = 0;
+
+#[cfg(all(
+    feature = "host",
+    not(any(target_arch = "wasm32", target_arch = "wasm64"))
+))]
+impl ExternRef {
+    /// Creates a new [ExternRef] with the value of `null`.
+    pub fn null() -> Self {
+        Self { inner: NULL_HANDLE }
+    }
+
+    /// Checks if this ref is null.
+    pub fn is_null(&self) -> bool {
+        self.inner == NULL_HANDLE
+    }
+}
+
+/// The intrinsics the transformer implements with `ref.null` and `ref.is_null`, so checking for
+/// null doesn't need anything from the host.
+#[cfg(any(target_arch = "wasm32", target_arch = "wasm64"))]
+mod intrinsics {
+    use crate::{externref, ExternRef};
+
+    #[externref(name = "__externref_intrinsic")]
+    extern "C" {
+        #[externref(name = "ref.null", intrinsic = "ref.null")]
+        pub fn ref_null() -> ExternRef;
+
+        #[externref(name = "ref.is_null", intrinsic = "ref.is_null")]
+        pub fn ref_is_null(reference: ExternRef) -> bool;
+
+        #[externref(name = "transformed", intrinsic = "transformed")]
+        pub fn transformed() -> bool;
+    }
+}
+
+/// Panics unless the module was processed by the transformer, e.g. at the start of the module's
+/// initialization to catch a deployment that forgot to run it.
+///
+/// This calls an intrinsic the transformer replaces with a function returning `true`, so once the
+/// module is transformed the check always passes and the host isn't involved. An untransformed
+/// module still imports it from the `__externref_intrinsic` module, so it either fails to
+/// instantiate because of the missing import, or panics here when the host stubs missing imports.
+///
+/// ```rust,ignore
+/// #[no_mangle]
+/// pub extern "C" fn init() {
+///     externref::assert_transformed();
+/// }
+/// ```
+#[cfg(any(target_arch = "wasm32", target_arch = "wasm64"))]
+pub fn assert_transformed() {
+    // SAFETY: The intrinsic takes nothing, and the transformer replaces it with a function
+    // returning `true`.
+    if !unsafe { intrinsics::transformed() } {
+        panic!("the module wasn't processed by the externref transformer");
+    }
+}
+
+/// There's nothing to transform on the host, so this never panics there.
+#[cfg(all(
+    feature = "host",
+    not(any(target_arch = "wasm32", target_arch = "wasm64"))
+))]
+pub fn assert_transformed() {}
+
+#[cfg(any(target_arch = "wasm32", target_arch = "wasm64"))]
 impl ExternRef {
     /// Creates a new [ExternRef] with the value of `null`.
     pub fn null() -> Self {
-        // TODO(zeb): Should we call a function that'll have it's definition swapped at
-        // transform time that just executes `ref.null`?
-        todo!("cannot call ref.null instruction until module is transformed");
+        // SAFETY: The transformer replaces the intrinsic with a function returning `ref.null`.
+        unsafe { intrinsics::ref_null() }
     }
 
     /// Checks if this ref is null.
     pub fn is_null(&self) -> bool {
-        // TODO(zeb): Should we call a function that'll have it's definition swapped at
-        // transform time that just executes `ref.is_null`?
-        todo!("cannot call ref.null instruction until module is transformed");
+        // SAFETY: The transformer replaces the intrinsic with a function checking the reference
+        // with `ref.is_null`, and the copy is moved into the call so it isn't dropped.
+        unsafe { intrinsics::ref_is_null(Self { inner: self.inner }) }
     }
 
     /// Converts a [usize] into a [ExternRef].
@@ -57,12 +223,567 @@ impl ExternRef {
     }
 }
 
+/// The imports the host is told about clones and drops with, see [ExternRef].
+#[cfg(all(
+    feature = "refcount",
+    any(target_arch = "wasm32", target_arch = "wasm64")
+))]
+mod refcount {
+    use crate::{externref, ExternRef};
+
+    #[externref(name = "__externref")]
+    extern "C" {
+        #[externref(name = "clone")]
+        pub fn clone_ref(reference: ExternRef) -> ExternRef;
+
+        #[externref(name = "drop")]
+        pub fn drop_ref(reference: ExternRef);
+    }
+}
+
+#[cfg(all(
+    feature = "refcount",
+    any(target_arch = "wasm32", target_arch = "wasm64")
+))]
+impl Clone for ExternRef {
+    fn clone(&self) -> Self {
+        if self.is_null() {
+            return Self::null();
+        }
+        // SAFETY: The transformer rewrites the import to take the reference and return the handle
+        // of a slot of its own, and the copy is moved into the call so it isn't dropped.
+        unsafe { refcount::clone_ref(Self { inner: self.inner }) }
+    }
+}
+
+#[cfg(all(
+    feature = "refcount",
+    any(target_arch = "wasm32", target_arch = "wasm64")
+))]
+impl Drop for ExternRef {
+    fn drop(&mut self) {
+        if !self.is_null() {
+            // SAFETY: As above, the copy is moved into the call so this isn't called again.
+            unsafe { refcount::drop_ref(Self { inner: self.inner }) };
+        }
+    }
+}
+
+/// The largest handle the transformer can hand out. Tables are indexed with an `i32` and
+/// `table.grow` returns `-1` when it fails, so the last index is never allocated.
+#[cfg(feature = "host")]
+const MAX_HANDLE: usize = (u32::MAX - 1) as usize;
+
+/// The error when a raw handle can't be a valid [ExternRef].
+#[cfg(feature = "host")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidHandle {
+    /// The handle is the reserved null slot, use [ExternRef::null] for a null reference.
+    Null,
+    /// The handle is beyond the largest index the externref table can have.
+    OutOfRange(usize),
+}
+
+#[cfg(feature = "host")]
+impl core::fmt::Display for InvalidHandle {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            InvalidHandle::Null => f.write_str("the handle is the reserved null slot"),
+            InvalidHandle::OutOfRange(handle) => {
+                write!(f, "the handle {handle} is beyond the largest table index")
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "host", feature = "std"))]
+impl std::error::Error for InvalidHandle {}
+
+/// Converts a raw handle, e.g. one read back by tooling, into an [ExternRef] after checking that
+/// it's a non-null slot within the range of the externref table.
+///
+/// This doesn't prove the slot holds a reference, only that it could.
+#[cfg(feature = "host")]
+impl TryFrom<usize> for ExternRef {
+    type Error = InvalidHandle;
+
+    fn try_from(handle: usize) -> Result<Self, Self::Error> {
+        match handle {
+            NULL_HANDLE => Err(InvalidHandle::Null),
+            handle if handle > MAX_HANDLE => Err(InvalidHandle::OutOfRange(handle)),
+            handle => Ok(Self { inner: handle }),
+        }
+    }
+}
+
+/// A stand-in for the host's side of the externref table, for testing code that uses [ExternRef]s
+/// on the host.
+///
+/// Like the transformer's table, slot `0` is reserved for [ExternRef::null] and every inserted
+/// value gets a new slot, even after others are removed.
+///
+/// ```rust
+/// use externref::{ExternRef, ExternRefTable};
+///
+/// let mut table = ExternRefTable::new();
+/// let reference = table.insert(String::from("node"));
+///
+/// let value = table.get(reference).and_then(|value| value.downcast_ref::<String>());
+/// assert_eq!(value.map(String::as_str), Some("node"));
+/// assert!(table.get(ExternRef::null()).is_none());
+/// ```
+#[cfg(all(feature = "host", feature = "std"))]
+#[derive(Default)]
+pub struct ExternRefTable {
+    /// The value of each slot, starting with the null slot.
+    slots: Vec<Option<Box<dyn std::any::Any>>>,
+}
+
+#[cfg(all(feature = "host", feature = "std"))]
+impl ExternRefTable {
+    /// Creates a table holding only the null slot.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores a value in a new slot, returning the reference to it.
+    pub fn insert<T: std::any::Any>(&mut self, value: T) -> ExternRef {
+        if self.slots.is_empty() {
+            self.slots.push(None);
+        }
+        self.slots.push(Some(Box::new(value)));
+        ExternRef {
+            inner: self.slots.len() - 1,
+        }
+    }
+
+    /// The value a reference refers to, or `None` for null and removed references.
+    pub fn get(&self, reference: ExternRef) -> Option<&dyn std::any::Any> {
+        self.slots.get(reference.inner)?.as_deref()
+    }
+
+    /// Removes the value a reference refers to, returning it.
+    ///
+    /// The slot isn't reused, so the reference stays invalid rather than referring to a later
+    /// value.
+    pub fn remove(&mut self, reference: ExternRef) -> Option<Box<dyn std::any::Any>> {
+        self.slots.get_mut(reference.inner)?.take()
+    }
+
+    /// Stores each value in a new slot, returning the references to them in the same order.
+    pub fn insert_all<T: std::any::Any>(
+        &mut self,
+        values: impl IntoIterator<Item = T>,
+    ) -> ExternRefList {
+        values.into_iter().map(|value| self.insert(value)).collect()
+    }
+
+    /// The values a list of references refers to, in the same order, with `None` for null and
+    /// removed references.
+    pub fn get_all(&self, references: &[ExternRef]) -> Vec<Option<&dyn std::any::Any>> {
+        references
+            .iter()
+            .map(|reference| self.slots.get(reference.inner)?.as_deref())
+            .collect()
+    }
+}
+
+/// A list of [ExternRef]s, for passing many references to the host in a single call.
+///
+/// References are `usize` handles in the module, so the list is laid out as a contiguous array of
+/// handles that an import can take as a pointer and a length. The handles index the module's
+/// externref table, so the host can only resolve them with access to that table, e.g. with
+/// `ExternRefTable::get_all` when testing on the host.
+///
+/// ```rust,ignore
+/// use externref::{externref, ExternRef, ExternRefList};
+///
+/// #[externref(name = "dom")]
+/// extern "C" {
+///     fn append_all(parent: ExternRef, children: *const usize, len: usize);
+/// }
+///
+/// fn append(parent: ExternRef, children: &ExternRefList) {
+///     let handles = children.handles();
+///     unsafe { append_all(parent, handles.as_ptr(), handles.len()) };
+/// }
+/// ```
+#[cfg(all(
+    feature = "std",
+    any(target_arch = "wasm32", target_arch = "wasm64", feature = "host")
+))]
+#[derive(Debug, Clone, Default)]
+pub struct ExternRefList {
+    refs: Vec<ExternRef>,
+}
+
+#[cfg(all(
+    feature = "std",
+    any(target_arch = "wasm32", target_arch = "wasm64", feature = "host")
+))]
+impl ExternRefList {
+    /// Creates an empty list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty list with space for at least `capacity` references.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            refs: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Adds a reference to the end of the list.
+    pub fn push(&mut self, reference: ExternRef) {
+        self.refs.push(reference);
+    }
+
+    /// The raw handles of the references, in the same order, to pass to an import.
+    pub fn handles(&self) -> &[usize] {
+        // SAFETY: An `ExternRef` is a `#[repr(transparent)]` wrapper around its `usize` handle.
+        unsafe { std::slice::from_raw_parts(self.refs.as_ptr().cast(), self.refs.len()) }
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    any(target_arch = "wasm32", target_arch = "wasm64", feature = "host")
+))]
+impl std::ops::Deref for ExternRefList {
+    type Target = [ExternRef];
+
+    fn deref(&self) -> &Self::Target {
+        &self.refs
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    any(target_arch = "wasm32", target_arch = "wasm64", feature = "host")
+))]
+impl From<Vec<ExternRef>> for ExternRefList {
+    fn from(refs: Vec<ExternRef>) -> Self {
+        Self { refs }
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    any(target_arch = "wasm32", target_arch = "wasm64", feature = "host")
+))]
+impl From<ExternRefList> for Vec<ExternRef> {
+    fn from(list: ExternRefList) -> Self {
+        list.refs
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    any(target_arch = "wasm32", target_arch = "wasm64", feature = "host")
+))]
+impl FromIterator<ExternRef> for ExternRefList {
+    fn from_iter<I: IntoIterator<Item = ExternRef>>(iter: I) -> Self {
+        Self {
+            refs: iter.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    any(target_arch = "wasm32", target_arch = "wasm64", feature = "host")
+))]
+impl Extend<ExternRef> for ExternRefList {
+    fn extend<I: IntoIterator<Item = ExternRef>>(&mut self, iter: I) {
+        self.refs.extend(iter);
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    any(target_arch = "wasm32", target_arch = "wasm64", feature = "host")
+))]
+impl IntoIterator for ExternRefList {
+    type Item = ExternRef;
+    type IntoIter = std::vec::IntoIter<ExternRef>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.refs.into_iter()
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    any(target_arch = "wasm32", target_arch = "wasm64", feature = "host")
+))]
+impl<'a> IntoIterator for &'a ExternRefList {
+    type Item = &'a ExternRef;
+    type IntoIter = std::slice::Iter<'a, ExternRef>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.refs.iter()
+    }
+}
+
+/// The default [ExternRef] is [null](ExternRef::null).
+///
+/// A null reference doesn't refer to any host object, so the host must check for `null` before
+/// dereferencing one it receives.
+#[cfg(any(target_arch = "wasm32", target_arch = "wasm64", feature = "host"))]
+impl Default for ExternRef {
+    fn default() -> Self {
+        Self::null()
+    }
+}
+
+/// Formats the reference as `externref#<index>`, or `externref(null)` for null.
+///
+/// The index is the reference's slot in the transformer's externref table and says nothing about
+/// the host object it refers to.
+impl core::fmt::Display for ExternRef {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.inner {
+            NULL_HANDLE => f.write_str("externref(null)"),
+            index => write!(f, "externref#{index}"),
+        }
+    }
+}
+
+/// Formats the reference as `ExternRef(#<index>)`, or `ExternRef(null)` for null.
+///
+/// Before the module is transformed the index is a placeholder that doesn't refer to any slot.
+impl core::fmt::Debug for ExternRef {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.inner {
+            NULL_HANDLE => f.write_str("ExternRef(null)"),
+            index => write!(f, "ExternRef(#{index})"),
+        }
+    }
+}
+
+/// Compares references by their handle, so two references are equal when they refer to the same
+/// slot of the externref table.
+///
+/// This is handle identity, not equality of the host objects, which the host can store in several
+/// slots. On wasm the handles are placeholders until the module is transformed, so this is only
+/// implemented on the host.
+#[cfg(all(
+    feature = "host",
+    not(any(target_arch = "wasm32", target_arch = "wasm64"))
+))]
+impl PartialEq for ExternRef {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+#[cfg(all(
+    feature = "host",
+    not(any(target_arch = "wasm32", target_arch = "wasm64"))
+))]
+impl Eq for ExternRef {}
+
+/// Orders references by their slot in the externref table, with [null](ExternRef::null) first, so
+/// they can key a `BTreeMap` or `BTreeSet`.
+///
+/// The order has no meaning for the host objects the references refer to, it only makes
+/// collections of references iterate in the same order every run.
+#[cfg(all(
+    feature = "host",
+    not(any(target_arch = "wasm32", target_arch = "wasm64"))
+))]
+impl Ord for ExternRef {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.inner.cmp(&other.inner)
+    }
+}
+
+#[cfg(all(
+    feature = "host",
+    not(any(target_arch = "wasm32", target_arch = "wasm64"))
+))]
+impl PartialOrd for ExternRef {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl From<ExternRef> for usize {
     fn from(val: ExternRef) -> Self {
-        val.inner
+        core::mem::ManuallyDrop::new(val).inner
+    }
+}
+
+/// An [ExternRef] labelled with the host type it refers to, e.g. `Typed<DomNode>`, so signatures
+/// say what they take without declaring a newtype for every host type.
+///
+/// The label is only a marker, nothing checks that the host object is a `T`, and `T` doesn't have
+/// to be a type that exists on the wasm side. The [externref] macro treats a `Typed<T>` argument or
+/// return like an [ExternRef], and records `T` as its type in the function data unless
+/// `arg_types` says otherwise.
+///
+/// ```rust,ignore
+/// pub enum DomNode {}
+///
+/// #[externref]
+/// #[link(wasm_import_module = "dom")]
+/// extern "C" {
+///     fn append_child(parent: Typed<DomNode>, child: Typed<DomNode>);
+/// }
+/// ```
+#[repr(transparent)]
+pub struct Typed<T> {
+    reference: ExternRef,
+    marker: core::marker::PhantomData<fn() -> T>,
+}
+
+// SAFETY: The `PhantomData` is zero-sized, so `Typed` is a transparent wrapper around an
+// `ExternRef`.
+unsafe impl<T> IsExternRef for Typed<T> {}
+
+impl<T> Typed<T> {
+    /// Labels the reference, trusting that it refers to a `T`.
+    pub fn new(reference: ExternRef) -> Self {
+        Self {
+            reference,
+            marker: core::marker::PhantomData,
+        }
+    }
+
+    /// Removes the label, returning the reference.
+    pub fn into_inner(self) -> ExternRef {
+        self.reference
+    }
+}
+
+#[cfg(all(
+    feature = "refcount",
+    any(target_arch = "wasm32", target_arch = "wasm64")
+))]
+impl<T> Clone for Typed<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.reference.clone())
+    }
+}
+
+// Derived impls would require `T` to be `Copy` and `Clone` too.
+#[cfg(not(all(
+    feature = "refcount",
+    any(target_arch = "wasm32", target_arch = "wasm64")
+)))]
+impl<T> Clone for Typed<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(not(all(
+    feature = "refcount",
+    any(target_arch = "wasm32", target_arch = "wasm64")
+)))]
+impl<T> Copy for Typed<T> {}
+
+impl<T> core::ops::Deref for Typed<T> {
+    type Target = ExternRef;
+
+    fn deref(&self) -> &Self::Target {
+        &self.reference
     }
 }
 
+impl<T> From<Typed<T>> for ExternRef {
+    fn from(typed: Typed<T>) -> Self {
+        typed.reference
+    }
+}
+
+/// Formats the reference like an [ExternRef].
+impl<T> core::fmt::Debug for Typed<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self.reference, f)
+    }
+}
+
+/// Conversions to and from `wasm_bindgen::JsValue`, which pass the handle through as is.
+///
+/// The transformer creates its own table, while a `JsValue` is an index into `wasm-bindgen`'s
+/// heap, so a handle only refers to the same object in both when the module is set up to share
+/// one table. Nothing checks that, which is why the conversions are `unsafe`.
+#[cfg(all(feature = "wasm-bindgen", target_arch = "wasm32"))]
+impl ExternRef {
+    /// Takes ownership of the value's slot in the table, which is never freed as an [ExternRef]
+    /// doesn't release the slot it refers to.
+    ///
+    /// # Safety
+    /// `wasm-bindgen` and the transformer must share the same table, otherwise the reference
+    /// points at an unrelated object, or at nothing.
+    pub unsafe fn from_js_value(value: wasm_bindgen::JsValue) -> Self {
+        use wasm_bindgen::convert::IntoWasmAbi;
+
+        Self {
+            inner: value.into_abi() as usize,
+        }
+    }
+
+    /// Gives ownership of the reference's slot in the table to a `JsValue`, which frees the slot
+    /// when it's dropped.
+    ///
+    /// # Safety
+    /// `wasm-bindgen` and the transformer must share the same table, otherwise the `JsValue`
+    /// adopts and later frees an unrelated slot of `wasm-bindgen`'s heap. No copy of the reference
+    /// may be used or converted again once the `JsValue` is dropped, as that would use or free the
+    /// slot a second time.
+    pub unsafe fn into_js_value(self) -> wasm_bindgen::JsValue {
+        use wasm_bindgen::convert::FromWasmAbi;
+
+        let handle = core::mem::ManuallyDrop::new(self).inner;
+        // SAFETY: The handle is a `u32` on `wasm32`, and the caller guarantees it's a slot of
+        // `wasm-bindgen`'s heap that it may take ownership of.
+        unsafe { wasm_bindgen::JsValue::from_abi(handle as u32) }
+    }
+}
+
+/// Conversions to and from the types of `js-sys` and `web-sys`, which are all wrappers around a
+/// `JsValue`, so they share its handle and its requirements the same way.
+#[cfg(all(feature = "js-sys", target_arch = "wasm32"))]
+impl ExternRef {
+    /// Takes ownership of a `js-sys` or `web-sys` value's slot in the table.
+    ///
+    /// # Safety
+    /// See [from_js_value](Self::from_js_value).
+    pub unsafe fn from_js<T: wasm_bindgen::JsCast>(value: T) -> Self {
+        unsafe { Self::from_js_value(value.unchecked_into()) }
+    }
+
+    /// Converts the reference into a `js-sys` or `web-sys` type, e.g. a `js_sys::Array`, giving
+    /// the value ownership of its slot, or returns it unchanged when the object isn't an
+    /// `instanceof` the type.
+    ///
+    /// # Safety
+    /// See [into_js_value](Self::into_js_value).
+    pub unsafe fn dyn_into<T: wasm_bindgen::JsCast>(self) -> Result<T, Self> {
+        unsafe {
+            wasm_bindgen::JsCast::dyn_into(self.into_js_value())
+                .map_err(|value| Self::from_js_value(value))
+        }
+    }
+
+    /// Converts the reference into a `js-sys` or `web-sys` type without checking it, for objects
+    /// whose type the caller already knows. Every reference the host passes is treated as an
+    /// object, even a primitive.
+    ///
+    /// # Safety
+    /// See [into_js_value](Self::into_js_value).
+    pub unsafe fn unchecked_into<T: wasm_bindgen::JsCast>(self) -> T {
+        wasm_bindgen::JsCast::unchecked_into(unsafe { self.into_js_value() })
+    }
+}
+
+// With the `manifest` feature the intrinsics and refcount imports are only described once this
+// crate writes its own manifest.
+manifest!();
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -78,8 +799,148 @@ mod tests {
     #[test]
     fn same_layout() {
         assert_eq!(
-            core::mem::align_of::<ExternRef>(),
-            core::mem::align_of::<usize>()
+            core::mem::size_of::<ExternRef>(),
+            core::mem::size_of::<usize>()
         )
     }
+
+    #[test]
+    fn typed_layout() {
+        struct DomNode;
+
+        assert_eq!(
+            core::mem::size_of::<Typed<DomNode>>(),
+            core::mem::size_of::<usize>()
+        );
+        assert_eq!(
+            core::mem::align_of::<Typed<DomNode>>(),
+            core::mem::align_of::<usize>()
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn formatting() {
+        let reference = ExternRef { inner: 5 };
+        assert_eq!(reference.to_string(), "externref#5");
+        assert_eq!(format!("{reference:?}"), "ExternRef(#5)");
+
+        let null = ExternRef { inner: NULL_HANDLE };
+        assert_eq!(null.to_string(), "externref(null)");
+        assert_eq!(format!("{null:?}"), "ExternRef(null)");
+    }
+
+    #[cfg(all(feature = "host", feature = "std"))]
+    #[test]
+    fn null_formatting() {
+        assert_eq!(ExternRef::null().to_string(), "externref(null)");
+        assert_eq!(ExternRef::default().to_string(), "externref(null)");
+    }
+
+    #[cfg(feature = "host")]
+    #[test]
+    fn try_from_handle() {
+        let reference = ExternRef::try_from(5).unwrap();
+        assert_eq!(usize::from(reference), 5);
+        assert!(ExternRef::try_from(MAX_HANDLE).is_ok());
+
+        assert_eq!(
+            ExternRef::try_from(NULL_HANDLE).unwrap_err(),
+            InvalidHandle::Null
+        );
+        assert_eq!(
+            ExternRef::try_from(MAX_HANDLE + 1).unwrap_err(),
+            InvalidHandle::OutOfRange(MAX_HANDLE + 1)
+        );
+    }
+
+    #[cfg(all(feature = "host", feature = "std"))]
+    #[test]
+    fn table() {
+        let mut table = ExternRefTable::new();
+        let node = table.insert("node");
+        let count = table.insert(3u32);
+        assert_eq!(usize::from(node), 1);
+        assert_eq!(usize::from(count), 2);
+
+        let value = table
+            .get(node)
+            .and_then(|value| value.downcast_ref::<&str>());
+        assert_eq!(value, Some(&"node"));
+        assert!(table.get(count).unwrap().downcast_ref::<&str>().is_none());
+
+        // Null is the reserved slot and never holds a value.
+        assert!(table.get(ExternRef::null()).is_none());
+        assert!(table.remove(ExternRef::null()).is_none());
+
+        let removed = table.remove(count).unwrap();
+        assert_eq!(removed.downcast_ref::<u32>(), Some(&3));
+        assert!(table.get(count).is_none());
+        assert!(table.remove(count).is_none());
+
+        // Removed slots aren't reused.
+        assert_eq!(usize::from(table.insert(4u32)), 3);
+    }
+
+    #[cfg(all(feature = "host", feature = "std"))]
+    #[test]
+    fn ordered_collections() {
+        use std::collections::BTreeSet;
+
+        let mut table = ExternRefTable::new();
+        let a = table.insert("a");
+        let b = table.insert("b");
+
+        let set = BTreeSet::from([b, ExternRef::null(), a, b]);
+        assert_eq!(set.len(), 3);
+        assert_eq!(
+            set.iter().map(|r| usize::from(*r)).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert!(set.contains(&a));
+        assert_ne!(a, b);
+        assert!(ExternRef::null() < a);
+    }
+
+    #[cfg(feature = "host")]
+    #[test]
+    fn transformed_on_host() {
+        assert_transformed();
+    }
+
+    #[cfg(feature = "host")]
+    #[test]
+    fn default_is_null() {
+        assert!(ExternRef::default().is_null());
+        assert!(ExternRef::null().is_null());
+    }
+
+    #[cfg(all(feature = "host", feature = "std"))]
+    #[test]
+    fn list() {
+        let mut table = ExternRefTable::new();
+        let mut list = table.insert_all(["a", "b"]);
+        list.push(ExternRef::null());
+        list.extend([table.insert("c")]);
+        assert_eq!(list.len(), 4);
+        assert_eq!(list.handles(), &[1, 2, 0, 3]);
+
+        let values: Vec<_> = table
+            .get_all(&list)
+            .into_iter()
+            .map(|value| value.and_then(|value| value.downcast_ref::<&str>().copied()))
+            .collect();
+        assert_eq!(values, [Some("a"), Some("b"), None, Some("c")]);
+
+        // The handles are all an import needs to get the same references back.
+        let copied: ExternRefList = list
+            .handles()
+            .iter()
+            .map(|handle| ExternRef::try_from(*handle).unwrap_or_default())
+            .collect();
+        assert_eq!(copied.handles(), list.handles());
+
+        let refs: Vec<ExternRef> = list.into();
+        assert_eq!(ExternRefList::from(refs).len(), 4);
+    }
 }
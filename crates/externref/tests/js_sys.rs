@@ -0,0 +1,27 @@
+//! Checks the conversions between [ExternRef] and `js-sys` types. These only exist on `wasm32`, so
+//! run with `wasm-pack test --node -- --features js-sys`.
+//!
+//! The module isn't transformed, so handles are `wasm-bindgen`'s heap indices and the table is
+//! shared as the conversions require.
+#![cfg(all(target_arch = "wasm32", feature = "js-sys"))]
+
+use externref::ExternRef;
+use js_sys::{Array, Function, Object};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn converts_js_sys_values() {
+    let array = Array::of1(&JsValue::from_f64(1.0));
+
+    // SAFETY: Without the transformer the only table is `wasm-bindgen`'s heap.
+    let reference = unsafe { ExternRef::from_js(array) };
+    // The checked conversion hands the reference back when the object has another type.
+    let reference = unsafe { reference.dyn_into::<Function>() }.unwrap_err();
+    let array = unsafe { reference.dyn_into::<Array>() }.unwrap();
+    assert_eq!(array.length(), 1);
+
+    let reference = unsafe { ExternRef::from_js(array) };
+    let object: Object = unsafe { reference.unchecked_into() };
+    assert!(Array::is_array(&object));
+}
@@ -0,0 +1,20 @@
+//! Checks the conversions between [ExternRef] and [JsValue]. These only exist on `wasm32`, so run
+//! with `wasm-pack test --node -- --features wasm-bindgen`.
+//!
+//! The module isn't transformed, so handles are `wasm-bindgen`'s heap indices and the table is
+//! shared as the conversions require.
+#![cfg(all(target_arch = "wasm32", feature = "wasm-bindgen"))]
+
+use externref::ExternRef;
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+#[wasm_bindgen_test]
+fn round_trips_js_values() {
+    let value = JsValue::from_str("externref");
+
+    // SAFETY: Without the transformer the only table is `wasm-bindgen`'s heap.
+    let reference = unsafe { ExternRef::from_js_value(value) };
+    let value = unsafe { reference.into_js_value() };
+    assert_eq!(value.as_string().as_deref(), Some("externref"));
+}
@@ -0,0 +1,13 @@
+//! Checks that [ExternRef] is usable from a `no_std` crate. Run with `--no-default-features` to
+//! also build `externref` itself without `std`.
+#![no_std]
+
+use externref::ExternRef;
+
+#[test]
+fn usable_without_std() {
+    assert_eq!(
+        core::mem::size_of::<ExternRef>(),
+        core::mem::size_of::<usize>()
+    );
+}
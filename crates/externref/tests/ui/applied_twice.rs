@@ -0,0 +1,13 @@
+use externref::externref;
+
+#[externref(name = "console")]
+#[externref(name = "console")]
+extern "C" {
+    fn log(message: externref::ExternRef);
+}
+
+#[externref]
+#[externref]
+pub fn run(message: externref::ExternRef) {}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use externref::externref;
+
+#[externref(name = "env")]
+extern "C" {
+    const fn answer() -> u32;
+}
+
+fn main() {}
@@ -0,0 +1,14 @@
+use externref::externref;
+
+#[externref(name = "console")]
+extern "C" {
+    fn log(message: externref::ExternRef);
+
+    #[externref(name = "log")]
+    fn log_value(value: externref::ExternRef);
+
+    #[externref(name = "log")]
+    fn log_again(value: externref::ExternRef);
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use externref::externref;
+
+#[externref(name = "__externref_export_ref")]
+extern "C" {
+    #[externref(name = "onEvent", export_ref = "onEvent")]
+    fn on_event_ref(value: u32) -> externref::ExternRef;
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+use externref::externref_type;
+
+#[externref_type]
+pub struct DomNode {
+    node: externref::ExternRef,
+}
+
+#[externref_type]
+pub struct Handle(u32);
+
+fn main() {}
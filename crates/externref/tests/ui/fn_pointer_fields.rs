@@ -0,0 +1,15 @@
+#[allow(unused_imports)]
+use externref::{externref, ExternRef};
+
+#[externref(name = "plugin")]
+struct HostVtable {
+    version: u32,
+    log: extern "C" fn(ExternRef),
+}
+
+#[externref(name = "plugin")]
+struct OptionalVtable {
+    create: Option<unsafe extern "C" fn(u32) -> ExternRef>,
+}
+
+fn main() {}
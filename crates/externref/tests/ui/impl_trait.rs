@@ -0,0 +1,18 @@
+// The items using `impl Trait` are replaced with the errors.
+#[allow(unused_imports)]
+use externref::{externref, ExternRef};
+
+#[externref]
+pub fn describe(reference: ExternRef) -> impl std::fmt::Display {
+    0
+}
+
+#[externref]
+pub fn log(value: &impl std::fmt::Debug) {}
+
+#[externref(name = "console")]
+extern "C" {
+    fn write(message: impl Into<ExternRef>);
+}
+
+fn main() {}
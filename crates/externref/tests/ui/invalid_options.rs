@@ -0,0 +1,17 @@
+use externref::externref;
+
+#[externref(name = "env", unknown_flag)]
+extern "C" {
+    fn log(message: externref::ExternRef);
+}
+
+#[externref(name = "env")]
+extern "C" {
+    #[externref(ref_args = "3")]
+    fn release(handle: u32);
+
+    #[externref(arg_types = "0:Node")]
+    fn count(value: u32) -> u32;
+}
+
+fn main() {}
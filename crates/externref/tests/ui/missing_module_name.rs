@@ -0,0 +1,8 @@
+use externref::externref;
+
+#[externref]
+extern "C" {
+    fn log(message: externref::ExternRef);
+}
+
+fn main() {}
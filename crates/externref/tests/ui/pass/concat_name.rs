@@ -0,0 +1,11 @@
+use externref::{externref, ExternRef};
+
+#[externref(name = concat!(env!("CARGO_PKG_NAME"), "_host"))]
+extern "C" {
+    #[externref(name = concat!("log", "Message"))]
+    fn log(message: ExternRef);
+    #[externref(name = env!("CARGO_PKG_NAME", "trybuild sets the package name"))]
+    fn warn(message: ExternRef);
+}
+
+fn main() {}
@@ -0,0 +1,19 @@
+use externref::{externref, ExternRef};
+
+#[externref]
+pub const fn identity(value: ExternRef) -> ExternRef {
+    value
+}
+
+#[externref(name = "answer")]
+pub const fn answer() -> u32 {
+    42
+}
+
+// The function can still be called in const contexts.
+const ANSWER: u32 = answer();
+
+fn main() {
+    let _ = identity;
+    assert_eq!(ANSWER, 42);
+}
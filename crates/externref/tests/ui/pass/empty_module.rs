@@ -0,0 +1,8 @@
+use externref::{externref, ExternRef};
+
+#[externref(name = "")]
+extern "C" {
+    fn log(message: ExternRef);
+}
+
+fn main() {}
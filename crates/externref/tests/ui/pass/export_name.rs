@@ -0,0 +1,11 @@
+use externref::{externref, ExternRef};
+
+#[externref(export_name = "on-click")]
+pub fn on_click(event: ExternRef) -> ExternRef {
+    event
+}
+
+fn main() {
+    // The Rust function keeps its own name.
+    let _ = on_click;
+}
@@ -0,0 +1,18 @@
+use externref::{externref, ExternRef};
+
+#[externref]
+#[no_mangle]
+pub fn on_event(event: ExternRef) {}
+
+#[externref]
+#[export_name = "onClick"]
+pub fn on_click(event: ExternRef) {}
+
+#[externref(name = "onKey")]
+#[export_name = "onKey"]
+pub fn on_key(event: ExternRef) {}
+
+#[externref(name = "onLoad", export)]
+pub fn on_load(event: ExternRef) {}
+
+fn main() {}
@@ -0,0 +1,19 @@
+use externref::{externref, externref_type, ExternRef, IsExternRef};
+
+#[externref_type]
+#[derive(Clone, Copy)]
+pub struct DomNode(ExternRef);
+
+// Strict mode only accepts the newtype because it's registered as an `externref`.
+#[externref(name = "dom", strict)]
+extern "C" {
+    #[externref(name = "appendChild")]
+    fn append_child(parent: DomNode, child: DomNode) -> DomNode;
+}
+
+fn is_extern_ref<T: IsExternRef>() {}
+
+fn main() {
+    is_extern_ref::<DomNode>();
+    let _: fn(DomNode) -> ExternRef = ExternRef::from;
+}
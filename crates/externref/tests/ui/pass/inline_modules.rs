@@ -0,0 +1,33 @@
+use externref::{externref, ExternRef};
+
+mod first {
+    use externref::{externref, ExternRef};
+
+    #[externref]
+    pub fn f(value: ExternRef) -> ExternRef {
+        value
+    }
+}
+
+mod second {
+    use externref::{externref, ExternRef};
+
+    #[externref]
+    pub fn f(value: ExternRef) -> ExternRef {
+        value
+    }
+}
+
+// The same import declared by two blocks in the same module.
+#[externref(name = "console")]
+extern "C" {
+    fn log(message: ExternRef);
+}
+
+#[externref(name = "console")]
+extern "C" {
+    #[externref(name = "log")]
+    fn log_again(message: ExternRef);
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+use externref::{externref, ExternRef};
+
+#[externref(name = "console")]
+extern "C" {
+    #[externref(name = "log")]
+    #[externref(group = "debug")]
+    fn trace(message: ExternRef);
+}
+
+#[externref(name = "host")]
+trait Host {
+    #[externref(name = "emit")]
+    #[externref(optional)]
+    fn send(&self, event: ExternRef);
+}
+
+fn main() {}
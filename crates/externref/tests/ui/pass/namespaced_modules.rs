@@ -0,0 +1,20 @@
+use externref::{externref, ExternRef};
+
+#[externref(name = "dom.node")]
+extern "C" {
+    fn append(node: ExternRef);
+}
+
+// Only differs from `dom.node` in characters that can't be in an identifier.
+#[externref(name = "dom_node")]
+extern "C" {
+    #[externref(name = "append")]
+    fn append_node(node: ExternRef);
+}
+
+#[externref(name = "wasi:io/streams")]
+extern "C" {
+    fn write(stream: ExternRef, len: usize);
+}
+
+fn main() {}
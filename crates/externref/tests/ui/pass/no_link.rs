@@ -0,0 +1,17 @@
+use externref::{externref, ExternRef};
+
+#[externref(no_link)]
+#[link(wasm_import_module = "console")]
+extern "C" {
+    #[link_name = "log"]
+    fn console_log(message: ExternRef);
+}
+
+#[externref(name = "dom")]
+extern "C" {
+    #[externref(no_link)]
+    #[link_name = "appendChild"]
+    fn append_child(parent: ExternRef, child: ExternRef);
+}
+
+fn main() {}
@@ -0,0 +1,20 @@
+use externref::externref;
+
+// Registered once, so the functions below don't need `ref_args`.
+#[externref(reexport = "HostRef")]
+pub use externref::ExternRef as HostRef;
+
+#[externref(name = "dom", strict)]
+extern "C" {
+    #[externref(name = "appendChild")]
+    fn append_child(parent: HostRef, child: HostRef) -> HostRef;
+}
+
+#[externref]
+pub fn identity(value: HostRef) -> HostRef {
+    value
+}
+
+fn main() {
+    let _: fn(HostRef) -> externref::ExternRef = identity;
+}
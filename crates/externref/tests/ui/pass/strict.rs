@@ -0,0 +1,15 @@
+use externref::{externref, ExternRef};
+
+pub struct Fd(u32);
+
+#[externref(name = "files", strict, allow_types = "Fd")]
+extern "C" {
+    fn open(path: ExternRef, flags: u32) -> ExternRef;
+
+    fn raw_fd(file: ExternRef) -> Fd;
+
+    #[externref(ref_args = "0")]
+    fn close(file: u32) -> bool;
+}
+
+fn main() {}
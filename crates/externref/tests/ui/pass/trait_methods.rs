@@ -0,0 +1,30 @@
+use externref::{externref, ExternRef};
+
+#[externref(name = "host")]
+pub trait Host {
+    #[externref(name = "emitEvent")]
+    fn emit(&self, event: ExternRef);
+
+    fn read(&mut self, (index, _): (u32, u32)) -> ExternRef;
+
+    // Every kind of receiver stays on the Rust side.
+    fn close(self, reason: ExternRef)
+    where
+        Self: Sized;
+
+    fn release(self: Box<Self>, node: ExternRef);
+
+    // Methods with a body are left alone.
+    fn emit_twice(&self, event: ExternRef) {
+        self.emit(event);
+        self.emit(event);
+    }
+}
+
+struct Client;
+
+impl Host for Client {}
+
+fn main() {
+    let _ = Client::emit_twice;
+}
@@ -0,0 +1,23 @@
+use externref::{externref, ExternRef, IsExternRef, Typed};
+
+pub enum DomNode {}
+
+// Strict mode accepts the wrapper as an `externref` whatever its label.
+#[externref(name = "dom", strict)]
+extern "C" {
+    #[externref(name = "appendChild")]
+    fn append_child(parent: Typed<DomNode>, child: Typed<DomNode>) -> Typed<DomNode>;
+}
+
+#[externref]
+pub fn detach(node: Typed<DomNode>) -> ExternRef {
+    node.into_inner()
+}
+
+fn is_extern_ref<T: IsExternRef>() {}
+
+fn main() {
+    is_extern_ref::<Typed<DomNode>>();
+    let _: fn(ExternRef) -> Typed<DomNode> = Typed::new;
+    let _: fn(Typed<DomNode>) -> ExternRef = ExternRef::from;
+}
@@ -0,0 +1,6 @@
+use externref::externref;
+
+#[externref]
+fn run(message: externref::ExternRef) {}
+
+fn main() {}
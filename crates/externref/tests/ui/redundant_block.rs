@@ -0,0 +1,10 @@
+#![deny(deprecated)]
+
+use externref::externref;
+
+#[externref(name = "env")]
+extern "C" {
+    fn add(a: u32, b: u32) -> u32;
+}
+
+fn main() {}
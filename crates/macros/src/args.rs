@@ -1,9 +1,95 @@
-use syn::NestedMeta;
+use std::collections::BTreeMap;
+
 use anyhow::Result;
+use externref_metadata::{Encoding, Intrinsic, Lowering};
+use proc_macro2::{TokenStream as TokenStream2, TokenTree};
+use syn::{
+    parse::{discouraged::Speculative, ParseStream},
+    punctuated::Punctuated,
+    spanned::Spanned,
+    token::Comma,
+    NestedMeta,
+};
+
+/// Every flag the macro accepts, e.g. `#[externref(optional)]`. Errors for unknown flags list these,
+/// so a flag must be added here along with its parsing.
+const FLAGS: &[&str] = &[
+    "optional",
+    "strict",
+    "callback",
+    "no_link",
+    "constructor",
+    "ret_nullable",
+    "export",
+    "host_callback",
+    "pack_args",
+];
+
+/// Every option taking a list, e.g. `#[externref(aliases("a", "b"))]`.
+const LIST_OPTIONS: &[&str] = &["aliases", "allow_types"];
+
+/// Every option taking a string, e.g. `#[externref(name = "log")]`.
+const VALUE_OPTIONS: &[&str] = &[
+    "name",
+    "ref_args",
+    "nullable_args",
+    "allow_types",
+    "alias",
+    "arg_types",
+    "export_ref",
+    "export_name",
+    "encoding",
+    "group",
+    "intrinsic",
+    "reexport",
+    "lowering",
+];
 
 #[derive(Debug, Default)]
 pub(crate) struct ExternRefOptions {
     pub(crate) name: Option<String>,
+    /// Indicies of arguments that should be treated as `externref`s regardless of their type.
+    pub(crate) ref_args: Vec<usize>,
+    /// If the import may be missing from the host.
+    pub(crate) optional: bool,
+    /// If argument and return types that aren't known to be plain values should be rejected.
+    pub(crate) strict: bool,
+    /// Types accepted in strict mode in addition to the primitives.
+    pub(crate) allow_types: Vec<String>,
+    /// Additional names to export the function under.
+    pub(crate) aliases: Vec<String>,
+    /// If the function is called by the host through a function table.
+    pub(crate) callback: bool,
+    /// The host type of `externref` arguments, keyed by the argument's index.
+    pub(crate) arg_types: BTreeMap<usize, String>,
+    /// The export whose function reference an imported intrinsic returns.
+    pub(crate) export_ref: Option<String>,
+    /// If the user links the imports with their own `#[link]` and `#[link_name]` attributes.
+    pub(crate) no_link: bool,
+    /// The name to export a function under, keeping the name of its Rust symbol.
+    pub(crate) export_name: Option<String>,
+    /// How the function data is encoded, the block's encoding or JSON when not given.
+    pub(crate) encoding: Option<Encoding>,
+    /// The feature area the function's metadata is tagged with.
+    pub(crate) group: Option<String>,
+    /// The intrinsic an import from the intrinsic module stands for.
+    pub(crate) intrinsic: Option<Intrinsic>,
+    /// If the import is a host constructor returning a new object.
+    pub(crate) constructor: bool,
+    /// Indicies of `externref` arguments that may be null.
+    pub(crate) nullable_args: Vec<usize>,
+    /// If a returned `externref` may be null.
+    pub(crate) ret_nullable: bool,
+    /// If an exported function is given `#[export_name]` with the name its data describes.
+    pub(crate) export: bool,
+    /// If the host may call back into the module while the function runs.
+    pub(crate) host_callback: bool,
+    /// The name a `use` item re-exports `ExternRef` under.
+    pub(crate) reexport: Option<String>,
+    /// How the transformer passes the function's `externref`s, directly when not given.
+    pub(crate) lowering: Option<Lowering>,
+    /// If the host receives the `externref` arguments after the others.
+    pub(crate) pack_args: bool,
 }
 
 impl ExternRefOptions {
@@ -14,9 +100,48 @@ impl ExternRefOptions {
         for meta in metas.into_iter() {
             let pair = match meta {
                 NestedMeta::Meta(syn::Meta::NameValue(pair)) => pair,
-                NestedMeta::Meta(_) | NestedMeta::Lit(_) => {
-                    anyhow::bail!("Only name value pairs are allowed in this proc-macro")
+                NestedMeta::Meta(syn::Meta::Path(path)) => {
+                    let flag = path
+                        .get_ident()
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("invalid identifier for attribute arguments")
+                        })?
+                        .to_string();
+
+                    match flag.as_ref() {
+                        "optional" => options.optional = true,
+                        "strict" => options.strict = true,
+                        "callback" => options.callback = true,
+                        "no_link" => options.no_link = true,
+                        "constructor" => options.constructor = true,
+                        "ret_nullable" => options.ret_nullable = true,
+                        "export" => options.export = true,
+                        "host_callback" => options.host_callback = true,
+                        "pack_args" => options.pack_args = true,
+                        x => return Err(unknown("flag", x, FLAGS)),
+                    }
+                    continue;
+                }
+                NestedMeta::Meta(syn::Meta::List(list)) => {
+                    let name = list
+                        .path
+                        .get_ident()
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("invalid identifier for attribute arguments")
+                        })?
+                        .to_string();
+                    let values = parse_str_list(list.nested)?;
+
+                    match name.as_ref() {
+                        "aliases" => options.aliases.extend(values),
+                        "allow_types" => options.allow_types.extend(values),
+                        x => return Err(unknown("list option", x, LIST_OPTIONS)),
+                    }
+                    continue;
                 }
+                NestedMeta::Lit(_) => anyhow::bail!(
+                    "Only name value pairs, lists and flags are allowed in this proc-macro"
+                ),
             };
 
             let name = pair
@@ -31,10 +156,533 @@ impl ExternRefOptions {
 
             match name.as_ref() {
                 "name" => options.name = Some(value),
-                x => anyhow::bail!("Invalid option {x}"),
+                "ref_args" => options.ref_args = parse_index_list("ref_args", &value)?,
+                "nullable_args" => {
+                    options
+                        .nullable_args
+                        .extend(parse_index_list("nullable_args", &value)?);
+                }
+                "allow_types" => options.allow_types.extend(parse_type_list(&value)),
+                "alias" => options.aliases.push(value),
+                "arg_types" => options.arg_types = parse_arg_types(&value)?,
+                "export_ref" => options.export_ref = Some(value),
+                "export_name" => options.export_name = Some(value),
+                "encoding" => options.encoding = Some(parse_encoding(&value)?),
+                "group" if value.is_empty() => anyhow::bail!("The group can't be empty"),
+                "group" => options.group = Some(value),
+                "intrinsic" => options.intrinsic = Some(parse_intrinsic(&value)?),
+                "reexport" => options.reexport = Some(value),
+                "lowering" => options.lowering = Some(parse_lowering(&value)?),
+                x => return Err(unknown("option", x, VALUE_OPTIONS)),
             }
         }
 
         Ok(options)
     }
+
+    /// Merges the options of another `externref` attribute on the same item into these. Flags and
+    /// lists are combined, while an option given a different value by each attribute is an error.
+    pub fn merge(&mut self, other: Self) -> Result<()> {
+        merge_value("name", &mut self.name, other.name)?;
+        merge_value("export_ref", &mut self.export_ref, other.export_ref)?;
+        merge_value("export_name", &mut self.export_name, other.export_name)?;
+        merge_value("encoding", &mut self.encoding, other.encoding)?;
+        merge_value("group", &mut self.group, other.group)?;
+        merge_value("intrinsic", &mut self.intrinsic, other.intrinsic)?;
+        merge_value("reexport", &mut self.reexport, other.reexport)?;
+        merge_value("lowering", &mut self.lowering, other.lowering)?;
+
+        if !other.ref_args.is_empty() {
+            if !self.ref_args.is_empty() && self.ref_args != other.ref_args {
+                anyhow::bail!(
+                    "Conflicting values for ref_args: {:?} and {:?}",
+                    self.ref_args,
+                    other.ref_args
+                );
+            }
+            self.ref_args = other.ref_args;
+        }
+        for (index, ty) in other.arg_types {
+            match self.arg_types.get(&index) {
+                Some(existing) if *existing != ty => anyhow::bail!(
+                    "Conflicting types for argument {index} in arg_types: {existing:?} and {ty:?}"
+                ),
+                _ => {
+                    self.arg_types.insert(index, ty);
+                }
+            }
+        }
+
+        self.optional |= other.optional;
+        self.strict |= other.strict;
+        self.callback |= other.callback;
+        self.no_link |= other.no_link;
+        self.constructor |= other.constructor;
+        self.ret_nullable |= other.ret_nullable;
+        self.export |= other.export;
+        self.host_callback |= other.host_callback;
+        self.pack_args |= other.pack_args;
+        self.nullable_args.extend(other.nullable_args);
+        self.allow_types.extend(other.allow_types);
+        self.aliases.extend(other.aliases);
+        Ok(())
+    }
+}
+
+/// The error for an option that isn't one of the `known` options of its kind.
+fn unknown(kind: &str, name: &str, known: &[&str]) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Invalid {kind} {name}, expected one of {}",
+        known.join(", ")
+    )
+}
+
+/// Sets an option from another attribute, unless it already has a different value.
+fn merge_value<T: PartialEq + std::fmt::Debug>(
+    key: &str,
+    value: &mut Option<T>,
+    other: Option<T>,
+) -> Result<()> {
+    match (value.as_ref(), other) {
+        (Some(existing), Some(other)) if *existing != other => {
+            anyhow::bail!("Conflicting values for {key}: {existing:?} and {other:?}")
+        }
+        (None, other) => *value = other,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Parses the arguments of the [externref](crate::externref) macro, evaluating `concat!` and
+/// `env!` invocations given as values, e.g. `name = concat!(env!("CARGO_PKG_NAME"), "_host")`.
+///
+/// The values end up in the metadata, so they have to be known while the macro expands rather than
+/// being left for the compiler to evaluate.
+pub(crate) fn parse_metas(input: ParseStream) -> syn::Result<Punctuated<NestedMeta, Comma>> {
+    let mut metas = Punctuated::new();
+    while !input.is_empty() {
+        let fork = input.fork();
+        let meta = match (
+            fork.parse::<syn::Path>(),
+            fork.parse::<syn::Token![=]>(),
+            fork.parse::<syn::ExprMacro>(),
+        ) {
+            (Ok(path), Ok(eq_token), Ok(expr)) if fork.is_empty() || fork.peek(syn::Token![,]) => {
+                input.advance_to(&fork);
+                let value = eval_str_macro(&expr.mac)?;
+                NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                    path,
+                    eq_token,
+                    lit: syn::LitStr::new(&value, expr.mac.path.span()).into(),
+                }))
+            }
+            _ => input.parse()?,
+        };
+        metas.push_value(meta);
+
+        if input.is_empty() {
+            break;
+        }
+        metas.push_punct(input.parse()?);
+    }
+    Ok(metas)
+}
+
+/// Evaluates a `concat!` or `env!` invocation to the string it expands to.
+fn eval_str_macro(mac: &syn::Macro) -> syn::Result<String> {
+    let name = mac
+        .path
+        .segments
+        .last()
+        .map(|segment| segment.ident.to_string());
+    match name.as_deref() {
+        Some("concat") => {
+            let parts = mac.parse_body_with(Punctuated::<syn::Expr, Comma>::parse_terminated)?;
+            parts
+                .iter()
+                .map(|part| match part {
+                    syn::Expr::Lit(expr) => match &expr.lit {
+                        syn::Lit::Str(lit) => Ok(lit.value()),
+                        syn::Lit::Char(lit) => Ok(lit.value().to_string()),
+                        syn::Lit::Int(lit) => Ok(lit.base10_digits().to_string()),
+                        syn::Lit::Float(lit) => Ok(lit.base10_digits().to_string()),
+                        syn::Lit::Bool(lit) => Ok(lit.value.to_string()),
+                        lit => Err(syn::Error::new_spanned(
+                            lit,
+                            "unsupported literal in concat!",
+                        )),
+                    },
+                    syn::Expr::Macro(expr) => eval_str_macro(&expr.mac),
+                    part => Err(syn::Error::new_spanned(
+                        part,
+                        "only literals, concat! and env! can be concatenated in externref options",
+                    )),
+                })
+                .collect()
+        }
+        Some("env") => {
+            let args = mac.parse_body_with(Punctuated::<syn::LitStr, Comma>::parse_terminated)?;
+            let Some(var) = args.first() else {
+                return Err(syn::Error::new_spanned(
+                    mac,
+                    "env! takes the name of a variable",
+                ));
+            };
+            // The macro runs in the compiler, which has the same variables `env!` reads.
+            std::env::var(var.value()).map_err(|_| match args.iter().nth(1) {
+                Some(message) => syn::Error::new_spanned(var, message.value()),
+                None => syn::Error::new_spanned(
+                    var,
+                    format!("environment variable `{}` not defined", var.value()),
+                ),
+            })
+        }
+        _ => Err(syn::Error::new_spanned(
+            mac,
+            "only string literals, concat! and env! are valid for externref options",
+        )),
+    }
+}
+
+/// Repeats every `env!` invocation in the tokens of options as an item of its own, e.g.
+/// `const _: &str = ::core::env!("PKG");`.
+///
+/// The macro reads the variables itself, which rustc doesn't know about, so without these items
+/// changing a variable wouldn't rebuild the crate.
+pub(crate) fn track_env(tokens: TokenStream2) -> TokenStream2 {
+    let mut tracked = TokenStream2::new();
+    let trees: Vec<_> = tokens.into_iter().collect();
+    for (i, tree) in trees.iter().enumerate() {
+        match (tree, trees.get(i + 1), trees.get(i + 2)) {
+            (
+                TokenTree::Ident(ident),
+                Some(TokenTree::Punct(bang)),
+                Some(TokenTree::Group(args)),
+            ) if ident == "env" && bang.as_char() == '!' => {
+                let args = args.stream();
+                tracked.extend(quote::quote_spanned! {ident.span()=>
+                    const _: &str = ::core::env!(#args);
+                });
+            }
+            (TokenTree::Group(group), ..) => tracked.extend(track_env(group.stream())),
+            _ => {}
+        }
+    }
+    tracked
+}
+
+/// Collects the string literals of a list option, e.g. `aliases("b", "c")`.
+fn parse_str_list(nested: impl IntoIterator<Item = NestedMeta>) -> Result<Vec<String>> {
+    nested
+        .into_iter()
+        .map(|meta| match meta {
+            NestedMeta::Lit(syn::Lit::Str(lit)) => Ok(lit.value()),
+            _ => anyhow::bail!("Only string literals are valid in externref list options"),
+        })
+        .collect()
+}
+
+/// Parses a comma separated list of argument indicies, e.g. `"0, 2"`.
+fn parse_index_list(key: &str, value: &str) -> Result<Vec<usize>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|index| !index.is_empty())
+        .map(|index| {
+            index
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid argument index {index:?} in {key}"))
+        })
+        .collect()
+}
+
+/// Parses a comma separated list of argument indicies and type names, e.g. `"0:DomNode, 2:Event"`.
+fn parse_arg_types(value: &str) -> Result<BTreeMap<usize, String>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (index, ty) = entry.split_once(':').ok_or_else(|| {
+                anyhow::anyhow!("Expected INDEX:TYPE in arg_types, found {entry:?}")
+            })?;
+            let index = index
+                .trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid argument index {index:?} in arg_types"))?;
+            let ty = ty.trim();
+            if ty.is_empty() {
+                anyhow::bail!("Missing the type of argument {index} in arg_types");
+            }
+            Ok((index, ty.to_string()))
+        })
+        .collect()
+}
+
+/// Parses a comma separated list of type names, e.g. `"Handle, Fd"`.
+fn parse_type_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|ty| !ty.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parses the encoding of the function data, `json` or `binary`.
+fn parse_encoding(value: &str) -> Result<Encoding> {
+    match value {
+        "json" => Ok(Encoding::Json),
+        "binary" => Ok(Encoding::Binary),
+        "gzip" => Ok(Encoding::Gzip),
+        _ => anyhow::bail!("Invalid encoding {value:?}, expected json, binary or gzip"),
+    }
+}
+
+fn parse_intrinsic(value: &str) -> Result<Intrinsic> {
+    Intrinsic::from_name(value).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Invalid intrinsic {value:?}, expected ref.null, ref.is_null or transformed"
+        )
+    })
+}
+
+fn parse_lowering(value: &str) -> Result<Lowering> {
+    Lowering::from_name(value)
+        .ok_or_else(|| anyhow::anyhow!("Invalid lowering {value:?}, expected direct or table"))
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use externref_metadata::{Encoding, Intrinsic, Lowering};
+
+    use super::{parse_metas, track_env, ExternRefOptions, FLAGS, LIST_OPTIONS, VALUE_OPTIONS};
+
+    fn parse(tokens: proc_macro2::TokenStream) -> Result<ExternRefOptions> {
+        let metas = syn::parse::Parser::parse2(parse_metas, tokens)?;
+        ExternRefOptions::parse(metas)
+    }
+
+    #[test]
+    fn parse_ref_args() -> Result<()> {
+        let opts = parse(quote::quote! { ref_args = "0, 2" })?;
+        assert_eq!(opts.ref_args, &[0, 2]);
+
+        let opts = parse(quote::quote! { name = "f", ref_args = "1" })?;
+        assert_eq!(opts.name.as_deref(), Some("f"));
+        assert_eq!(opts.ref_args, &[1]);
+
+        let opts = parse(quote::quote! { name = "a", alias = "b", alias = "c" })?;
+        assert_eq!(opts.aliases, &["b", "c"]);
+
+        assert!(parse(quote::quote! { ref_args = "0, one" }).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_lists() -> Result<()> {
+        let opts = parse(quote::quote! { name = "a", aliases("b", "c") })?;
+        assert_eq!(opts.name.as_deref(), Some("a"));
+        assert_eq!(opts.aliases, &["b", "c"]);
+
+        // List and name value forms of the same option are combined.
+        let opts = parse(quote::quote! { alias = "b", aliases("c"), aliases() })?;
+        assert_eq!(opts.aliases, &["b", "c"]);
+
+        let opts = parse(quote::quote! { strict, allow_types("Handle", "Fd") })?;
+        assert_eq!(opts.allow_types, &["Handle", "Fd"]);
+
+        assert!(parse(quote::quote! { aliases(b) }).is_err());
+        assert!(parse(quote::quote! { aliases(1) }).is_err());
+        assert!(parse(quote::quote! { unknown("a") }).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_arg_types() -> Result<()> {
+        let opts = parse(quote::quote! { arg_types = "0:DomNode, 2: Event" })?;
+        assert_eq!(
+            opts.arg_types.into_iter().collect::<Vec<_>>(),
+            [(0, "DomNode".to_string()), (2, "Event".to_string())]
+        );
+
+        assert!(parse(quote::quote! { arg_types = "DomNode" }).is_err());
+        assert!(parse(quote::quote! { arg_types = "x:DomNode" }).is_err());
+        assert!(parse(quote::quote! { arg_types = "0:" }).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_encoding() -> Result<()> {
+        assert_eq!(parse(quote::quote! { name = "dom" })?.encoding, None);
+        let opts = parse(quote::quote! { encoding = "binary" })?;
+        assert_eq!(opts.encoding, Some(Encoding::Binary));
+        let opts = parse(quote::quote! { encoding = "json" })?;
+        assert_eq!(opts.encoding, Some(Encoding::Json));
+        let opts = parse(quote::quote! { encoding = "gzip" })?;
+        assert_eq!(opts.encoding, Some(Encoding::Gzip));
+
+        assert!(parse(quote::quote! { encoding = "cbor" }).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_group() -> Result<()> {
+        let opts = parse(quote::quote! { name = "console", group = "dom" })?;
+        assert_eq!(opts.group.as_deref(), Some("dom"));
+        assert_eq!(parse(quote::quote! { name = "console" })?.group, None);
+        assert!(parse(quote::quote! { group = "" }).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_intrinsic() -> Result<()> {
+        let opts = parse(quote::quote! { intrinsic = "ref.is_null" })?;
+        assert_eq!(opts.intrinsic, Some(Intrinsic::RefIsNull));
+        let opts = parse(quote::quote! { intrinsic = "transformed" })?;
+        assert_eq!(opts.intrinsic, Some(Intrinsic::Transformed));
+        assert_eq!(parse(quote::quote! { name = "null" })?.intrinsic, None);
+        assert!(parse(quote::quote! { intrinsic = "ref.func" }).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_lowering() -> Result<()> {
+        let opts = parse(quote::quote! { lowering = "table" })?;
+        assert_eq!(opts.lowering, Some(Lowering::Table));
+        let opts = parse(quote::quote! { lowering = "direct" })?;
+        assert_eq!(opts.lowering, Some(Lowering::Direct));
+        assert_eq!(parse(quote::quote! { name = "log" })?.lowering, None);
+        assert!(parse(quote::quote! { lowering = "memory" }).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_options() -> Result<()> {
+        let mut opts = parse(quote::quote! { name = "log", alias = "a", arg_types = "0:Node" })?;
+        opts.merge(parse(quote::quote! {
+            name = "log", optional, alias = "b", arg_types = "1:Event", nullable_args = "1"
+        })?)?;
+        assert_eq!(opts.name.as_deref(), Some("log"));
+        assert!(opts.optional);
+        assert_eq!(opts.aliases, ["a", "b"]);
+        assert_eq!(opts.nullable_args, [1]);
+        assert_eq!(opts.arg_types.len(), 2);
+
+        // The same option can't be given different values.
+        for other in [
+            quote::quote! { name = "error" },
+            quote::quote! { arg_types = "0:Event" },
+        ] {
+            let mut opts = parse(quote::quote! { name = "log", arg_types = "0:Node" })?;
+            assert!(opts.merge(parse(other)?).is_err());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_options() {
+        let error = parse(quote::quote! { nullable = "0" })
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("Invalid option nullable, expected one of name, "),
+            "{error}"
+        );
+        for name in ["name", "group", "nullable_args", "lowering", "reexport"] {
+            assert!(error.contains(name), "{error}");
+        }
+        let error = parse(quote::quote! { unknown }).unwrap_err().to_string();
+        assert!(error.contains("optional, strict"), "{error}");
+        let error = parse(quote::quote! { names("a") }).unwrap_err().to_string();
+        assert!(
+            error.ends_with("expected one of aliases, allow_types"),
+            "{error}"
+        );
+
+        // The lists are what's parsed, so every listed option is accepted as its kind.
+        let ident = |name: &str| syn::Ident::new(name, proc_macro2::Span::call_site());
+        for name in FLAGS.iter().map(|name| ident(name)) {
+            assert!(parse(quote::quote! { #name }).is_ok(), "{name}");
+        }
+        for name in LIST_OPTIONS.iter().map(|name| ident(name)) {
+            assert!(parse(quote::quote! { #name("a") }).is_ok(), "{name}");
+        }
+        for name in VALUE_OPTIONS.iter().map(|name| ident(name)) {
+            if let Err(error) = parse(quote::quote! { #name = "0" }) {
+                assert!(!error.to_string().starts_with("Invalid option"), "{error}");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_flags() -> Result<()> {
+        let opts = parse(quote::quote! { name = "console", optional })?;
+        assert_eq!(opts.name.as_deref(), Some("console"));
+        assert!(opts.optional);
+
+        assert!(!parse(quote::quote! { name = "console" })?.optional);
+        assert!(parse(quote::quote! { unknown }).is_err());
+        assert!(parse(quote::quote! { callback })?.callback);
+        assert!(parse(quote::quote! { no_link })?.no_link);
+        assert!(parse(quote::quote! { constructor })?.constructor);
+        assert!(parse(quote::quote! { ret_nullable })?.ret_nullable);
+        assert!(parse(quote::quote! { export })?.export);
+        assert!(parse(quote::quote! { host_callback })?.host_callback);
+        assert!(parse(quote::quote! { pack_args })?.pack_args);
+
+        let opts = parse(quote::quote! { strict, allow_types = "Handle, Fd" })?;
+        assert!(opts.strict);
+        assert_eq!(opts.allow_types, &["Handle", "Fd"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_macro_values() -> Result<()> {
+        let opts = parse(quote::quote! { name = concat!("app", "_host"), optional })?;
+        assert_eq!(opts.name.as_deref(), Some("app_host"));
+        assert!(opts.optional);
+
+        let opts = parse(quote::quote! {
+            name = concat!(env!("CARGO_PKG_NAME"), "_", 1, std::concat!("_", 'v')),
+            alias = env!("CARGO_PKG_NAME"),
+        })?;
+        assert_eq!(opts.name.as_deref(), Some("externref-macros_1_v"));
+        assert_eq!(opts.aliases, &["externref-macros"]);
+
+        assert!(parse(quote::quote! { name = env!("EXTERNREF_UNDEFINED_VARIABLE") }).is_err());
+        assert!(parse(quote::quote! { name = format!("{}", 1) }).is_err());
+        assert!(parse(quote::quote! { name = concat!(HOST, "_dom") }).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn env_dependencies() {
+        // Every variable is tracked, including ones nested in `concat!`.
+        let tracked = track_env(quote::quote! {
+            name = concat!(env!("CARGO_PKG_NAME"), "_host"),
+            alias = env!("HOST_ALIAS", "set HOST_ALIAS"),
+            group = "dom",
+        });
+        assert_eq!(
+            tracked.to_string(),
+            quote::quote! {
+                const _: &str = ::core::env!("CARGO_PKG_NAME");
+                const _: &str = ::core::env!("HOST_ALIAS", "set HOST_ALIAS");
+            }
+            .to_string()
+        );
+        assert!(track_env(quote::quote! { name = "env" }).is_empty());
+    }
 }
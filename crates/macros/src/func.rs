@@ -1,32 +1,112 @@
 use anyhow::Result;
+use externref_metadata::{
+    data_section_name, group_section_name, Encoding, FunctionData, Lowering, ResultRefs,
+    MANIFEST_SECTION,
+};
 use proc_macro2::{Span, TokenStream};
-use serde::{Deserialize, Serialize};
-use syn::{punctuated::Punctuated, token::Comma, *};
+use syn::*;
 
-use crate::args::ExternRefOptions;
+use crate::{
+    args::{parse_metas, ExternRefOptions},
+    registry,
+};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub(crate) struct FunctionData {
-    /// The name of the function as it appears in the transformed WASM binary.
-    pub name: String,
-    /// The indicies of arguments that should have the type `externref`.
-    pub arg_indicies: Vec<usize>,
-    /// If the return type is an `externref`.
-    pub ret_is_extern_ref: bool,
+/// Macro specific functionality for the [FunctionData] shared with the transformer.
+pub(crate) trait FunctionDataExt: Sized {
+    /// Parses data necessary for the functions transformation based on it's signature and options.
+    fn parse<'attrs>(
+        sig: &Signature,
+        attrs_or_opts: impl Into<AttributesOrOptions<'attrs>>,
+    ) -> Result<Self>;
+
+    /// Generates a [TokenStream] of a static variable that acts as a custom WASM section
+    /// containing information about the function for the transformer.
+    fn to_data_section_token_stream(
+        &self,
+        module: Option<&str>,
+        encoding: Encoding,
+    ) -> Result<TokenStream>;
 }
 
-impl FunctionData {
-    /// Parses data necessary for the functions transformation based on it's signature and options.
-    pub fn parse<'attrs>(
+impl FunctionDataExt for FunctionData {
+    fn parse<'attrs>(
         sig: &Signature,
         attrs_or_opts: impl Into<AttributesOrOptions<'attrs>>,
     ) -> Result<Self> {
         let attrs_or_opts: AttributesOrOptions<'attrs> = attrs_or_opts.into();
         let opts: ExternRefOptions = attrs_or_opts.try_into()?;
+        if opts.reexport.is_some() {
+            anyhow::bail!("reexport can only be given on a `use` item");
+        }
+        // WASM functions have a fixed number of parameters, so there's no way to pass the extra
+        // arguments.
+        if let Some(variadic) = &sig.variadic {
+            return Err(Error::new_spanned(
+                variadic,
+                "variadic functions aren't supported by externref as WASM has no varargs",
+            )
+            .into());
+        }
+
+        // Indicies are of wasm parameters, and a receiver has no parameter of its own to lower to.
+        if let Some(receiver) = sig.inputs.iter().find(|arg| is_receiver(arg)) {
+            return Err(Error::new_spanned(
+                receiver,
+                "functions with a receiver can't be imported or exported, as `self` has no wasm \
+                 parameter to lower to",
+            )
+            .into());
+        }
+
+        // Only references in the signature itself are converted, a `Vec` is returned through a
+        // pointer to handles the host can't resolve.
+        if let Some(ty) = returned_ref_list(&sig.output) {
+            return Err(Error::new_spanned(
+                ty,
+                "returning a list of references isn't supported, as only references passed \
+                 directly are converted; return the number of references and export a function \
+                 returning the one at an index, or pass each of them to an import taking an \
+                 `ExternRef`",
+            )
+            .into());
+        }
+
+        let ret_type = match &sig.output {
+            ReturnType::Type(_, ty) => Some(&**ty),
+            ReturnType::Default => None,
+        };
+        let input_types = sig.inputs.iter().filter_map(|arg| match arg {
+            FnArg::Typed(pat_type) => Some(&*pat_type.ty),
+            FnArg::Receiver(_) => None,
+        });
+
+        // `impl Trait` is a type only the compiler knows, so there's nothing to pass to the host.
+        if let Some(ty) = input_types.clone().chain(ret_type).find_map(impl_trait) {
+            return Err(Error::new_spanned(
+                ty,
+                "`impl Trait` isn't supported in imported or exported functions, as it has no wasm \
+                 type to lower to; use a concrete type instead",
+            )
+            .into());
+        }
+
+        // `ExternRef` has no niche for `None`, so an `Option` of one is passed as a tag and a
+        // handle rather than the handle the host expects.
+        if let Some(ty) = input_types
+            .chain(ret_type)
+            .find(|ty| is_optional_extern_ref(ty))
+        {
+            return Err(Error::new_spanned(
+                ty,
+                "an `Option<ExternRef>` isn't a single handle at the ABI level; use an `ExternRef` \
+                 that may be null and mark it with `nullable_args` or `ret_nullable` instead",
+            )
+            .into());
+        }
+
         let name = opts.name.unwrap_or_else(|| sig.ident.to_string());
 
-        let arg_indicies = sig
+        let mut arg_indicies: Vec<usize> = sig
             .inputs
             .iter()
             .enumerate()
@@ -36,56 +116,520 @@ impl FunctionData {
             })
             .collect();
 
+        // Arguments can also be marked by index for signatures using raw handles, e.g. `u32`.
+        if let Some(index) = opts.ref_args.iter().find(|i| **i >= sig.inputs.len()) {
+            anyhow::bail!(
+                "ref_args index {index} is out of range for a function with {} arguments",
+                sig.inputs.len()
+            );
+        }
+        arg_indicies.extend(opts.ref_args);
+        arg_indicies.sort_unstable();
+        arg_indicies.dedup();
+
+        if let Some(index) = opts.arg_types.keys().find(|i| !arg_indicies.contains(i)) {
+            anyhow::bail!("arg_types index {index} isn't an externref argument");
+        }
+        let mut nullable_args = opts.nullable_args;
+        nullable_args.sort_unstable();
+        nullable_args.dedup();
+        if let Some(index) = nullable_args.iter().find(|i| !arg_indicies.contains(i)) {
+            anyhow::bail!("nullable_args index {index} isn't an externref argument");
+        }
+        // Intrinsics never reach the host, so there's nothing to lower.
+        if opts.lowering == Some(Lowering::Table)
+            && (opts.intrinsic.is_some() || opts.export_ref.is_some())
+        {
+            anyhow::bail!("intrinsics can't be lowered through the table");
+        }
+        let ret_is_extern_ref = ret_type.is_some_and(type_is_extern_ref);
+        if opts.ret_nullable && !ret_is_extern_ref {
+            anyhow::bail!("ret_nullable requires the function to return an externref");
+        }
+        if opts.pack_args && arg_indicies.is_empty() {
+            anyhow::bail!("pack_args requires the function to take an externref");
+        }
+
+        // A registered newtype or a `Typed<T>` names the host type unless it's given explicitly.
+        let mut arg_types = opts.arg_types;
+        for (i, arg) in sig.inputs.iter().enumerate() {
+            if let FnArg::Typed(pat_type) = arg {
+                if let Some(name) = registered_type_name(&pat_type.ty) {
+                    arg_types.entry(i).or_insert(name);
+                }
+            }
+        }
+
+        if opts.strict {
+            check_strict_types(sig, &arg_indicies, &opts.allow_types)?;
+        }
+
         Ok(Self {
             name,
             arg_indicies,
-            ret_is_extern_ref: match &sig.output {
-                ReturnType::Type(_, ret_type) => type_is_extern_ref(ret_type),
-                _ => false,
+            ret_is_extern_ref,
+            optional: opts.optional,
+            arg_names: sig.inputs.iter().map(arg_name).collect(),
+            aliases: opts.aliases,
+            module: None,
+            ret_result: match &sig.output {
+                ReturnType::Type(_, ret_type) => result_refs(ret_type),
+                _ => None,
             },
+            callback: opts.callback,
+            arg_types,
+            export_ref: opts.export_ref,
+            param_count: Some(sig.inputs.len()),
+            group: opts.group,
+            intrinsic: opts.intrinsic,
+            constructor: opts.constructor,
+            nullable_args,
+            ret_nullable: opts.ret_nullable,
+            reentrant: opts.host_callback,
+            lowering: opts.lowering.unwrap_or_default(),
+            pack_args: opts.pack_args,
         })
     }
 
-    /// Generates a [TokenStream] of a static variable that acts as a custom WASM section
-    /// containing information about the function for the transformer.
-    pub fn to_data_section_token_stream(&self, module: Option<&str>) -> Result<TokenStream> {
-        let fn_name = match module {
-            Some(module) => format!("__extern_ref_data_{module}_{}", self.name),
-            None => format!("__extern_ref_data_{}", self.name),
+    fn to_data_section_token_stream(
+        &self,
+        module: Option<&str>,
+        encoding: Encoding,
+    ) -> Result<TokenStream> {
+        // Grouped sections are named after the group, the data still records which it is.
+        let fn_name = match &self.group {
+            Some(group) => group_section_name(group, module, &self.name),
+            None => data_section_name(module, &self.name),
         };
+        let bytes = encode(self, encoding)?;
+
+        Ok(section_static(&fn_name, &fn_name, &bytes))
+    }
+}
+
+/// Generates a [TokenStream] of a static variable holding the manifest section, a JSON array of
+/// the data of every function.
+pub(crate) fn manifest_token_stream(functions: &[FunctionData]) -> Result<TokenStream> {
+    let bytes = serde_json::to_vec(functions)?;
+    Ok(section_static(MANIFEST_SECTION, MANIFEST_SECTION, &bytes))
+}
 
-        let ident = Ident::new(&fn_name, Span::call_site());
+/// The byte representation of the function data in the section.
+fn encode(data: &FunctionData, encoding: Encoding) -> Result<Vec<u8>> {
+    Ok(match encoding {
+        Encoding::Json => serde_json::to_vec(data)?,
+        Encoding::Binary => data.to_binary(),
+        #[cfg(feature = "compression")]
+        Encoding::Gzip => externref_metadata::compress(&serde_json::to_vec(data)?),
+        #[cfg(not(feature = "compression"))]
+        Encoding::Gzip => {
+            anyhow::bail!("the gzip encoding needs the `compression` feature of externref")
+        }
+    })
+}
 
-        // The byte representation of the function data encoded into JSON.
-        let bytes = serde_json::to_vec(self)?;
-        let length = LitInt::new(&bytes.len().to_string(), Span::call_site());
+/// Generates a static named after `ident_name` that places `bytes` in the custom section `section`.
+fn section_static(ident_name: &str, section: &str, bytes: &[u8]) -> TokenStream {
+    // Module names can contain characters that aren't valid in identifiers, e.g. `${HOST}` or
+    // `wasi:io/streams`. They're escaped rather than replaced with `_` so `dom.node` and
+    // `dom_node` don't declare the same static.
+    let ident_name: String = ident_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c.to_string()
+            } else {
+                format!("_u{:x}_", c as u32)
+            }
+        })
+        .collect();
+    let ident = Ident::new(&ident_name, Span::call_site());
+    let length = LitInt::new(&bytes.len().to_string(), Span::call_site());
 
-        // Creates a comma separated list of byte literals that are for an array of the JSON bytes.
-        let data_byte_str = Lit::ByteStr(LitByteStr::new(&bytes, Span::call_site()));
+    // A single byte string literal, which stays cheap to build and parse for large sections.
+    let data_byte_str = Lit::ByteStr(LitByteStr::new(bytes, Span::call_site()));
 
-        Ok(quote::quote! {
+    // `#[used]` keeps the section alive even when the function is only referenced indirectly,
+    // e.g. from a function table, rather than by name. The static is scoped to an anonymous const
+    // so functions with the same name in different modules, or an import declared by two blocks,
+    // don't declare the same static.
+    quote::quote! {
+        const _: () = {
             #[allow(incorrect_ident_case)]
             #[allow(clippy::all)]
-            #[link_section = #fn_name]
+            #[link_section = #section]
+            #[used]
             static #ident: [u8; #length] = *#data_byte_str;
-        })
+        };
+    }
+}
+
+/// Checks that an exported function is `pub`, as other visibilities don't become wasm exports.
+pub(crate) fn check_export_visibility(vis: &Visibility) -> Result<()> {
+    match vis {
+        Visibility::Public(_) => Ok(()),
+        Visibility::Inherited => {
+            anyhow::bail!("exported functions must be `pub` to become wasm exports")
+        }
+        vis => anyhow::bail!(
+            "exported functions must be `pub` to become wasm exports, found `{}`",
+            quote::quote!(#vis)
+        ),
+    }
+}
+
+/// The symbol a function is exported under when it's given one with `#[no_mangle]` or
+/// `#[export_name = "..."]`.
+pub(crate) fn export_symbol(func: &ItemFn) -> syn::Result<Option<String>> {
+    let mut symbol = None;
+    for attr in &func.attrs {
+        let Ok(meta) = attr.parse_meta() else {
+            continue;
+        };
+
+        match meta {
+            Meta::Path(path) if path.is_ident("no_mangle") => {
+                symbol.get_or_insert_with(|| func.sig.ident.to_string());
+            }
+            Meta::NameValue(pair) if pair.path.is_ident("export_name") => match pair.lit {
+                // `export_name` takes precedence over `no_mangle`.
+                Lit::Str(lit) => symbol = Some(lit.value()),
+                lit => return Err(Error::new_spanned(lit, "export_name must be a string")),
+            },
+            _ => {}
+        }
+    }
+    Ok(symbol)
+}
+
+/// The module given by a `#[link(wasm_import_module = "...")]` attribute.
+pub(crate) fn link_import_module(attrs: &[Attribute]) -> syn::Result<Option<String>> {
+    for attr in attrs {
+        let Ok(Meta::List(list)) = attr.parse_meta() else {
+            continue;
+        };
+        if !list.path.is_ident("link") {
+            continue;
+        }
+
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(pair))
+                    if pair.path.is_ident("wasm_import_module") =>
+                {
+                    match pair.lit {
+                        Lit::Str(lit) => return Ok(Some(lit.value())),
+                        lit => {
+                            return Err(Error::new_spanned(
+                                lit,
+                                "wasm_import_module must be a string",
+                            ))
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// The symbol given by a `#[link_name = "..."]` attribute.
+pub(crate) fn link_name(attrs: &[Attribute]) -> syn::Result<Option<String>> {
+    for attr in attrs {
+        match attr.parse_meta() {
+            Ok(Meta::NameValue(pair)) if pair.path.is_ident("link_name") => match pair.lit {
+                Lit::Str(lit) => return Ok(Some(lit.value())),
+                lit => return Err(Error::new_spanned(lit, "link_name must be a string")),
+            },
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// If an argument is a method's receiver, including typed ones like `self: Box<Self>`, which are
+/// parsed as an ordinary argument named `self`.
+pub(crate) fn is_receiver(arg: &FnArg) -> bool {
+    match arg {
+        FnArg::Receiver(_) => true,
+        FnArg::Typed(pat_type) => {
+            matches!(&*pat_type.pat, Pat::Ident(pat_ident) if pat_ident.ident == "self")
+        }
+    }
+}
+
+/// The name an argument is bound to, if it's a plain identifier.
+fn arg_name(arg: &FnArg) -> Option<String> {
+    match arg {
+        FnArg::Typed(pat_type) => match &*pat_type.pat {
+            Pat::Ident(pat_ident) => Some(pat_ident.ident.to_string()),
+            _ => None,
+        },
+        FnArg::Receiver(_) => None,
+    }
+}
+
+/// The types strict mode accepts without them being listed in `allow_types`.
+const STRICT_PRIMITIVES: &[&str] = &[
+    "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32", "u64", "u128", "usize", "f32",
+    "f64", "bool", "char",
+];
+
+/// Checks that every argument and the return type is either an `ExternRef`, a primitive, or one of
+/// the allowed types, so a handle type that should have been an `ExternRef` isn't silently passed
+/// through as a plain value.
+///
+/// Arguments marked with `ref_args` are already acknowledged as handles and aren't checked.
+fn check_strict_types(
+    sig: &Signature,
+    arg_indicies: &[usize],
+    allow_types: &[String],
+) -> syn::Result<()> {
+    let is_allowed = |ty: &Type| {
+        if type_is_extern_ref(ty) {
+            return true;
+        }
+
+        match ty {
+            Type::Path(type_path) => type_path
+                .path
+                .get_ident()
+                .map(|ident| {
+                    STRICT_PRIMITIVES.iter().any(|primitive| ident == primitive)
+                        || allow_types.iter().any(|allowed| ident == allowed)
+                })
+                .unwrap_or(false),
+            Type::Tuple(tuple) => tuple.elems.is_empty(),
+            // A diverging function has no results, the same as one returning `()`.
+            Type::Never(_) => true,
+            _ => false,
+        }
+    };
+
+    for (i, arg) in sig.inputs.iter().enumerate() {
+        let FnArg::Typed(pat_type) = arg else {
+            continue;
+        };
+        let ty = &pat_type.ty;
+        if !arg_indicies.contains(&i) && !is_allowed(ty) {
+            return Err(Error::new_spanned(
+                ty,
+                format!(
+                    "strict mode doesn't accept argument {i} of type `{}`, use `ExternRef`, mark \
+                     it with `ref_args` or add it to `allow_types`",
+                    quote::quote!(#ty)
+                ),
+            ));
+        }
+    }
+
+    if let ReturnType::Type(_, ty) = &sig.output {
+        // A `Result` with an `ExternRef` variant is lowered by the transformer.
+        let allowed = match result_variants(ty) {
+            Some((ok, err)) if result_refs(ty).is_some() => is_allowed(ok) && is_allowed(err),
+            _ => is_allowed(ty),
+        };
+        if !allowed {
+            return Err(Error::new_spanned(
+                ty,
+                format!(
+                    "strict mode doesn't accept the return type `{}`, use `ExternRef` or add it \
+                     to `allow_types`",
+                    quote::quote!(#ty)
+                ),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Which variants are `ExternRef`s if the type is a `Result` with an `ExternRef` variant.
+fn result_refs(ty: &Type) -> Option<ResultRefs> {
+    let (ok, err) = result_variants(ty)?;
+    let refs = ResultRefs {
+        ok: type_is_extern_ref(ok),
+        err: type_is_extern_ref(err),
+    };
+    (refs.ok || refs.err).then_some(refs)
+}
+
+/// The `Ok` and `Err` types if the type is a `Result`.
+fn result_variants(ty: &Type) -> Option<(&Type, &Type)> {
+    let Type::Path(type_path) = ty else {
+        return None;
+    };
+    let segment = type_path.path.segments.last()?;
+    if segment.ident != "Result" {
+        return None;
+    }
+
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    let mut types = args.args.iter().filter_map(|arg| match arg {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    });
+    match (types.next(), types.next(), types.next()) {
+        (Some(ok), Some(err), None) => Some((ok, err)),
+        _ => None,
+    }
+}
+
+/// The `impl Trait` a type is, looking through references, e.g. `&impl Display`.
+fn impl_trait(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::ImplTrait(_) => Some(ty),
+        Type::Reference(reference) => impl_trait(&reference.elem),
+        Type::Paren(paren) => impl_trait(&paren.elem),
+        Type::Group(group) => impl_trait(&group.elem),
+        _ => None,
+    }
+}
+
+/// The returned list of references, if a function returns a `Vec` of `ExternRef`s or an
+/// `ExternRefList`, including as a variant of a `Result`.
+fn returned_ref_list(output: &ReturnType) -> Option<&Type> {
+    let ReturnType::Type(_, ty) = output else {
+        return None;
+    };
+    let is_ref_list = |ty: &Type| {
+        let Type::Path(type_path) = ty else {
+            return false;
+        };
+        let Some(segment) = type_path.path.segments.last() else {
+            return false;
+        };
+        match &segment.arguments {
+            PathArguments::None => segment.ident == "ExternRefList",
+            PathArguments::AngleBracketed(args) if segment.ident == "Vec" => {
+                matches!(args.args.first(), Some(GenericArgument::Type(ty)) if type_is_extern_ref(ty))
+            }
+            _ => false,
+        }
+    };
+
+    match result_variants(ty) {
+        Some((ok, err)) => [ok, err].into_iter().find(|ty| is_ref_list(ty)),
+        None => is_ref_list(ty).then_some(&**ty),
+    }
+}
+
+/// A function pointer type that takes or returns an `ExternRef`, looking through generic
+/// arguments like the `Option` of an optional vtable entry.
+///
+/// Calls through a function pointer are `call_indirect`s the transformer can't tell apart from
+/// any other, so nothing converts the references they pass.
+pub(crate) fn ref_fn_pointer(ty: &Type) -> Option<&TypeBareFn> {
+    match ty {
+        Type::BareFn(bare_fn) => {
+            let returns_ref = match &bare_fn.output {
+                ReturnType::Type(_, ty) => type_is_extern_ref(ty),
+                ReturnType::Default => false,
+            };
+            let takes_ref = bare_fn.inputs.iter().any(|arg| type_is_extern_ref(&arg.ty));
+            (takes_ref || returns_ref).then_some(bare_fn)
+        }
+        Type::Paren(paren) => ref_fn_pointer(&paren.elem),
+        Type::Group(group) => ref_fn_pointer(&group.elem),
+        Type::Path(type_path) => type_path
+            .path
+            .segments
+            .iter()
+            .filter_map(|segment| match &segment.arguments {
+                PathArguments::AngleBracketed(args) => Some(args),
+                _ => None,
+            })
+            .flat_map(|args| &args.args)
+            .find_map(|arg| match arg {
+                GenericArgument::Type(ty) => ref_fn_pointer(ty),
+                _ => None,
+            }),
+        _ => None,
     }
 }
 
 // TODO(zeb): support qualified paths and type aliases /somehow/
 fn type_is_extern_ref(ty: &Type) -> bool {
+    match ty {
+        Type::Path(type_path) => {
+            typed_param(ty).is_some()
+                || type_path
+                    .path
+                    .get_ident()
+                    .map(|ident| {
+                        let name = ident.to_string();
+                        name == "ExternRef"
+                            || registry::is_reexport(&name)
+                            || registry::is_registered(&name)
+                    })
+                    .unwrap_or(false)
+        }
+        _ => false,
+    }
+}
+
+/// The `T` of a `Typed<T>` reference, whatever type it is.
+fn typed_param(ty: &Type) -> Option<&Type> {
+    let Type::Path(type_path) = ty else {
+        return None;
+    };
+    let segments = &type_path.path.segments;
+    let segment = segments.first().filter(|_| segments.len() == 1)?;
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if segment.ident == "Typed" && args.args.len() == 1 => {
+            match args.args.first() {
+                Some(GenericArgument::Type(param)) => Some(param),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The label of a `Typed<T>` reference, e.g. `DomNode` for `Typed<DomNode>`, or `None` when `T`
+/// isn't a path, e.g. for `Typed<()>`, which is still a reference but doesn't name a host type.
+fn typed_label(ty: &Type) -> Option<String> {
+    match typed_param(ty)? {
+        Type::Path(label) => label.path.segments.last().map(|s| s.ident.to_string()),
+        _ => None,
+    }
+}
+
+/// If the type is an `Option` of an `ExternRef`.
+fn is_optional_extern_ref(ty: &Type) -> bool {
+    let Type::Path(type_path) = ty else {
+        return false;
+    };
+    let Some(segment) = type_path.path.segments.last() else {
+        return false;
+    };
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if segment.ident == "Option" => {
+            matches!(args.args.first(), Some(GenericArgument::Type(ty)) if type_is_extern_ref(ty))
+        }
+        _ => false,
+    }
+}
+
+/// The name of a type declared with [externref_type](crate::externref_type), or the label of a
+/// `Typed<T>`.
+fn registered_type_name(ty: &Type) -> Option<String> {
     match ty {
         Type::Path(type_path) => type_path
             .path
             .get_ident()
-            .map(|ident| *ident == "ExternRef")
-            .unwrap_or(false),
-        _ => false,
+            .map(ToString::to_string)
+            .filter(|name| registry::is_registered(name))
+            .or_else(|| typed_label(ty)),
+        _ => None,
     }
 }
 
 pub(crate) enum AttributesOrOptions<'a> {
-    Options(ExternRefOptions),
+    Options(Box<ExternRefOptions>),
     Attributes(&'a [Attribute]),
 }
 
@@ -94,28 +638,27 @@ impl TryInto<ExternRefOptions> for AttributesOrOptions<'_> {
 
     fn try_into(self) -> Result<ExternRefOptions, Self::Error> {
         let attrs = match self {
-            AttributesOrOptions::Options(opts) => return Ok(opts),
+            AttributesOrOptions::Options(opts) => return Ok(*opts),
             AttributesOrOptions::Attributes(attrs) => attrs,
         };
 
-        // Try to find an `externref` attribute and parse those options if found.
+        // Every `externref` attribute contributes its options, e.g. one added by another macro and
+        // one written by hand.
+        let mut options = ExternRefOptions::default();
         for attr in attrs {
-            if let Some(ident) = attr.path.get_ident() {
-                if *ident == "externref" {
-                    let list: Punctuated<NestedMeta, Comma> =
-                        attr.parse_args_with(Punctuated::parse_terminated)?;
-                    return ExternRefOptions::parse(list);
-                }
+            if attr.path.is_ident("externref") && !attr.tokens.is_empty() {
+                let list = attr.parse_args_with(parse_metas)?;
+                options.merge(ExternRefOptions::parse(list)?)?;
             }
         }
 
-        Ok(ExternRefOptions::default())
+        Ok(options)
     }
 }
 
 impl From<ExternRefOptions> for AttributesOrOptions<'_> {
     fn from(value: ExternRefOptions) -> Self {
-        Self::Options(value)
+        Self::Options(Box::new(value))
     }
 }
 
@@ -127,10 +670,14 @@ impl<'a> From<&'a [Attribute]> for AttributesOrOptions<'a> {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
+
     use anyhow::Result;
-    use syn::ItemFn;
+    use externref_metadata::{Encoding, FunctionData, Lowering, ResultRefs, BINARY_MAGIC};
+    use syn::{ForeignItemFn, ItemFn, Signature};
 
-    use super::FunctionData;
+    use super::{check_export_visibility, export_symbol, ref_fn_pointer, FunctionDataExt};
+    use crate::registry;
 
     use crate::args::ExternRefOptions;
 
@@ -195,42 +742,611 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn parse_ref_args() -> Result<()> {
+        let func: ItemFn = syn::parse_quote! {
+            #[externref(ref_args = "2, 0")]
+            fn raw_handles(_: u32, _: usize, _: u32) {}
+        };
+        let data = FunctionData::parse(&func.sig, func.attrs.as_ref())?;
+        assert_eq!(data.arg_indicies, &[0, 2]);
+
+        // Type-based and index-based detection are merged and deduplicated.
+        let func: ItemFn = syn::parse_quote! {
+            #[externref(ref_args = "2, 1")]
+            fn mixed(_: u32, _: ExternRef, _: u32) {}
+        };
+        let data = FunctionData::parse(&func.sig, func.attrs.as_ref())?;
+        assert_eq!(data.arg_indicies, &[1, 2]);
+
+        let func: ItemFn = syn::parse_quote! {
+            #[externref(ref_args = "3")]
+            fn out_of_range(_: u32, _: u32) {}
+        };
+        assert!(FunctionData::parse(&func.sig, func.attrs.as_ref()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_arg_names() -> Result<()> {
+        let data = FunctionData::parse(
+            &syn::parse_quote! { fn f(message: ExternRef, count: usize) },
+            ExternRefOptions::default(),
+        )?;
+        assert_eq!(
+            data.arg_names,
+            &[Some("message".to_string()), Some("count".to_string())]
+        );
+
+        // Patterns other than plain identifiers don't have a name.
+        let data = FunctionData::parse(
+            &syn::parse_quote! { fn f(_: ExternRef, (a, b): (u32, u32), mut c: u32) },
+            ExternRefOptions::default(),
+        )?;
+        assert_eq!(data.arg_names, &[None, None, Some("c".to_string())]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_result_returns() -> Result<()> {
+        let data = FunctionData::parse(
+            &syn::parse_quote! { fn ok_ref() -> Result<ExternRef, ()> },
+            ExternRefOptions::default(),
+        )?;
+        assert!(!data.ret_is_extern_ref);
+        assert_eq!(
+            data.ret_result,
+            Some(ResultRefs {
+                ok: true,
+                err: false
+            })
+        );
+
+        let data = FunctionData::parse(
+            &syn::parse_quote! { fn err_ref() -> std::result::Result<u32, ExternRef> },
+            ExternRefOptions::default(),
+        )?;
+        assert_eq!(
+            data.ret_result,
+            Some(ResultRefs {
+                ok: false,
+                err: true
+            })
+        );
+
+        // Results without an `ExternRef` aren't lowered.
+        let data = FunctionData::parse(
+            &syn::parse_quote! { fn plain() -> Result<u32, u32> },
+            ExternRefOptions::default(),
+        )?;
+        assert_eq!(data.ret_result, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_arg_types() -> Result<()> {
+        let func: ItemFn = syn::parse_quote! {
+            #[externref(arg_types = "0:DomNode, 2:Event")]
+            fn on_event(_: ExternRef, _: u32, _: ExternRef) {}
+        };
+        let data = FunctionData::parse(&func.sig, func.attrs.as_ref())?;
+        assert_eq!(
+            data.arg_types,
+            BTreeMap::from([(0, "DomNode".to_string()), (2, "Event".to_string())])
+        );
+        let json = serde_json::to_string(&data)?;
+        assert!(json.contains(r#""argTypes":{"0":"DomNode","2":"Event"}"#));
+
+        // Only externref arguments have a host type.
+        let func: ItemFn = syn::parse_quote! {
+            #[externref(arg_types = "1:DomNode")]
+            fn on_event(_: ExternRef, _: u32) {}
+        };
+        assert!(FunctionData::parse(&func.sig, func.attrs.as_ref()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_host_callback() -> Result<()> {
+        let func: ItemFn = syn::parse_quote! {
+            #[externref(host_callback)]
+            fn dispatch(_: ExternRef) {}
+        };
+        let data = FunctionData::parse(&func.sig, func.attrs.as_ref())?;
+        assert!(data.reentrant);
+        let json = serde_json::to_string(&data)?;
+        assert!(json.ends_with(r#""reentrant":true}"#), "{json}");
+
+        let func: ItemFn = syn::parse_quote! { fn log(_: ExternRef) {} };
+        assert!(!FunctionData::parse(&func.sig, func.attrs.as_ref())?.reentrant);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_pack_args() -> Result<()> {
+        let func: ForeignItemFn = syn::parse_quote! {
+            #[externref(pack_args)]
+            fn append(parent: ExternRef, index: u32, child: ExternRef);
+        };
+        let data = FunctionData::parse(&func.sig, func.attrs.as_ref())?;
+        assert!(data.pack_args);
+        assert_eq!(data.arg_indicies, [0, 2]);
+        let json = serde_json::to_string(&data)?;
+        assert!(json.ends_with(r#""packArgs":true}"#), "{json}");
+
+        // There's nothing to pack without an externref argument.
+        let func: ForeignItemFn = syn::parse_quote! {
+            #[externref(pack_args)]
+            fn len(index: u32) -> ExternRef;
+        };
+        assert!(FunctionData::parse(&func.sig, func.attrs.as_ref()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_lowering() -> Result<()> {
+        let func: ForeignItemFn = syn::parse_quote! {
+            #[externref(lowering = "table")]
+            fn log(_: ExternRef);
+        };
+        let data = FunctionData::parse(&func.sig, func.attrs.as_ref())?;
+        assert_eq!(data.lowering, Lowering::Table);
+        let json = serde_json::to_string(&data)?;
+        assert!(json.ends_with(r#""lowering":"table"}"#), "{json}");
+
+        // Direct lowering is the default, so it's left out of the JSON.
+        let func: ForeignItemFn = syn::parse_quote! { fn log(_: ExternRef); };
+        let data = FunctionData::parse(&func.sig, func.attrs.as_ref())?;
+        assert_eq!(data.lowering, Lowering::Direct);
+        assert!(!serde_json::to_string(&data)?.contains("lowering"));
+
+        let func: ForeignItemFn = syn::parse_quote! {
+            #[externref(intrinsic = "ref.null", lowering = "table")]
+            fn null() -> ExternRef;
+        };
+        assert!(FunctionData::parse(&func.sig, func.attrs.as_ref()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_nullable() -> Result<()> {
+        let func: ItemFn = syn::parse_quote! {
+            #[externref(nullable_args = "2, 0", ret_nullable)]
+            fn find(_: ExternRef, _: u32, _: ExternRef) -> ExternRef {}
+        };
+        let data = FunctionData::parse(&func.sig, func.attrs.as_ref())?;
+        assert_eq!(data.nullable_args, [0, 2]);
+        assert!(data.ret_nullable);
+        let json = serde_json::to_string(&data)?;
+        assert!(
+            json.contains(r#""nullableArgs":[0,2],"retNullable":true"#),
+            "{json}"
+        );
+
+        // Only externrefs can be null.
+        for func in [
+            quote::quote! { #[externref(nullable_args = "1")] fn f(_: ExternRef, _: u32) {} },
+            quote::quote! { #[externref(ret_nullable)] fn f(_: ExternRef) -> u32 {} },
+        ] {
+            let func: ItemFn = syn::parse2(func)?;
+            assert!(FunctionData::parse(&func.sig, func.attrs.as_ref()).is_err());
+        }
+
+        // An `Option` isn't a handle, so it's rejected rather than passed as a plain value.
+        for sig in [
+            quote::quote! { fn f(_: Option<ExternRef>) },
+            quote::quote! { fn f() -> std::option::Option<ExternRef> },
+        ] {
+            let sig: Signature = syn::parse2(sig)?;
+            let error = FunctionData::parse(&sig, ExternRefOptions::default()).unwrap_err();
+            assert!(error.to_string().contains("nullable_args"), "{error}");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_unit_and_never_returns() -> Result<()> {
+        let sigs: [Signature; 2] = [
+            syn::parse_quote! { fn unit(value: ExternRef) -> () },
+            syn::parse_quote! { fn never(value: ExternRef) -> ! },
+        ];
+        for sig in &sigs {
+            let data = FunctionData::parse(sig, ExternRefOptions::default())?;
+            assert_eq!(data.arg_indicies, &[0]);
+            assert!(!data.ret_is_extern_ref);
+            assert_eq!(data.ret_result, None);
+
+            let opts = ExternRefOptions {
+                strict: true,
+                ..Default::default()
+            };
+            assert!(FunctionData::parse(sig, opts).is_ok());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_registered_types() -> Result<()> {
+        // The registry is shared by every test, so the name is unique to this one.
+        registry::register("RegisteredNode".into());
+
+        let func: ItemFn = syn::parse_quote! {
+            #[externref(strict)]
+            fn append(parent: RegisteredNode, index: u32) -> RegisteredNode {}
+        };
+        let data = FunctionData::parse(&func.sig, func.attrs.as_ref())?;
+        assert_eq!(data.arg_indicies, &[0]);
+        assert!(data.ret_is_extern_ref);
+        assert_eq!(
+            data.arg_types,
+            BTreeMap::from([(0, "RegisteredNode".to_string())])
+        );
+
+        // An explicit host type wins over the newtype's name.
+        let func: ItemFn = syn::parse_quote! {
+            #[externref(arg_types = "0:Element")]
+            fn remove(node: RegisteredNode) {}
+        };
+        let data = FunctionData::parse(&func.sig, func.attrs.as_ref())?;
+        assert_eq!(data.arg_types, BTreeMap::from([(0, "Element".to_string())]));
+
+        let func: ItemFn = syn::parse_quote! { fn unregistered(node: UnregisteredNode) {} };
+        assert!(FunctionData::parse(&func.sig, func.attrs.as_ref())?
+            .arg_indicies
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_typed() -> Result<()> {
+        let func: ItemFn = syn::parse_quote! {
+            #[externref(strict)]
+            fn append(parent: Typed<DomNode>, index: u32, child: Typed<dom::Node>) -> Typed<DomNode> {}
+        };
+        let data = FunctionData::parse(&func.sig, func.attrs.as_ref())?;
+        assert_eq!(data.arg_indicies, &[0, 2]);
+        assert!(data.ret_is_extern_ref);
+        assert_eq!(
+            data.arg_types,
+            BTreeMap::from([(0, "DomNode".to_string()), (2, "Node".to_string())])
+        );
+
+        let func: ItemFn = syn::parse_quote! { fn maybe(node: Option<Typed<DomNode>>) {} };
+        assert!(FunctionData::parse(&func.sig, func.attrs.as_ref()).is_err());
+
+        // Labels that aren't paths are still references, they just don't name a host type.
+        let func: ItemFn = syn::parse_quote! {
+            fn unlabeled(_: Typed<()>, _: Typed<[u8; 4]>, _: Typed<&'static str>) -> Typed<()> {}
+        };
+        let data = FunctionData::parse(&func.sig, func.attrs.as_ref())?;
+        assert_eq!(data.arg_indicies, &[0, 1, 2]);
+        assert!(data.ret_is_extern_ref);
+        assert!(data.arg_types.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_strict() -> Result<()> {
+        let func: ItemFn = syn::parse_quote! {
+            #[externref(strict)]
+            fn primitives(_: ExternRef, _: u32, _: f64, _: bool) -> ExternRef {}
+        };
+        assert!(FunctionData::parse(&func.sig, func.attrs.as_ref()).is_ok());
+
+        let func: ItemFn = syn::parse_quote! {
+            #[externref(strict)]
+            fn custom(_: ExternRef, _: Handle) {}
+        };
+        let error = FunctionData::parse(&func.sig, func.attrs.as_ref()).unwrap_err();
+        assert!(error.to_string().contains("argument 1 of type `Handle`"));
+
+        let func: ItemFn = syn::parse_quote! {
+            #[externref(strict)]
+            fn qualified() -> externref::ExternRef {}
+        };
+        assert!(FunctionData::parse(&func.sig, func.attrs.as_ref()).is_err());
+
+        // Acknowledged handles are accepted.
+        let func: ItemFn = syn::parse_quote! {
+            #[externref(strict, ref_args = "0", allow_types = "Handle")]
+            fn acknowledged(_: RawHandle, _: Handle) -> Handle {}
+        };
+        assert!(FunctionData::parse(&func.sig, func.attrs.as_ref()).is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_variadic() {
+        let func: ForeignItemFn = syn::parse_quote! {
+            fn printf(format: ExternRef, ...);
+        };
+        let error = FunctionData::parse(&func.sig, func.attrs.as_ref()).unwrap_err();
+        assert!(error.to_string().contains("variadic"));
+    }
+
+    #[test]
+    fn parse_ref_list_returns() -> Result<()> {
+        for sig in [
+            syn::parse_quote! { fn nodes() -> Vec<ExternRef> },
+            syn::parse_quote! { fn nodes() -> std::vec::Vec<ExternRef> },
+            syn::parse_quote! { fn nodes() -> ExternRefList },
+            syn::parse_quote! { fn nodes() -> Result<Vec<ExternRef>, u32> },
+        ] {
+            let sig: Signature = sig;
+            let error = FunctionData::parse(&sig, ExternRefOptions::default()).unwrap_err();
+            assert!(error.to_string().contains("isn't supported"), "{error}");
+        }
+
+        // Lists that aren't returned, or don't hold references, are passed through as is.
+        FunctionData::parse(
+            &syn::parse_quote! { fn append(nodes: Vec<ExternRef>) },
+            ExternRefOptions::default(),
+        )?;
+        FunctionData::parse(
+            &syn::parse_quote! { fn sizes() -> Vec<u32> },
+            ExternRefOptions::default(),
+        )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn detect_ref_fn_pointers() {
+        let types: [syn::Type; 4] = [
+            syn::parse_quote! { extern "C" fn(ExternRef) },
+            syn::parse_quote! { unsafe extern "C" fn(u32) -> ExternRef },
+            syn::parse_quote! { Option<extern "C" fn(u32, ExternRef)> },
+            syn::parse_quote! { core::option::Option<(fn(ExternRef))> },
+        ];
+        for ty in &types {
+            assert!(ref_fn_pointer(ty).is_some(), "{}", quote::quote!(#ty));
+        }
+
+        let types: [syn::Type; 4] = [
+            syn::parse_quote! { extern "C" fn(u32) -> u32 },
+            syn::parse_quote! { Option<extern "C" fn()> },
+            syn::parse_quote! { ExternRef },
+            syn::parse_quote! { Vec<ExternRef> },
+        ];
+        for ty in &types {
+            assert!(ref_fn_pointer(ty).is_none(), "{}", quote::quote!(#ty));
+        }
+    }
+
+    #[test]
+    fn export_visibility() {
+        let func: ItemFn = syn::parse_quote! { pub fn f() {} };
+        assert!(check_export_visibility(&func.vis).is_ok());
+
+        let func: ItemFn = syn::parse_quote! { pub(crate) fn f() {} };
+        let error = check_export_visibility(&func.vis).unwrap_err();
+        assert!(error.to_string().contains("found `pub (crate)`"));
+
+        let func: ItemFn = syn::parse_quote! { fn f() {} };
+        assert!(check_export_visibility(&func.vis).is_err());
+    }
+
     #[test]
     fn generate_data_section() -> Result<()> {
         let function_data = FunctionData {
             name: "Example".into(),
             arg_indicies: vec![0, 1],
             ret_is_extern_ref: false,
+            optional: false,
+            arg_names: Vec::new(),
+            aliases: Vec::new(),
+            module: None,
+            ret_result: None,
+            callback: false,
+            arg_types: BTreeMap::new(),
+            export_ref: None,
+            param_count: None,
+            group: None,
+            intrinsic: None,
+            constructor: false,
+            nullable_args: Vec::new(),
+            ret_nullable: false,
+            reentrant: false,
+            lowering: Lowering::Direct,
+            pack_args: false,
         };
 
         // An export that doesn't have a module
         let data_section_tokens = function_data
-            .to_data_section_token_stream(None)?
+            .to_data_section_token_stream(None, Encoding::Json)?
             .to_string();
         let expected_tokens = quote::quote! {
-            #[allow(incorrect_ident_case)]
-            #[allow(clippy::all)]
-            #[link_section = "__extern_ref_data_Example"]
-            static __extern_ref_data_Example: [u8; 61] =
-                *b"{\"name\":\"Example\",\"argIndicies\":[0,1],\"retIsExternRef\":false}";
+            const _: () = {
+                #[allow(incorrect_ident_case)]
+                #[allow(clippy::all)]
+                #[link_section = "__extern_ref_data_Example"]
+                #[used]
+                static __extern_ref_data_Example: [u8; 61] =
+                    *b"{\"name\":\"Example\",\"argIndicies\":[0,1],\"retIsExternRef\":false}";
+            };
         }
         .to_string();
         assert_eq!(data_section_tokens, expected_tokens);
 
         // An import that does have a module.
         let data_section_tokens = function_data
-            .to_data_section_token_stream(Some("theModuleName"))?
+            .to_data_section_token_stream(Some("theModuleName"), Encoding::Json)?
             .to_string();
         let expected_tokens = quote::quote! {
-            #[allow(incorrect_ident_case)]
-            #[allow(clippy::all)]
-            #[link_section = "__extern_ref_data_theModuleName_Example"]
-            static __extern_ref_data_theModuleName_Example: [u8; 61] =
-                *b"{\"name\":\"Example\",\"argIndicies\":[0,1],\"retIsExternRef\":false}";
+            const _: () = {
+                #[allow(incorrect_ident_case)]
+                #[allow(clippy::all)]
+                #[link_section = "__extern_ref_data_theModuleName_Example"]
+                #[used]
+                static __extern_ref_data_theModuleName_Example: [u8; 61] =
+                    *b"{\"name\":\"Example\",\"argIndicies\":[0,1],\"retIsExternRef\":false}";
+            };
         }
         .to_string();
         assert_eq!(data_section_tokens, expected_tokens);
 
+        // Data parsed from a signature has its fields in a fixed order, whichever are recorded.
+        let func: ForeignItemFn = syn::parse_quote! {
+            #[externref(name = "append", optional)]
+            fn append_child(parent: ExternRef, index: u32) -> ExternRef;
+        };
+        let data_section_tokens = FunctionData::parse(&func.sig, func.attrs.as_ref())?
+            .to_data_section_token_stream(Some("dom"), Encoding::Json)?
+            .to_string();
+        let expected_tokens = quote::quote! {
+            const _: () = {
+                #[allow(incorrect_ident_case)]
+                #[allow(clippy::all)]
+                #[link_section = "__extern_ref_data_dom_append"]
+                #[used]
+                static __extern_ref_data_dom_append: [u8; 118] =
+                    *b"{\"name\":\"append\",\"argIndicies\":[0],\"retIsExternRef\":true,\"optional\":true,\"argNames\":[\"parent\",\"index\"],\"paramCount\":2}";
+            };
+        }
+        .to_string();
+        assert_eq!(data_section_tokens, expected_tokens);
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_manifest() -> Result<()> {
+        let function_data = FunctionData {
+            name: "log".into(),
+            arg_indicies: vec![0],
+            ret_is_extern_ref: false,
+            optional: false,
+            arg_names: Vec::new(),
+            aliases: Vec::new(),
+            module: Some("console".into()),
+            ret_result: None,
+            callback: false,
+            arg_types: BTreeMap::new(),
+            export_ref: None,
+            param_count: None,
+            group: None,
+            intrinsic: None,
+            constructor: false,
+            nullable_args: Vec::new(),
+            ret_nullable: false,
+            reentrant: false,
+            lowering: Lowering::Direct,
+            pack_args: false,
+        };
+
+        // Every function is described by an element of a single array, which records the module
+        // of imports, as it isn't part of a section's name.
+        let other = FunctionData {
+            name: "run".into(),
+            module: None,
+            ..function_data.clone()
+        };
+        let manifest_tokens = super::manifest_token_stream(&[function_data, other])?.to_string();
+        let expected_tokens = quote::quote! {
+            const _: () = {
+                #[allow(incorrect_ident_case)]
+                #[allow(clippy::all)]
+                #[link_section = "__extern_ref_manifest"]
+                #[used]
+                static __extern_ref_manifest: [u8; 132] =
+                    *b"[{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false,\"module\":\"console\"},{\"name\":\"run\",\"argIndicies\":[0],\"retIsExternRef\":false}]";
+            };
+        }
+        .to_string();
+        assert_eq!(manifest_tokens, expected_tokens);
+
+        Ok(())
+    }
+
+    #[test]
+    fn generate_binary_section() -> Result<()> {
+        let func: ForeignItemFn = syn::parse_quote! {
+            #[externref(name = "append", encoding = "binary")]
+            fn append_child(parent: ExternRef, index: u32) -> ExternRef;
+        };
+        let data = FunctionData::parse(&func.sig, func.attrs.as_ref())?;
+        let bytes = data.to_binary();
+        assert!(bytes.starts_with(&BINARY_MAGIC));
+        assert_eq!(
+            FunctionData::from_binary(&bytes)?,
+            (data.clone(), bytes.len())
+        );
+
+        // The section holds the binary record as is.
+        let literal = |data: &FunctionData| {
+            let bytes = syn::LitByteStr::new(&data.to_binary(), proc_macro2::Span::call_site());
+            quote::quote!(#bytes).to_string()
+        };
+        let tokens = data
+            .to_data_section_token_stream(Some("dom"), Encoding::Binary)?
+            .to_string();
+        assert!(tokens.contains(&literal(&data)), "{tokens}");
+
+        Ok(())
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn generate_gzip_section() -> Result<()> {
+        let func: ForeignItemFn = syn::parse_quote! {
+            #[externref(name = "append", encoding = "gzip")]
+            fn append_child(parent: ExternRef, index: u32) -> ExternRef;
+        };
+        let data = FunctionData::parse(&func.sig, func.attrs.as_ref())?;
+
+        // The section holds a single gzip member of the JSON record.
+        let bytes = super::encode(&data, Encoding::Gzip)?;
+        assert!(bytes.starts_with(&externref_metadata::GZIP_MAGIC));
+        let (json, len) = externref_metadata::decompress(&bytes)?;
+        assert_eq!(len, bytes.len());
+        assert_eq!(serde_json::from_slice::<FunctionData>(&json)?, data);
+
+        let literal = syn::LitByteStr::new(&bytes, proc_macro2::Span::call_site());
+        let tokens = data
+            .to_data_section_token_stream(Some("dom"), Encoding::Gzip)?
+            .to_string();
+        assert!(
+            tokens.contains(&quote::quote!(#literal).to_string()),
+            "{tokens}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn export_symbols() -> syn::Result<()> {
+        let func: ItemFn = syn::parse_quote! { pub fn run() {} };
+        assert_eq!(export_symbol(&func)?, None);
+
+        let func: ItemFn = syn::parse_quote! { #[no_mangle] pub fn run() {} };
+        assert_eq!(export_symbol(&func)?.as_deref(), Some("run"));
+
+        let func: ItemFn = syn::parse_quote! { #[export_name = "start"] pub fn run() {} };
+        assert_eq!(export_symbol(&func)?.as_deref(), Some("start"));
+
+        let func: ItemFn = syn::parse_quote! {
+            #[export_name = "start"]
+            #[no_mangle]
+            #[inline]
+            pub fn run() {}
+        };
+        assert_eq!(export_symbol(&func)?.as_deref(), Some("start"));
+
+        let func: ItemFn = syn::parse_quote! { #[export_name = 1] pub fn run() {} };
+        assert!(export_symbol(&func).is_err());
+
         Ok(())
     }
 }
@@ -3,14 +3,25 @@
 
 mod args;
 mod func;
+mod registry;
+
+use std::collections::{hash_map::Entry, HashMap};
 
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
-use quote::ToTokens;
-use syn::{AttributeArgs, ForeignItem, ForeignItemFn, ItemFn, ItemForeignMod};
+use quote::{quote, quote_spanned, ToTokens};
+use syn::{
+    spanned::Spanned, Fields, ForeignItem, ForeignItemFn, Item, ItemConst, ItemEnum, ItemFn,
+    ItemForeignMod, ItemImpl, ItemMod, ItemStatic, ItemStruct, ItemTrait, ItemType, ItemUnion,
+    ItemUse, ReturnType, TraitItem, TraitItemMethod, Type, UseTree,
+};
+
+use externref_metadata::{
+    data_section_name, group_section_name, Encoding, FunctionData, Intrinsic, INTRINSIC_MODULE,
+};
 
 use crate::args::ExternRefOptions;
-use crate::func::FunctionData;
+use crate::func::{AttributesOrOptions, FunctionDataExt};
 
 /// An attribute macro for declaring WASM imports/exports that contain `externref`s.
 ///
@@ -23,6 +34,210 @@ use crate::func::FunctionData;
 /// # Arguments
 ///
 /// - name: Marks the name of an import module or overrides the name of an imported or exported function.
+///   Module names can be namespaced, e.g. `dom.node` or `wasi:io/streams`, and can contain
+///   `${NAME}` placeholders that the transformer substitutes. Two
+///   functions in the same extern block can't import the same name, and functions whose data
+///   sections collide, e.g. an import `b_c` from `a` and one `c` from `a_b`, are a compile error
+///   within a crate and rejected by the transformer across crates.
+///   An extern block must be given a module name, but it can be empty, `name = ""`, for hosts that
+///   provide functions in the empty module.
+///
+///   Option values can also be built with `concat!` and `env!`, e.g.
+///   `name = concat!(env!("CARGO_PKG_NAME"), "_host")`. These are evaluated when the macro expands,
+///   as the names are recorded in the metadata, so other macros and constants aren't supported.
+///   The `env!` invocations are also repeated in the expansion, so changing a variable rebuilds
+///   the crate.
+/// - ref_args: A comma separated list of argument indicies to treat as `externref`s regardless of
+///   their type, e.g. `ref_args = "0, 2"` for bindings that use raw `u32` handles.
+/// - optional: Marks an import, or every import of an extern block, as possibly missing from the
+///   host. The transformer can replace missing optional imports with a stub that traps.
+/// - strict: Rejects argument and return types that aren't an `ExternRef` or a primitive (`i8` to
+///   `i128`, `u8` to `u128`, `isize`, `usize`, `f32`, `f64`, `bool` and `char`), so a custom handle
+///   type that should have been an `ExternRef` doesn't go unnoticed. Arguments in `ref_args` are
+///   accepted. On an extern block it applies to every function in it.
+/// - alias: An additional name to export a function under, can be given multiple times. The
+///   transformer adds an export for each alias pointing at the same function. Several aliases can
+///   also be given at once with `aliases("b", "c")`.
+/// - arg_types: A comma separated list of `INDEX:TYPE` pairs naming the host type of `externref`
+///   arguments, e.g. `arg_types = "0:DomNode, 2:Event"`. This is recorded for glue generators and
+///   doesn't change how the arguments are transformed. A `Typed<DomNode>` argument records `DomNode`
+///   unless this names another type.
+/// - callback: Marks a function the host calls through a function table instead of an export, see
+///   [Callbacks](#callbacks).
+/// - allow_types: A comma separated list of additional type names strict mode accepts, e.g.
+///   `allow_types = "Fd, Status"` or `allow_types("Fd", "Status")`.
+/// - no_link: Leaves linking an extern block or import to the user's own
+///   `#[link(wasm_import_module = "...")]` and `#[link_name = "..."]` attributes instead of adding
+///   them, e.g. when they're generated by another macro. The metadata is still emitted, using the
+///   module and symbols from those attributes when the names aren't given, and names that don't
+///   match them are an error. On an extern block it applies to every function in it.
+/// - export_ref: Marks an import taking no arguments and returning an `ExternRef` as an intrinsic
+///   that returns a reference to the named export instead of calling the host. This is what the
+///   `externref::export_ref!` macro expands to.
+/// - intrinsic: Marks an import as an intrinsic the transformer implements with wasm instructions
+///   instead of calling the host, `ref.null` for a function returning a null `ExternRef` or
+///   `ref.is_null` for one taking an `ExternRef` and returning a `bool`, or `transformed` for one
+///   taking nothing and returning a `bool` that's `true` once the module is transformed.
+///   Intrinsics must be imported from the reserved `__externref_intrinsic` module, which can't
+///   import anything else. This is how `ExternRef::null`, `ExternRef::is_null` and
+///   `externref::assert_transformed` are implemented on wasm targets.
+/// - constructor: Marks an import returning an `ExternRef` as a host constructor, so glue
+///   generators can call it with `new`, e.g. `new Foo(...)` rather than `Foo(...)`. The returned
+///   reference is transformed like any other.
+/// - export_name: Exports a function under a name that the Rust function doesn't have to share,
+///   e.g. `export_name = "on-click"` for a host expecting a name that isn't a valid identifier. The
+///   function is given `#[export_name = "..."]` and the metadata records the export name, so it
+///   can't be combined with a different `name`, or with `#[no_mangle]` or `#[export_name]` of its
+///   own. Imports are renamed with `name` instead.
+/// - encoding: How the function's metadata is encoded, `json` (the default), `binary` for the
+///   smaller encoding of `FunctionData::to_binary` in the `externref-metadata` crate, or `gzip`
+///   for the JSON compressed with gzip, which needs the `compression` feature of `externref` and
+///   of the transformer. The transformer reads all of them, even from the same section, so crates
+///   can switch one at a time. On an extern block or trait it applies to every function that
+///   doesn't choose its own.
+/// - group: Tags the function's metadata with a feature area, e.g. `group = "dom"`. The data
+///   section is named after the group, e.g. `__extern_ref_data_dom@console_log`, and the
+///   transformer reports each function's group, so tools can handle one area at a time. On an
+///   extern block or trait it applies to every function that doesn't choose its own.
+/// - nullable_args: A comma separated list of `externref` arguments that may be null, e.g.
+///   `nullable_args = "0, 2"`. Other `externref` arguments are recorded as never being null, which
+///   the transformer can check while debugging. An `Option<ExternRef>` isn't a single handle, so
+///   it's rejected in favor of an `ExternRef` marked with this.
+/// - ret_nullable: Marks a returned `ExternRef` as possibly null.
+/// - export: Exports a function under the name its metadata describes, `name` or the function's
+///   own name, by giving it `#[export_name = "..."]`. By default the macro doesn't export
+///   functions itself, see [Exports](#exports).
+/// - host_callback: Marks a function as reentrant, recorded as `reentrant` in its metadata. On an
+///   import it means the host may call back into the module before the import returns, e.g. to
+///   dispatch events to exports, and on an export or callback that the host may call it from
+///   within another call. Tools and glue generators can use it to decide how to call the
+///   function. The transformer doesn't need to do anything differently, as the functions it adds
+///   keep no state between calls and references in the table stay where they are.
+/// - reexport: Registers another name for `ExternRef` on the `use` item declaring it, see
+///   [Re-exports](#re-exports).
+/// - lowering: How the transformer passes the function's `externref`s to and from the host,
+///   `lowering = "direct"` or `lowering = "table"`. Direct lowering, the default, rewrites the
+///   signature to take and return `externref`s. Table lowering keeps the `i32` handles, which
+///   index the transformer's exported externref table, for hosts that can't pass references
+///   through this function but can access the table. On an extern block or trait it applies to
+///   every function that doesn't choose its own.
+/// - pack_args: Moves the `externref` arguments to the end of the signature the host sees, after
+///   the other arguments, so glue code can pass them as a single array. The metadata's
+///   `arg_indicies` still hold their positions in the Rust signature, and the references follow
+///   in that order. The function must take at least one `ExternRef`.
+///
+/// A function in an extern block or trait can have several `#[externref(...)]` attributes, e.g.
+/// one added by another macro and one written by hand, and their options are merged. Flags and
+/// lists are combined, while giving an option a different value in each attribute is an error.
+///
+/// # Return types
+///
+/// A function returning `()` or `!` has no results in wasm. Diverging functions are allowed for
+/// both imports and exports, e.g. a host `abort`, as wasm has no notion of a function that never
+/// returns. Calling one just never comes back, or traps.
+///
+/// # Imports
+///
+/// An extern block where no function takes or returns an `ExternRef`, and none is `optional`, is
+/// left alone by the transformer, so the macro warns that the attribute may be redundant. The
+/// warning is reported as a use of a deprecated item, and `#[allow(deprecated)]` on the block
+/// silences it.
+///
+/// # Exports
+///
+/// Only `pub` functions become wasm exports, so annotating a function with any other visibility is
+/// an error.
+///
+/// The macro only describes a function by default, it's exported by the `#[no_mangle]` or
+/// `#[export_name = "..."]` the function already has, like any other Rust function. This keeps
+/// functions with the same name in different modules from clashing, but one without either isn't
+/// exported, and the transformer warns that its data doesn't match anything. With the `export`
+/// flag, or the `export_name` option, the macro adds `#[export_name = "..."]` itself, so the
+/// export is always named after the metadata.
+///
+/// A function with `#[no_mangle]` or `#[export_name = "..."]` is described under the name it's
+/// exported as, and giving it a different `name` is an error, as the transformer couldn't find the
+/// export the data describes. The `export` flag leaves such a function's symbol alone.
+///
+/// An exported function can return a `Result` with an `ExternRef` in either variant, as long as it
+/// lowers the `Result` to two values: an `i32` tag that is `0` for `Ok` and `1` for `Err`, followed
+/// by the handle of the active variant. The host receives the tag and an `externref`, which is
+/// `null` when the active variant isn't an `ExternRef`, so any other value should be encoded in the
+/// tag.
+///
+/// Returning a `Vec` of `ExternRef`s or an `ExternRefList` is an error, as only the references in
+/// the signature itself are converted. Return the number of references and export a function
+/// returning the reference at an index instead, or pass each one to an import.
+///
+/// A `const fn` can be exported, and can still be called in const contexts from Rust, as the
+/// export itself is an ordinary function. Imports can't be `const fn`, as the host is only called
+/// at run time.
+///
+/// An exported function that also appears in a function table is rewritten there too, the same as
+/// a [callback](#callbacks).
+///
+/// # Callbacks
+///
+/// A function marked with `callback` is rewritten wherever it appears in a function table, so the
+/// host can call it with `externref`s after receiving its table index, e.g. as the shim of a
+/// closure registered with the host. It doesn't have to be `pub`, but it must keep its symbol name
+/// in the module's name section, e.g. with `#[no_mangle]`, and the transformer fails when it isn't
+/// in any function table.
+///
+/// The macro only describes the shim. Keeping whatever closure the shim calls alive for as long as
+/// the host may call it is up to the caller, e.g. by leaking it or unregistering it from the host
+/// before dropping it. The module itself can't call the shim through the table once it's
+/// transformed, as the table entry then has the `externref` signature.
+///
+/// # Function pointers
+///
+/// Calls through function pointers aren't converted, so a struct of `extern "C" fn` pointers
+/// taking or returning an `ExternRef`, like a plugin's vtable of host functions, can't be
+/// annotated, and doing so is an error pointing at the field. The references in a call through a
+/// pointer are passed as the handles Rust sees, so the function it points to has to convert them:
+///
+/// - To call the host, point the field at an ordinary Rust function that calls an `#[externref]`
+///   import. The references are converted where the import is called.
+/// - For a function only the host calls through the function table, mark it with
+///   [`callback`](#callbacks). The module itself can't call it through the pointer afterwards.
+///
+/// # Traits
+///
+/// A trait can be given the name of a module, `#[externref(name = "host")]`, to import each of its
+/// methods without a body from it, the same as the functions of an extern block. Methods can have
+/// their own `#[externref(...)]` attribute with the options of an import, and argument indicies
+/// such as `ref_args` don't count the receiver, as it stays on the Rust side.
+///
+/// The import takes the arguments after the receiver, so whatever kind of receiver a method has,
+/// e.g. `&self`, `self` or `self: Box<Self>`, its first argument after the receiver is index `0` of
+/// the import and the first parameter of the wasm function.
+///
+/// The macro gives each of these methods a default body that calls the import, so implementers
+/// inherit the import by not overriding the method, and overriding it replaces the call to the host.
+/// The methods can't be generic, and are safe to call, so declaring one asserts that the host
+/// function is safe.
+///
+/// # Re-exports
+///
+/// Arguments and returns are recognized by their type's name, so an `ExternRef` re-exported under
+/// another name isn't an `externref` to the macro. Giving the `use` item declaring the name the
+/// `reexport` option registers it for every function expanded after it in the crate, without
+/// `ref_args` on each of them:
+///
+/// ```rust,ignore
+/// #[externref(reexport = "HostRef")]
+/// pub use externref::ExternRef as HostRef;
+///
+/// #[externref(name = "console")]
+/// extern "C" {
+///     fn log(message: HostRef);
+/// }
+/// ```
+///
+/// Custom inner attributes are unstable, so the name is registered on the `use` item rather than
+/// with a crate-level `#![externref(...)]`. Like types declared with [macro@externref_type], it
+/// must come before the functions using it, and is only known within the crate. The macro can't
+/// resolve paths, so it only checks that the item the name is given to is called `ExternRef`.
 ///
 /// # Example
 /// ```rust,ignore
@@ -46,70 +261,1696 @@ use crate::func::FunctionData;
 /// }
 #[proc_macro_attribute]
 pub fn externref(args: TokenStream, item: TokenStream) -> TokenStream {
-    let args: AttributeArgs = syn::parse_macro_input!(args as AttributeArgs);
-    let opts = ExternRefOptions::parse(args).expect("cannot parse macro options");
+    let tracked_env = args::track_env(args.clone().into());
+    let args = syn::parse_macro_input!(args with args::parse_metas);
+    let opts = match ExternRefOptions::parse(args.clone()) {
+        Ok(opts) => opts,
+        Err(error) => {
+            return spanned_error(error, &args, "invalid options")
+                .to_compile_error()
+                .into()
+        }
+    };
 
-    let output_stream = if let Ok(ffi_mod) = syn::parse::<ItemForeignMod>(item.clone()) {
+    let output = if let Ok(ffi_mod) = syn::parse::<ItemForeignMod>(item.clone()) {
         process_foreign_mod(ffi_mod, opts)
     } else if let Ok(func) = syn::parse::<ForeignItemFn>(item.clone()) {
-        func.into_token_stream()
-    } else if let Ok(func) = syn::parse::<ItemFn>(item) {
-        process_fn(func, opts).into_token_stream()
+        // Only items can follow an extern block's function, so its block tracks its variables.
+        return func.into_token_stream().into();
+    } else if let Ok(func) = syn::parse::<ItemFn>(item.clone()) {
+        process_fn(func, opts)
+    } else if let Ok(item) = syn::parse::<ItemTrait>(item.clone()) {
+        process_trait(item, opts)
+    } else if let (Ok(item), Some(name)) = (syn::parse::<ItemUse>(item.clone()), &opts.reexport) {
+        process_use(item, name.clone())
     } else {
-        panic!("Not")
+        Err(unsupported_target(item))
     };
 
-    output_stream.into()
+    let output = output.unwrap_or_else(|error| error.to_compile_error());
+    quote! {
+        #tracked_env
+        #output
+    }
+    .into()
 }
 
-fn process_foreign_mod(mut ffi_mod: ItemForeignMod, opts: ExternRefOptions) -> TokenStream2 {
-    let name = opts.name.expect("extern blocks must have wasm module name");
+/// An attribute macro declaring a newtype around an `ExternRef` as a host type, e.g. `DomNode`.
+///
+/// Arguments and returns of the type are then treated as `externref`s by [macro@externref], the
+/// same as an `ExternRef`, and the type's name is recorded as the argument's host type unless
+/// `arg_types` names another one. The type must be a tuple struct with a single `ExternRef` field,
+/// and is made `#[repr(transparent)]` if it isn't already. It implements `IsExternRef` and
+/// converts into an `ExternRef`.
+///
+/// Proc macros can't resolve traits, so the type is recognized by its name. It must be declared
+/// before the functions using it in the same crate, and used by its plain name rather than a path.
+/// Use `ref_args` for host types from other crates.
+///
+/// # Example
+/// ```rust,ignore
+/// use externref::{externref, externref_type, ExternRef};
+///
+/// #[externref_type]
+/// #[derive(Clone, Copy)]
+/// pub struct DomNode(ExternRef);
+///
+/// #[externref(name = "dom")]
+/// extern "C" {
+///     #[externref(name = "appendChild")]
+///     fn append_child(parent: DomNode, child: DomNode);
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn externref_type(args: TokenStream, item: TokenStream) -> TokenStream {
+    let output = if args.is_empty() {
+        let item = syn::parse_macro_input!(item as ItemStruct);
+        process_type(item).unwrap_or_else(|error| error.to_compile_error())
+    } else {
+        syn::Error::new_spanned(
+            TokenStream2::from(args),
+            "externref_type doesn't take any arguments",
+        )
+        .to_compile_error()
+    };
 
-    ffi_mod.attrs.push(syn::parse_quote! {
-        #[link(wasm_import_module = #name)]
-    });
+    output.into()
+}
+
+/// Describes every function expanded since the last invocation in the `__extern_ref_manifest`
+/// section, as a JSON array, with the `manifest` feature.
+///
+/// Proc macros can't tell when the last item of a crate was expanded, so the manifest is written
+/// by invoking this at the end of the crate root, after every module declaring functions. Any
+/// function expanded after the invocation is left out, and so isn't transformed. Without the
+/// feature this expands to nothing, so crates can invoke it whether or not it's enabled, which
+/// they should, as cargo enables the feature for every crate in the build.
+///
+/// The linker concatenates the arrays of every crate into a single section.
+///
+/// # Example
+/// ```rust,ignore
+/// mod dom;
+///
+/// #[externref(name = "console")]
+/// extern "C" {
+///     fn log(message: ExternRef);
+/// }
+///
+/// externref::manifest!();
+/// ```
+#[proc_macro]
+pub fn manifest(input: TokenStream) -> TokenStream {
+    if !input.is_empty() {
+        return syn::Error::new_spanned(
+            TokenStream2::from(input),
+            "manifest doesn't take any arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+    if !cfg!(feature = "manifest") {
+        return TokenStream::new();
+    }
+
+    let functions = registry::take_manifest();
+    if functions.is_empty() {
+        return TokenStream::new();
+    }
+    func::manifest_token_stream(&functions)
+        .unwrap_or_else(|error| {
+            syn::Error::new(proc_macro2::Span::call_site(), error).to_compile_error()
+        })
+        .into()
+}
+
+/// Describes an item the [macro@externref] macro can't be applied to, pointing at its keyword and
+/// name.
+fn unsupported_target(item: TokenStream) -> syn::Error {
+    const EXPECTED: &str = "expected fn, foreign fn, or extern block";
+
+    let Ok(item) = syn::parse::<Item>(item) else {
+        return syn::Error::new(proc_macro2::Span::call_site(), EXPECTED);
+    };
+    // A struct of function pointers is usually a vtable of host functions, which needs more than
+    // pointing at the struct.
+    if let Item::Struct(item) = &item {
+        if let Some(fn_pointer) = item
+            .fields
+            .iter()
+            .find_map(|field| func::ref_fn_pointer(&field.ty))
+        {
+            return syn::Error::new_spanned(
+                fn_pointer,
+                "references passed through function pointers aren't converted, as the \
+                 transformer can't tell which indirect calls go through the field; point the \
+                 field at a Rust function calling an `#[externref]` import instead, or mark the \
+                 function it holds with `#[externref(callback)]` when only the host calls it",
+            );
+        }
+    }
+
+    let (found, tokens) = match &item {
+        Item::Use(item) => ("`use` import", item.to_token_stream()),
+        Item::Mod(ItemMod {
+            mod_token, ident, ..
+        }) => ("module", quote!(#mod_token #ident)),
+        Item::Struct(ItemStruct {
+            struct_token,
+            ident,
+            ..
+        }) => ("struct", quote!(#struct_token #ident)),
+        Item::Enum(ItemEnum {
+            enum_token, ident, ..
+        }) => ("enum", quote!(#enum_token #ident)),
+        Item::Union(ItemUnion {
+            union_token, ident, ..
+        }) => ("union", quote!(#union_token #ident)),
+        Item::Trait(ItemTrait {
+            trait_token, ident, ..
+        }) => ("trait", quote!(#trait_token #ident)),
+        Item::Impl(ItemImpl {
+            impl_token,
+            self_ty,
+            ..
+        }) => ("impl block", quote!(#impl_token #self_ty)),
+        Item::Const(ItemConst {
+            const_token, ident, ..
+        }) => ("const", quote!(#const_token #ident)),
+        Item::Static(ItemStatic {
+            static_token,
+            ident,
+            ..
+        }) => ("static", quote!(#static_token #ident)),
+        Item::Type(ItemType {
+            type_token, ident, ..
+        }) => ("type alias", quote!(#type_token #ident)),
+        Item::ExternCrate(item) => ("`extern crate`", item.to_token_stream()),
+        Item::Macro(item) => ("macro invocation", item.mac.path.to_token_stream()),
+        item => ("an unsupported item", item.to_token_stream()),
+    };
+
+    syn::Error::new_spanned(tokens, format!("{EXPECTED}, found {found}"))
+}
+
+fn process_type(mut item: ItemStruct) -> syn::Result<TokenStream2> {
+    let field_ty = match &item.fields {
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => fields.unnamed[0].ty.clone(),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &item.fields,
+                "externref_type must be a tuple struct with a single `ExternRef` field",
+            ))
+        }
+    };
+    if !item.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &item.generics,
+            "externref_type can't be generic",
+        ));
+    }
+
+    match item.attrs.iter().find(|attr| attr.path.is_ident("repr")) {
+        Some(repr) if repr.tokens.to_string() != "(transparent)" => {
+            return Err(syn::Error::new_spanned(
+                repr,
+                "externref_type must be `#[repr(transparent)]`",
+            ))
+        }
+        Some(_) => {}
+        None => item.attrs.push(syn::parse_quote! { #[repr(transparent)] }),
+    }
+
+    registry::register(item.ident.to_string());
+
+    let ident = &item.ident;
+    // Points a field that isn't an `ExternRef` at the field rather than the attribute.
+    let inner = quote_spanned! {field_ty.span()=> value.0 };
+    Ok(quote! {
+        #item
+
+        // SAFETY: The type is a transparent wrapper around its field, which the conversion below
+        // checks is an `ExternRef`.
+        unsafe impl ::externref::IsExternRef for #ident {}
+
+        impl ::core::convert::From<#ident> for ::externref::ExternRef {
+            fn from(value: #ident) -> Self {
+                #inner
+            }
+        }
+    })
+}
+
+/// Registers the name a `use` item re-exports `ExternRef` under, see
+/// [Re-exports](macro@externref#re-exports).
+fn process_use(item: ItemUse, name: String) -> syn::Result<TokenStream2> {
+    let Some((item_name, _)) = use_tree_names(&item.tree).find(|(_, ident)| **ident == name) else {
+        return Err(syn::Error::new_spanned(
+            &item.tree,
+            format!("the use item doesn't bring {name} into scope"),
+        ));
+    };
+    // Only the last segment of the path can be checked, as the macro can't resolve it.
+    if item_name != "ExternRef" {
+        return Err(syn::Error::new_spanned(
+            item_name,
+            format!("{name} must be a re-export of `ExternRef`, but it's `{item_name}`"),
+        ));
+    }
+
+    registry::register_reexport(name);
+    Ok(item.into_token_stream())
+}
+
+/// The items a use tree brings into scope, with the names they're brought in under after any
+/// renames.
+fn use_tree_names(tree: &UseTree) -> Box<dyn Iterator<Item = (&syn::Ident, &syn::Ident)> + '_> {
+    match tree {
+        UseTree::Path(path) => use_tree_names(&path.tree),
+        UseTree::Name(name) => Box::new(std::iter::once((&name.ident, &name.ident))),
+        UseTree::Rename(rename) => Box::new(std::iter::once((&rename.ident, &rename.rename))),
+        UseTree::Glob(_) => Box::new(std::iter::empty()),
+        UseTree::Group(group) => Box::new(group.items.iter().flat_map(use_tree_names)),
+    }
+}
+
+fn process_foreign_mod(
+    mut ffi_mod: ItemForeignMod,
+    opts: ExternRefOptions,
+) -> syn::Result<TokenStream2> {
+    // With `no_link` the block is linked by the user's own attribute, which can name the module.
+    let link_module = match opts.no_link {
+        true => func::link_import_module(&ffi_mod.attrs)?,
+        false => None,
+    };
+
+    // An empty name is a valid module, so only a missing one is an error.
+    let name = match (opts.name.clone(), link_module) {
+        (Some(name), Some(module)) if name != module => {
+            return Err(syn::Error::new_spanned(
+                &ffi_mod.abi,
+                format!("the block is linked to `{module}`, but its name is given as `{name}`"),
+            ));
+        }
+        (Some(name), _) | (None, Some(name)) => name,
+        (None, None) => {
+            return Err(syn::Error::new_spanned(
+                &ffi_mod.abi,
+                "extern blocks need the name of the module they import from, use `name = \"\"` \
+                 for the empty module",
+            ));
+        }
+    };
+
+    check_applied_once(&ffi_mod.attrs)?;
+    if !opts.no_link {
+        ffi_mod.attrs.push(syn::parse_quote! {
+            #[link(wasm_import_module = #name)]
+        });
+    }
 
     let mut ffi_fn_data = Vec::new();
+    let mut tracked_env = TokenStream2::new();
+    let mut declared = HashMap::new();
+    let mut errors: Option<syn::Error> = None;
 
+    // Other items, e.g. foreign types and statics, are passed through without a data section.
     for item in &mut ffi_mod.items {
         if let ForeignItem::Fn(func) = item {
-            ffi_fn_data.push(process_foreign_fn(func));
+            tracked_env.extend(track_env_in_attrs(&func.attrs));
+            let (mut data, encoding) = match process_foreign_fn(func, &opts) {
+                Ok(data) => data,
+                Err(error) => {
+                    push_error(&mut errors, error);
+                    continue;
+                }
+            };
+            data.optional |= opts.optional;
+
+            // The intrinsic module is reserved, so the transformer never leaves one of its imports
+            // for the host.
+            if data.intrinsic.is_some() && name != INTRINSIC_MODULE {
+                push_error(
+                    &mut errors,
+                    syn::Error::new_spanned(
+                        &func.sig.ident,
+                        format!("intrinsics must be imported from the `{INTRINSIC_MODULE}` module"),
+                    ),
+                );
+            } else if data.intrinsic.is_none() && name == INTRINSIC_MODULE {
+                push_error(
+                    &mut errors,
+                    syn::Error::new_spanned(
+                        &func.sig.ident,
+                        format!("only intrinsics can be imported from `{INTRINSIC_MODULE}`"),
+                    ),
+                );
+            }
+
+            if let Err(error) = check_duplicate(&mut declared, &data, &func.sig.ident, &name) {
+                push_error(&mut errors, error);
+            }
+
+            ffi_fn_data.push((data, encoding));
+        }
+    }
+
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
+
+    // Without references to convert or optional imports to stub, the transformer leaves the block
+    // alone, so the attribute only links it.
+    let mut output = tracked_env;
+    let uses_refs = |data: &FunctionData| {
+        !data.arg_indicies.is_empty()
+            || data.ret_is_extern_ref
+            || data.ret_result.is_some()
+            || data.export_ref.is_some()
+            || data.intrinsic.is_some()
+    };
+    if !ffi_fn_data
+        .iter()
+        .any(|(data, _)| uses_refs(data) || data.optional)
+    {
+        output.extend(redundant_block_warning(&ffi_mod, &name));
+    }
+
+    for (data, encoding) in ffi_fn_data {
+        let section = section_token_stream(&data, Some(&name), encoding)
+            .map_err(|error| spanned_error(error, &ffi_mod.abi, "invalid data"))?;
+        output.extend(section);
+    }
+    output.extend(ffi_mod.into_token_stream());
+    Ok(output)
+}
+
+/// Warns that an extern block doesn't need the attribute. Proc macros can only emit errors on
+/// stable, so the warning is the one for using a deprecated item, which `#[allow(deprecated)]`
+/// silences.
+fn redundant_block_warning(ffi_mod: &ItemForeignMod, module: &str) -> TokenStream2 {
+    let note = format!(
+        "no function in this block takes or returns an `ExternRef`, so `#[externref]` may be \
+         redundant, `#[link(wasm_import_module = {module:?})]` links it the same way"
+    );
+    quote_spanned! {ffi_mod.abi.span()=>
+        const _: () = {
+            #[deprecated(note = #note)]
+            #[allow(non_camel_case_types)]
+            struct externref_may_be_redundant;
+            let _ = externref_may_be_redundant;
+        };
+    }
+}
+
+/// Turns every method of a trait without a default body into a provided method calling an import.
+fn process_trait(mut item: ItemTrait, opts: ExternRefOptions) -> syn::Result<TokenStream2> {
+    let Some(name) = opts.name.clone() else {
+        return Err(syn::Error::new_spanned(
+            item.trait_token,
+            "traits need the name of the module they import from, use `name = \"\"` for the \
+             empty module",
+        ));
+    };
+    check_applied_once(&item.attrs)?;
+    // The extern blocks calling the host are generated, so there's nothing for the user to link.
+    if opts.no_link {
+        return Err(syn::Error::new_spanned(
+            item.trait_token,
+            "traits can't be no_link, as the macro generates the extern blocks they import with",
+        ));
+    }
+
+    let mut sections = TokenStream2::new();
+    let mut declared = HashMap::new();
+    let mut errors: Option<syn::Error> = None;
+
+    for trait_item in &mut item.items {
+        let TraitItem::Method(method) = trait_item else {
+            continue;
+        };
+        if method.default.is_some() {
+            continue;
+        }
+
+        // The method's attribute is removed along with its variables.
+        sections.extend(track_env_in_attrs(&method.attrs));
+        match process_trait_method(method, &opts, &name, &mut declared) {
+            Ok((data, encoding)) => match section_token_stream(&data, Some(&name), encoding) {
+                Ok(section) => sections.extend(section),
+                Err(error) => push_error(
+                    &mut errors,
+                    spanned_error(error, &method.sig.ident, "invalid data"),
+                ),
+            },
+            Err(error) => push_error(&mut errors, error),
         }
     }
 
-    ffi_fn_data
+    match errors {
+        Some(errors) => Err(errors),
+        None => Ok(quote! {
+            #sections
+            #item
+        }),
+    }
+}
+
+/// Gives a trait method a default body that calls the host, returning the data of the import.
+///
+/// The receiver stays on the Rust side, so the import takes the remaining arguments and their
+/// indicies are counted without it.
+fn process_trait_method(
+    method: &mut TraitItemMethod,
+    block_opts: &ExternRefOptions,
+    module: &str,
+    declared: &mut HashMap<String, syn::Ident>,
+) -> syn::Result<(FunctionData, Encoding)> {
+    if !method.sig.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &method.sig.generics,
+            "imported trait methods can't be generic",
+        ));
+    }
+
+    // Typed receivers like `self: Box<Self>` stay on the Rust side too, so any kind of receiver is
+    // left out of the import.
+    let mut sig = method.sig.clone();
+    sig.inputs = sig
+        .inputs
         .into_iter()
-        .flat_map(|data| {
-            data.to_data_section_token_stream(Some(&name))
-                .expect("failed to create data section token stream")
-                .into_iter()
-        })
-        .chain(ffi_mod.into_token_stream().into_iter())
-        .collect()
+        .filter(|arg| !func::is_receiver(arg))
+        .collect();
+    // A bound like `where Self: Sized` on a method taking `self` refers to the trait, which the
+    // import can't name.
+    sig.generics.where_clause = None;
+    // Patterns other than plain identifiers can't be forwarded to the import.
+    for (i, arg) in sig.inputs.iter_mut().enumerate() {
+        if let syn::FnArg::Typed(pat_type) = arg {
+            if !matches!(&*pat_type.pat, syn::Pat::Ident(_)) {
+                let ident = quote::format_ident!("__externref_arg{i}");
+                *pat_type.pat = syn::parse_quote!(#ident);
+            }
+        }
+    }
+
+    let mut import = ForeignItemFn {
+        attrs: method.attrs.clone(),
+        vis: syn::Visibility::Inherited,
+        sig,
+        semi_token: Default::default(),
+    };
+    let (data, encoding) = process_foreign_fn(&mut import, block_opts)?;
+    check_duplicate(declared, &data, &method.sig.ident, module)?;
+
+    // The options were read, and the method gets a body, so the attribute can't expand again.
+    import.attrs.retain(|attr| !attr.path.is_ident("externref"));
+    method.attrs.retain(|attr| !attr.path.is_ident("externref"));
+    method.sig.inputs = method
+        .sig
+        .inputs
+        .iter()
+        .filter(|arg| func::is_receiver(arg))
+        .cloned()
+        .chain(import.sig.inputs.iter().cloned())
+        .collect();
+
+    let ident = &import.sig.ident;
+    let args = import.sig.inputs.iter().map(|arg| match arg {
+        syn::FnArg::Typed(pat_type) => pat_type.pat.to_token_stream(),
+        syn::FnArg::Receiver(receiver) => receiver.to_token_stream(),
+    });
+    method.default = Some(syn::parse_quote! {{
+        #[link(wasm_import_module = #module)]
+        extern "C" {
+            #import
+        }
+
+        // SAFETY: Declaring the method safe asserts the host function is safe to call.
+        #[allow(unused_unsafe)]
+        unsafe { #ident(#(#args),*) }
+    }});
+    method.semi_token = None;
+
+    Ok((data, encoding))
+}
+
+/// Checks that a function doesn't import the same name as an earlier one from the same module,
+/// which would only fail once the module is linked.
+fn check_duplicate(
+    declared: &mut HashMap<String, syn::Ident>,
+    data: &FunctionData,
+    ident: &syn::Ident,
+    module: &str,
+) -> syn::Result<()> {
+    // The first declaration is kept, so every later one points back at it.
+    let first = match declared.entry(data.name.clone()) {
+        Entry::Occupied(entry) => entry.get().clone(),
+        Entry::Vacant(entry) => {
+            entry.insert(ident.clone());
+            return Ok(());
+        }
+    };
+
+    let mut error = syn::Error::new_spanned(
+        ident,
+        format!("`{}` is already imported from `{module}`", data.name),
+    );
+    error.combine(syn::Error::new_spanned(
+        &first,
+        format!("`{}` is first imported here", data.name),
+    ));
+    Err(error)
 }
 
-fn process_fn(mut func: ItemFn, opts: ExternRefOptions) -> TokenStream2 {
-    if let Some(name) = &opts.name {
+fn process_fn(mut func: ItemFn, mut opts: ExternRefOptions) -> syn::Result<TokenStream2> {
+    check_applied_once(&func.attrs)?;
+
+    // Callbacks are called through a function table, so they don't need to be exported.
+    if !opts.callback {
+        func::check_export_visibility(&func.vis)
+            .map_err(|error| spanned_error(error, &func.sig.ident, "invalid export"))?;
+    }
+
+    if let Some(name) = opts.name.as_ref().filter(|_| !opts.no_link) {
         func.attrs.push(syn::parse_quote! {
             #[link(wasm_import_module = #name)]
         });
     }
 
-    let function_data = FunctionData::parse(&func.sig, opts).expect("cannot parse function");
-    function_data
-        .to_data_section_token_stream(None)
-        .expect("failed to create data section token stream")
-        .into_iter()
-        .chain(func.into_token_stream().into_iter())
+    if let Some(export_name) = opts.export_name.take() {
+        if let Some(name) = opts.name.as_ref().filter(|name| **name != export_name) {
+            return Err(syn::Error::new_spanned(
+                &func.sig.ident,
+                format!("the function is exported as `{export_name}`, but its name is given as `{name}`"),
+            ));
+        }
+        if func::export_symbol(&func)?.is_some() {
+            return Err(syn::Error::new_spanned(
+                &func.sig.ident,
+                "`export_name` can't be combined with `#[no_mangle]` or `#[export_name]`",
+            ));
+        }
+        func.attrs.push(syn::parse_quote! {
+            #[export_name = #export_name]
+        });
+    }
+
+    // Without a symbol of its own the function is only exported when asked to, under the name its
+    // data describes.
+    if opts.export && func::export_symbol(&func)?.is_none() {
+        if opts.callback {
+            return Err(syn::Error::new_spanned(
+                &func.sig.ident,
+                "callbacks are called through a function table, so they can't be exported",
+            ));
+        }
+        let name = opts
+            .name
+            .clone()
+            .unwrap_or_else(|| func.sig.ident.to_string());
+        func.attrs.push(syn::parse_quote! {
+            #[export_name = #name]
+        });
+    }
+
+    // The data has to describe the name the function is really exported under, or the transformer
+    // won't find the export.
+    if let Some(symbol) = func::export_symbol(&func)? {
+        match &opts.name {
+            Some(name) if *name != symbol => {
+                return Err(syn::Error::new_spanned(
+                    &func.sig.ident,
+                    format!(
+                        "the function is exported as `{symbol}`, but its name is given as `{name}`"
+                    ),
+                ));
+            }
+            _ => opts.name = Some(symbol),
+        }
+    }
+
+    let encoding = opts.encoding.unwrap_or_default();
+    let function_data = FunctionData::parse(&func.sig, opts)
+        .map_err(|error| spanned_error(error, &func.sig, "cannot parse function"))?;
+    if function_data.export_ref.is_some() {
+        return Err(syn::Error::new_spanned(
+            &func.sig.ident,
+            "only imports can be an export_ref",
+        ));
+    }
+    if function_data.intrinsic.is_some() {
+        return Err(syn::Error::new_spanned(
+            &func.sig.ident,
+            "only imports can be an intrinsic",
+        ));
+    }
+    if function_data.constructor {
+        return Err(syn::Error::new_spanned(
+            &func.sig.ident,
+            "only imports can be constructors",
+        ));
+    }
+    let mut output = section_token_stream(&function_data, None, encoding)
+        .map_err(|error| spanned_error(error, &func.sig.ident, "invalid data"))?;
+    output.extend(func.into_token_stream());
+    Ok(output)
+}
+
+fn process_foreign_fn(
+    func: &mut ForeignItemFn,
+    block_opts: &ExternRefOptions,
+) -> syn::Result<(FunctionData, Encoding)> {
+    // The host is only called at run time, so an import can't be evaluated in a const context.
+    if let Some(constness) = &func.sig.constness {
+        return Err(syn::Error::new_spanned(
+            constness,
+            "imported functions can't be `const fn`, as the host is only called at run time",
+        ));
+    }
+
+    let mut opts: ExternRefOptions = AttributesOrOptions::from(func.attrs.as_ref())
+        .try_into()
+        .map_err(|error| spanned_error(error, &func.sig.ident, "invalid options"))?;
+
+    if opts.export_name.is_some() || block_opts.export_name.is_some() {
+        return Err(syn::Error::new_spanned(
+            &func.sig.ident,
+            "only exported functions can have an `export_name`, use `name` to rename an import",
+        ));
+    }
+
+    // With `no_link` the function is linked by the user's own `link_name`, if it has one.
+    opts.no_link |= block_opts.no_link;
+    if opts.no_link {
+        match (func::link_name(&func.attrs)?, &opts.name) {
+            (Some(symbol), Some(name)) if symbol != *name => {
+                return Err(syn::Error::new_spanned(
+                    &func.sig.ident,
+                    format!(
+                        "the function is linked to `{symbol}`, but its name is given as `{name}`"
+                    ),
+                ));
+            }
+            (Some(symbol), _) => opts.name = Some(symbol),
+            (None, _) => {}
+        }
+    }
+
+    // Strict mode on the extern block applies to every function in it.
+    opts.strict |= block_opts.strict;
+    opts.allow_types
+        .extend(block_opts.allow_types.iter().cloned());
+
+    // The block's group, lowering and encoding apply to functions that don't choose their own.
+    opts.group = opts.group.or_else(|| block_opts.group.clone());
+    opts.lowering = opts.lowering.or(block_opts.lowering);
+    let encoding = opts.encoding.or(block_opts.encoding).unwrap_or_default();
+    let no_link = opts.no_link;
+    let data = FunctionData::parse(&func.sig, opts)
+        .map_err(|error| spanned_error(error, &func.sig, "failed to parse function data"))?;
+    if data.ret_result.is_some() {
+        return Err(syn::Error::new_spanned(
+            &func.sig.output,
+            "only exported functions can return a `Result` with an `ExternRef`",
+        ));
+    }
+    if data.callback {
+        return Err(syn::Error::new_spanned(
+            &func.sig.ident,
+            "imported functions can't be callbacks",
+        ));
+    }
+    // The transformer replaces the import with a function that returns the reference.
+    if data.export_ref.is_some() && (!func.sig.inputs.is_empty() || !data.ret_is_extern_ref) {
+        return Err(syn::Error::new_spanned(
+            &func.sig,
+            "an export_ref import must take no arguments and return an `ExternRef`",
+        ));
+    }
+    if data.constructor && !data.ret_is_extern_ref {
+        return Err(syn::Error::new_spanned(
+            &func.sig,
+            "a constructor must return the new object as an `ExternRef`",
+        ));
+    }
+    // The transformer synthesizes the body, so the signature has to match its instructions.
+    let returns_bool = matches!(
+        &func.sig.output,
+        ReturnType::Type(_, ty) if matches!(&**ty, Type::Path(path) if path.path.is_ident("bool"))
+    );
+    match data.intrinsic {
+        Some(Intrinsic::RefNull) if !func.sig.inputs.is_empty() || !data.ret_is_extern_ref => {
+            return Err(syn::Error::new_spanned(
+                &func.sig,
+                "the ref.null intrinsic must take no arguments and return an `ExternRef`",
+            ));
+        }
+        Some(Intrinsic::RefIsNull)
+            if func.sig.inputs.len() != 1 || data.arg_indicies != [0] || !returns_bool =>
+        {
+            return Err(syn::Error::new_spanned(
+                &func.sig,
+                "the ref.is_null intrinsic must take an `ExternRef` and return a `bool`",
+            ));
+        }
+        Some(Intrinsic::Transformed) if !func.sig.inputs.is_empty() || !returns_bool => {
+            return Err(syn::Error::new_spanned(
+                &func.sig,
+                "the transformed intrinsic must take no arguments and return a `bool`",
+            ));
+        }
+        _ => {}
+    }
+
+    if !no_link {
+        let name = &data.name;
+        func.attrs.push(syn::parse_quote! {  #[link_name = #name] });
+    }
+
+    Ok((data, encoding))
+}
+
+/// Rejects an item that still has an `#[externref]` attribute once one has been expanded, which
+/// would describe it twice and link it to its module twice.
+fn check_applied_once(attrs: &[syn::Attribute]) -> syn::Result<()> {
+    match attrs.iter().find(|attr| is_externref_attr(attr)) {
+        Some(attr) => Err(syn::Error::new_spanned(
+            attr,
+            "`#[externref]` is already applied to this item",
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Tracks the `env!` variables of the `#[externref]` attributes of a function in an extern block
+/// or trait, which are read while the block or trait expands.
+fn track_env_in_attrs(attrs: &[syn::Attribute]) -> TokenStream2 {
+    attrs
+        .iter()
+        .filter(|attr| is_externref_attr(attr))
+        .map(|attr| args::track_env(attr.tokens.clone()))
         .collect()
 }
 
-fn process_foreign_fn(func: &mut ForeignItemFn) -> FunctionData {
-    let data =
-        FunctionData::parse(&func.sig, func.attrs.as_ref()).expect("failed to parse function data");
+/// If an attribute is `#[externref]`, including a path to it like `#[externref::externref]`.
+fn is_externref_attr(attr: &syn::Attribute) -> bool {
+    attr.path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "externref")
+}
+
+/// Describes a function in its own data section, or adds it to the next [manifest] with the
+/// `manifest` feature.
+fn section_token_stream(
+    data: &FunctionData,
+    module: Option<&str>,
+    encoding: Encoding,
+) -> anyhow::Result<TokenStream2> {
+    if cfg!(feature = "manifest") {
+        if encoding != Encoding::Json {
+            anyhow::bail!(
+                "the manifest is a JSON array, so `encoding` isn't supported with the `manifest` \
+                 feature"
+            );
+        }
+        // The manifest doesn't have a section per function, so the module is recorded instead.
+        registry::add_to_manifest(FunctionData {
+            module: module.map(str::to_string),
+            ..data.clone()
+        });
+        Ok(TokenStream2::new())
+    } else {
+        let section = match &data.group {
+            Some(group) => group_section_name(group, module, &data.name),
+            None => data_section_name(module, &data.name),
+        };
+        let function = match module {
+            Some(module) => format!("{module}.{}", data.name),
+            None => data.name.clone(),
+        };
+        if let Some(other) = registry::claim_section(section.clone(), function.clone()) {
+            anyhow::bail!(
+                "the data section of {function} is named `{section}`, which already describes \
+                 {other}, rename one of them"
+            );
+        }
+        data.to_data_section_token_stream(module, encoding)
+    }
+}
+
+/// Extracts an error that points at the offending tokens so it can be reported as a compile
+/// error, pointing any other error at `tokens`.
+fn spanned_error(error: anyhow::Error, tokens: impl ToTokens, context: &str) -> syn::Error {
+    match error.downcast::<syn::Error>() {
+        Ok(error) => error,
+        Err(error) => syn::Error::new_spanned(tokens, format!("{context}: {error}")),
+    }
+}
+
+/// Adds an error to the ones reported for an item.
+fn push_error(errors: &mut Option<syn::Error>, error: syn::Error) {
+    match errors {
+        Some(errors) => errors.combine(error),
+        None => *errors = Some(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use externref_metadata::{data_section_name, Encoding, Intrinsic, Lowering, INTRINSIC_MODULE};
+    use proc_macro2::TokenStream as TokenStream2;
+    use syn::ItemForeignMod;
+
+    use super::{process_fn, process_foreign_mod, process_trait, process_use};
+    use crate::args::ExternRefOptions;
+
+    fn process(ffi_mod: ItemForeignMod) -> String {
+        let opts = ExternRefOptions {
+            name: Some("env".into()),
+            ..Default::default()
+        };
+        expand(process_foreign_mod(ffi_mod, opts))
+    }
+
+    fn expand(output: syn::Result<TokenStream2>) -> String {
+        output
+            .unwrap_or_else(|error| error.to_compile_error())
+            .to_string()
+    }
+
+    #[test]
+    fn blocks_without_functions() {
+        // Blocks without functions are still linked against the module, but describe nothing.
+        for ffi_mod in [
+            syn::parse_quote! { extern "C" {} },
+            syn::parse_quote! { extern "C" { type Opaque; } },
+        ] {
+            let output = process(ffi_mod);
+            assert!(output.contains("wasm_import_module = \"env\""), "{output}");
+            assert!(!output.contains("link_section"), "{output}");
+        }
+
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                type Opaque;
+                fn log(message: ExternRef);
+            }
+        });
+        assert_eq!(output.matches("link_section").count(), 1, "{output}");
+        assert!(output.contains("__extern_ref_data_env_log"), "{output}");
+        assert!(output.contains("type Opaque"), "{output}");
+    }
+
+    #[test]
+    fn env_dependencies() {
+        // The options of the functions are read while the block expands, so the block tracks them.
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                #[externref(name = concat!(env!("CARGO_PKG_NAME"), "_log"))]
+                fn log(message: ExternRef);
+                fn warn(message: ExternRef);
+            }
+        });
+        let tracked = "const _ : & str = :: core :: env ! (\"CARGO_PKG_NAME\") ;";
+        assert_eq!(output.matches(tracked).count(), 1, "{output}");
+        assert!(output.contains("externref-macros_log"), "{output}");
+
+        let output = expand(process_trait(
+            syn::parse_quote! {
+                trait Console {
+                    #[externref(name = env!("CARGO_PKG_NAME"))]
+                    fn log(&self, message: ExternRef);
+                }
+            },
+            ExternRefOptions {
+                name: Some("env".into()),
+                ..Default::default()
+            },
+        ));
+        assert!(output.contains(tracked), "{output}");
+    }
+
+    #[test]
+    fn section_collisions() {
+        let opts = |name: &str| ExternRefOptions {
+            name: Some(name.into()),
+            ..Default::default()
+        };
+        let first = expand(process_foreign_mod(
+            syn::parse_quote! { extern "C" { fn collides_b_c(value: ExternRef); } },
+            opts("collides_a"),
+        ));
+        assert!(!first.contains("compile_error"), "{first}");
+
+        // Declaring the same import again describes the same function.
+        let again = expand(process_foreign_mod(
+            syn::parse_quote! { extern "C" { fn collides_b_c(value: ExternRef); } },
+            opts("collides_a"),
+        ));
+        assert!(!again.contains("compile_error"), "{again}");
+
+        let output = expand(process_foreign_mod(
+            syn::parse_quote! { extern "C" { fn c(value: ExternRef); } },
+            opts("collides_a_collides_b"),
+        ));
+        assert!(output.contains("compile_error"), "{output}");
+        assert!(
+            output.contains("already describes collides_a.collides_b_c"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn encodings() {
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                fn log(message: ExternRef);
+                #[externref(encoding = "binary")]
+                fn warn(message: ExternRef);
+            }
+        });
+        assert!(output.contains("argIndicies"), "{output}");
+        assert_eq!(output.matches("\\0xrd").count(), 1, "{output}");
+
+        let output = expand(process_foreign_mod(
+            syn::parse_quote! {
+                extern "C" {
+                    fn log(message: ExternRef);
+                    #[externref(encoding = "json")]
+                    fn warn(message: ExternRef);
+                }
+            },
+            ExternRefOptions {
+                name: Some("env".into()),
+                encoding: Some(Encoding::Binary),
+                ..Default::default()
+            },
+        ));
+        assert_eq!(output.matches("argIndicies").count(), 1, "{output}");
+        assert_eq!(output.matches("\\0xrd").count(), 1, "{output}");
+
+        // Compressed data needs the feature to be encoded.
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                #[externref(encoding = "gzip")]
+                fn log(message: ExternRef);
+            }
+        });
+        if cfg!(feature = "compression") {
+            assert!(output.contains("\\x1F\\x8B"), "{output}");
+            assert!(!output.contains("argIndicies"), "{output}");
+        } else {
+            assert!(output.contains("compile_error"), "{output}");
+        }
+    }
+
+    #[test]
+    fn block_lowering() {
+        let output = expand(process_foreign_mod(
+            syn::parse_quote! {
+                extern "C" {
+                    fn log(message: ExternRef);
+                    #[externref(lowering = "direct")]
+                    fn trace(message: ExternRef);
+                }
+            },
+            ExternRefOptions {
+                name: Some("console".into()),
+                lowering: Some(Lowering::Table),
+                ..Default::default()
+            },
+        ));
+        assert_eq!(
+            output.matches(r#"\"lowering\":\"table\""#).count(),
+            1,
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn groups() {
+        let output = expand(process_foreign_mod(
+            syn::parse_quote! {
+                extern "C" {
+                    fn log(message: ExternRef);
+                    #[externref(group = "debug")]
+                    fn trace(message: ExternRef);
+                }
+            },
+            ExternRefOptions {
+                name: Some("console".into()),
+                group: Some("dom".into()),
+                ..Default::default()
+            },
+        ));
+        assert!(
+            output.contains(r#"link_section = "__extern_ref_data_dom@console_log""#),
+            "{output}"
+        );
+        assert!(
+            output.contains(r#"link_section = "__extern_ref_data_debug@console_trace""#),
+            "{output}"
+        );
+        assert!(output.contains(r#"\"group\":\"dom\""#), "{output}");
+        assert!(output.contains(r#"\"group\":\"debug\""#), "{output}");
+
+        let output = expand(process_fn(
+            syn::parse_quote! { pub fn run(node: ExternRef) {} },
+            ExternRefOptions {
+                group: Some("dom".into()),
+                ..Default::default()
+            },
+        ));
+        assert!(
+            output.contains(r#"link_section = "__extern_ref_data_dom@run""#),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn redundant_blocks() {
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                fn add(a: u32, b: u32) -> u32;
+            }
+        });
+        assert!(output.contains("externref_may_be_redundant"), "{output}");
+        assert!(
+            output.contains("wasm_import_module = \\\"env\\\""),
+            "{output}"
+        );
+
+        for ffi_mod in [
+            syn::parse_quote! { extern "C" { fn log(message: ExternRef); } },
+            syn::parse_quote! { extern "C" { fn window() -> ExternRef; } },
+            syn::parse_quote! { extern "C" { #[externref(optional)] fn add(a: u32) -> u32; } },
+        ] {
+            let output = process(ffi_mod);
+            assert!(!output.contains("externref_may_be_redundant"), "{output}");
+        }
+    }
+
+    #[test]
+    fn empty_module() {
+        let opts = ExternRefOptions {
+            name: Some(String::new()),
+            ..Default::default()
+        };
+        let output = expand(process_foreign_mod(
+            syn::parse_quote! { extern "C" { fn log(message: ExternRef); } },
+            opts,
+        ));
+        assert!(output.contains("wasm_import_module = \"\""), "{output}");
+        assert!(output.contains("__extern_ref_data__log"), "{output}");
+
+        // A missing name is an error rather than the empty module.
+        let output = expand(process_foreign_mod(
+            syn::parse_quote! { extern "C" { fn log(message: ExternRef); } },
+            ExternRefOptions::default(),
+        ));
+        assert!(output.contains("compile_error"), "{output}");
+    }
+
+    #[test]
+    fn trait_methods() {
+        let opts = ExternRefOptions {
+            name: Some("host".into()),
+            ..Default::default()
+        };
+        let output = expand(process_trait(
+            syn::parse_quote! {
+                trait Host {
+                    #[externref(ref_args = "1")]
+                    fn emit(&self, id: u32, handle: u32);
+                    fn provided(&self) {}
+                }
+            },
+            opts,
+        ));
+        // Only the method without a body is imported, and the receiver isn't counted.
+        assert_eq!(output.matches("link_section").count(), 1, "{output}");
+        assert!(output.contains("__extern_ref_data_host_emit"), "{output}");
+        assert!(output.contains("wasm_import_module = \"host\""), "{output}");
+        assert!(output.contains("emit (id , handle)"), "{output}");
+        assert!(!output.contains("externref (ref_args"), "{output}");
+
+        // Without a module name there is nothing to import from.
+        let output = expand(process_trait(
+            syn::parse_quote! { trait Host { fn emit(&self, event: ExternRef); } },
+            ExternRefOptions::default(),
+        ));
+        assert!(output.contains("compile_error"), "{output}");
+    }
+
+    #[test]
+    fn trait_receivers() {
+        // The import takes the arguments after the receiver, so the reference is index 0 either way.
+        for receiver in [
+            quote::quote! { &self },
+            quote::quote! { &mut self },
+            quote::quote! { self },
+            quote::quote! { self: Box<Self> },
+            quote::quote! { self: std::rc::Rc<Self> },
+        ] {
+            let output = expand(process_trait(
+                syn::parse_quote! { trait Host { fn emit(#receiver, id: u32, node: ExternRef); } },
+                ExternRefOptions {
+                    name: Some("host".into()),
+                    ..Default::default()
+                },
+            ));
+            assert!(output.contains(r#"\"argIndicies\":[1]"#), "{output}");
+            assert!(output.contains(r#"\"paramCount\":2"#), "{output}");
+            assert!(
+                output.contains("fn emit (id : u32 , node : ExternRef) ;"),
+                "{output}"
+            );
+            assert!(output.contains("emit (id , node)"), "{output}");
+            assert!(
+                output.contains(&format!("fn emit ({receiver} , id : u32")),
+                "{output}"
+            );
+        }
+
+        // Outside of a trait a receiver has no wasm parameter to lower to.
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                fn emit(self: Box<Self>, node: ExternRef);
+            }
+        });
+        assert!(output.contains("has no wasm parameter"), "{output}");
+    }
+
+    #[test]
+    fn applied_twice() {
+        let already = "already applied";
+
+        // The attribute left on an item means the macro is about to expand on it again.
+        let output = process(syn::parse_quote! {
+            #[externref(name = "env")]
+            extern "C" {
+                fn log(message: ExternRef);
+            }
+        });
+        assert!(output.contains(already), "{output}");
+        assert!(!output.contains("link_section"), "{output}");
+
+        let output = expand(process_fn(
+            syn::parse_quote! {
+                #[externref::externref]
+                pub fn run(message: ExternRef) {}
+            },
+            ExternRefOptions::default(),
+        ));
+        assert!(output.contains(already), "{output}");
+        assert!(!output.contains("link_section"), "{output}");
+
+        let output = expand(process_trait(
+            syn::parse_quote! {
+                #[externref(name = "host")]
+                trait Host {
+                    fn emit(&self, event: ExternRef);
+                }
+            },
+            ExternRefOptions {
+                name: Some("host".into()),
+                ..Default::default()
+            },
+        ));
+        assert!(output.contains(already), "{output}");
+
+        // Expanding the output of a single application doesn't find another one.
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                #[externref(name = "log")]
+                fn log(message: ExternRef);
+            }
+        });
+        assert!(!output.contains(already), "{output}");
+    }
+
+    #[test]
+    fn export_symbols() {
+        let output = expand(process_fn(
+            syn::parse_quote! {
+                #[export_name = "start"]
+                pub fn run(message: ExternRef) {}
+            },
+            ExternRefOptions::default(),
+        ));
+        assert!(output.contains("__extern_ref_data_start"), "{output}");
+
+        // A name matching the symbol is fine, but describing another export isn't.
+        let opts = |name: &str| ExternRefOptions {
+            name: Some(name.into()),
+            ..Default::default()
+        };
+        let output = expand(process_fn(
+            syn::parse_quote! {
+                #[no_mangle]
+                pub fn run(message: ExternRef) {}
+            },
+            opts("run"),
+        ));
+        assert!(output.contains("__extern_ref_data_run"), "{output}");
+
+        let output = expand(process_fn(
+            syn::parse_quote! {
+                #[no_mangle]
+                pub fn run(message: ExternRef) {}
+            },
+            opts("start"),
+        ));
+        assert!(output.contains("compile_error"), "{output}");
+        assert!(!output.contains("link_section"), "{output}");
+    }
+
+    #[test]
+    fn export_name() {
+        let opts = |name: Option<&str>| ExternRefOptions {
+            name: name.map(Into::into),
+            export_name: Some("on-click".into()),
+            ..Default::default()
+        };
+
+        // The Rust function keeps its name, while the export and metadata use the export name.
+        let output = expand(process_fn(
+            syn::parse_quote! { pub fn on_click(event: ExternRef) {} },
+            opts(None),
+        ));
+        assert!(output.contains("export_name = \"on-click\""), "{output}");
+        assert!(output.contains("pub fn on_click"), "{output}");
+        assert!(
+            output.contains("\"__extern_ref_data_on-click\""),
+            "{output}"
+        );
+        assert!(output.contains(r#"\"name\":\"on-click\""#), "{output}");
+
+        let output = expand(process_fn(
+            syn::parse_quote! { pub fn on_click(event: ExternRef) {} },
+            opts(Some("on-click")),
+        ));
+        assert!(!output.contains("compile_error"), "{output}");
+
+        // Another name, or symbol, would describe a different export.
+        let output = expand(process_fn(
+            syn::parse_quote! { pub fn on_click(event: ExternRef) {} },
+            opts(Some("onClick")),
+        ));
+        assert!(output.contains("compile_error"), "{output}");
+        let output = expand(process_fn(
+            syn::parse_quote! {
+                #[no_mangle]
+                pub fn on_click(event: ExternRef) {}
+            },
+            opts(None),
+        ));
+        assert!(output.contains("compile_error"), "{output}");
+
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                #[externref(export_name = "on-click")]
+                fn on_click(event: ExternRef);
+            }
+        });
+        assert!(output.contains("compile_error"), "{output}");
+    }
+
+    #[test]
+    fn export_flag() {
+        let opts = |name: Option<&str>| ExternRefOptions {
+            name: name.map(Into::into),
+            export: true,
+            ..Default::default()
+        };
+
+        // The export is named after the metadata, whether or not a name is given.
+        for (name, expected) in [(Some("onEvent"), "onEvent"), (None, "on_event")] {
+            let output = expand(process_fn(
+                syn::parse_quote! { pub fn on_event(event: ExternRef) {} },
+                opts(name),
+            ));
+            assert!(
+                output.contains(&format!("export_name = {expected:?}")),
+                "{output}"
+            );
+            assert!(
+                output.contains(&format!(r#"\"name\":\"{expected}\""#)),
+                "{output}"
+            );
+            assert!(!output.contains("compile_error"), "{output}");
+        }
+
+        // A function's own symbol is left alone.
+        let output = expand(process_fn(
+            syn::parse_quote! {
+                #[no_mangle]
+                pub fn on_event(event: ExternRef) {}
+            },
+            opts(None),
+        ));
+        assert!(!output.contains("export_name"), "{output}");
+        assert!(output.contains(r#"\"name\":\"on_event\""#), "{output}");
+
+        // Without the flag the function isn't exported by the macro.
+        let output = expand(process_fn(
+            syn::parse_quote! { pub fn on_event(event: ExternRef) {} },
+            ExternRefOptions::default(),
+        ));
+        assert!(!output.contains("export_name"), "{output}");
 
-    let name = &data.name;
-    func.attrs.push(syn::parse_quote! {  #[link_name = #name] });
+        // Callbacks aren't exports.
+        let output = expand(process_fn(
+            syn::parse_quote! { fn on_event(event: ExternRef) {} },
+            ExternRefOptions {
+                callback: true,
+                ..opts(None)
+            },
+        ));
+        assert!(output.contains("compile_error"), "{output}");
+    }
+
+    #[test]
+    fn const_fns() {
+        let output = expand(process_fn(
+            syn::parse_quote! { pub const fn answer() -> u32 { 42 } },
+            ExternRefOptions::default(),
+        ));
+        assert!(output.contains("__extern_ref_data_answer"), "{output}");
+        assert!(output.contains("pub const fn answer"), "{output}");
+
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                const fn answer() -> u32;
+            }
+        });
+        assert!(output.contains("can't be `const fn`"), "{output}");
+        assert!(!output.contains("link_section"), "{output}");
+    }
+
+    #[test]
+    fn section_names_match_metadata() {
+        for (module, name) in [
+            ("console", "log"),
+            ("", "log"),
+            ("dom.node", "append"),
+            ("wasi:io/streams", "write"),
+            ("${HOST}", "log"),
+        ] {
+            let opts = ExternRefOptions {
+                name: Some(module.into()),
+                ..Default::default()
+            };
+            let output = expand(process_foreign_mod(
+                syn::parse_quote! {
+                    extern "C" {
+                        #[externref(name = #name)]
+                        fn import(value: ExternRef);
+                    }
+                },
+                opts,
+            ));
+            let section = data_section_name(Some(module), name);
+            assert!(
+                output.contains(&format!("link_section = {section:?}")),
+                "{output}"
+            );
+        }
+
+        for name in ["run", "on-click"] {
+            let opts = ExternRefOptions {
+                name: Some(name.into()),
+                ..Default::default()
+            };
+            let output = expand(process_fn(
+                syn::parse_quote! { pub fn export(value: ExternRef) {} },
+                opts,
+            ));
+            let section = data_section_name(None, name);
+            assert!(
+                output.contains(&format!("link_section = {section:?}")),
+                "{output}"
+            );
+        }
+    }
+
+    #[test]
+    fn namespaced_modules() {
+        for (module, ident) in [
+            ("dom.node", "__extern_ref_data_dom_u2e_node_append"),
+            (
+                "wasi:io/streams",
+                "__extern_ref_data_wasi_u3a_io_u2f_streams_append",
+            ),
+            ("dom_node", "__extern_ref_data_dom_node_append"),
+        ] {
+            let opts = ExternRefOptions {
+                name: Some(module.into()),
+                ..Default::default()
+            };
+            let output = expand(process_foreign_mod(
+                syn::parse_quote! { extern "C" { fn append(node: ExternRef); } },
+                opts,
+            ));
 
-    data
+            // The section keeps the module's name, only the static is escaped.
+            let section = format!("__extern_ref_data_{module}_append");
+            assert!(output.contains(&format!("{section:?}")), "{output}");
+            assert!(output.contains(&format!("static {ident}")), "{output}");
+            assert!(
+                output.contains(&format!("wasm_import_module = {module:?}")),
+                "{output}"
+            );
+        }
+    }
+
+    #[test]
+    fn no_link() {
+        let opts = ExternRefOptions {
+            no_link: true,
+            ..Default::default()
+        };
+        let output = expand(process_foreign_mod(
+            syn::parse_quote! {
+                #[link(wasm_import_module = "console")]
+                extern "C" {
+                    #[link_name = "log"]
+                    fn console_log(message: ExternRef);
+                    fn warn(message: ExternRef);
+                }
+            },
+            opts,
+        ));
+        // The user's attributes name the module and functions, and aren't duplicated.
+        assert_eq!(output.matches("wasm_import_module").count(), 1, "{output}");
+        assert_eq!(output.matches("link_name").count(), 1, "{output}");
+        assert!(output.contains("__extern_ref_data_console_log"), "{output}");
+        assert!(
+            output.contains("__extern_ref_data_console_warn"),
+            "{output}"
+        );
+
+        // Names that don't match what the user links to are an error.
+        let opts = ExternRefOptions {
+            name: Some("env".into()),
+            no_link: true,
+            ..Default::default()
+        };
+        let output = expand(process_foreign_mod(
+            syn::parse_quote! {
+                #[link(wasm_import_module = "console")]
+                extern "C" {
+                    fn log(message: ExternRef);
+                }
+            },
+            opts,
+        ));
+        assert!(output.contains("compile_error"), "{output}");
+
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                #[externref(name = "log", no_link)]
+                #[link_name = "error"]
+                fn log(message: ExternRef);
+            }
+        });
+        assert!(output.contains("compile_error"), "{output}");
+    }
+
+    #[test]
+    fn intrinsics() {
+        let intrinsics = ExternRefOptions {
+            name: Some(INTRINSIC_MODULE.into()),
+            ..Default::default()
+        };
+        let output = expand(process_foreign_mod(
+            syn::parse_quote! {
+                extern "C" {
+                    #[externref(name = "ref.null", intrinsic = "ref.null")]
+                    fn ref_null() -> ExternRef;
+                    #[externref(name = "ref.is_null", intrinsic = "ref.is_null")]
+                    fn ref_is_null(reference: ExternRef) -> bool;
+                    #[externref(name = "transformed", intrinsic = "transformed")]
+                    fn transformed() -> bool;
+                }
+            },
+            intrinsics,
+        ));
+        assert!(
+            output.contains("__extern_ref_data___externref_intrinsic_ref.is_null"),
+            "{output}"
+        );
+        assert!(
+            output.contains(r#"\"intrinsic\":\"ref.is_null\""#),
+            "{output}"
+        );
+        // Intrinsics are described even when they don't use references.
+        assert!(
+            output.contains(r#"\"intrinsic\":\"transformed\""#),
+            "{output}"
+        );
+        assert!(!output.contains("compile_error"), "{output}");
+
+        // The signature has to match the instructions the transformer synthesizes.
+        for ffi_fn in [
+            quote::quote! { #[externref(intrinsic = "ref.null")] fn null(a: u32) -> ExternRef; },
+            quote::quote! { #[externref(intrinsic = "ref.is_null")] fn is_null(a: u32) -> bool; },
+            quote::quote! { #[externref(intrinsic = "ref.is_null")] fn is_null(a: ExternRef); },
+            quote::quote! { #[externref(intrinsic = "transformed")] fn transformed() -> u32; },
+        ] {
+            let output = expand(process_foreign_mod(
+                syn::parse_quote! { extern "C" { #ffi_fn } },
+                ExternRefOptions {
+                    name: Some(INTRINSIC_MODULE.into()),
+                    ..Default::default()
+                },
+            ));
+            assert!(output.contains("compile_error"), "{output}");
+        }
+
+        // The module is reserved for intrinsics, which can't be imported from anywhere else.
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                #[externref(intrinsic = "ref.null")]
+                fn null() -> ExternRef;
+            }
+        });
+        assert!(output.contains("compile_error"), "{output}");
+        let output = expand(process_foreign_mod(
+            syn::parse_quote! { extern "C" { fn log(message: ExternRef); } },
+            ExternRefOptions {
+                name: Some(INTRINSIC_MODULE.into()),
+                ..Default::default()
+            },
+        ));
+        assert!(output.contains("compile_error"), "{output}");
+
+        let output = expand(process_fn(
+            syn::parse_quote! { pub fn null() -> ExternRef {} },
+            ExternRefOptions {
+                intrinsic: Some(Intrinsic::RefNull),
+                ..Default::default()
+            },
+        ));
+        assert!(output.contains("compile_error"), "{output}");
+    }
+
+    #[test]
+    fn merged_attributes() {
+        // Attributes added by different tools are merged.
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                #[externref(name = "log")]
+                #[externref(group = "debug")]
+                fn trace(message: ExternRef);
+            }
+        });
+        assert!(!output.contains("compile_error"), "{output}");
+        assert!(
+            output.contains(r#"link_section = "__extern_ref_data_debug@env_log""#),
+            "{output}"
+        );
+
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                #[externref(name = "log")]
+                #[externref(name = "error")]
+                fn log(message: ExternRef);
+            }
+        });
+        assert!(output.contains("Conflicting values for name"), "{output}");
+    }
+
+    #[test]
+    fn constructors() {
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                #[externref(name = "Foo", constructor)]
+                fn new_foo(size: u32) -> ExternRef;
+            }
+        });
+        assert!(output.contains(r#"\"constructor\":true"#), "{output}");
+
+        // The constructed object is returned, and the host doesn't construct exports.
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                #[externref(constructor)]
+                fn new_foo(size: u32);
+            }
+        });
+        assert!(output.contains("compile_error"), "{output}");
+        let output = expand(process_fn(
+            syn::parse_quote! { pub fn new_foo() -> ExternRef {} },
+            ExternRefOptions {
+                constructor: true,
+                ..Default::default()
+            },
+        ));
+        assert!(output.contains("compile_error"), "{output}");
+    }
+
+    #[test]
+    fn reexports() {
+        // The registry is shared by every test, so the name is unique to this one.
+        let reexport = |item| expand(process_use(item, "CanonicalRef".into()));
+        assert!(
+            reexport(syn::parse_quote! { use externref::Other as CanonicalRefs; })
+                .contains("compile_error"),
+        );
+        let output = reexport(syn::parse_quote! { use std::string::String as CanonicalRef; });
+        assert!(
+            output.contains("CanonicalRef must be a re-export of `ExternRef`, but it's `String`"),
+            "{output}"
+        );
+        let output = reexport(syn::parse_quote! {
+            pub use externref::{ExternRef as CanonicalRef, ExternRefList};
+        });
+        assert_eq!(
+            output,
+            quote::quote! { pub use externref::{ExternRef as CanonicalRef, ExternRefList}; }
+                .to_string()
+        );
+
+        let output = process(syn::parse_quote! {
+            extern "C" {
+                fn clone(reference: CanonicalRef, times: u32) -> CanonicalRef;
+            }
+        });
+        assert!(output.contains(r#"\"argIndicies\":[0]"#), "{output}");
+        assert!(output.contains(r#"\"retIsExternRef\":true"#), "{output}");
+        // It's the same type as an `ExternRef`, so it doesn't name a host type.
+        assert!(!output.contains("argTypes"), "{output}");
+
+        // Only a `use` item can register a name.
+        let output = expand(process_fn(
+            syn::parse_quote! { pub fn run(value: CanonicalRef) {} },
+            ExternRefOptions {
+                reexport: Some("Other".into()),
+                ..Default::default()
+            },
+        ));
+        assert!(output.contains("compile_error"), "{output}");
+    }
 }
@@ -0,0 +1,119 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use externref_metadata::FunctionData;
+
+/// The names of the types declared with [externref_type](crate::externref_type).
+///
+/// Proc macros can't resolve traits, so the types are recognized by name instead. The registry
+/// lives as long as the compiler process, which means a type is only known to functions expanded
+/// after it, and only within the crate declaring it.
+static TYPES: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+/// Registers a type that is an `externref` at the ABI level.
+pub(crate) fn register(name: String) {
+    let mut types = TYPES.lock().unwrap_or_else(|error| error.into_inner());
+    types.get_or_insert_with(HashSet::new).insert(name);
+}
+
+/// If a type with this name has been registered.
+pub(crate) fn is_registered(name: &str) -> bool {
+    let types = TYPES.lock().unwrap_or_else(|error| error.into_inner());
+    types.as_ref().is_some_and(|types| types.contains(name))
+}
+
+/// The names `ExternRef` is re-exported under, declared with `#[externref(reexport = "...")]`.
+///
+/// These are the same type as an `ExternRef`, so unlike the registered types they don't name a
+/// host type.
+static REEXPORTS: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+
+/// Registers another name for `ExternRef`.
+pub(crate) fn register_reexport(name: String) {
+    let mut reexports = REEXPORTS.lock().unwrap_or_else(|error| error.into_inner());
+    reexports.get_or_insert_with(HashSet::new).insert(name);
+}
+
+/// If `ExternRef` has been re-exported under this name.
+pub(crate) fn is_reexport(name: &str) -> bool {
+    let reexports = REEXPORTS.lock().unwrap_or_else(|error| error.into_inner());
+    reexports
+        .as_ref()
+        .is_some_and(|reexports| reexports.contains(name))
+}
+
+/// The functions described since the last [manifest](crate::manifest) invocation, with the
+/// `manifest` feature.
+static MANIFEST: Mutex<Vec<FunctionData>> = Mutex::new(Vec::new());
+
+/// Adds a function to the next manifest.
+pub(crate) fn add_to_manifest(data: FunctionData) {
+    let mut manifest = MANIFEST.lock().unwrap_or_else(|error| error.into_inner());
+    manifest.push(data);
+}
+
+/// Takes the functions described since the last manifest, in the order they were expanded.
+pub(crate) fn take_manifest() -> Vec<FunctionData> {
+    let mut manifest = MANIFEST.lock().unwrap_or_else(|error| error.into_inner());
+    std::mem::take(&mut *manifest)
+}
+
+/// The function each data section emitted so far describes, as `module.name` for imports and the
+/// export name otherwise, keyed by the crate emitting it and the section name.
+///
+/// Different functions can end up with the same section name, like `a_b` from `a` and `b_c` from
+/// `a_b`, and the transformer would only see one of them. This only catches collisions within a
+/// crate, and sections are keyed by the crate because a compiler process, like the proc macro
+/// server of rust-analyzer, can expand several crates. The check is best-effort there: the server
+/// also keeps sections across edits, so a renamed function can still collide with its old name
+/// until the server restarts.
+static SECTIONS: Mutex<Option<HashMap<(String, String), String>>> = Mutex::new(None);
+
+/// The crate being compiled, from the variables Cargo sets for the compiler. Two versions or
+/// copies of a package have the same crate name, so the package's identity is included too.
+fn crate_identity() -> String {
+    [
+        "CARGO_CRATE_NAME",
+        "CARGO_PKG_NAME",
+        "CARGO_PKG_VERSION",
+        "CARGO_MANIFEST_DIR",
+    ]
+    .map(|var| std::env::var(var).unwrap_or_default())
+    .join("\0")
+}
+
+/// Claims a data section for a function, returning the function that already claimed it in the
+/// same crate if it's a different one.
+pub(crate) fn claim_section(section: String, function: String) -> Option<String> {
+    claim_section_in(crate_identity(), section, function)
+}
+
+fn claim_section_in(krate: String, section: String, function: String) -> Option<String> {
+    let mut sections = SECTIONS.lock().unwrap_or_else(|error| error.into_inner());
+    let claimed = sections
+        .get_or_insert_with(HashMap::new)
+        .entry((krate, section))
+        .or_insert_with(|| function.clone());
+    (*claimed != function).then(|| claimed.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::claim_section_in;
+
+    #[test]
+    fn sections_per_crate() {
+        let claim = |krate: &str, function: &str| {
+            claim_section_in(krate.into(), "registry_a_b".into(), function.into())
+        };
+        assert_eq!(claim("app", "a.b"), None);
+        assert_eq!(claim("app", "a.b"), None);
+        assert_eq!(claim("app", "a_b."), Some("a.b".into()));
+
+        // Another crate, or another version of the same one, has sections of its own.
+        assert_eq!(claim("app 1.0.0", "a_b."), None);
+        assert_eq!(claim("app 2.0.0", "a.b"), None);
+    }
+}
@@ -0,0 +1,401 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{FunctionData, Intrinsic, Lowering, ResultRefs};
+
+/// The bytes every binary encoded [FunctionData] starts with.
+///
+/// JSON records start with `{` or whitespace, so a reader can tell the encodings apart by their
+/// first byte even when the linker concatenated records of both into one section.
+pub const BINARY_MAGIC: [u8; 4] = *b"\0xrd";
+
+/// How the macro encodes a [FunctionData] into its section.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// A JSON object, the format of every module built before the binary encoding existed.
+    #[default]
+    Json,
+    /// The compact encoding of [FunctionData::to_binary].
+    Binary,
+    /// The JSON object compressed with gzip, which starts with [GZIP_MAGIC](crate::GZIP_MAGIC).
+    Gzip,
+}
+
+/// An error decoding a binary encoded [FunctionData].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    offset: usize,
+    message: &'static str,
+}
+
+impl DecodeError {
+    /// The offset of the invalid byte from the start of the record.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl FunctionData {
+    /// Encodes the data in the binary encoding.
+    ///
+    /// A record is [BINARY_MAGIC], the length of the rest of the record as an unsigned LEB128,
+    /// then every field in the order they're declared. Integers are unsigned LEB128s, strings and
+    /// lists are prefixed with their length, booleans are a byte, and an `Option` is a `0` byte
+    /// for `None` or a `1` byte followed by the value. New fields are appended, readers skip any
+    /// bytes after the fields they know, and fields missing from the end of an older record take
+    /// their defaults.
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut fields = Vec::new();
+        write_str(&mut fields, &self.name);
+        write_uleb(&mut fields, self.arg_indicies.len());
+        for index in &self.arg_indicies {
+            write_uleb(&mut fields, *index);
+        }
+        fields.push(self.ret_is_extern_ref.into());
+        fields.push(self.optional.into());
+        write_uleb(&mut fields, self.arg_names.len());
+        for name in &self.arg_names {
+            write_option(&mut fields, name.as_deref(), write_str);
+        }
+        write_uleb(&mut fields, self.aliases.len());
+        for alias in &self.aliases {
+            write_str(&mut fields, alias);
+        }
+        write_option(&mut fields, self.module.as_deref(), write_str);
+        write_option(&mut fields, self.ret_result, |out, refs| {
+            out.push(refs.ok.into());
+            out.push(refs.err.into());
+        });
+        fields.push(self.callback.into());
+        write_uleb(&mut fields, self.arg_types.len());
+        for (index, ty) in &self.arg_types {
+            write_uleb(&mut fields, *index);
+            write_str(&mut fields, ty);
+        }
+        write_option(&mut fields, self.export_ref.as_deref(), write_str);
+        write_option(&mut fields, self.param_count, write_uleb);
+        write_option(&mut fields, self.group.as_deref(), write_str);
+        write_option(&mut fields, self.intrinsic.map(Intrinsic::name), write_str);
+        fields.push(self.constructor.into());
+        write_uleb(&mut fields, self.nullable_args.len());
+        for index in &self.nullable_args {
+            write_uleb(&mut fields, *index);
+        }
+        fields.push(self.ret_nullable.into());
+        fields.push(self.reentrant.into());
+        write_str(&mut fields, self.lowering.name());
+        fields.push(self.pack_args.into());
+
+        let mut out = BINARY_MAGIC.to_vec();
+        write_uleb(&mut out, fields.len());
+        out.extend(fields);
+        out
+    }
+
+    /// Decodes a record in the binary encoding from the start of `bytes`, returning the data and
+    /// the length of the record. Anything after the record is left alone, so a section holding
+    /// several records can be decoded one after the other.
+    pub fn from_binary(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let mut reader = Reader { bytes, pos: 0 };
+        if !bytes.starts_with(&BINARY_MAGIC) {
+            return Err(reader.error("missing magic"));
+        }
+        reader.pos = BINARY_MAGIC.len();
+        let len = reader.uleb()?;
+        let end = reader
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= bytes.len())
+            .ok_or_else(|| reader.error("record length past the end of the data"))?;
+        reader.bytes = &bytes[..end];
+
+        let data = FunctionData {
+            name: reader.string()?,
+            arg_indicies: reader.list(Reader::uleb)?,
+            ret_is_extern_ref: reader.bool()?,
+            optional: reader.bool()?,
+            arg_names: reader.list(|reader| reader.option(Reader::string))?,
+            aliases: reader.list(Reader::string)?,
+            module: reader.option(Reader::string)?,
+            ret_result: reader.option(|reader| {
+                Ok(ResultRefs {
+                    ok: reader.bool()?,
+                    err: reader.bool()?,
+                })
+            })?,
+            callback: reader.bool()?,
+            arg_types: reader
+                .list(|reader| Ok((reader.uleb()?, reader.string()?)))?
+                .into_iter()
+                .collect::<BTreeMap<_, _>>(),
+            export_ref: reader.option(Reader::string)?,
+            param_count: reader.option(Reader::uleb)?,
+            // The fields from here on were appended after the first release of the encoding, so
+            // they take their defaults when a record from an older macro ends before them.
+            group: reader.appended(|reader| reader.option(Reader::string))?,
+            intrinsic: reader.appended(|reader| {
+                reader.option(|reader| {
+                    let name = reader.string()?;
+                    Intrinsic::from_name(&name).ok_or_else(|| reader.error("unknown intrinsic"))
+                })
+            })?,
+            constructor: reader.appended(Reader::bool)?,
+            nullable_args: reader.appended(|reader| reader.list(Reader::uleb))?,
+            ret_nullable: reader.appended(Reader::bool)?,
+            reentrant: reader.appended(Reader::bool)?,
+            lowering: reader.appended(|reader| {
+                let name = reader.string()?;
+                Lowering::from_name(&name).ok_or_else(|| reader.error("unknown lowering"))
+            })?,
+            pack_args: reader.appended(Reader::bool)?,
+        };
+        Ok((data, end))
+    }
+}
+
+fn write_uleb(out: &mut Vec<u8>, value: usize) {
+    let mut value = value as u64;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn write_str(out: &mut Vec<u8>, value: &str) {
+    write_uleb(out, value.len());
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn write_option<T>(out: &mut Vec<u8>, value: Option<T>, write: impl FnOnce(&mut Vec<u8>, T)) {
+    match value {
+        Some(value) => {
+            out.push(1);
+            write(out, value);
+        }
+        None => out.push(0),
+    }
+}
+
+/// Reads the fields of a single record.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn error(&self, message: &'static str) -> DecodeError {
+        DecodeError {
+            offset: self.pos,
+            message,
+        }
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self
+            .bytes
+            .get(self.pos)
+            .ok_or_else(|| self.error("unexpected end of record"))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn uleb(&mut self) -> Result<usize, DecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return usize::try_from(value).map_err(|_| self.error("integer too large"));
+            }
+        }
+        Err(self.error("integer too large"))
+    }
+
+    fn bool(&mut self) -> Result<bool, DecodeError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(self.error("invalid boolean")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.uleb()?;
+        let bytes = self
+            .bytes
+            .get(self.pos..)
+            .and_then(|rest| rest.get(..len))
+            .ok_or_else(|| self.error("string past the end of the record"))?;
+        let value = std::str::from_utf8(bytes).map_err(|_| self.error("invalid UTF-8"))?;
+        self.pos += len;
+        Ok(value.to_string())
+    }
+
+    fn option<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, DecodeError>,
+    ) -> Result<Option<T>, DecodeError> {
+        match self.byte()? {
+            0 => Ok(None),
+            1 => read(self).map(Some),
+            _ => Err(self.error("invalid option tag")),
+        }
+    }
+
+    /// Reads a field that was added to the encoding later, or returns its default when the record
+    /// ends before it.
+    fn appended<T: Default>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, DecodeError>,
+    ) -> Result<T, DecodeError> {
+        if self.pos == self.bytes.len() {
+            return Ok(T::default());
+        }
+        read(self)
+    }
+
+    fn list<T>(
+        &mut self,
+        mut read: impl FnMut(&mut Self) -> Result<T, DecodeError>,
+    ) -> Result<Vec<T>, DecodeError> {
+        let len = self.uleb()?;
+        // Every element takes at least a byte, which bounds the allocation for corrupt lengths.
+        let mut values = Vec::with_capacity(len.min(self.bytes.len() - self.pos));
+        for _ in 0..len {
+            values.push(read(self)?);
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::BINARY_MAGIC;
+    use crate::{FunctionData, Intrinsic, Lowering, ResultRefs};
+
+    #[test]
+    fn binary_round_trip() {
+        let data = FunctionData {
+            name: "append".into(),
+            arg_indicies: vec![0, 200],
+            ret_is_extern_ref: true,
+            optional: false,
+            arg_names: vec![Some("parent".into()), None],
+            aliases: vec!["appendChild".into()],
+            module: Some("dom".into()),
+            ret_result: Some(ResultRefs {
+                ok: false,
+                err: true,
+            }),
+            callback: false,
+            arg_types: BTreeMap::from([(0, "Element".into())]),
+            export_ref: None,
+            param_count: Some(2),
+            group: Some("dom".into()),
+            intrinsic: Some(Intrinsic::RefIsNull),
+            constructor: true,
+            nullable_args: vec![200],
+            ret_nullable: true,
+            reentrant: true,
+            lowering: Lowering::Table,
+            pack_args: true,
+        };
+        let mut bytes = data.to_binary();
+        assert!(bytes.starts_with(&BINARY_MAGIC));
+        let len = bytes.len();
+
+        // Records in a section follow each other, so trailing bytes belong to the next one.
+        bytes.extend_from_slice(b"{}");
+        assert_eq!(FunctionData::from_binary(&bytes), Ok((data, len)));
+    }
+
+    #[test]
+    fn binary_errors() {
+        let data = FunctionData {
+            name: "log".into(),
+            arg_indicies: vec![0],
+            ret_is_extern_ref: false,
+            optional: false,
+            arg_names: Vec::new(),
+            aliases: Vec::new(),
+            module: None,
+            ret_result: None,
+            callback: false,
+            arg_types: BTreeMap::new(),
+            export_ref: None,
+            param_count: None,
+            group: None,
+            intrinsic: None,
+            constructor: false,
+            nullable_args: Vec::new(),
+            ret_nullable: false,
+            reentrant: false,
+            lowering: Lowering::Direct,
+            pack_args: false,
+        };
+        let bytes = data.to_binary();
+
+        let error = FunctionData::from_binary(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "record length past the end of the data at byte 5"
+        );
+        let error = FunctionData::from_binary(br#"{"name":"log"}"#).unwrap_err();
+        assert_eq!(error.to_string(), "missing magic at byte 0");
+
+        // Bytes after the known fields are skipped, as they're fields added by a newer macro.
+        let mut newer = bytes.clone();
+        newer[4] += 1;
+        newer.push(0);
+        assert_eq!(FunctionData::from_binary(&newer), Ok((data, newer.len())));
+    }
+
+    #[test]
+    fn binary_older_record() {
+        let data = FunctionData {
+            name: "log".into(),
+            arg_indicies: vec![0],
+            ret_is_extern_ref: false,
+            optional: true,
+            arg_names: vec![Some("message".into())],
+            aliases: Vec::new(),
+            module: Some("console".into()),
+            ret_result: None,
+            callback: false,
+            arg_types: BTreeMap::new(),
+            export_ref: None,
+            param_count: Some(1),
+            group: None,
+            intrinsic: None,
+            constructor: false,
+            nullable_args: Vec::new(),
+            ret_nullable: false,
+            reentrant: false,
+            lowering: Lowering::Direct,
+            pack_args: false,
+        };
+        let mut older = data.to_binary();
+
+        // A macro from before `group` ends the record after `param_count`. At their defaults the
+        // fields appended since take a byte each, except for the `direct` lowering's 7.
+        let appended = 14;
+        older.truncate(older.len() - appended);
+        older[BINARY_MAGIC.len()] -= appended as u8;
+
+        assert_eq!(FunctionData::from_binary(&older), Ok((data, older.len())));
+    }
+}
@@ -0,0 +1,64 @@
+/// The bytes every gzip compressed record starts with, the magic number of a gzip member.
+///
+/// Neither JSON nor binary records can start with `0x1f`, so compressed records can share a section
+/// with the other encodings.
+pub const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// The most bytes [decompress] produces, far more than the record of any function needs.
+///
+/// A small gzip member can expand to gigabytes, so data past this is treated as invalid rather
+/// than read into memory.
+pub const MAX_DECOMPRESSED_LEN: usize = 1 << 20;
+
+/// Compresses encoded function data into a single gzip member.
+///
+/// The member's header has no timestamp or file name, so the same data always compresses to the
+/// same bytes.
+///
+/// ```rust
+/// use externref_metadata::{compress, decompress, GZIP_MAGIC};
+///
+/// let json = br#"{"name":"log","argIndicies":[0],"retIsExternRef":false}"#;
+/// let compressed = compress(json);
+/// assert!(compressed.starts_with(&GZIP_MAGIC));
+/// assert_eq!(decompress(&compressed)?, (json.to_vec(), compressed.len()));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[cfg(feature = "compression")]
+pub fn compress(bytes: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder
+        .write_all(bytes)
+        .expect("writing to a Vec can't fail");
+    encoder.finish().expect("writing to a Vec can't fail")
+}
+
+/// Decompresses the gzip member at the start of `bytes`, returning the decompressed data and the
+/// length of the member.
+///
+/// The linker concatenates sections that share a name, so anything after the member is left
+/// alone for the next record. Members that decompress to more than [MAX_DECOMPRESSED_LEN] bytes
+/// are rejected.
+#[cfg(feature = "compression")]
+pub fn decompress(bytes: &[u8]) -> std::io::Result<(Vec<u8>, usize)> {
+    use std::io::{Error, ErrorKind, Read};
+
+    use flate2::bufread::GzDecoder;
+
+    let mut remaining = bytes;
+    let mut decompressed = Vec::new();
+    GzDecoder::new(&mut remaining)
+        .take(MAX_DECOMPRESSED_LEN as u64 + 1)
+        .read_to_end(&mut decompressed)?;
+    if decompressed.len() > MAX_DECOMPRESSED_LEN {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("decompressed data is larger than {MAX_DECOMPRESSED_LEN} bytes"),
+        ));
+    }
+    Ok((decompressed, bytes.len() - remaining.len()))
+}
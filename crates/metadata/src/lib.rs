@@ -0,0 +1,424 @@
+//! A crate describing the metadata the [externref](https://docs.rs/externref) macro embeds into
+//! WASM modules for the transformer.
+//!
+//! Each annotated function gets a custom section whose name starts with `__extern_ref_data_` and
+//! whose contents are a [FunctionData] encoded as JSON. With the `manifest` feature of the macro
+//! every function is instead described in a single `__extern_ref_manifest` section holding a JSON
+//! array of [FunctionData], with [FunctionData::module] set for imports. The linker concatenates the
+//! arrays of every crate.
+//!
+//! A function can also be described in the [binary encoding](FunctionData::to_binary) with the
+//! macro's `encoding = "binary"` option. Binary records start with [BINARY_MAGIC], so readers can
+//! decode sections holding records of both encodings.
+//!
+//! With `encoding = "gzip"` the JSON is compressed with gzip, and the compressed records start with
+//! [GZIP_MAGIC]. Compressing and decompressing them needs this crate's `compression` feature.
+//!
+//! ```rust
+//! use externref_metadata::FunctionData;
+//!
+//! let section = br#"{"name":"log","argIndicies":[0],"retIsExternRef":false}"#;
+//! let data: FunctionData = serde_json::from_slice(section)?;
+//!
+//! assert_eq!(data.name, "log");
+//! assert_eq!(data.arg_indicies, &[0]);
+//! assert!(!data.ret_is_extern_ref);
+//! # Ok::<(), serde_json::Error>(())
+//! ```
+//!
+//! The section describing a function can be found without running the macro with
+//! [data_section_name].
+//!
+//! With the `schema` feature, [json_schema] generates the JSON Schema of the JSON encoding, for
+//! tools that validate data sections without using this crate.
+#![forbid(missing_docs)]
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+mod binary;
+mod compression;
+#[cfg(feature = "schema")]
+mod schema;
+
+pub use binary::{DecodeError, Encoding, BINARY_MAGIC};
+#[cfg(feature = "compression")]
+pub use compression::{compress, decompress};
+pub use compression::{GZIP_MAGIC, MAX_DECOMPRESSED_LEN};
+#[cfg(feature = "schema")]
+pub use schema::json_schema;
+
+/// The prefix of every custom section describing a single function.
+pub const SECTION_PREFIX: &str = "__extern_ref_data_";
+
+/// The section every function is described in when the macro's `manifest` feature is enabled.
+pub const MANIFEST_SECTION: &str = "__extern_ref_manifest";
+
+/// The module intrinsic imports are declared in. The transformer replaces every import of it that
+/// has an [Intrinsic] with a local function, so the host never sees the module.
+pub const INTRINSIC_MODULE: &str = "__externref_intrinsic";
+
+/// The name of the custom section describing a function, the same as the `link_section` the macro
+/// emits.
+///
+/// Imports pass the module they're imported from, before any placeholders are substituted, and
+/// exports pass `None`. `name` is the name of the import or export, after any `name` option. The
+/// names are used as is, so a module like `wasi:io/streams` appears unchanged in the section name.
+///
+/// ```rust
+/// use externref_metadata::data_section_name;
+///
+/// assert_eq!(data_section_name(Some("console"), "log"), "__extern_ref_data_console_log");
+/// assert_eq!(data_section_name(None, "run"), "__extern_ref_data_run");
+/// ```
+pub fn data_section_name(module: Option<&str>, name: &str) -> String {
+    match module {
+        Some(module) => format!("{SECTION_PREFIX}{module}_{name}"),
+        None => format!("{SECTION_PREFIX}{name}"),
+    }
+}
+
+/// The name of the custom section describing a function tagged with a `group`, which puts the
+/// group before the name [data_section_name] would give it.
+///
+/// The group is read back from the section's data, so the name doesn't need to be split apart.
+///
+/// ```rust
+/// use externref_metadata::group_section_name;
+///
+/// assert_eq!(
+///     group_section_name("dom", Some("console"), "log"),
+///     "__extern_ref_data_dom@console_log"
+/// );
+/// assert_eq!(group_section_name("dom", None, "run"), "__extern_ref_data_dom@run");
+/// ```
+pub fn group_section_name(group: &str, module: Option<&str>, name: &str) -> String {
+    let ungrouped = data_section_name(module, name);
+    format!(
+        "{SECTION_PREFIX}{group}@{}",
+        &ungrouped[SECTION_PREFIX.len()..]
+    )
+}
+
+/// Information about an imported or exported function that uses `externref`s.
+///
+/// The serialized field names are part of the format baked into existing modules and must not
+/// change. Fields added after the initial format are omitted when they have their default value
+/// so older readers keep working.
+///
+/// Fields are serialized in the order they're declared, and the bytes end up in the module, so
+/// reordering them changes the output of otherwise identical builds. New fields go at the end.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "camelCase")]
+pub struct FunctionData {
+    /// The name of the function as it appears in the transformed WASM binary.
+    pub name: String,
+    /// The indicies of arguments that should have the type `externref`.
+    ///
+    /// This is written as `argIndicies` for compatibility with existing modules, but the
+    /// corrected `argIndices` spelling is accepted when reading.
+    #[serde(alias = "argIndices")]
+    pub arg_indicies: Vec<usize>,
+    /// If the return type is an `externref`.
+    pub ret_is_extern_ref: bool,
+    /// If the function is an import that the host may not provide.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub optional: bool,
+    /// The name of each argument, or `None` when it isn't bound to a plain identifier.
+    ///
+    /// Glue generators can use these to produce readable signatures.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub arg_names: Vec<Option<String>>,
+    /// Additional names an exported function is exported under.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// The module an imported function is imported from.
+    ///
+    /// This is only recorded in the manifest section, as a data section's name already includes
+    /// the module.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    /// Which variants are `externref`s when an exported function returns a `Result`.
+    ///
+    /// A `Result` is returned as two values, an `i32` tag that is `0` for `Ok` and `1` for `Err`
+    /// followed by the handle of the active variant. The handle is replaced with an `externref`,
+    /// which is `null` when the active variant isn't an `externref`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ret_result: Option<ResultRefs>,
+    /// If the function is a callback the host calls through a function table rather than an
+    /// export.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub callback: bool,
+    /// The host type of `externref` arguments, keyed by the argument's index, e.g. `DomNode`.
+    ///
+    /// The types are informational, for glue generators to produce precise types. The transformer
+    /// doesn't change how the arguments are encoded based on them.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub arg_types: BTreeMap<usize, String>,
+    /// The export whose function reference an imported intrinsic returns.
+    ///
+    /// The transformer replaces such an import with a local function that takes a `ref.func` of
+    /// the export instead of asking the host for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export_ref: Option<String>,
+    /// The number of parameters the function was declared with.
+    ///
+    /// The transformer warns when the function it matched has a different number, which usually
+    /// means the data describes another function with the same name. It still rewrites the
+    /// function, as this is the Rust arity, which ABI lowering of e.g. `u128` arguments changes.
+    /// This is `None` for modules built before it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub param_count: Option<usize>,
+    /// The feature area the function was tagged with by the macro's `group` option, e.g. `dom`.
+    ///
+    /// A grouped function's data section is named with [group_section_name], so tools can find a
+    /// group's functions by section name, and the transformer reports the group of every function
+    /// it rewrites.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// The intrinsic an import from [INTRINSIC_MODULE] stands for.
+    ///
+    /// The transformer synthesizes the body of such an import from wasm instructions instead of
+    /// asking the host for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intrinsic: Option<Intrinsic>,
+    /// If the import is a host constructor returning a new object, e.g. `new Foo(...)` in
+    /// JavaScript.
+    ///
+    /// This only documents intent for glue generators, the transformer rewrites the returned
+    /// `externref` like any other.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub constructor: bool,
+    /// The indicies of `externref` arguments that may be `null`.
+    ///
+    /// Every other `externref` argument must be a non-null reference. The transformer only checks
+    /// this when asked to, trapping on a `null` passed where it isn't allowed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nullable_args: Vec<usize>,
+    /// If a returned `externref` may be `null`.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub ret_nullable: bool,
+    /// If the host may call back into the module while the function runs, e.g. an import that
+    /// dispatches events to exports before it returns, or if the host calls the function itself
+    /// from within another call.
+    ///
+    /// This documents intent for glue generators and tools. The functions the transformer adds
+    /// keep no state between calls, and the externref table is only ever grown, so reentrant
+    /// calls are rewritten like any other.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub reentrant: bool,
+    /// How the transformer passes the function's `externref`s to and from the host.
+    #[serde(default, skip_serializing_if = "Lowering::is_direct")]
+    pub lowering: Lowering,
+    /// If the `externref` arguments are moved to the end of the signature the host sees, so glue
+    /// code can pass them as a single array.
+    ///
+    /// The other arguments keep their order and come first, followed by the `externref`s in the
+    /// order of [FunctionData::arg_indicies], which still holds their indicies in the Rust
+    /// signature. A glue generator collects the trailing references into one array argument, e.g.
+    /// spreading it in JavaScript. Without reference types in the signature, e.g. with table
+    /// lowering, the arguments aren't moved.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub pack_args: bool,
+}
+
+/// Which variants of a returned `Result` are `externref`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ResultRefs {
+    /// If the `Ok` variant is an `externref`.
+    pub ok: bool,
+    /// If the `Err` variant is an `externref`.
+    pub err: bool,
+}
+
+/// An operation on references that the transformer implements with wasm instructions rather than
+/// an import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Intrinsic {
+    /// `() -> handle`, returns the handle of `ref.null extern`.
+    #[serde(rename = "ref.null")]
+    RefNull,
+    /// `(handle) -> i32`, checks the reference a handle points at with `ref.is_null`.
+    #[serde(rename = "ref.is_null")]
+    RefIsNull,
+    /// `() -> i32`, returns `1` once the module is transformed, while an untransformed module
+    /// still imports it.
+    #[serde(rename = "transformed")]
+    Transformed,
+}
+
+impl Intrinsic {
+    /// The name of the intrinsic, which is also the instruction it's implemented with, if there is
+    /// one.
+    ///
+    /// ```rust
+    /// use externref_metadata::Intrinsic;
+    ///
+    /// assert_eq!(Intrinsic::RefIsNull.name(), "ref.is_null");
+    /// assert_eq!(Intrinsic::from_name("ref.null"), Some(Intrinsic::RefNull));
+    /// ```
+    pub fn name(self) -> &'static str {
+        match self {
+            Intrinsic::RefNull => "ref.null",
+            Intrinsic::RefIsNull => "ref.is_null",
+            Intrinsic::Transformed => "transformed",
+        }
+    }
+
+    /// The intrinsic with a [name](Intrinsic::name), if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ref.null" => Some(Intrinsic::RefNull),
+            "ref.is_null" => Some(Intrinsic::RefIsNull),
+            "transformed" => Some(Intrinsic::Transformed),
+            _ => None,
+        }
+    }
+}
+
+/// How the `externref`s of a function are passed between the module and the host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Lowering {
+    /// The signature is rewritten to take and return `externref`s, which needs the host to support
+    /// reference types in function signatures.
+    #[default]
+    Direct,
+    /// The signature keeps its `i32` handles, which index the module's exported externref table.
+    /// The host reads the references the module passes from the table and stores the ones it
+    /// passes in by growing the table, e.g. with `WebAssembly.Table.prototype.grow` in JavaScript.
+    Table,
+}
+
+impl Lowering {
+    /// The name of the lowering, as given to the macro's `lowering` option.
+    ///
+    /// ```rust
+    /// use externref_metadata::Lowering;
+    ///
+    /// assert_eq!(Lowering::Table.name(), "table");
+    /// assert_eq!(Lowering::from_name("direct"), Some(Lowering::Direct));
+    /// ```
+    pub fn name(self) -> &'static str {
+        match self {
+            Lowering::Direct => "direct",
+            Lowering::Table => "table",
+        }
+    }
+
+    /// The lowering with a [name](Lowering::name), if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "direct" => Some(Lowering::Direct),
+            "table" => Some(Lowering::Table),
+            _ => None,
+        }
+    }
+
+    fn is_direct(&self) -> bool {
+        *self == Lowering::Direct
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{FunctionData, Intrinsic, Lowering, ResultRefs};
+
+    #[test]
+    fn accepts_both_arg_indicies_spellings() -> serde_json::Result<()> {
+        let legacy: FunctionData =
+            serde_json::from_str(r#"{"name":"log","argIndicies":[0,2],"retIsExternRef":true}"#)?;
+        let corrected: FunctionData =
+            serde_json::from_str(r#"{"name":"log","argIndices":[0,2],"retIsExternRef":true}"#)?;
+        assert_eq!(legacy, corrected);
+        assert_eq!(legacy.arg_indicies, &[0, 2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn writes_legacy_spelling() -> serde_json::Result<()> {
+        let data = FunctionData {
+            name: "log".into(),
+            arg_indicies: vec![1],
+            ret_is_extern_ref: false,
+            optional: false,
+            arg_names: Vec::new(),
+            aliases: Vec::new(),
+            module: None,
+            ret_result: None,
+            callback: false,
+            arg_types: BTreeMap::new(),
+            export_ref: None,
+            param_count: None,
+            group: None,
+            intrinsic: None,
+            constructor: false,
+            nullable_args: Vec::new(),
+            ret_nullable: false,
+            reentrant: false,
+            lowering: Lowering::Direct,
+            pack_args: false,
+        };
+        assert_eq!(
+            serde_json::to_string(&data)?,
+            r#"{"name":"log","argIndicies":[1],"retIsExternRef":false}"#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn serialized_field_order() -> serde_json::Result<()> {
+        let data = FunctionData {
+            name: "append".into(),
+            arg_indicies: vec![0, 1],
+            ret_is_extern_ref: true,
+            optional: true,
+            arg_names: vec![Some("parent".into()), None],
+            aliases: vec!["appendChild".into()],
+            module: Some("dom".into()),
+            ret_result: Some(ResultRefs {
+                ok: true,
+                err: false,
+            }),
+            callback: true,
+            arg_types: BTreeMap::from([(1, "Node".into()), (0, "Element".into())]),
+            export_ref: Some("onAppend".into()),
+            param_count: Some(2),
+            group: Some("dom".into()),
+            intrinsic: Some(Intrinsic::RefNull),
+            constructor: true,
+            nullable_args: vec![1],
+            ret_nullable: true,
+            reentrant: true,
+            lowering: Lowering::Table,
+            pack_args: true,
+        };
+        assert_eq!(
+            serde_json::to_string(&data)?,
+            concat!(
+                r#"{"name":"append","argIndicies":[0,1],"retIsExternRef":true,"optional":true,"#,
+                r#""argNames":["parent",null],"aliases":["appendChild"],"module":"dom","#,
+                r#""retResult":{"ok":true,"err":false},"callback":true,"#,
+                r#""argTypes":{"0":"Element","1":"Node"},"exportRef":"onAppend","paramCount":2,"#,
+                r#""group":"dom","intrinsic":"ref.null","constructor":true,"nullableArgs":[1],"#,
+                r#""retNullable":true,"reentrant":true,"lowering":"table","#,
+                r#""packArgs":true}"#,
+            )
+        );
+
+        Ok(())
+    }
+}
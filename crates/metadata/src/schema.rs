@@ -0,0 +1,78 @@
+use crate::FunctionData;
+
+/// The JSON Schema of a [FunctionData] record in the JSON encoding, for tools that validate the
+/// contents of data sections without using this crate.
+///
+/// The schema is generated from [FunctionData] itself, so it always describes the records this
+/// version of the macro writes. The format has no version field, fields are only ever added and
+/// are optional, so records written by older versions still match. The corrected `argIndices`
+/// spelling that readers also accept isn't part of it, as it's never written.
+///
+/// ```rust
+/// let schema = externref_metadata::json_schema();
+/// assert_eq!(schema["title"], "FunctionData");
+/// assert_eq!(schema["required"], serde_json::json!(["name", "argIndicies", "retIsExternRef"]));
+/// ```
+pub fn json_schema() -> serde_json::Value {
+    schemars::schema_for!(FunctionData).to_value()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use super::json_schema;
+    use crate::{FunctionData, Intrinsic, Lowering, ResultRefs};
+
+    #[test]
+    fn records_match_schema() -> Result<(), Box<dyn std::error::Error>> {
+        let validator = jsonschema::validator_for(&json_schema())?;
+
+        let minimal = json!({"name": "log", "argIndicies": [0], "retIsExternRef": false});
+        validator
+            .validate(&minimal)
+            .map_err(|error| error.to_string())?;
+        let full = FunctionData {
+            name: "append".into(),
+            arg_indicies: vec![0, 1],
+            ret_is_extern_ref: true,
+            optional: true,
+            arg_names: vec![Some("parent".into()), None],
+            aliases: vec!["appendChild".into()],
+            module: Some("dom".into()),
+            ret_result: Some(ResultRefs {
+                ok: true,
+                err: false,
+            }),
+            callback: true,
+            arg_types: BTreeMap::from([(0, "Element".into())]),
+            export_ref: Some("onAppend".into()),
+            param_count: Some(2),
+            group: Some("dom".into()),
+            intrinsic: Some(Intrinsic::RefIsNull),
+            constructor: true,
+            nullable_args: vec![1],
+            ret_nullable: true,
+            reentrant: true,
+            lowering: Lowering::Table,
+            pack_args: true,
+        };
+        let full = serde_json::to_value(&full)?;
+        validator
+            .validate(&full)
+            .map_err(|error| error.to_string())?;
+
+        for invalid in [
+            json!({"argIndicies": [0], "retIsExternRef": false}),
+            json!({"name": "log", "argIndicies": [-1], "retIsExternRef": false}),
+            json!({"name": "log", "argIndicies": [], "retIsExternRef": false, "intrinsic": "ref.func"}),
+            json!({"name": "log", "argIndicies": [], "retIsExternRef": false, "lowering": "memory"}),
+        ] {
+            assert!(!validator.is_valid(&invalid), "{invalid}");
+        }
+
+        Ok(())
+    }
+}
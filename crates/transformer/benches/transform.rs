@@ -0,0 +1,50 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use externref_transformer::{transform_with_config, Config};
+
+/// Builds a module with `count` externref imports and exports.
+fn module_with_functions(count: usize) -> Vec<u8> {
+    let mut wat = String::from("(module\n");
+    for i in 0..count {
+        wat.push_str(&format!(
+            r#"(import "env" "f{i}" (func (param i32 i32) (result i32)))
+            (@custom "__extern_ref_data_env_f{i}"
+                "{{\"name\":\"f{i}\",\"argIndicies\":[0,1],\"retIsExternRef\":true}}")
+            "#
+        ));
+    }
+    for i in 0..count {
+        wat.push_str(&format!(
+            r#"(func (export "g{i}") (param i32 i32) (result i32) local.get 0)
+            (@custom "__extern_ref_data_g{i}"
+                "{{\"name\":\"g{i}\",\"argIndicies\":[0,1],\"retIsExternRef\":true}}")
+            "#
+        ));
+    }
+    wat.push(')');
+
+    wat::parse_str(&wat).expect("invalid benchmark module")
+}
+
+fn transform_threads(c: &mut Criterion) {
+    let mut group = c.benchmark_group("transform");
+
+    for count in [100, 1000, 5000] {
+        let wasm = module_with_functions(count);
+
+        for threads in [1, 0] {
+            let config = Config {
+                threads,
+                ..Default::default()
+            };
+            let id = BenchmarkId::new(format!("threads={threads}"), count);
+            group.bench_with_input(id, &wasm, |b, wasm| {
+                b.iter(|| transform_with_config(wasm, &config).unwrap())
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, transform_threads);
+criterion_main!(benches);
@@ -0,0 +1,37 @@
+//! Feeds arbitrary bytes to the transformer as the body of an externref data section.
+//!
+//! Run with `cargo fuzz run section` from `crates/transformer`.
+#![no_main]
+
+use externref_transformer::{
+    read_metadata, transform_module,
+    walrus::{FunctionBuilder, Module, RawCustomSection, ValType},
+};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // An import and an export the section can describe, so valid data reaches the rewrites.
+    let mut module = Module::default();
+    let ty = module.types.add(&[ValType::I32], &[ValType::I32]);
+    let (log, _) = module.add_import_func("console", "log", ty);
+    let mut builder = FunctionBuilder::new(&mut module.types, &[ValType::I32], &[ValType::I32]);
+    let arg = module.locals.add(ValType::I32);
+    builder.func_body().local_get(arg).call(log);
+    let run = builder.finish(vec![arg], &mut module.funcs);
+    module.exports.add("run", run);
+
+    for name in ["__extern_ref_data_console_log", "__extern_ref_data_run"] {
+        module.customs.add(RawCustomSection {
+            name: name.to_string(),
+            data: data.to_vec(),
+        });
+    }
+
+    // Data that doesn't decode must be reported rather than ignored. The reader decodes the
+    // sections the same way as the transform, whether the records are JSON, binary or compressed.
+    let invalid = read_metadata(&module.emit_wasm()).is_err();
+    let result = transform_module(&mut module);
+    if invalid {
+        assert!(result.is_err(), "invalid data was accepted: {data:?}");
+    }
+});
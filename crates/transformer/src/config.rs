@@ -0,0 +1,164 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::host::HostSignatures;
+
+/// Options controlling how a module is transformed.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Values for placeholders in import module names, keyed by the placeholder's name.
+    ///
+    /// A placeholder is written as `${NAME}` anywhere in a module name, e.g. an import declared
+    /// with `#[externref(name = "${HOST}")]` is imported from `browserEnv` when `HOST` maps to
+    /// `browserEnv`. This allows a single build to target hosts that use different module names.
+    /// Transforming a module with a placeholder that has no value is an error.
+    pub substitutions: HashMap<String, String>,
+    /// Imports the host doesn't provide, written as `module.name` after placeholder substitution.
+    ///
+    /// Imports marked with `#[externref(optional)]` in this set are replaced with a stub that traps
+    /// when called, named after the missing import so it shows up in stack traces. A required
+    /// import in this set is an error.
+    pub missing_imports: HashSet<String>,
+    /// Functions to leave untouched, written as `module.name` for imports, after placeholder
+    /// substitution, and by their name for exports and callbacks.
+    ///
+    /// Their data sections are still removed, but their signatures keep the `usize` handles, e.g.
+    /// for functions another pass takes care of.
+    pub skip: HashSet<String>,
+    /// The number of threads used to plan function rewrites, `0` uses one thread per core.
+    ///
+    /// The output is identical regardless of the number of threads.
+    pub threads: usize,
+    /// The signatures of the functions the host provides.
+    ///
+    /// When set, every rewritten import must be declared with a matching signature, catching
+    /// drift between the module and its host at transform time rather than when instantiating.
+    pub host_signatures: Option<HostSignatures>,
+    /// The proposal the reference types in the transformed module are encoded for.
+    pub proposal: Proposal,
+    /// Removes the `name` section from the transformed module.
+    ///
+    /// By default every rewritten function and the functions added in its place are named after
+    /// the import or export they describe, keeping stack traces readable.
+    pub strip_names: bool,
+    /// The number of slots the externref table starts with.
+    ///
+    /// The table is grown by a slot whenever the host hands the module a new reference, so this
+    /// only avoids the first few `table.grow`s. Slot `0` is reserved for `null`, so the table
+    /// always has at least one slot.
+    pub table_initial: u32,
+    /// The most slots the externref table can grow to, or unbounded when `None`.
+    ///
+    /// Receiving a reference once the table is full traps.
+    pub table_maximum: Option<u32>,
+    /// How references are split between externref tables.
+    pub table_strategy: TableStrategy,
+    /// Exports the externref table under this name, e.g. `externref_table`, so the host can read
+    /// and write the references directly.
+    ///
+    /// With [TableStrategy::PerModule] the table of each import module is exported too, named
+    /// after this name and the import module, e.g. `externref_table_console`. Nothing is exported
+    /// when no function was rewritten, as there's no table.
+    ///
+    /// Functions the macro's `lowering = "table"` option lowers through the table keep their `i32`
+    /// handles, so the host needs the table to resolve them, and transforming them without it is
+    /// an error.
+    pub export_table: Option<String>,
+    /// Exports the module's memory under this name, e.g. `memory`, unless it's already exported
+    /// under it.
+    ///
+    /// The module must define or import a memory, and nothing else can be exported under the name.
+    pub export_memory: Option<String>,
+    /// Calls `__externref.trace` with the name of every rewritten import before calling it.
+    ///
+    /// The host receives a pointer to the import's name, e.g. `console.log`, and its length in
+    /// bytes, which it can read from the module's first memory. The names are stored in pages
+    /// added after the memory's initial pages, so the memory must be defined by the module. Every
+    /// traced call makes an extra call to the host, so this is meant for debugging and shouldn't
+    /// be used for release builds.
+    pub trace_imports: bool,
+    /// Traps when a `null` reference is passed or returned where the function data doesn't allow
+    /// one, i.e. an `externref` argument that isn't in `nullable_args` or a returned `externref`
+    /// without `ret_nullable`.
+    ///
+    /// The shims of rewritten imports check the references the module passes to the host and the
+    /// host returns, while the wrappers of exports and callbacks check the ones the host passes in
+    /// and the module returns. The references in a returned `Result` aren't checked. Every check
+    /// is a call, so this is meant for debugging and shouldn't be used for release builds.
+    pub check_nulls: bool,
+    /// Keeps references as `i32` handles instead of rewriting them to `externref`s, for runtimes
+    /// without reference types.
+    ///
+    /// The data sections are still read and removed, but no signature changes and no table is
+    /// added. Instead the module exports `__externref_alloc`, which takes no arguments and returns
+    /// a new handle, starting at `1` as `0` is `null`. The host stores each reference it passes in
+    /// under such a handle and looks handles up itself, much like `wasm-bindgen`'s heap. The
+    /// references are only as alive as the host's storage keeps them, so there's no integration
+    /// with the engine's garbage collector, and the host has to free them itself.
+    ///
+    /// [Config::table_maximum] limits the number of handles, and the other table options are
+    /// ignored. `export_ref!` can't be lowered, and host signatures, traced imports, null checks
+    /// and an exported table describe references the module doesn't have, so they're errors.
+    pub lower_to_i32: bool,
+    /// Copies the helpers moving references in and out of the externref table into every function
+    /// calling them, instead of keeping them as separate functions.
+    ///
+    /// Each rewritten import, export and callback saves a call per reference it passes, in exchange
+    /// for a larger module, as every call site gets its own copy. On a module with 6 calls to the
+    /// helpers, inlining added 40 bytes with [TableStrategy::Single] and about 100 bytes with
+    /// [TableStrategy::PerModule], whose helpers are larger and call each other.
+    /// Engines that inline small functions themselves won't see a difference, so this is mostly
+    /// useful for interpreters and baseline compilers.
+    pub inline_accessors: bool,
+    /// Initializes every mutable `externref` global the module defines to `ref.null extern`.
+    ///
+    /// Globals holding `externref`s, e.g. from hand-written wat linked into the module, are kept
+    /// as they are, since they already hold references rather than handles. With this they start
+    /// out as null instead of whatever their initializer reads, e.g. an imported global, for
+    /// modules that set them at runtime. Immutable globals keep their initializer, as nothing
+    /// could set them otherwise. Modules with `externref` globals can't be lowered with
+    /// [Config::lower_to_i32], as they need reference types either way.
+    pub null_externref_globals: bool,
+    /// Makes the output stable for diffing, so builds of the same source produce identical bytes.
+    ///
+    /// Once the module is rewritten, local functions are ordered by their names, imports by their
+    /// module and name, and exports by their name, so the order the linker happened to emit them
+    /// in doesn't matter. Every custom section is removed, including the `producers` section and
+    /// the transformer's own `processed-by` entry, as they record details of the build like tool
+    /// versions rather than the module's behavior. The `name` section is kept unless
+    /// [Config::strip_names] is set.
+    pub normalize: bool,
+}
+
+/// The WASM proposal providing the reference types of the transformed module, for runtimes that
+/// only support one of them.
+///
+/// Both modes use the same instructions, `table.get`, `table.grow`, `ref.null` and `ref.is_null`
+/// from `reference-types`, and only differ in how the types are encoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Proposal {
+    /// `reference-types`, where rewritten signatures and the externref table use the `externref`
+    /// and `funcref` shorthands.
+    #[default]
+    ReferenceTypes,
+    /// `function-references`, where every reference type in the type and table sections is written
+    /// as a nullable typed reference, e.g. `(ref null extern)`.
+    FunctionReferences,
+}
+
+/// How the references a module receives are split between externref tables.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TableStrategy {
+    /// A single table holds every reference, and handles are indicies into it.
+    #[default]
+    Single,
+    /// References returned by imports are stored in a table for the import's module, after
+    /// placeholder substitution, and every other reference in a default table.
+    ///
+    /// This keeps the references of each host namespace apart, e.g. to inspect or drop them
+    /// together from the host. Handles can still be passed to any import, as the top 8 bits of a
+    /// handle hold the index of its table and the other 24 its slot. In exchange, loading a
+    /// reference compares the index against each table, there can be at most 255 import modules,
+    /// and each table is limited to 2^24 slots, which lowers [Config::table_maximum] if it's
+    /// larger or unbounded.
+    PerModule,
+}
@@ -0,0 +1,85 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Result};
+use externref_metadata::FunctionData;
+use walrus::{FunctionId, Module};
+
+use crate::{
+    exports, metadata::export_section_name, null_check::NullCheck, shim::Step,
+    signature::ExternRefSignature, table::ExternRefTable,
+};
+
+/// Finds the functions referenced from element segments that have a data section.
+///
+/// These functions are called indirectly, e.g. callbacks registered with the host, so they're
+/// matched by their name in the `name` section rather than by an export. This requires the name
+/// section to be present and the function's symbol to be unmangled, e.g. with `#[no_mangle]`.
+pub(crate) fn find<'a>(
+    module: &Module,
+    sections: &'a HashMap<String, FunctionData>,
+) -> Vec<(FunctionId, &'a FunctionData)> {
+    let mut seen = HashSet::new();
+
+    module
+        .elements
+        .iter()
+        .flat_map(|element| element.members.iter().flatten())
+        .filter(|func_id| seen.insert(**func_id))
+        .filter_map(|func_id| {
+            let name = module.funcs.get(*func_id).name.as_deref()?;
+            let data = sections.get(&export_section_name(name))?;
+            Some((*func_id, data))
+        })
+        .collect()
+}
+
+/// Checks that every function marked as a callback was found in a function table, as the host
+/// couldn't call it with `externref`s otherwise.
+pub(crate) fn check_callbacks(
+    sections: &HashMap<String, FunctionData>,
+    found: &[(FunctionId, &FunctionData)],
+) -> Result<()> {
+    for data in sections.values().filter(|data| data.callback) {
+        if !found.iter().any(|(_, found)| std::ptr::eq(*found, data)) {
+            bail!(
+                "callback {} isn't in a function table, or its name is missing from the name \
+                 section",
+                data.name
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces every element segment entry of an indirectly called function with a wrapper that
+/// takes and returns `externref`s.
+///
+/// Indirect calls from within the module with the handle based signature will trap once the
+/// module is transformed, as the table entry's type no longer matches.
+pub(crate) fn rewrite(
+    module: &mut Module,
+    table: &ExternRefTable,
+    func_id: FunctionId,
+    signature: &ExternRefSignature,
+    steps: &[Step],
+    null_check: Option<&NullCheck>,
+) {
+    let name = module.funcs.get(func_id).name.clone().unwrap_or_default();
+    log::debug!(
+        "rewriting indirect function {name}: {:?} -> {:?} to {:?} -> {:?}",
+        signature.original_params,
+        signature.original_results,
+        signature.params,
+        signature.results
+    );
+
+    let wrapper = exports::wrap(module, table, func_id, &name, signature, steps, null_check);
+    for element in module.elements.iter_mut() {
+        for member in element.members.iter_mut().flatten() {
+            if *member == func_id {
+                *member = wrapper;
+            }
+        }
+    }
+}
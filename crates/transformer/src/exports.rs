@@ -0,0 +1,99 @@
+use anyhow::{bail, Result};
+use walrus::{ExportId, ExportItem, FunctionBuilder, FunctionId, Module};
+
+use crate::{
+    null_check::NullCheck,
+    shim::{self, Callees, Step},
+    signature::ExternRefSignature,
+    table::ExternRefTable,
+};
+
+/// Rewrites an exported function so the host passes and receives `externref`s.
+///
+/// A wrapper with the `externref` signature is exported in place of the original function. The
+/// wrapper stores each reference it's given in the table, calls the original function with the
+/// resulting handles and loads any returned handle back out of the table.
+///
+/// The wrapper is also exported under each of the `aliases`.
+pub(crate) fn rewrite(
+    module: &mut Module,
+    table: &ExternRefTable,
+    export_id: ExportId,
+    signature: &ExternRefSignature,
+    steps: &[Step],
+    aliases: &[String],
+    null_check: Option<&NullCheck>,
+) -> Result<()> {
+    let export = module.exports.get(export_id);
+    let name = export.name.clone();
+    let func_id = match export.item {
+        ExportItem::Function(func_id) => func_id,
+        _ => bail!("export {name} is not a function"),
+    };
+
+    let (params, results) = (&signature.original_params, &signature.original_results);
+    log::debug!(
+        "rewriting export {name}: {params:?} -> {results:?} to {:?} -> {:?}",
+        signature.params,
+        signature.results
+    );
+
+    // The original function is named after the export when the name section doesn't name it, so
+    // stack traces through the wrapper stay readable.
+    module
+        .funcs
+        .get_mut(func_id)
+        .name
+        .get_or_insert_with(|| name.clone());
+
+    let wrapper = wrap(module, table, func_id, &name, signature, steps, null_check);
+    module.exports.get_mut(export_id).item = ExportItem::Function(wrapper);
+
+    for alias in aliases {
+        if module.exports.iter().any(|export| export.name == *alias) {
+            bail!("alias {alias} of export {name} is already exported");
+        }
+        log::debug!("exporting {name} as {alias}");
+        module.exports.add(alias, wrapper);
+    }
+
+    Ok(())
+}
+
+/// Creates a function with the `externref` signature that calls a function with the handle based
+/// signature, for the host to call in its place, from the steps planned for it. With a [NullCheck]
+/// it checks the references that can't be `null`.
+pub(crate) fn wrap(
+    module: &mut Module,
+    table: &ExternRefTable,
+    func_id: FunctionId,
+    name: &str,
+    signature: &ExternRefSignature,
+    steps: &[Step],
+    null_check: Option<&NullCheck>,
+) -> FunctionId {
+    let mut builder =
+        FunctionBuilder::new(&mut module.types, &signature.params, &signature.results);
+    builder.name(format!("{name} externref shim"));
+    let args: Vec<_> = signature
+        .params
+        .iter()
+        .map(|ty| module.locals.add(*ty))
+        .collect();
+
+    let callees = Callees {
+        table,
+        func: func_id,
+        tracer: None,
+        null_check,
+    };
+    shim::emit(
+        steps,
+        &mut builder.func_body(),
+        &mut module.locals,
+        &args,
+        &callees,
+    );
+
+    builder.finish(args, &mut module.funcs)
+}
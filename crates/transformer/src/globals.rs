@@ -0,0 +1,54 @@
+use anyhow::{bail, Result};
+use walrus::{GlobalId, GlobalKind, InitExpr, Module, ValType};
+
+/// The globals of the module holding an `externref`, e.g. from hand-written wat linked into it.
+///
+/// The transformer leaves them alone, as they already hold real references rather than handles.
+fn externref_globals(module: &Module) -> Vec<GlobalId> {
+    module
+        .globals
+        .iter()
+        .filter(|global| global.ty == ValType::Externref)
+        .map(|global| global.id())
+        .collect()
+}
+
+/// Fails when the module has an `externref` global, as lowering to `i32` handles is meant for
+/// runtimes that can't load one.
+pub(crate) fn check_lowered(module: &Module) -> Result<()> {
+    if let Some(id) = externref_globals(module).first() {
+        let name = module
+            .globals
+            .get(*id)
+            .name
+            .as_deref()
+            .unwrap_or("<unnamed>");
+        bail!(
+            "global {name} holds an externref, so the module needs reference types even when \
+             lowering to i32 handles"
+        );
+    }
+    Ok(())
+}
+
+/// Initializes every mutable `externref` global the module defines to `ref.null extern`,
+/// returning how many were changed.
+///
+/// Immutable globals keep their initializer, as nothing could set them to anything else.
+pub(crate) fn initialize_to_null(module: &mut Module) -> usize {
+    let mut initialized = 0;
+    for id in externref_globals(module) {
+        let global = module.globals.get_mut(id);
+        if !global.mutable {
+            continue;
+        }
+        if let GlobalKind::Local(init) = &mut global.kind {
+            if !matches!(init, InitExpr::RefNull(_)) {
+                *init = InitExpr::RefNull(ValType::Externref);
+                initialized += 1;
+            }
+        }
+    }
+    log::debug!("initialized {initialized} externref globals to null");
+    initialized
+}
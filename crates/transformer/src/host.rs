@@ -0,0 +1,108 @@
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use walrus::ValType;
+
+use crate::signature::ExternRefSignature;
+
+/// The signatures of the functions a host provides, used to check the rewritten imports against
+/// before the module ever runs.
+///
+/// This is usually read from a JSON file shaped like:
+///
+/// ```json
+/// {
+///   "imports": [
+///     { "module": "console", "name": "log", "params": ["externref"], "results": [] },
+///     { "module": "dom", "name": "createElement", "params": ["externref"], "results": ["externref"] }
+///   ]
+/// }
+/// ```
+///
+/// Module names are compared after placeholder substitution.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostSignatures {
+    /// The functions the host provides.
+    pub imports: Vec<HostSignature>,
+}
+
+/// The signature of a single function provided by the host.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostSignature {
+    /// The module the function is imported from.
+    pub module: String,
+    /// The name the function is imported as.
+    pub name: String,
+    /// The parameter types.
+    #[serde(default)]
+    pub params: Vec<HostValType>,
+    /// The result types.
+    #[serde(default)]
+    pub results: Vec<HostValType>,
+}
+
+/// A value type in a [HostSignature], written in lowercase like in the text format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HostValType {
+    /// A 32-bit integer.
+    I32,
+    /// A 64-bit integer.
+    I64,
+    /// A 32-bit float.
+    F32,
+    /// A 64-bit float.
+    F64,
+    /// A 128-bit vector.
+    V128,
+    /// A reference to a host object.
+    Externref,
+    /// A reference to a function.
+    Funcref,
+}
+
+impl From<HostValType> for ValType {
+    fn from(ty: HostValType) -> Self {
+        match ty {
+            HostValType::I32 => ValType::I32,
+            HostValType::I64 => ValType::I64,
+            HostValType::F32 => ValType::F32,
+            HostValType::F64 => ValType::F64,
+            HostValType::V128 => ValType::V128,
+            HostValType::Externref => ValType::Externref,
+            HostValType::Funcref => ValType::Funcref,
+        }
+    }
+}
+
+impl HostSignatures {
+    /// Checks that the rewritten signature of the import `module.name` matches the host's.
+    pub(crate) fn check(
+        &self,
+        module: &str,
+        name: &str,
+        signature: &ExternRefSignature,
+    ) -> Result<()> {
+        let Some(host) = self
+            .imports
+            .iter()
+            .find(|host| host.module == module && host.name == name)
+        else {
+            bail!("import {module}.{name} isn't provided by the host");
+        };
+
+        let params: Vec<ValType> = host.params.iter().copied().map(Into::into).collect();
+        let results: Vec<ValType> = host.results.iter().copied().map(Into::into).collect();
+        if params != signature.params || results != signature.results {
+            bail!(
+                "import {module}.{name} has the signature {:?} -> {:?} but the host expects \
+                 {params:?} -> {results:?}",
+                signature.params,
+                signature.results
+            );
+        }
+
+        Ok(())
+    }
+}
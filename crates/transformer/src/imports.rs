@@ -0,0 +1,262 @@
+use anyhow::{bail, Result};
+use externref_metadata::Intrinsic;
+use walrus::{
+    ir::UnaryOp, ElementKind, ExportItem, FunctionBuilder, FunctionId, FunctionKind, ImportId,
+    ImportKind, Module, ValType,
+};
+
+use crate::{
+    null_check::NullCheck,
+    shim::{self, Callees, Step},
+    signature::ExternRefSignature,
+    table::{ExternRefTable, IndexType},
+    trace::Tracer,
+};
+
+/// The module and name of the host function converting a `funcref` into an `externref`.
+const FROM_FUNCREF: (&str, &str) = ("__externref", "from_funcref");
+
+/// Replaces an optional import the host doesn't provide with a local function that traps.
+pub(crate) fn stub(module: &mut Module, import_id: ImportId) -> Result<()> {
+    let import = module.imports.get(import_id);
+    let (module_name, name) = (import.module.clone(), import.name.clone());
+    let func_id = match import.kind {
+        ImportKind::Function(func_id) => func_id,
+        _ => bail!("import {module_name}.{name} is not a function"),
+    };
+    log::debug!("stubbing missing optional import {module_name}.{name}");
+
+    let ty = module.types.get(module.funcs.get(func_id).ty());
+    let (params, results) = (ty.params().to_vec(), ty.results().to_vec());
+
+    let mut builder = FunctionBuilder::new(&mut module.types, &params, &results);
+    builder.func_body().unreachable();
+    let args = params.iter().map(|ty| module.locals.add(*ty)).collect();
+
+    module.imports.delete(import_id);
+    let func = module.funcs.get_mut(func_id);
+    func.kind = FunctionKind::Local(builder.local_func(args));
+    func.name = Some(format!("missing optional import {module_name}.{name}"));
+
+    Ok(())
+}
+
+/// Rewrites an imported function so the host receives and returns `externref`s.
+///
+/// The import is replaced with one using the `externref` signature, and the original function
+/// becomes a local shim with the untransformed signature so existing call sites keep working,
+/// built from the steps planned for it. The shim loads each handle out of the table before calling
+/// the host and stores any returned reference in it afterwards. With a [Tracer], the shim first
+/// calls the host's trace function with the name of the import, and with a [NullCheck] it checks
+/// the references that can't be `null`.
+pub(crate) fn rewrite(
+    module: &mut Module,
+    table: &ExternRefTable,
+    import_id: ImportId,
+    signature: &ExternRefSignature,
+    steps: &[Step],
+    tracer: Option<&Tracer>,
+    null_check: Option<&NullCheck>,
+) -> Result<()> {
+    let import = module.imports.get(import_id);
+    let (module_name, name) = (import.module.clone(), import.name.clone());
+    let func_id = match import.kind {
+        ImportKind::Function(func_id) => func_id,
+        _ => bail!("import {module_name}.{name} is not a function"),
+    };
+
+    if signature.ret_result.is_some() {
+        bail!("import {module_name}.{name} can't return a result, only exports can");
+    }
+
+    let (params, results) = (&signature.original_params, &signature.original_results);
+    log::debug!(
+        "rewriting import {module_name}.{name}: {params:?} -> {results:?} to {:?} -> {:?}",
+        signature.params,
+        signature.results
+    );
+
+    let new_ty = module.types.add(&signature.params, &signature.results);
+    let (new_func, _) = module.add_import_func(&module_name, &name, new_ty);
+    module.imports.delete(import_id);
+
+    // Both functions are named so stack traces stay readable, keeping any name the shim already
+    // had from the original name section.
+    module.funcs.get_mut(new_func).name = Some(format!("{module_name}.{name}"));
+    module
+        .funcs
+        .get_mut(func_id)
+        .name
+        .get_or_insert_with(|| format!("{module_name}.{name} externref shim"));
+
+    let mut builder = FunctionBuilder::new(&mut module.types, params, results);
+    let args: Vec<_> = params.iter().map(|ty| module.locals.add(*ty)).collect();
+
+    let callees = Callees {
+        table,
+        func: new_func,
+        tracer: tracer.map(|tracer| (tracer, import_id)),
+        null_check,
+    };
+    shim::emit(
+        steps,
+        &mut builder.func_body(),
+        &mut module.locals,
+        &args,
+        &callees,
+    );
+
+    module.funcs.get_mut(func_id).kind = FunctionKind::Local(builder.local_func(args));
+
+    Ok(())
+}
+
+/// Imports the host function that converts a `funcref` into an `externref`, which is the identity
+/// function in JavaScript.
+pub(crate) fn import_from_funcref(module: &mut Module) -> FunctionId {
+    let (module_name, name) = FROM_FUNCREF;
+    let ty = module.types.add(&[ValType::Funcref], &[ValType::Externref]);
+    let (func_id, _) = module.add_import_func(module_name, name, ty);
+    module.funcs.get_mut(func_id).name = Some(format!("{module_name}.{name}"));
+
+    func_id
+}
+
+/// Replaces an intrinsic import with a local function returning a reference to an export.
+///
+/// `ref.func` produces a `funcref` rather than an `externref`, so the reference is converted by
+/// the host with `from_funcref` before it's stored in the table. Exports are looked up after
+/// they've been rewritten, so the reference is to the function the host sees as the export.
+pub(crate) fn export_ref(
+    module: &mut Module,
+    table: &ExternRefTable,
+    import_id: ImportId,
+    export: &str,
+    from_funcref: FunctionId,
+) -> Result<()> {
+    let import = module.imports.get(import_id);
+    let (module_name, name) = (import.module.clone(), import.name.clone());
+    let func_id = match import.kind {
+        ImportKind::Function(func_id) => func_id,
+        _ => bail!("import {module_name}.{name} is not a function"),
+    };
+
+    let target = match module.exports.iter().find(|item| item.name == export) {
+        Some(item) => match item.item {
+            ExportItem::Function(target) => target,
+            _ => bail!("export {export} referenced by {module_name}.{name} is not a function"),
+        },
+        None => bail!("export {export} referenced by {module_name}.{name} doesn't exist"),
+    };
+
+    let ty = module.types.get(module.funcs.get(func_id).ty());
+    let index_type = match (ty.params(), ty.results()) {
+        ([], [handle]) => IndexType::from_val_type(*handle)?,
+        _ => bail!("export reference {module_name}.{name} must take nothing and return a handle"),
+    };
+    log::debug!("replacing import {module_name}.{name} with a reference to export {export}");
+
+    // `ref.func` is only valid for functions declared in an element segment.
+    module
+        .elements
+        .add(ElementKind::Declared, ValType::Funcref, vec![Some(target)]);
+
+    let mut builder = FunctionBuilder::new(&mut module.types, &[], &[index_type.val_type()]);
+    let mut body = builder.func_body();
+    body.ref_func(target).call(from_funcref).call(table.alloc);
+    index_type.index_to_handle(&mut body);
+
+    module.imports.delete(import_id);
+    let func = module.funcs.get_mut(func_id);
+    func.kind = FunctionKind::Local(builder.local_func(Vec::new()));
+    func.name
+        .get_or_insert_with(|| format!("export reference {export}"));
+
+    Ok(())
+}
+
+/// Replaces an intrinsic import with a local function implementing it with wasm instructions.
+///
+/// With a `table` the handles index into it, so `ref.null` is stored with the table's `alloc`,
+/// which maps it to the null slot, and `ref.is_null` checks the reference a handle points at.
+/// Without one the module keeps its handles, and the null handle is `0`. The `transformed`
+/// intrinsic doesn't use handles and always returns `1`.
+pub(crate) fn intrinsic(
+    module: &mut Module,
+    table: Option<&ExternRefTable>,
+    import_id: ImportId,
+    intrinsic: Intrinsic,
+) -> Result<()> {
+    let import = module.imports.get(import_id);
+    let (module_name, name) = (import.module.clone(), import.name.clone());
+    let func_id = match import.kind {
+        ImportKind::Function(func_id) => func_id,
+        _ => bail!("import {module_name}.{name} is not a function"),
+    };
+
+    let ty = module.types.get(module.funcs.get(func_id).ty());
+    let (params, results) = (ty.params().to_vec(), ty.results().to_vec());
+    let mut builder = FunctionBuilder::new(&mut module.types, &params, &results);
+    let mut body = builder.func_body();
+    let args = match (intrinsic, params.as_slice(), results.as_slice()) {
+        (Intrinsic::RefNull, [], [handle]) => {
+            let index_type = IndexType::from_val_type(*handle)?;
+            match table {
+                Some(table) => {
+                    body.ref_null(ValType::Externref).call(table.alloc);
+                    index_type.index_to_handle(&mut body);
+                }
+                None if index_type == IndexType::I64 => {
+                    body.i64_const(0);
+                }
+                None => {
+                    body.i32_const(0);
+                }
+            }
+            Vec::new()
+        }
+        (Intrinsic::RefIsNull, [handle], [ValType::I32]) => {
+            let index_type = IndexType::from_val_type(*handle)?;
+            let handle = module.locals.add(*handle);
+            body.local_get(handle);
+            match table {
+                Some(table) => {
+                    index_type.handle_to_index(&mut body);
+                    body.call(table.get).ref_is_null();
+                }
+                None if index_type == IndexType::I64 => {
+                    body.unop(UnaryOp::I64Eqz);
+                }
+                None => {
+                    body.unop(UnaryOp::I32Eqz);
+                }
+            }
+            vec![handle]
+        }
+        (Intrinsic::Transformed, [], [ValType::I32]) => {
+            body.i32_const(1);
+            Vec::new()
+        }
+        (Intrinsic::RefNull, ..) => {
+            bail!("intrinsic {module_name}.{name} must take nothing and return a handle")
+        }
+        (Intrinsic::RefIsNull, ..) => {
+            bail!("intrinsic {module_name}.{name} must take a handle and return an i32")
+        }
+        (Intrinsic::Transformed, ..) => {
+            bail!("intrinsic {module_name}.{name} must take nothing and return an i32")
+        }
+    };
+    log::debug!(
+        "replacing import {module_name}.{name} with the {} intrinsic",
+        intrinsic.name()
+    );
+
+    module.imports.delete(import_id);
+    let func = module.funcs.get_mut(func_id);
+    func.kind = FunctionKind::Local(builder.local_func(args));
+    func.name
+        .get_or_insert_with(|| format!("intrinsic {}", intrinsic.name()));
+
+    Ok(())
+}
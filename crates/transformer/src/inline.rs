@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use walrus::{
+    ir::{self, Instr, InstrSeqId, InstrSeqType},
+    FunctionId, FunctionKind, LocalFunction, LocalId, Module, ModuleLocals, ValType,
+};
+
+/// A copy of a function's body, taken so it can be pasted into its callers.
+struct Body {
+    /// The function's parameters, in order.
+    args: Vec<LocalId>,
+    /// Every local the body uses, including the parameters.
+    locals: Vec<(LocalId, ValType)>,
+    /// The sequence holding the function's body.
+    entry: InstrSeqId,
+    /// Every sequence of the body, starting with the entry.
+    seqs: Vec<(InstrSeqId, InstrSeqType, Vec<Instr>)>,
+}
+
+/// Replaces every call to the `accessors` with a copy of their body in a block, then removes them.
+///
+/// The arguments are moved into fresh locals of the caller, so each call site gets its own copy
+/// of the accessor's locals.
+pub(crate) fn inline_accessors(module: &mut Module, accessors: &[FunctionId]) -> Result<()> {
+    let bodies = accessors
+        .iter()
+        .map(|id| Ok((*id, Body::copy(module, *id)?)))
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    let callers: Vec<_> = module
+        .funcs
+        .iter_local()
+        .map(|(id, _)| id)
+        .filter(|id| !bodies.contains_key(id))
+        .collect();
+    let mut inlined = 0;
+    for caller in callers {
+        let FunctionKind::Local(func) = &mut module.funcs.get_mut(caller).kind else {
+            continue;
+        };
+        // Accessors can call each other, e.g. the alloc of a tagged table calls the alloc of the
+        // table itself, so copies are inlined again until no calls are left.
+        loop {
+            let count = inline_calls(func, &mut module.locals, &bodies);
+            if count == 0 {
+                break;
+            }
+            inlined += count;
+        }
+    }
+    log::debug!("inlined {inlined} calls to externref table accessors");
+
+    for id in accessors {
+        module.funcs.delete(*id);
+    }
+    Ok(())
+}
+
+/// Inlines the calls to any of the `bodies` in the function, returning how many were inlined.
+fn inline_calls(
+    func: &mut LocalFunction,
+    locals: &mut ModuleLocals,
+    bodies: &HashMap<FunctionId, Body>,
+) -> usize {
+    let mut inlined = 0;
+    for seq in seq_ids(func) {
+        while let Some((position, callee)) =
+            func.block(seq)
+                .instrs
+                .iter()
+                .enumerate()
+                .find_map(|(position, (instr, _))| match instr {
+                    Instr::Call(ir::Call { func }) if bodies.contains_key(func) => {
+                        Some((position, *func))
+                    }
+                    _ => None,
+                })
+        {
+            let body = &bodies[&callee];
+            let new_locals: HashMap<_, _> = body
+                .locals
+                .iter()
+                .map(|(local, ty)| (*local, locals.add(*ty)))
+                .collect();
+            let block = body.paste(func, &new_locals);
+
+            // The arguments are on the stack in order, so the last one is popped first.
+            let mut replacement: Vec<Instr> = body
+                .args
+                .iter()
+                .rev()
+                .map(|arg| {
+                    Instr::LocalSet(ir::LocalSet {
+                        local: new_locals[arg],
+                    })
+                })
+                .collect();
+            replacement.push(Instr::Block(ir::Block { seq: block }));
+
+            let instrs = &mut func.block_mut(seq).instrs;
+            let loc = instrs[position].1;
+            instrs.splice(
+                position..=position,
+                replacement.into_iter().map(|instr| (instr, loc)),
+            );
+            inlined += 1;
+        }
+    }
+    inlined
+}
+
+impl Body {
+    /// Copies the body of a local function.
+    fn copy(module: &mut Module, id: FunctionId) -> Result<Self> {
+        let function = module.funcs.get(id);
+        let name = function.name.as_deref().unwrap_or("<unnamed>");
+        let FunctionKind::Local(func) = &function.kind else {
+            bail!("{name} isn't a local function, so it can't be inlined");
+        };
+
+        let mut locals: Vec<_> = func.args.clone();
+        let mut seqs = Vec::new();
+        for id in seq_ids(func) {
+            let block = func.block(id);
+            let mut instrs = Vec::with_capacity(block.instrs.len());
+            for (instr, _) in &block.instrs {
+                match instr {
+                    // The copy is a block, so returning would leave the caller instead.
+                    Instr::Return(_) => bail!("{name} returns early, so it can't be inlined"),
+                    Instr::LocalGet(ir::LocalGet { local })
+                    | Instr::LocalSet(ir::LocalSet { local })
+                    | Instr::LocalTee(ir::LocalTee { local }) => locals.push(*local),
+                    _ => {}
+                }
+                instrs.push(instr.clone());
+            }
+            seqs.push((id, block.ty, instrs));
+        }
+        locals.sort_unstable();
+        locals.dedup();
+
+        // The entry has the function's type, but the arguments are taken off the stack before the
+        // block is entered.
+        let results = module.types.get(func.ty()).results().to_vec();
+        seqs[0].1 = InstrSeqType::new(&mut module.types, &[], &results);
+
+        Ok(Self {
+            args: func.args.clone(),
+            locals: locals
+                .into_iter()
+                .map(|local| (local, module.locals.get(local).ty()))
+                .collect(),
+            entry: func.entry_block(),
+            seqs,
+        })
+    }
+
+    /// Adds a copy of the body to the function using the `locals` in place of its own, returning
+    /// the sequence to use as a block.
+    fn paste(&self, func: &mut LocalFunction, locals: &HashMap<LocalId, LocalId>) -> InstrSeqId {
+        let builder = func.builder_mut();
+        let ids: HashMap<_, _> = self
+            .seqs
+            .iter()
+            .map(|(id, ty, _)| (*id, builder.dangling_instr_seq(*ty).id()))
+            .collect();
+
+        for (id, _, instrs) in &self.seqs {
+            let mut seq = builder.instr_seq(ids[id]);
+            for instr in instrs {
+                seq.instr(remap(instr.clone(), &ids, locals));
+            }
+        }
+        ids[&self.entry]
+    }
+}
+
+/// Points the sequences and locals an instruction uses at their copies.
+fn remap(
+    mut instr: Instr,
+    seqs: &HashMap<InstrSeqId, InstrSeqId>,
+    locals: &HashMap<LocalId, LocalId>,
+) -> Instr {
+    match &mut instr {
+        Instr::Block(ir::Block { seq }) | Instr::Loop(ir::Loop { seq }) => *seq = seqs[seq],
+        Instr::IfElse(ir::IfElse {
+            consequent,
+            alternative,
+        }) => {
+            *consequent = seqs[consequent];
+            *alternative = seqs[alternative];
+        }
+        Instr::Br(ir::Br { block }) | Instr::BrIf(ir::BrIf { block }) => *block = seqs[block],
+        Instr::BrTable(ir::BrTable { blocks, default }) => {
+            for block in blocks.iter_mut() {
+                *block = seqs[block];
+            }
+            *default = seqs[default];
+        }
+        Instr::LocalGet(ir::LocalGet { local })
+        | Instr::LocalSet(ir::LocalSet { local })
+        | Instr::LocalTee(ir::LocalTee { local }) => *local = locals[local],
+        _ => {}
+    }
+    instr
+}
+
+/// The sequences of a function's body, starting with the entry.
+fn seq_ids(func: &LocalFunction) -> Vec<InstrSeqId> {
+    let mut ids = vec![func.entry_block()];
+    let mut next = 0;
+    while let Some(id) = ids.get(next).copied() {
+        next += 1;
+        for (instr, _) in &func.block(id).instrs {
+            match instr {
+                Instr::Block(ir::Block { seq }) | Instr::Loop(ir::Loop { seq }) => ids.push(*seq),
+                Instr::IfElse(ir::IfElse {
+                    consequent,
+                    alternative,
+                }) => ids.extend([*consequent, *alternative]),
+                _ => {}
+            }
+        }
+    }
+    ids
+}
@@ -0,0 +1,2761 @@
+//! A crate for transforming WASM modules built with the `externref` crate so their imports and
+//! exports take and return real `externref`s.
+//!
+//! The [externref](https://docs.rs/externref) macro describes every annotated function in a custom
+//! section. The transformer reads those sections, rewrites the matching imports and exports to use
+//! `externref` in place of the `usize` handles Rust sees and stores the references in a table
+//! that the handles index into.
+//!
+//! Handles are `usize`s, so they're `i32`s on `wasm32` and `i64`s on `wasm64`. The index type of
+//! each handle is taken from the untransformed signature and `i64` handles are narrowed to the
+//! table's `i32` indices. That's as far as `wasm64` support goes for now: `walrus` can't parse a
+//! module with a 64-bit memory, so `memory64` modules are rejected with an error saying so, and
+//! tables are always 32-bit.
+//!
+//! Import module names can contain `${NAME}` placeholders that are substituted at transform time,
+//! see [Config::substitutions]. Functions another pass takes care of can be left untouched with
+//! [Config::skip]. Modules built with the macro's `manifest` feature describe every
+//! function in a single `__extern_ref_manifest` section instead, which is read the same way.
+//! The rewritten imports can also be checked against the signatures
+//! the host provides, see [Config::host_signatures].
+//! Calls to the rewritten imports can be traced while debugging, see [Config::trace_imports], and
+//! null references where the function data doesn't allow them can trap, see
+//! [Config::check_nulls].
+//! The references of each import module can be kept in their own table, see [TableStrategy].
+//! The host can access the table and memory directly when they're exported, see
+//! [Config::export_table] and [Config::export_memory]. Runtimes without reference types can use
+//! modules that keep their `i32` handles instead, see [Config::lower_to_i32]. Otherwise a module
+//! whose `target_features` section disallows `reference-types` is rejected with the flags to build
+//! it with, and one whose section doesn't list it is transformed with a warning. The helpers
+//! moving references in and out of the table can be inlined into their callers, see
+//! [Config::inline_accessors]. Functions whose data asks for
+//! `table` lowering keep their handles, which the host resolves with the table exported by
+//! [Config::export_table]. Globals that already hold `externref`s are kept as they are, see
+//! [Config::null_externref_globals]. Functions marked with the macro's `pack_args` option take
+//! their `externref`s after their other arguments, so glue code can pass them as one array.
+//! The `externref`s a transformed module takes and returns can be counted with [stats()], and the
+//! signatures a transform would change can be listed beforehand with [preview()].
+//!
+//! Tools that already hold a parsed module can transform it in place with [transform_module].
+//!
+//! Everything that parses or rewrites modules with `walrus` is behind the default `transform`
+//! feature. Tools that only need the function data of a module, e.g. to generate glue code, can
+//! disable it and use [read_metadata], which only depends on `wasmparser`.
+//!
+//! Transformed modules list the transformer and its version as `processed-by` in their `producers`
+//! section, once no matter how many times they're transformed. For reviewing transformed modules
+//! in diffs, [Config::normalize] sorts imports and exports and removes the custom sections instead.
+//!
+//! Imports created by `externref::export_ref!` never reach the host. They're replaced with a
+//! `ref.func` of the named export, converted to an `externref` by a `__externref.from_funcref`
+//! import the host must provide.
+//!
+//! Imports of `ExternRef::null` and `ExternRef::is_null` from the `__externref_intrinsic` module
+//! don't reach the host either. They're replaced with functions using `ref.null` and
+//! `ref.is_null`, or comparing the handle with `0` when lowering to `i32` handles. The import
+//! behind `externref::assert_transformed` is replaced with a function returning `1`.
+#![forbid(missing_docs)]
+
+#[cfg(feature = "transform")]
+mod config;
+#[cfg(feature = "transform")]
+mod elements;
+#[cfg(feature = "transform")]
+mod exports;
+#[cfg(feature = "transform")]
+mod globals;
+#[cfg(feature = "transform")]
+mod host;
+#[cfg(feature = "transform")]
+mod imports;
+#[cfg(feature = "transform")]
+mod inline;
+#[cfg(feature = "transform")]
+mod lower;
+#[cfg(feature = "transform")]
+mod metadata;
+#[cfg(feature = "transform")]
+mod normalize;
+#[cfg(feature = "transform")]
+mod null_check;
+#[cfg(feature = "transform")]
+mod placeholders;
+#[cfg(feature = "transform")]
+mod plan;
+#[cfg(feature = "transform")]
+mod preview;
+#[cfg(feature = "transform")]
+mod proposal;
+mod reader;
+#[cfg(feature = "transform")]
+mod refcount;
+#[cfg(feature = "transform")]
+mod report;
+#[cfg(feature = "transform")]
+mod shim;
+#[cfg(feature = "transform")]
+mod signature;
+#[cfg(feature = "transform")]
+mod stats;
+#[cfg(feature = "transform")]
+mod table;
+#[cfg(feature = "transform")]
+mod target_features;
+#[cfg(feature = "transform")]
+mod trace;
+
+#[cfg(feature = "transform")]
+use std::collections::HashMap;
+
+#[cfg(feature = "transform")]
+use anyhow::{bail, Context, Result};
+#[cfg(feature = "transform")]
+use externref_metadata::{FunctionData, Intrinsic, Lowering};
+#[cfg(feature = "transform")]
+use walrus::{ExportItem, ImportKind, Module, TableId};
+
+#[cfg(feature = "transform")]
+pub use crate::config::{Config, Proposal, TableStrategy};
+#[cfg(feature = "transform")]
+pub use crate::host::{HostSignature, HostSignatures, HostValType};
+#[cfg(feature = "transform")]
+pub use crate::preview::{preview, SignatureDiff};
+#[cfg(feature = "transform")]
+pub use crate::report::{Report, RewriteKind, RewrittenFunction};
+#[cfg(feature = "transform")]
+pub use crate::stats::{stats, Stats};
+#[cfg(feature = "transform")]
+pub use walrus;
+
+pub use crate::reader::read_metadata;
+
+#[cfg(feature = "transform")]
+use crate::metadata::{export_section_name, import_section_name};
+#[cfg(feature = "transform")]
+use crate::null_check::NullCheck;
+#[cfg(feature = "transform")]
+use crate::plan::Target;
+#[cfg(feature = "transform")]
+use crate::shim::Step;
+#[cfg(feature = "transform")]
+use crate::table::ExternRefTables;
+#[cfg(feature = "transform")]
+use crate::trace::Tracer;
+
+/// Transforms a WASM module with the default [Config], returning the bytes of the transformed
+/// module.
+#[cfg(feature = "transform")]
+pub fn transform(wasm: &[u8]) -> Result<Vec<u8>> {
+    transform_with_config(wasm, &Config::default())
+}
+
+/// Transforms a WASM module, returning the bytes of the transformed module.
+#[cfg(feature = "transform")]
+pub fn transform_with_config(wasm: &[u8], config: &Config) -> Result<Vec<u8>> {
+    transform_with_report(wasm, config).map(|(wasm, _)| wasm)
+}
+
+/// Transforms a WASM module, returning the bytes of the transformed module along with a [Report]
+/// of what was changed.
+#[cfg(feature = "transform")]
+pub fn transform_with_report(wasm: &[u8], config: &Config) -> Result<(Vec<u8>, Report)> {
+    check_memory64(wasm)?;
+    let mut module = Module::from_buffer(wasm).context("failed to parse wasm module")?;
+    let report = transform_module_with_config(&mut module, config)?;
+
+    let wasm = module.emit_wasm();
+    let wasm = match config.proposal {
+        Proposal::ReferenceTypes => wasm,
+        Proposal::FunctionReferences => {
+            proposal::encode_typed_references(&wasm).context("failed to encode typed references")?
+        }
+    };
+
+    Ok((wasm, report))
+}
+
+/// Rejects a module with a 64-bit memory up front, as `walrus` can't parse it.
+///
+/// Anything else the module has wrong is left for `walrus` to report.
+#[cfg(feature = "transform")]
+fn check_memory64(wasm: &[u8]) -> Result<()> {
+    use wasmparser::{Parser, Payload, TypeRef};
+
+    for payload in Parser::new(0).parse_all(wasm) {
+        let memory64 = match payload {
+            Ok(Payload::ImportSection(reader)) => reader.into_imports().any(|import| {
+                let Ok(import) = import else { return false };
+                matches!(import.ty, TypeRef::Memory(ty) if ty.memory64)
+            }),
+            Ok(Payload::MemorySection(reader)) => reader
+                .into_iter()
+                .any(|memory| matches!(memory, Ok(memory) if memory.memory64)),
+            Ok(_) => false,
+            Err(_) => break,
+        };
+        if memory64 {
+            bail!("modules with a 64-bit memory (memory64) can't be transformed yet");
+        }
+    }
+
+    Ok(())
+}
+
+/// Transforms a parsed module in place with the default [Config].
+///
+/// This is for tools that already hold a [walrus::Module], e.g. to run other passes before or
+/// after without encoding and parsing the module in between. The module must come from the same
+/// version of `walrus` as the transformer uses, currently 0.20, which is re-exported as
+/// [walrus](crate::walrus).
+#[cfg(feature = "transform")]
+pub fn transform_module(module: &mut Module) -> Result<()> {
+    transform_module_with_config(module, &Config::default()).map(|_| ())
+}
+
+/// Transforms a parsed module in place, returning a [Report] of what was changed.
+///
+/// [Config::proposal] only affects how the module is encoded, so it's ignored here and applied by
+/// the functions returning bytes instead.
+#[cfg(feature = "transform")]
+pub fn transform_module_with_config(module: &mut Module, config: &Config) -> Result<Report> {
+    transform_module_inner(module, config).map(|(report, _)| report)
+}
+
+/// Transforms a parsed module in place, returning a [Report] along with how each rewritten
+/// signature changed.
+#[cfg(feature = "transform")]
+fn transform_module_inner(
+    module: &mut Module,
+    config: &Config,
+) -> Result<(Report, Vec<SignatureDiff>)> {
+    if config.lower_to_i32 {
+        if config.host_signatures.is_some() {
+            bail!("host signatures can't be checked when lowering to i32 handles");
+        }
+        if config.trace_imports {
+            bail!("imports can't be traced when lowering to i32 handles");
+        }
+        if config.check_nulls {
+            bail!("null references can't be checked when lowering to i32 handles");
+        }
+        if config.export_table.is_some() {
+            bail!("there's no externref table to export when lowering to i32 handles");
+        }
+        if config.inline_accessors {
+            bail!("there are no table accessors to inline when lowering to i32 handles");
+        }
+        globals::check_lowered(module)?;
+    }
+
+    // Externref globals already hold references, so they're kept as they are unless asked to
+    // start out as null.
+    if config.null_externref_globals {
+        globals::initialize_to_null(module);
+    }
+
+    let metadata::Sections {
+        functions: sections,
+        removed,
+    } = metadata::take_sections(module)?;
+    let mut report = Report {
+        sections_stripped: removed,
+        ..Report::default()
+    };
+    let mut diffs = Vec::new();
+
+    let imports: Vec<_> = module
+        .imports
+        .iter()
+        .filter(|import| matches!(import.kind, ImportKind::Function(_)))
+        .filter_map(|import| {
+            let section = import_section_name(&import.module, &import.name);
+            sections.get(&section).map(|data| (import.id(), data))
+        })
+        .collect();
+
+    // With `wasm-ld --export-all` every function is exported, including the shims of imports
+    // under the name of their section, so an export is never matched with a section that already
+    // describes an import, whose name the macro linked itself. The arity can't rule out a match,
+    // as the recorded count is the Rust one, which ABI lowering changes, so a mismatch is only
+    // warned about below.
+    let mut exports = Vec::new();
+    let mut skipped = Vec::new();
+    for export in module.exports.iter() {
+        if !matches!(export.item, ExportItem::Function(_)) {
+            continue;
+        }
+        let section = export_section_name(&export.name);
+        let Some(data) = sections.get(&section) else {
+            continue;
+        };
+
+        let skip = if imports
+            .iter()
+            .any(|(_, matched)| std::ptr::eq(*matched, data))
+        {
+            format!("{section} also describes an import")
+        } else if config.skip.contains(&export.name) {
+            log::info!("skipped export {}", export.name);
+            skipped.push(data);
+            continue;
+        } else {
+            exports.push((Target::Export(export.id()), data));
+            continue;
+        };
+        let warning = format!("skipped export {}, {skip}", export.name);
+        log::warn!("{warning}");
+        report.warnings.push(warning);
+        skipped.push(data);
+    }
+
+    log::info!(
+        "matched {} imports and {} exports",
+        imports.len(),
+        exports.len()
+    );
+
+    let mut stubs = Vec::new();
+    let mut export_refs = Vec::new();
+    let mut intrinsics = Vec::new();
+    let mut targets = Vec::new();
+    for (import_id, data) in imports {
+        // Export references and intrinsics never reach the host.
+        if data.export_ref.is_some() {
+            export_refs.push((import_id, data));
+            continue;
+        }
+        if let Some(intrinsic) = data.intrinsic {
+            intrinsics.push((import_id, intrinsic, data));
+            continue;
+        }
+
+        let import = module.imports.get(import_id);
+        let import_name = format!(
+            "{}.{}",
+            placeholders::substitute(&import.module, &config.substitutions)?,
+            import.name
+        );
+
+        if config.skip.contains(&import_name) {
+            log::info!("skipped import {import_name}");
+            skipped.push(data);
+        } else if !config.missing_imports.contains(&import_name) {
+            targets.push((Target::Import(import_id), data));
+        } else if data.optional {
+            report
+                .warnings
+                .push(format!("stubbed missing optional import {import_name}"));
+            stubs.push((import_id, data));
+        } else {
+            bail!("required import {import_name} is missing from the host");
+        }
+    }
+    targets.extend(exports);
+    let indirect = elements::find(module, &sections);
+    elements::check_callbacks(&sections, &indirect)?;
+    for (func_id, data) in indirect {
+        if config.skip.contains(&data.name) {
+            log::info!("skipped callback {}", data.name);
+            skipped.push(data);
+        } else {
+            targets.push((Target::Element(func_id), data));
+        }
+    }
+
+    // A section that doesn't match anything usually means a function was renamed or removed
+    // without updating its `name` option. The `externref` crate declares its intrinsics together,
+    // so a module using one of them carries the sections of the others as well.
+    let matched: Vec<&FunctionData> = targets
+        .iter()
+        .map(|(_, data)| *data)
+        .chain(stubs.iter().map(|(_, data)| *data))
+        .chain(export_refs.iter().map(|(_, data)| *data))
+        .chain(intrinsics.iter().map(|(_, _, data)| *data))
+        .chain(skipped)
+        .collect();
+    let mut unmatched: Vec<_> = sections
+        .iter()
+        .filter(|(_, data)| data.intrinsic.is_none())
+        .filter(|(_, data)| !matched.iter().any(|matched| std::ptr::eq(*matched, *data)))
+        .map(|(name, _)| name)
+        .collect();
+    unmatched.sort();
+    for name in unmatched {
+        log::warn!("{name} doesn't describe any import or export");
+        report
+            .warnings
+            .push(format!("{name} doesn't describe any import or export"));
+    }
+
+    // ABI lowering (e.g. of `i128` arguments or struct returns) can change the number of wasm
+    // parameters, but a different count usually means the data describes the wrong function.
+    for (target, data) in &targets {
+        let Some(param_count) = data.param_count else {
+            continue;
+        };
+        let ty = module.funcs.get(target.func_id(module)?).ty();
+        let params = module.types.get(ty).params().len();
+        if params != param_count {
+            let warning = format!(
+                "{} has {params} parameters, but was declared with {param_count}",
+                target.describe(module)
+            );
+            log::warn!("{warning}");
+            report.warnings.push(warning);
+        }
+    }
+
+    // Functions lowered through the table keep their signatures, and the host resolves their
+    // handles with the exported table instead.
+    let (lowered, targets): (Vec<_>, Vec<_>) = targets
+        .into_iter()
+        .partition(|(_, data)| data.lowering == Lowering::Table && !config.lower_to_i32);
+    if let Some((target, _)) = lowered.first() {
+        let function = target.describe(module);
+        if config.export_table.is_none() {
+            bail!(
+                "{function} is lowered through the table, so the table must be exported with \
+                 `export_table`"
+            );
+        }
+        if config.table_strategy == TableStrategy::PerModule {
+            bail!(
+                "{function} is lowered through the table, which needs a single table as its \
+                 handles could point into any of them"
+            );
+        }
+    }
+
+    let rewrites = plan::plan(module, &targets, config)?;
+
+    if let Some(host_signatures) = &config.host_signatures {
+        for rewrite in &rewrites {
+            if let Target::Import(import_id) = rewrite.target {
+                let import = module.imports.get(import_id);
+                let import_module =
+                    placeholders::substitute(&import.module, &config.substitutions)?;
+                host_signatures.check(&import_module, &import.name, &rewrite.signature)?;
+            }
+        }
+    }
+
+    // Only the `transformed` marker can be replaced without reference types.
+    let needs_reference_types = !rewrites.is_empty()
+        || !lowered.is_empty()
+        || !export_refs.is_empty()
+        || intrinsics
+            .iter()
+            .any(|(_, intrinsic, _)| *intrinsic != Intrinsic::Transformed);
+    if !config.lower_to_i32 && needs_reference_types {
+        if let Some(warning) = target_features::check_reference_types(module)? {
+            log::warn!("{warning}");
+            report.warnings.push(warning);
+        }
+    }
+
+    for (import_id, _) in stubs {
+        imports::stub(module, import_id)?;
+    }
+
+    // Checking if the module is transformed doesn't involve references, so it doesn't need a table.
+    let (markers, intrinsics): (Vec<_>, Vec<_>) = intrinsics
+        .into_iter()
+        .partition(|(_, intrinsic, _)| *intrinsic == Intrinsic::Transformed);
+    for (import_id, intrinsic, _) in markers {
+        imports::intrinsic(module, None, import_id, intrinsic)?;
+    }
+
+    let uses_table = !rewrites.is_empty()
+        || !lowered.is_empty()
+        || !export_refs.is_empty()
+        || !intrinsics.is_empty();
+    if config.lower_to_i32 && uses_table {
+        if !export_refs.is_empty() {
+            bail!("export_ref needs reference types, so it can't be lowered to i32 handles");
+        }
+        for (import_id, intrinsic, _) in intrinsics {
+            imports::intrinsic(module, None, import_id, intrinsic)?;
+        }
+        // The functions keep their signatures, but the host still treats the same arguments as
+        // references.
+        for (rewrite, (_, data)) in rewrites.iter().zip(&targets) {
+            report.functions.push(RewrittenFunction::new(
+                module,
+                rewrite.target,
+                data,
+                &config.substitutions,
+            )?);
+        }
+        if !rewrites.is_empty() {
+            lower::create_alloc(module, config.table_maximum)?;
+        }
+    } else if uses_table {
+        // Imports are grouped by the module the host sees them in.
+        let mut import_modules = HashMap::new();
+        for rewrite in &rewrites {
+            if let Target::Import(import_id) = rewrite.target {
+                let import = module.imports.get(import_id);
+                let import_module =
+                    placeholders::substitute(&import.module, &config.substitutions)?;
+                import_modules.insert(import_id, import_module);
+            }
+        }
+
+        let tables = ExternRefTables::create(
+            module,
+            config.table_strategy,
+            config.table_initial,
+            config.table_maximum,
+            import_modules.values().map(String::as_str),
+            // Slots are only recycled once references can be dropped.
+            rewrites
+                .iter()
+                .any(|rewrite| rewrite.steps.contains(&Step::Free)),
+        )?;
+        report.table_created = true;
+
+        // Only imports going to the host are traced, export references never reach it.
+        let mut traced = Vec::new();
+        if config.trace_imports {
+            for rewrite in &rewrites {
+                if let Target::Import(import_id) = rewrite.target {
+                    let import = module.imports.get(import_id);
+                    let import_module = &import_modules[&import_id];
+                    traced.push((import_id, format!("{import_module}.{}", import.name)));
+                }
+            }
+        }
+        let tracer = if traced.is_empty() {
+            None
+        } else {
+            Some(Tracer::create(module, &traced)?)
+        };
+        let null_check = (config.check_nulls
+            && rewrites
+                .iter()
+                .any(|rewrite| rewrite.signature.has_non_null_refs()))
+        .then(|| NullCheck::create(module));
+
+        for (rewrite, (_, data)) in rewrites.iter().zip(&targets) {
+            let function =
+                RewrittenFunction::new(module, rewrite.target, data, &config.substitutions)?;
+            diffs.push(SignatureDiff::new(function.clone(), &rewrite.signature));
+            report.functions.push(function);
+        }
+        for (target, data) in &lowered {
+            report.functions.push(RewrittenFunction::new(
+                module,
+                *target,
+                data,
+                &config.substitutions,
+            )?);
+        }
+
+        // The rewrites are in the same order as their targets.
+        for (rewrite, (_, data)) in rewrites.iter().zip(&targets) {
+            match rewrite.target {
+                Target::Import(import_id) => imports::rewrite(
+                    module,
+                    tables.import(&import_modules[&import_id]),
+                    import_id,
+                    &rewrite.signature,
+                    &rewrite.steps,
+                    tracer.as_ref(),
+                    null_check.as_ref(),
+                )?,
+                Target::Export(export_id) => exports::rewrite(
+                    module,
+                    tables.default(),
+                    export_id,
+                    &rewrite.signature,
+                    &rewrite.steps,
+                    &data.aliases,
+                    null_check.as_ref(),
+                )?,
+                Target::Element(func_id) => elements::rewrite(
+                    module,
+                    tables.default(),
+                    func_id,
+                    &rewrite.signature,
+                    &rewrite.steps,
+                    null_check.as_ref(),
+                ),
+            }
+        }
+
+        if let Some(name) = &config.export_table {
+            export_table(module, name, tables.default().table)?;
+            for (import_module, table) in tables.modules() {
+                export_table(module, &format!("{name}_{import_module}"), table.table)?;
+            }
+        }
+
+        for (import_id, intrinsic, _) in intrinsics {
+            imports::intrinsic(module, Some(tables.default()), import_id, intrinsic)?;
+        }
+
+        // References are taken after the exports are rewritten so they point at the wrappers.
+        if !export_refs.is_empty() {
+            let from_funcref = imports::import_from_funcref(module);
+            for (import_id, data) in export_refs {
+                let export = data.export_ref.as_deref().unwrap_or_default();
+                imports::export_ref(module, tables.default(), import_id, export, from_funcref)?;
+                report.functions.push(RewrittenFunction {
+                    name: export.to_string(),
+                    module: None,
+                    kind: RewriteKind::ExportRef,
+                    arg_indices: Vec::new(),
+                    group: data.group.clone(),
+                    constructor: false,
+                    reentrant: false,
+                    lowering: Lowering::Direct,
+                    pack_args: false,
+                });
+            }
+        }
+
+        if config.inline_accessors {
+            inline::inline_accessors(module, tables.accessors())?;
+        }
+    }
+
+    if let Some(name) = &config.export_memory {
+        export_memory(module, name)?;
+    }
+
+    // Placeholders are substituted last as the data sections are named after the declared module.
+    placeholders::substitute_imports(module, &config.substitutions)?;
+
+    // Functions are ordered by their names, so this comes before they're stripped.
+    if config.normalize {
+        normalize::normalize(module);
+    }
+    if config.strip_names {
+        strip_names(module);
+    }
+
+    // An entry with the same name is replaced, so transforming again doesn't add another one.
+    // Normalized modules leave out the details of the build.
+    if !config.normalize {
+        module
+            .producers
+            .add_processed_by(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    }
+
+    Ok((report, diffs))
+}
+
+/// Exports an externref table under `name`.
+#[cfg(feature = "transform")]
+fn export_table(module: &mut Module, name: &str, table: TableId) -> Result<()> {
+    if module.exports.iter().any(|export| export.name == name) {
+        bail!("can't export the externref table as {name}, another export has that name");
+    }
+    module.exports.add(name, table);
+    Ok(())
+}
+
+/// Exports the module's memory under `name`, unless it's already exported under it.
+#[cfg(feature = "transform")]
+fn export_memory(module: &mut Module, name: &str) -> Result<()> {
+    let Some(memory) = module.memories.iter().next().map(|memory| memory.id()) else {
+        bail!("can't export the memory as {name}, the module doesn't have one");
+    };
+    let existing = module
+        .exports
+        .iter()
+        .find(|export| export.name == name)
+        .map(|export| export.item);
+    match existing {
+        Some(ExportItem::Memory(id)) if id == memory => {}
+        Some(_) => bail!("can't export the memory as {name}, another export has that name"),
+        None => {
+            module.exports.add(name, memory);
+        }
+    }
+    Ok(())
+}
+
+/// Removes every name that would be written to the `name` section.
+#[cfg(feature = "transform")]
+fn strip_names(module: &mut Module) {
+    module.name = None;
+    for func in module.funcs.iter_mut() {
+        func.name = None;
+    }
+    let locals: Vec<_> = module.locals.iter().map(|local| local.id()).collect();
+    for id in locals {
+        module.locals.get_mut(id).name = None;
+    }
+    for table in module.tables.iter_mut() {
+        table.name = None;
+    }
+    for memory in module.memories.iter_mut() {
+        memory.name = None;
+    }
+    let globals: Vec<_> = module.globals.iter().map(|global| global.id()).collect();
+    for id in globals {
+        module.globals.get_mut(id).name = None;
+    }
+    let data: Vec<_> = module.data.iter().map(|data| data.id()).collect();
+    for id in data {
+        module.data.get_mut(id).name = None;
+    }
+    for element in module.elements.iter_mut() {
+        element.name = None;
+    }
+}
+
+#[cfg(all(test, feature = "transform"))]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use anyhow::Result;
+    use externref_metadata::{FunctionData, Lowering};
+    use walrus::{
+        ir::{Const, Instr, UnaryOp, Unop, Unreachable, Value},
+        ExportItem, FunctionId, FunctionKind, GlobalKind, InitExpr, Module, RawCustomSection,
+        ValType,
+    };
+    use wasmparser::WasmFeatures;
+
+    use super::{
+        transform, transform_module, transform_with_config, transform_with_report, Config,
+        Proposal, TableStrategy,
+    };
+
+    fn signature(module: &Module, func_id: FunctionId) -> (Vec<ValType>, Vec<ValType>) {
+        let ty = module.types.get(module.funcs.get(func_id).ty());
+        (ty.params().to_vec(), ty.results().to_vec())
+    }
+
+    fn exported_func(module: &Module, name: &str) -> FunctionId {
+        match module.exports.iter().find(|export| export.name == name) {
+            Some(export) => match export.item {
+                ExportItem::Function(func_id) => func_id,
+                _ => panic!("export {name} is not a function"),
+            },
+            None => panic!("missing export {name}"),
+        }
+    }
+
+    #[test]
+    fn transform_import() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32 i32) (result i32)))
+                (func (export "run") (param i32) (result i32)
+                    local.get 0
+                    i32.const 1
+                    call $log)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+        let module = Module::from_buffer(&transform(&wasm)?)?;
+
+        let log = module.imports.get_func("console", "log")?;
+        assert_eq!(
+            signature(&module, log),
+            (
+                vec![ValType::Externref, ValType::I32],
+                vec![ValType::Externref]
+            )
+        );
+
+        // Callers of the import still see the handle based signature.
+        let run = exported_func(&module, "run");
+        assert_eq!(
+            signature(&module, run),
+            (vec![ValType::I32], vec![ValType::I32])
+        );
+
+        assert_eq!(module.tables.iter().count(), 1);
+        assert_eq!(module.customs.iter().count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_empty_module_import() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "" "log" (func $log (param i32)))
+                (func (export "run") (param i32)
+                    local.get 0
+                    call $log)
+                (@custom "__extern_ref_data__log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+            "#,
+        )?;
+        let module = Module::from_buffer(&transform(&wasm)?)?;
+
+        let log = module.imports.get_func("", "log")?;
+        assert_eq!(signature(&module, log), (vec![ValType::Externref], vec![]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_namespaced_imports() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "dom.node" "append" (func (param i32)))
+                (import "wasi:io/streams" "write" (func (param i32 i32)))
+                (@custom "__extern_ref_data_dom.node_append"
+                    "{\"name\":\"append\",\"argIndicies\":[0],\"retIsExternRef\":false}")
+                (@custom "__extern_ref_data_wasi:io/streams_write"
+                    "{\"name\":\"write\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+            "#,
+        )?;
+        let module = Module::from_buffer(&transform(&wasm)?)?;
+
+        let append = module.imports.get_func("dom.node", "append")?;
+        assert_eq!(
+            signature(&module, append),
+            (vec![ValType::Externref], vec![])
+        );
+        let write = module.imports.get_func("wasi:io/streams", "write")?;
+        assert_eq!(
+            signature(&module, write),
+            (vec![ValType::Externref, ValType::I32], vec![])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_parsed_module() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32)))
+                (func (export "identity") (param i32) (result i32)
+                    local.get 0
+                    call $log
+                    local.get 0)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}")
+                (@custom "__extern_ref_data_identity"
+                    "{\"name\":\"identity\",\"argIndicies\":[0],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+
+        let mut module = Module::from_buffer(&wasm)?;
+        transform_module(&mut module)?;
+        assert_eq!(module.emit_wasm(), transform(&wasm)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_export() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (func (export "identity") (param i32 f32) (result i32)
+                    local.get 0)
+                (@custom "__extern_ref_data_identity"
+                    "{\"name\":\"identity\",\"argIndicies\":[0],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+        let module = Module::from_buffer(&transform(&wasm)?)?;
+
+        let identity = exported_func(&module, "identity");
+        assert_eq!(
+            signature(&module, identity),
+            (
+                vec![ValType::Externref, ValType::F32],
+                vec![ValType::Externref]
+            )
+        );
+        assert_eq!(module.customs.iter().count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_manifest() -> Result<()> {
+        // The linker merges the array of each crate into a single section.
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32)))
+                (func (export "identity") (param i32) (result i32)
+                    local.get 0
+                    call $log
+                    local.get 0)
+                (@custom "__extern_ref_manifest"
+                    "[{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false,\"module\":\"console\"}]"
+                    "[{\"name\":\"identity\",\"argIndicies\":[0],\"retIsExternRef\":true}]"))
+            "#,
+        )?;
+        let module = Module::from_buffer(&transform(&wasm)?)?;
+
+        let log = module.imports.get_func("console", "log")?;
+        assert_eq!(signature(&module, log), (vec![ValType::Externref], vec![]));
+
+        let identity = exported_func(&module, "identity");
+        assert_eq!(
+            signature(&module, identity),
+            (vec![ValType::Externref], vec![ValType::Externref])
+        );
+        assert_eq!(module.customs.iter().count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn grouped_sections() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32)))
+                (func (export "identity") (param i32) (result i32)
+                    local.get 0
+                    call $log
+                    local.get 0)
+                (@custom "__extern_ref_data_debug@console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false,\"group\":\"debug\"}")
+                (@custom "__extern_ref_data_dom@identity"
+                    "{\"name\":\"identity\",\"argIndicies\":[0],\"retIsExternRef\":true,\"group\":\"dom\"}"))
+            "#,
+        )?;
+        let (wasm, report) = transform_with_report(&wasm, &Config::default())?;
+        let module = Module::from_buffer(&wasm)?;
+
+        let log = module.imports.get_func("console", "log")?;
+        assert_eq!(signature(&module, log), (vec![ValType::Externref], vec![]));
+        assert_eq!(
+            signature(&module, exported_func(&module, "identity")),
+            (vec![ValType::Externref], vec![ValType::Externref])
+        );
+
+        // Tools can pick out the functions of a group from the report.
+        let groups: Vec<_> = report
+            .functions
+            .iter()
+            .map(|function| (function.name.as_str(), function.group.as_deref()))
+            .collect();
+        assert_eq!(groups, [("log", Some("debug")), ("identity", Some("dom"))]);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_mixed_encodings() -> Result<()> {
+        // Crates migrating to the binary encoding are linked with crates still using JSON.
+        let binary = |data: &str| -> Result<Vec<u8>> {
+            Ok(serde_json::from_str::<FunctionData>(data)?.to_binary())
+        };
+        let mut module = Module::from_buffer(&wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32)))
+                (import "console" "warn" (func $warn (param i32)))
+                (func (export "identity") (param i32) (result i32)
+                    local.get 0
+                    call $log
+                    local.get 0
+                    call $warn
+                    local.get 0)
+                (func (export "first") (param i32) (result i32)
+                    local.get 0)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+            "#,
+        )?)?;
+        module.customs.add(RawCustomSection {
+            name: "__extern_ref_data_identity".into(),
+            data: binary(r#"{"name":"identity","argIndicies":[0],"retIsExternRef":true}"#)?,
+        });
+        let mut manifest = binary(
+            r#"{"name":"warn","argIndicies":[0],"retIsExternRef":false,"module":"console"}"#,
+        )?;
+        manifest.extend(b"{\"name\":\"first\",\"argIndicies\":[0],\"retIsExternRef\":true}\n");
+        module.customs.add(RawCustomSection {
+            name: "__extern_ref_manifest".into(),
+            data: manifest,
+        });
+
+        let module = Module::from_buffer(&transform(&module.emit_wasm())?)?;
+        for name in ["log", "warn"] {
+            let import = module.imports.get_func("console", name)?;
+            assert_eq!(
+                signature(&module, import),
+                (vec![ValType::Externref], vec![])
+            );
+        }
+        for name in ["identity", "first"] {
+            assert_eq!(
+                signature(&module, exported_func(&module, name)),
+                (vec![ValType::Externref], vec![ValType::Externref])
+            );
+        }
+        assert_eq!(module.customs.iter().count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn export_aliases() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (func (export "identity") (param i32) (result i32)
+                    local.get 0)
+                (@custom "__extern_ref_data_identity"
+                    "{\"name\":\"identity\",\"argIndicies\":[0],\"retIsExternRef\":true,\"aliases\":[\"id\",\"same\"]}"))
+            "#,
+        )?;
+        let module = Module::from_buffer(&transform(&wasm)?)?;
+
+        let identity = exported_func(&module, "identity");
+        assert_eq!(exported_func(&module, "id"), identity);
+        assert_eq!(exported_func(&module, "same"), identity);
+        assert_eq!(
+            signature(&module, identity),
+            (vec![ValType::Externref], vec![ValType::Externref])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_result_exports() -> Result<()> {
+        // The `Ok` variant is an externref in the first case and the `Err` variant in the second.
+        for (ok, err) in [(true, false), (false, true)] {
+            // Returns `Ok` for `0` and `Err` for anything else with the argument as the handle.
+            let wasm = wat::parse_str(format!(
+                r#"
+                (module
+                    (func (export "parse") (param i32) (result i32 i32)
+                        local.get 0
+                        i32.const 0
+                        i32.ne
+                        local.get 0)
+                    (@custom "__extern_ref_data_parse"
+                        "{{\"name\":\"parse\",\"argIndicies\":[],\"retIsExternRef\":false,\"retResult\":{{\"ok\":{ok},\"err\":{err}}}}}"))
+                "#
+            ))?;
+            let transformed = transform(&wasm)?;
+            wasmparser::validate(&transformed)?;
+
+            let module = Module::from_buffer(&transformed)?;
+            let parse = exported_func(&module, "parse");
+            assert_eq!(
+                signature(&module, parse),
+                (vec![ValType::I32], vec![ValType::I32, ValType::Externref])
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_64_bit_handles() -> Result<()> {
+        // `ExternRef` is an `i64` on `wasm64`, the table is still indexed with an `i32`.
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "env" "clone" (func $clone (param i64) (result i64)))
+                (func (export "identity") (param i64) (result i64)
+                    local.get 0
+                    call $clone)
+                (@custom "__extern_ref_data_env_clone"
+                    "{\"name\":\"clone\",\"argIndicies\":[0],\"retIsExternRef\":true}")
+                (@custom "__extern_ref_data_identity"
+                    "{\"name\":\"identity\",\"argIndicies\":[0],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+        let module = Module::from_buffer(&transform(&wasm)?)?;
+
+        let clone = module.imports.get_func("env", "clone")?;
+        assert_eq!(
+            signature(&module, clone),
+            (vec![ValType::Externref], vec![ValType::Externref])
+        );
+
+        let identity = exported_func(&module, "identity");
+        assert_eq!(
+            signature(&module, identity),
+            (vec![ValType::Externref], vec![ValType::Externref])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn memory64_unsupported() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "env" "clone" (func $clone (param i64) (result i64)))
+                (memory i64 1)
+                (@custom "__extern_ref_data_env_clone"
+                    "{\"name\":\"clone\",\"argIndicies\":[0],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+        let error = transform(&wasm).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("64-bit memory (memory64) can't be transformed"),
+            "{error:#}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn untouched_without_data_sections() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func (param i32))))
+            "#,
+        )?;
+        let module = Module::from_buffer(&transform(&wasm)?)?;
+
+        let log = module.imports.get_func("console", "log")?;
+        assert_eq!(signature(&module, log), (vec![ValType::I32], vec![]));
+        assert_eq!(module.tables.iter().count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_arg_index() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func (param i32)))
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[1],\"retIsExternRef\":false}"))
+            "#,
+        )?;
+        let error = transform(&wasm).unwrap_err();
+        assert!(format!("{error:#}").contains("out of range"));
+
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func (param f64)))
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+            "#,
+        )?;
+        let error = transform(&wasm).unwrap_err();
+        assert!(format!("{error:#}").contains("expected an i32 or i64 externref handle"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_host_signatures() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32 f64)))
+                (func (export "run") (param i32)
+                    local.get 0
+                    f64.const 1
+                    call $log)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+            "#,
+        )?;
+        let host_signatures = |json: &str| -> Result<Config> {
+            Ok(Config {
+                host_signatures: Some(serde_json::from_str(json)?),
+                ..Default::default()
+            })
+        };
+
+        let matching = host_signatures(
+            r#"{"imports":[{"module":"console","name":"log","params":["externref","f64"]}]}"#,
+        )?;
+        transform_with_config(&wasm, &matching)?;
+
+        let mismatching = host_signatures(
+            r#"{"imports":[{"module":"console","name":"log","params":["externref","f32"]}]}"#,
+        )?;
+        let error = transform_with_config(&wasm, &mismatching).unwrap_err();
+        assert!(error.to_string().contains("console.log"));
+
+        let undeclared = host_signatures(r#"{"imports":[]}"#)?;
+        assert!(transform_with_config(&wasm, &undeclared).is_err());
+
+        Ok(())
+    }
+
+    /// The contents of the type section of a module.
+    fn type_section(wasm: &[u8]) -> Result<&[u8]> {
+        for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+            if let wasmparser::Payload::TypeSection(types) = payload? {
+                let range = types.range();
+                return Ok(&wasm[range.start as usize..range.end as usize]);
+            }
+        }
+        anyhow::bail!("missing type section")
+    }
+
+    #[test]
+    fn encode_for_proposal() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (func (export "identity") (param i32) (result i32)
+                    local.get 0)
+                (@custom "__extern_ref_data_identity"
+                    "{\"name\":\"identity\",\"argIndicies\":[0],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+        let (shorthand, typed) = ([0x6f], [0x63, 0x6f]);
+
+        let reference_types = transform(&wasm)?;
+        wasmparser::validate(&reference_types)?;
+        let types = type_section(&reference_types)?;
+        assert!(types.windows(1).any(|ty| ty == shorthand));
+        assert!(!types.windows(2).any(|ty| ty == typed));
+
+        let config = Config {
+            proposal: Proposal::FunctionReferences,
+            ..Default::default()
+        };
+        let function_references = transform_with_config(&wasm, &config)?;
+        wasmparser::validate(&function_references)?;
+        let types = type_section(&function_references)?;
+        assert!(types.windows(2).any(|ty| ty == typed));
+
+        Ok(())
+    }
+
+    #[test]
+    fn name_rewritten_functions() -> Result<()> {
+        // Without `$` identifiers the input has no name section.
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func (param i32)))
+                (func (export "run") (param i32)
+                    local.get 0
+                    call 0)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}")
+                (@custom "__extern_ref_data_run"
+                    "{\"name\":\"run\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+            "#,
+        )?;
+        let module = Module::from_buffer(&transform(&wasm)?)?;
+
+        let mut names: Vec<_> = module
+            .funcs
+            .iter()
+            .filter_map(|func| func.name.as_deref())
+            .collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                "__externref_table_alloc",
+                "__externref_table_get",
+                "console.log",
+                "console.log externref shim",
+                "run",
+                "run externref shim",
+            ]
+        );
+
+        let config = Config {
+            strip_names: true,
+            ..Default::default()
+        };
+        let stripped = transform_with_config(&wasm, &config)?;
+        for payload in wasmparser::Parser::new(0).parse_all(&stripped) {
+            if let wasmparser::Payload::CustomSection(section) = payload? {
+                assert_ne!(section.name(), "name");
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn producers_entry() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32)))
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+            "#,
+        )?;
+
+        fn processed_by(wasm: &[u8]) -> Result<Vec<String>> {
+            let mut tools = Vec::new();
+            for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+                let wasmparser::Payload::CustomSection(section) = payload? else {
+                    continue;
+                };
+                let wasmparser::KnownCustom::Producers(producers) = section.as_known() else {
+                    continue;
+                };
+                for field in producers {
+                    let field = field?;
+                    if field.name != "processed-by" {
+                        continue;
+                    }
+                    for value in field.values {
+                        let value = value?;
+                        tools.push(format!("{} {}", value.name, value.version));
+                    }
+                }
+            }
+            Ok(tools)
+        }
+
+        let entry = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        let once = transform(&wasm)?;
+        let twice = transform(&once)?;
+        for transformed in [once, twice] {
+            let tools = processed_by(&transformed)?;
+            assert_eq!(
+                tools.iter().filter(|tool| **tool == entry).count(),
+                1,
+                "{tools:?}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn trace_imports() -> Result<()> {
+        let wat = |memory: &str| {
+            wat::parse_str(format!(
+                r#"
+                (module
+                    (import "console" "log" (func $log (param i32)))
+                    {memory}
+                    (func (export "run") (param i32)
+                        local.get 0
+                        call $log)
+                    (@custom "__extern_ref_data_console_log"
+                        "{{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}}"))
+                "#
+            ))
+        };
+        let config = Config {
+            trace_imports: true,
+            ..Default::default()
+        };
+
+        let transformed = transform_with_config(&wat("(memory 1)")?, &config)?;
+        wasmparser::validate(&transformed)?;
+        let module = Module::from_buffer(&transformed)?;
+
+        let trace = module.imports.get_func("__externref", "trace")?;
+        assert_eq!(
+            signature(&module, trace),
+            (vec![ValType::I32, ValType::I32], vec![])
+        );
+
+        // The name is stored in a page added after the initial one.
+        let memory = module.memories.iter().next().unwrap();
+        assert_eq!(memory.initial, 2);
+        let data = module.data.iter().next().unwrap();
+        assert_eq!(data.value, b"console.log");
+
+        // The shim passes the address and length of the name to the trace function first.
+        let run = exported_func(&module, "run");
+        let FunctionKind::Local(run) = &module.funcs.get(run).kind else {
+            panic!("run is not a local function");
+        };
+        let shim = run
+            .block(run.entry_block())
+            .instrs
+            .iter()
+            .find_map(|(instr, _)| match instr {
+                Instr::Call(call) => Some(call.func),
+                _ => None,
+            })
+            .unwrap();
+        let FunctionKind::Local(shim) = &module.funcs.get(shim).kind else {
+            panic!("the shim is not a local function");
+        };
+        let instrs: Vec<_> = shim
+            .block(shim.entry_block())
+            .instrs
+            .iter()
+            .take(3)
+            .map(|(instr, _)| instr.clone())
+            .collect();
+        assert!(
+            matches!(
+                instrs.as_slice(),
+                [
+                    Instr::Const(Const { value: Value::I32(65536) }),
+                    Instr::Const(Const { value: Value::I32(11) }),
+                    Instr::Call(call),
+                ] if call.func == trace
+            ),
+            "{instrs:?}"
+        );
+
+        // Without tracing, nothing is added.
+        let module = Module::from_buffer(&transform(&wat("(memory 1)")?)?)?;
+        assert!(module.imports.get_func("__externref", "trace").is_err());
+        assert_eq!(module.memories.iter().next().unwrap().initial, 1);
+
+        // The names need a memory to be stored in.
+        assert!(transform_with_config(&wat("")?, &config).is_err());
+        assert!(transform_with_config(&wat("(memory 1 1)")?, &config).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_nulls() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "dom" "append" (func $append (param i32 i32)))
+                (func (export "find") (param i32 i32) (result i32)
+                    local.get 0
+                    local.get 1
+                    call $append
+                    local.get 1)
+                (@custom "__extern_ref_data_dom_append"
+                    "{\"name\":\"append\",\"argIndicies\":[0,1],\"retIsExternRef\":false,\"nullableArgs\":[1]}")
+                (@custom "__extern_ref_data_find"
+                    "{\"name\":\"find\",\"argIndicies\":[0,1],\"retIsExternRef\":true,\"nullableArgs\":[1],\"retNullable\":true}"))
+            "#,
+        )?;
+        let config = Config {
+            check_nulls: true,
+            ..Default::default()
+        };
+        // The names of the functions a shim calls, in order.
+        let checked = |module: &Module, func_id: FunctionId| {
+            let FunctionKind::Local(func) = &module.funcs.get(func_id).kind else {
+                panic!("not a local function");
+            };
+            func.block(func.entry_block())
+                .instrs
+                .iter()
+                .filter_map(|(instr, _)| match instr {
+                    Instr::Call(call) => module.funcs.get(call.func).name.clone(),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let transformed = transform_with_config(&wasm, &config)?;
+        wasmparser::validate(&transformed)?;
+        let module = Module::from_buffer(&transformed)?;
+        let null_check = module.funcs.by_name("externref null check").unwrap();
+        assert_eq!(
+            signature(&module, null_check),
+            (vec![ValType::Externref], vec![ValType::Externref])
+        );
+
+        // Only the first argument can't be null, in either direction.
+        let shim = module.funcs.by_name("append").unwrap();
+        assert_eq!(
+            checked(&module, shim),
+            [
+                "__externref_table_get",
+                "externref null check",
+                "__externref_table_get",
+                "dom.append"
+            ]
+        );
+        let wrapper = exported_func(&module, "find");
+        assert_eq!(
+            checked(&module, wrapper),
+            [
+                "externref null check",
+                "__externref_table_alloc",
+                "__externref_table_alloc",
+                "find",
+                "__externref_table_get"
+            ]
+        );
+
+        // Without the option nothing is checked.
+        let module = Module::from_buffer(&transform(&wasm)?)?;
+        assert!(module.funcs.by_name("externref null check").is_none());
+
+        // Lowered handles aren't references to check.
+        let config = Config {
+            lower_to_i32: true,
+            ..config
+        };
+        assert!(transform_with_config(&wasm, &config).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn substitute_module_placeholders() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "${HOST}" "log" (func (param i32)))
+                (import "${HOST}_dom" "append" (func (param i32 i32)))
+                (import "console" "error" (func (param i32)))
+                (@custom "__extern_ref_data_${HOST}_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+            "#,
+        )?;
+        let config = Config {
+            substitutions: HashMap::from([("HOST".into(), "browserEnv".into())]),
+            ..Default::default()
+        };
+        let module = Module::from_buffer(&transform_with_config(&wasm, &config)?)?;
+
+        let modules: Vec<_> = module
+            .imports
+            .iter()
+            .map(|import| (import.module.as_str(), import.name.as_str()))
+            .collect();
+        assert!(modules.contains(&("browserEnv", "log")));
+        assert!(modules.contains(&("browserEnv_dom", "append")));
+        assert!(modules.contains(&("console", "error")));
+
+        // The data section is matched against the declared module name.
+        let log = module.imports.get_func("browserEnv", "log")?;
+        assert_eq!(signature(&module, log), (vec![ValType::Externref], vec![]));
+
+        assert!(transform(&wasm).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn stub_missing_optional_import() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32)))
+                (import "console" "warn" (func $warn (param i32)))
+                (func (export "run") (param i32)
+                    local.get 0
+                    call $log)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false,\"optional\":true}")
+                (@custom "__extern_ref_data_console_warn"
+                    "{\"name\":\"warn\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+            "#,
+        )?;
+        let config = Config {
+            missing_imports: HashSet::from(["console.log".into()]),
+            ..Default::default()
+        };
+        let module = Module::from_buffer(&transform_with_config(&wasm, &config)?)?;
+
+        assert!(module.imports.find("console", "log").is_none());
+        assert!(module.imports.find("console", "warn").is_some());
+
+        let stub = module
+            .funcs
+            .by_name("missing optional import console.log")
+            .expect("missing stub");
+        let FunctionKind::Local(stub) = &module.funcs.get(stub).kind else {
+            panic!("stub isn't a local function");
+        };
+        let body: Vec<_> = stub
+            .block(stub.entry_block())
+            .instrs
+            .iter()
+            .map(|(instr, _)| instr.clone())
+            .collect();
+        assert!(matches!(
+            body.as_slice(),
+            [Instr::Unreachable(Unreachable {})]
+        ));
+
+        // Required imports can't be stubbed.
+        let config = Config {
+            missing_imports: HashSet::from(["console.warn".into()]),
+            ..Default::default()
+        };
+        let error = transform_with_config(&wasm, &config).unwrap_err();
+        assert!(error.to_string().contains("console.warn"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reentrant_functions() -> Result<()> {
+        let module = |reentrant: bool| {
+            wat::parse_str(format!(
+                r#"
+                (module
+                    (import "events" "dispatch" (func $dispatch (param i32)))
+                    (func (export "on_event") (param i32)
+                        local.get 0
+                        call $dispatch)
+                    (@custom "__extern_ref_data_events_dispatch"
+                        "{{\"name\":\"dispatch\",\"argIndicies\":[0],\"retIsExternRef\":false,\"reentrant\":{reentrant}}}")
+                    (@custom "__extern_ref_data_on_event"
+                        "{{\"name\":\"on_event\",\"argIndicies\":[0],\"retIsExternRef\":false,\"reentrant\":{reentrant}}}"))
+                "#
+            ))
+        };
+
+        let (transformed, report) = transform_with_report(&module(true)?, &Config::default())?;
+        assert!(report.functions.iter().all(|function| function.reentrant));
+
+        // The added functions keep no state between calls, so nothing needs to be saved around
+        // calls the host may reenter.
+        let (plain, report) = transform_with_report(&module(false)?, &Config::default())?;
+        assert!(report.functions.iter().all(|function| !function.reentrant));
+        assert_eq!(transformed, plain);
+        assert_eq!(Module::from_buffer(&transformed)?.globals.iter().count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn skip_functions() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32)))
+                (import "console" "warn" (func $warn (param i32)))
+                (func (export "identity") (param i32) (result i32)
+                    local.get 0
+                    call $log
+                    local.get 0
+                    call $warn
+                    local.get 0)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}")
+                (@custom "__extern_ref_data_console_warn"
+                    "{\"name\":\"warn\",\"argIndicies\":[0],\"retIsExternRef\":false}")
+                (@custom "__extern_ref_data_identity"
+                    "{\"name\":\"identity\",\"argIndicies\":[0],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+        let config = Config {
+            skip: HashSet::from(["console.log".into(), "identity".into()]),
+            ..Default::default()
+        };
+        let (transformed, report) = transform_with_report(&wasm, &config)?;
+        let module = Module::from_buffer(&transformed)?;
+
+        let log = module.imports.get_func("console", "log")?;
+        assert_eq!(signature(&module, log), (vec![ValType::I32], vec![]));
+        assert_eq!(
+            signature(&module, exported_func(&module, "identity")),
+            (vec![ValType::I32], vec![ValType::I32])
+        );
+        let warn = module.imports.get_func("console", "warn")?;
+        assert_eq!(signature(&module, warn), (vec![ValType::Externref], vec![]));
+
+        // The skipped functions' sections are removed without being reported as unmatched.
+        assert_eq!(report.sections_stripped.len(), 3);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parallel_matches_serial() -> Result<()> {
+        let mut wat = String::from("(module\n");
+        for i in 0..64 {
+            wat.push_str(&format!(
+                r#"(import "env" "f{i}" (func (param i32 i64) (result i32)))
+                (@custom "__extern_ref_data_env_f{i}"
+                    "{{\"name\":\"f{i}\",\"argIndicies\":[0],\"retIsExternRef\":true}}")
+                "#
+            ));
+        }
+        for i in 0..64 {
+            wat.push_str(&format!(
+                r#"(func (export "g{i}") (param i32) (result i32) local.get 0)
+                (@custom "__extern_ref_data_g{i}"
+                    "{{\"name\":\"g{i}\",\"argIndicies\":[0],\"retIsExternRef\":false}}")
+                "#
+            ));
+        }
+        wat.push(')');
+        let wasm = wat::parse_str(&wat)?;
+
+        let config = |threads| Config {
+            threads,
+            check_nulls: true,
+            ..Default::default()
+        };
+        let serial = transform_with_config(&wasm, &config(1))?;
+        for threads in [0, 4] {
+            let parallel = transform_with_config(&wasm, &config(threads))?;
+            assert_eq!(serial, parallel);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_callback() -> Result<()> {
+        // A closure shim registered with the host by its table index.
+        let callback = r#"
+            (@custom "__extern_ref_data_on_click"
+                "{\"name\":\"on_click\",\"argIndicies\":[0],\"retIsExternRef\":false,\"callback\":true}")
+        "#;
+        let wasm = wat::parse_str(format!(
+            r#"
+            (module
+                (import "dom" "register" (func $register (param i32)))
+                (table 2 funcref)
+                (elem (i32.const 1) $on_click)
+                (func $on_click (param i32))
+                (func (export "main")
+                    i32.const 1
+                    call $register)
+                {callback})
+            "#
+        ))?;
+        let transformed = transform(&wasm)?;
+        wasmparser::validate(&transformed)?;
+
+        let module = Module::from_buffer(&transformed)?;
+        let element = module
+            .elements
+            .iter()
+            .next()
+            .expect("missing element segment");
+        let shim = element.members[0].expect("missing table entry");
+        assert_eq!(signature(&module, shim), (vec![ValType::Externref], vec![]));
+
+        // A callback that isn't in a table can't be called by the host.
+        let wasm = wat::parse_str(format!("(module (func $on_click (param i32)) {callback})"))?;
+        let error = transform(&wasm).unwrap_err();
+        assert!(error.to_string().contains("on_click"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_indirect_function() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (table (export "callbacks") 1 funcref)
+                (elem (i32.const 0) $on_event)
+                (func $on_event (param i32 i32))
+                (@custom "__extern_ref_data_on_event"
+                    "{\"name\":\"on_event\",\"argIndicies\":[1],\"retIsExternRef\":false}"))
+            "#,
+        )?;
+        let module = Module::from_buffer(&transform(&wasm)?)?;
+
+        let element = module
+            .elements
+            .iter()
+            .next()
+            .expect("missing element segment");
+        let callback = element.members[0].expect("missing table entry");
+        assert_eq!(
+            signature(&module, callback),
+            (vec![ValType::I32, ValType::Externref], vec![])
+        );
+
+        // The original function is still called by the wrapper.
+        let on_event = module.funcs.by_name("on_event").expect("missing function");
+        assert_ne!(callback, on_event);
+        assert_eq!(
+            signature(&module, on_event),
+            (vec![ValType::I32, ValType::I32], vec![])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_export_ref() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "__externref_export_ref" "onEvent" (func $on_event_ref (result i32)))
+                (func (export "onEvent") (param i32))
+                (func (export "register") (result i32)
+                    call $on_event_ref)
+                (@custom "__extern_ref_data_onEvent"
+                    "{\"name\":\"onEvent\",\"argIndicies\":[0],\"retIsExternRef\":false}")
+                (@custom "__extern_ref_data___externref_export_ref_onEvent"
+                    "{\"name\":\"onEvent\",\"argIndicies\":[],\"retIsExternRef\":true,\"exportRef\":\"onEvent\"}"))
+            "#,
+        )?;
+        let transformed = transform(&wasm)?;
+        wasmparser::validate(&transformed)?;
+        let module = Module::from_buffer(&transformed)?;
+
+        // The intrinsic never reaches the host, the reference is converted by it instead.
+        assert!(module
+            .imports
+            .get_func("__externref_export_ref", "onEvent")
+            .is_err());
+        let from_funcref = module.imports.get_func("__externref", "from_funcref")?;
+        assert_eq!(
+            signature(&module, from_funcref),
+            (vec![ValType::Funcref], vec![ValType::Externref])
+        );
+
+        // The reference is to the rewritten export the host sees.
+        let on_event = exported_func(&module, "onEvent");
+        assert_eq!(
+            signature(&module, on_event),
+            (vec![ValType::Externref], vec![])
+        );
+        let on_event_ref = module
+            .funcs
+            .by_name("on_event_ref")
+            .expect("missing function");
+        let FunctionKind::Local(local) = &module.funcs.get(on_event_ref).kind else {
+            panic!("the export reference is still imported");
+        };
+        let referenced: Vec<_> = local
+            .block(local.entry_block())
+            .instrs
+            .iter()
+            .filter_map(|(instr, _)| match instr {
+                Instr::RefFunc(ref_func) => Some(ref_func.func),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(referenced, [on_event]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_intrinsics() -> Result<()> {
+        let wat = r#"
+            (module
+                (import "__externref_intrinsic" "ref.null" (func $ref_null (result i32)))
+                (import "__externref_intrinsic" "ref.is_null" (func $ref_is_null (param i32) (result i32)))
+                (func (export "check") (result i32)
+                    call $ref_null
+                    call $ref_is_null)
+                (@custom "__extern_ref_data___externref_intrinsic_ref.null"
+                    "{\"name\":\"ref.null\",\"argIndicies\":[],\"retIsExternRef\":true,\"intrinsic\":\"ref.null\"}")
+                (@custom "__extern_ref_data___externref_intrinsic_ref.is_null"
+                    "{\"name\":\"ref.is_null\",\"argIndicies\":[0],\"retIsExternRef\":false,\"intrinsic\":\"ref.is_null\"}"))
+        "#;
+        let body = |module: &Module, name: &str| {
+            let func_id = module.funcs.by_name(name).expect("missing function");
+            let FunctionKind::Local(local) = &module.funcs.get(func_id).kind else {
+                panic!("the intrinsic {name} is still imported");
+            };
+            local
+                .block(local.entry_block())
+                .instrs
+                .iter()
+                .map(|(instr, _)| instr.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let (transformed, report) =
+            transform_with_report(&wat::parse_str(wat)?, &Config::default())?;
+        wasmparser::validate(&transformed)?;
+        let module = Module::from_buffer(&transformed)?;
+
+        // The host doesn't provide anything, the bodies are made of the intrinsic instructions.
+        assert_eq!(module.imports.iter().count(), 0);
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        assert!(body(&module, "ref_null")
+            .iter()
+            .any(|instr| matches!(instr, Instr::RefNull(_))));
+        assert!(body(&module, "ref_is_null")
+            .iter()
+            .any(|instr| matches!(instr, Instr::RefIsNull(_))));
+
+        // Lowered handles are compared with the null handle instead.
+        let config = Config {
+            lower_to_i32: true,
+            ..Default::default()
+        };
+        let transformed = transform_with_config(&wat::parse_str(wat)?, &config)?;
+        wasmparser::validate(&transformed)?;
+        let module = Module::from_buffer(&transformed)?;
+        assert_eq!(module.imports.iter().count(), 0);
+        assert_eq!(module.tables.iter().count(), 0);
+        assert!(body(&module, "ref_is_null").iter().any(|instr| matches!(
+            instr,
+            Instr::Unop(Unop {
+                op: UnaryOp::I32Eqz
+            })
+        )));
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_assert_transformed() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "__externref_intrinsic" "transformed" (func $transformed (result i32)))
+                (func (export "init")
+                    call $transformed
+                    i32.eqz
+                    if
+                        unreachable
+                    end)
+                (@custom "__extern_ref_data___externref_intrinsic_transformed"
+                    "{\"name\":\"transformed\",\"argIndicies\":[],\"retIsExternRef\":false,\"intrinsic\":\"transformed\"}"))
+            "#,
+        )?;
+
+        for config in [
+            Config::default(),
+            Config {
+                lower_to_i32: true,
+                ..Default::default()
+            },
+        ] {
+            let (transformed, report) = transform_with_report(&wasm, &config)?;
+            wasmparser::validate(&transformed)?;
+            let module = Module::from_buffer(&transformed)?;
+
+            // The check always passes, and nothing else is needed for it.
+            assert_eq!(module.imports.iter().count(), 0);
+            assert_eq!(module.tables.iter().count(), 0);
+            assert!(!report.table_created);
+            let func_id = module.funcs.by_name("transformed").unwrap();
+            let FunctionKind::Local(local) = &module.funcs.get(func_id).kind else {
+                panic!("the intrinsic is still imported");
+            };
+            let instrs = &local.block(local.entry_block()).instrs;
+            assert!(matches!(
+                instrs.as_slice(),
+                [(
+                    Instr::Const(Const {
+                        value: Value::I32(1)
+                    }),
+                    _
+                )]
+            ));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn unused_intrinsics() -> Result<()> {
+        // `ExternRef::null` links in the sections of the other intrinsics, which aren't imported.
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "__externref_intrinsic" "ref.null" (func $ref_null (result i32)))
+                (func (export "null") (result i32)
+                    call $ref_null)
+                (@custom "__extern_ref_data___externref_intrinsic_ref.null"
+                    "{\"name\":\"ref.null\",\"argIndicies\":[],\"retIsExternRef\":true,\"intrinsic\":\"ref.null\"}")
+                (@custom "__extern_ref_data___externref_intrinsic_transformed"
+                    "{\"name\":\"transformed\",\"argIndicies\":[],\"retIsExternRef\":false,\"intrinsic\":\"transformed\"}"))
+            "#,
+        )?;
+        let (transformed, report) = transform_with_report(&wasm, &Config::default())?;
+        wasmparser::validate(&transformed)?;
+
+        assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+        assert!(report
+            .sections_stripped
+            .contains(&"__extern_ref_data___externref_intrinsic_transformed".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn transform_report() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "${HOST}" "log" (func $log (param i32)))
+                (import "${HOST}" "Foo" (func $foo (result i32)))
+                (func (export "identity") (param i32) (result i32)
+                    local.get 0
+                    call $log
+                    call $foo)
+                (@custom "__extern_ref_data_${HOST}_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}")
+                (@custom "__extern_ref_data_${HOST}_Foo"
+                    "{\"name\":\"Foo\",\"argIndicies\":[],\"retIsExternRef\":true,\"constructor\":true}")
+                (@custom "__extern_ref_data_identity"
+                    "{\"name\":\"identity\",\"argIndicies\":[0],\"retIsExternRef\":true}")
+                (@custom "__extern_ref_data_removed"
+                    "{\"name\":\"removed\",\"argIndicies\":[],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+        let config = Config {
+            substitutions: HashMap::from([("HOST".into(), "console".into())]),
+            ..Config::default()
+        };
+        let (_, report) = transform_with_report(&wasm, &config)?;
+
+        assert_eq!(
+            serde_json::to_value(&report)?,
+            serde_json::json!({
+                "functions": [
+                    { "name": "log", "module": "console", "kind": "import", "argIndices": [0] },
+                    {
+                        "name": "Foo",
+                        "module": "console",
+                        "kind": "import",
+                        "argIndices": [],
+                        "constructor": true,
+                    },
+                    { "name": "identity", "kind": "export", "argIndices": [0] },
+                ],
+                "sectionsStripped": [
+                    "__extern_ref_data_${HOST}_log",
+                    "__extern_ref_data_${HOST}_Foo",
+                    "__extern_ref_data_identity",
+                    "__extern_ref_data_removed",
+                ],
+                "tableCreated": true,
+                "warnings": [
+                    "__extern_ref_data_removed doesn't describe any import or export",
+                ],
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn refcount_hooks() -> Result<()> {
+        // What the `refcount` feature of the `externref` crate compiles to, an export cloning and
+        // dropping the reference it's given.
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "__externref" "clone" (func $clone (param i32) (result i32)))
+                (import "__externref" "drop" (func $drop (param i32)))
+                (func (export "copy") (param i32) (result i32)
+                    local.get 0
+                    call $clone
+                    local.get 0
+                    call $drop)
+                (@custom "__extern_ref_data___externref_clone"
+                    "{\"name\":\"clone\",\"argIndicies\":[0],\"retIsExternRef\":true}")
+                (@custom "__extern_ref_data___externref_drop"
+                    "{\"name\":\"drop\",\"argIndicies\":[0],\"retIsExternRef\":false}")
+                (@custom "__extern_ref_data_copy"
+                    "{\"name\":\"copy\",\"argIndicies\":[0],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+        let transformed = transform(&wasm)?;
+        wasmparser::validate(&transformed)?;
+        let module = Module::from_buffer(&transformed)?;
+
+        // The host receives the reference itself rather than its handle.
+        let clone = module.imports.get_func("__externref", "clone")?;
+        let drop = module.imports.get_func("__externref", "drop")?;
+        assert_eq!(
+            signature(&module, clone),
+            (vec![ValType::Externref], vec![])
+        );
+        assert_eq!(signature(&module, drop), (vec![ValType::Externref], vec![]));
+
+        let mut called = HashSet::new();
+        for (_, local) in module.funcs.iter_local() {
+            walrus::ir::dfs_in_order(&mut Calls(&mut called), local, local.entry_block());
+        }
+        assert!(called.contains(&clone));
+        assert!(called.contains(&drop));
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_reference_types() -> Result<()> {
+        // The features are a count followed by a prefix and a name for each.
+        let module = |features: &str| {
+            wat::parse_str(format!(
+                r#"
+                (module
+                    (import "console" "log" (func (param i32)))
+                    (@custom "target_features" "{features}")
+                    (@custom "__extern_ref_data_console_log"
+                        "{{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}}"))
+                "#
+            ))
+        };
+
+        let disallowed = module(r"\02+\0fmutable-globals-\0freference-types")?;
+        let err = transform(&disallowed).unwrap_err().to_string();
+        assert!(
+            err.contains("reference-types target feature disallowed"),
+            "{err}"
+        );
+        assert!(err.contains("-C target-feature=+reference-types"), "{err}");
+
+        // Handles lowered to `i32`s don't need the feature.
+        let config = Config {
+            lower_to_i32: true,
+            ..Config::default()
+        };
+        transform_with_config(&disallowed, &config)?;
+
+        // Older rustc versions don't list the feature, while the module itself doesn't need it.
+        let unlisted = module(r"\01+\0fmutable-globals")?;
+        let (_, report) = transform_with_report(&unlisted, &Config::default())?;
+        assert_eq!(report.warnings.len(), 1);
+        assert!(
+            report.warnings[0].contains("don't include reference-types"),
+            "{:?}",
+            report.warnings
+        );
+
+        transform(&module(r"\02+\0fmutable-globals+\0freference-types")?)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn lower_to_i32() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32 i32) (result i32)))
+                (func (export "run") (param i32) (result i32)
+                    local.get 0
+                    i32.const 1
+                    call $log)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":true}")
+                (@custom "__extern_ref_data_run"
+                    "{\"name\":\"run\",\"argIndicies\":[0],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+        let config = Config {
+            lower_to_i32: true,
+            ..Default::default()
+        };
+        let (transformed, report) = transform_with_report(&wasm, &config)?;
+
+        // Without reference types the module only validates if it doesn't use them.
+        let mut validator = wasmparser::Validator::new_with_features(WasmFeatures::WASM1);
+        validator.validate_all(&transformed)?;
+        let mut validator = wasmparser::Validator::new_with_features(WasmFeatures::WASM1);
+        assert!(validator.validate_all(&transform(&wasm)?).is_err());
+
+        let module = Module::from_buffer(&transformed)?;
+        let log = module.imports.get_func("console", "log")?;
+        assert_eq!(
+            signature(&module, log),
+            (vec![ValType::I32, ValType::I32], vec![ValType::I32])
+        );
+        let alloc = exported_func(&module, "__externref_alloc");
+        assert_eq!(signature(&module, alloc), (vec![], vec![ValType::I32]));
+        assert_eq!(module.customs.iter().count(), 0);
+        assert_eq!(report.functions.len(), 2);
+        assert!(!report.table_created);
+
+        let config = Config {
+            lower_to_i32: true,
+            trace_imports: true,
+            ..Default::default()
+        };
+        assert!(transform_with_config(&wasm, &config).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn mismatched_param_count() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "env" "log" (func $log (param i32)))
+                (func (export "run") (param i32)
+                    local.get 0
+                    call $log)
+                (@custom "__extern_ref_data_env_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false,\"paramCount\":3}")
+                (@custom "__extern_ref_data_run"
+                    "{\"name\":\"run\",\"argIndicies\":[0],\"retIsExternRef\":false,\"paramCount\":1}"))
+            "#,
+        )?;
+        let (_, report) = transform_with_report(&wasm, &Config::default())?;
+
+        assert_eq!(
+            report.warnings,
+            ["import env.log has 1 parameters, but was declared with 3"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn export_all() -> Result<()> {
+        // `wasm-ld --export-all` exports every function, including `env_log`, whose section name
+        // is the import's.
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "env" "log" (func $log (param i32)))
+                (func (export "env_log") (param i32))
+                (func (export "run") (param i32)
+                    local.get 0
+                    call $log)
+                (@custom "__extern_ref_data_env_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false,\"paramCount\":1}")
+                (@custom "__extern_ref_data_run"
+                    "{\"name\":\"run\",\"argIndicies\":[0],\"retIsExternRef\":false,\"paramCount\":1}"))
+            "#,
+        )?;
+        let (wasm, report) = transform_with_report(&wasm, &Config::default())?;
+        let module = Module::from_buffer(&wasm)?;
+
+        let log = module.imports.get_func("env", "log")?;
+        assert_eq!(signature(&module, log), (vec![ValType::Externref], vec![]));
+        let run = exported_func(&module, "run");
+        assert_eq!(signature(&module, run), (vec![ValType::Externref], vec![]));
+
+        assert_eq!(
+            signature(&module, exported_func(&module, "env_log")),
+            (vec![ValType::I32], vec![])
+        );
+        assert_eq!(
+            report.warnings,
+            ["skipped export env_log, __extern_ref_data_env_log also describes an import"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn abi_lowered_export() -> Result<()> {
+        // `fn hash(value: ExternRef, seed: u128) -> u32` takes two arguments in Rust, but the
+        // `u128` is lowered to two `i64`s.
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (func (export "hash") (param i32 i64 i64) (result i32)
+                    local.get 0)
+                (@custom "__extern_ref_data_hash"
+                    "{\"name\":\"hash\",\"argIndicies\":[0],\"retIsExternRef\":false,\"paramCount\":2}"))
+            "#,
+        )?;
+        let (wasm, report) = transform_with_report(&wasm, &Config::default())?;
+        let module = Module::from_buffer(&wasm)?;
+
+        assert_eq!(
+            signature(&module, exported_func(&module, "hash")),
+            (
+                vec![ValType::Externref, ValType::I64, ValType::I64],
+                vec![ValType::I32]
+            )
+        );
+        assert_eq!(
+            report.warnings,
+            ["export hash has 3 parameters, but was declared with 2"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn table_limits() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "dom" "create" (func $create (result i32)))
+                (func (export "run") (result i32)
+                    call $create)
+                (@custom "__extern_ref_data_dom_create"
+                    "{\"name\":\"create\",\"argIndicies\":[],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+        let config = Config {
+            table_initial: 16,
+            table_maximum: Some(1024),
+            ..Default::default()
+        };
+        let module = Module::from_buffer(&transform_with_config(&wasm, &config)?)?;
+
+        let table = module.tables.iter().next().expect("missing table");
+        assert_eq!((table.initial, table.maximum), (16, Some(1024)));
+
+        // References returned by the host are stored in a freshly grown slot.
+        let alloc = module
+            .funcs
+            .by_name("__externref_table_alloc")
+            .expect("missing alloc");
+        let FunctionKind::Local(local) = &module.funcs.get(alloc).kind else {
+            panic!("alloc isn't a local function");
+        };
+        let mut grows = 0;
+        walrus::ir::dfs_in_order(&mut CountGrows(&mut grows), local, local.entry_block());
+        assert_eq!(grows, 1);
+
+        // The null slot is always reserved, and the table can't be smaller than it starts.
+        let config = Config {
+            table_maximum: Some(0),
+            ..Default::default()
+        };
+        assert!(transform_with_config(&wasm, &config).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn table_strategies() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32)))
+                (import "dom" "create" (func $create (result i32)))
+                (import "dom" "append" (func $append (param i32 i32)))
+                (func (export "identity") (param i32) (result i32)
+                    local.get 0)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}")
+                (@custom "__extern_ref_data_dom_create"
+                    "{\"name\":\"create\",\"argIndicies\":[],\"retIsExternRef\":true}")
+                (@custom "__extern_ref_data_dom_append"
+                    "{\"name\":\"append\",\"argIndicies\":[0,1],\"retIsExternRef\":false}")
+                (@custom "__extern_ref_data_identity"
+                    "{\"name\":\"identity\",\"argIndicies\":[0],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+        let table_count = |module: &Module| {
+            module
+                .tables
+                .iter()
+                .filter(|table| table.element_ty == ValType::Externref)
+                .count()
+        };
+
+        let module = Module::from_buffer(&transform(&wasm)?)?;
+        assert_eq!(table_count(&module), 1);
+
+        // Every import module gets a table, and exports use the default one.
+        let config = Config {
+            table_strategy: TableStrategy::PerModule,
+            ..Default::default()
+        };
+        let transformed = transform_with_config(&wasm, &config)?;
+        wasmparser::validate(&transformed)?;
+        let module = Module::from_buffer(&transformed)?;
+        assert_eq!(table_count(&module), 3);
+        for table in module.tables.iter() {
+            assert_eq!(table.maximum, Some(1 << 24));
+        }
+        for name in [
+            "__externref_table_alloc",
+            "__externref_table_console_alloc",
+            "__externref_table_dom_alloc",
+        ] {
+            assert!(module.funcs.by_name(name).is_some(), "missing {name}");
+        }
+
+        // A smaller maximum is kept, but each table can't have more slots than a handle can hold.
+        let config = Config {
+            table_strategy: TableStrategy::PerModule,
+            table_maximum: Some(64),
+            ..Default::default()
+        };
+        let module = Module::from_buffer(&transform_with_config(&wasm, &config)?)?;
+        assert!(module.tables.iter().all(|table| table.maximum == Some(64)));
+
+        let config = Config {
+            table_strategy: TableStrategy::PerModule,
+            table_initial: (1 << 24) + 1,
+            ..Default::default()
+        };
+        assert!(transform_with_config(&wasm, &config).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn export_table_and_memory() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32)))
+                (memory 1)
+                (func (export "run") (param i32)
+                    local.get 0
+                    call $log)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+            "#,
+        )?;
+        let export_names = |module: &Module| {
+            let mut names: Vec<_> = module
+                .exports
+                .iter()
+                .map(|export| export.name.clone())
+                .collect();
+            names.sort();
+            names
+        };
+
+        let config = Config {
+            export_table: Some("refs".into()),
+            export_memory: Some("mem".into()),
+            ..Default::default()
+        };
+        let transformed = transform_with_config(&wasm, &config)?;
+        wasmparser::validate(&transformed)?;
+        let module = Module::from_buffer(&transformed)?;
+        assert_eq!(export_names(&module), ["mem", "refs", "run"]);
+        let refs = module.exports.iter().find(|export| export.name == "refs");
+        assert!(matches!(refs.unwrap().item, ExportItem::Table(_)));
+
+        // Each import module's table is exported after the default one.
+        let config = Config {
+            table_strategy: TableStrategy::PerModule,
+            export_table: Some("refs".into()),
+            ..Default::default()
+        };
+        let module = Module::from_buffer(&transform_with_config(&wasm, &config)?)?;
+        assert_eq!(export_names(&module), ["refs", "refs_console", "run"]);
+
+        // A memory that's already exported under the name isn't exported twice.
+        let config = Config {
+            export_memory: Some("mem".into()),
+            ..Default::default()
+        };
+        let module = Module::from_buffer(&transform_with_config(&transformed, &config)?)?;
+        assert_eq!(export_names(&module), ["mem", "refs", "run"]);
+
+        // Other exports can't be shadowed.
+        let config = Config {
+            export_table: Some("run".into()),
+            ..Default::default()
+        };
+        assert!(transform_with_config(&wasm, &config).is_err());
+        let config = Config {
+            export_memory: Some("run".into()),
+            ..Default::default()
+        };
+        assert!(transform_with_config(&wasm, &config).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn inline_accessors() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32)))
+                (import "dom" "create" (func $create (result i32)))
+                (func (export "identity") (param i32) (result i32)
+                    local.get 0
+                    call $log
+                    call $create)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}")
+                (@custom "__extern_ref_data_dom_create"
+                    "{\"name\":\"create\",\"argIndicies\":[],\"retIsExternRef\":true}")
+                (@custom "__extern_ref_data_identity"
+                    "{\"name\":\"identity\",\"argIndicies\":[0],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+
+        // A tagged get and a tagged alloc for each table, which calls the table's own alloc.
+        for (table_strategy, accessors) in
+            [(TableStrategy::Single, 2), (TableStrategy::PerModule, 7)]
+        {
+            let config = Config {
+                table_strategy,
+                ..Default::default()
+            };
+            let plain = Module::from_buffer(&transform_with_config(&wasm, &config)?)?;
+            let config = Config {
+                inline_accessors: true,
+                ..config
+            };
+            let inlined = transform_with_config(&wasm, &config)?;
+            wasmparser::validate(&inlined)?;
+            let module = Module::from_buffer(&inlined)?;
+
+            let names: Vec<_> = module
+                .funcs
+                .iter()
+                .filter_map(|func| func.name.as_deref())
+                .collect();
+            assert!(
+                !names
+                    .iter()
+                    .any(|name| name.starts_with("__externref_table")),
+                "{names:?}"
+            );
+            assert_eq!(
+                module.funcs.iter().count() + accessors,
+                plain.funcs.iter().count()
+            );
+
+            // The host sees the same functions.
+            for (import_module, name) in [("console", "log"), ("dom", "create")] {
+                assert_eq!(
+                    signature(&module, module.imports.get_func(import_module, name)?),
+                    signature(&plain, plain.imports.get_func(import_module, name)?)
+                );
+            }
+            assert_eq!(
+                signature(&module, exported_func(&module, "identity")),
+                signature(&plain, exported_func(&plain, "identity"))
+            );
+
+            // The wrapper of `identity` and the shim of `create` each store a reference.
+            let mut grows = 0;
+            for (_, local) in module.funcs.iter_local() {
+                walrus::ir::dfs_in_order(&mut CountGrows(&mut grows), local, local.entry_block());
+            }
+            assert_eq!(grows, 2);
+        }
+
+        // Lowered handles have no table to inline the accessors of.
+        let config = Config {
+            inline_accessors: true,
+            lower_to_i32: true,
+            ..Default::default()
+        };
+        assert!(transform_with_config(&wasm, &config).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn table_lowering() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32)))
+                (import "console" "warn" (func $warn (param i32)))
+                (func (export "run") (param i32)
+                    local.get 0
+                    call $log
+                    local.get 0
+                    call $warn)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}")
+                (@custom "__extern_ref_data_console_warn"
+                    "{\"name\":\"warn\",\"argIndicies\":[0],\"retIsExternRef\":false,\"lowering\":\"table\"}")
+                (@custom "__extern_ref_data_run"
+                    "{\"name\":\"run\",\"argIndicies\":[0],\"retIsExternRef\":false,\"lowering\":\"table\"}"))
+            "#,
+        )?;
+        let config = Config {
+            export_table: Some("refs".into()),
+            ..Default::default()
+        };
+        let (transformed, report) = transform_with_report(&wasm, &config)?;
+        wasmparser::validate(&transformed)?;
+        let module = Module::from_buffer(&transformed)?;
+
+        // Only the directly lowered import takes an `externref`.
+        let log = module.imports.get_func("console", "log")?;
+        assert_eq!(signature(&module, log), (vec![ValType::Externref], vec![]));
+        let warn = module.imports.get_func("console", "warn")?;
+        assert_eq!(signature(&module, warn), (vec![ValType::I32], vec![]));
+
+        // The export isn't wrapped, so it passes its handle to `warn` as is, while `log` is called
+        // through a shim loading the reference from the table.
+        let run = exported_func(&module, "run");
+        assert_eq!(signature(&module, run), (vec![ValType::I32], vec![]));
+        let FunctionKind::Local(local) = &module.funcs.get(run).kind else {
+            panic!("run isn't a local function");
+        };
+        let calls: Vec<_> = local
+            .block(local.entry_block())
+            .instrs
+            .iter()
+            .filter_map(|(instr, _)| match instr {
+                Instr::Call(call) => Some(call.func),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(calls.len(), 2);
+        assert_ne!(calls[0], log);
+        assert_eq!(calls[1], warn);
+        let mut grows = 0;
+        walrus::ir::dfs_in_order(&mut CountGrows(&mut grows), local, local.entry_block());
+        assert_eq!(grows, 0);
+
+        let lowerings: Vec<_> = report
+            .functions
+            .iter()
+            .map(|function| (function.name.as_str(), function.lowering))
+            .collect();
+        assert_eq!(
+            lowerings,
+            [
+                ("log", Lowering::Direct),
+                ("warn", Lowering::Table),
+                ("run", Lowering::Table)
+            ]
+        );
+        assert!(report.table_created);
+
+        // The host can only reach the table when it's exported, and only a single one.
+        assert!(transform_with_config(&wasm, &Config::default()).is_err());
+        let config = Config {
+            table_strategy: TableStrategy::PerModule,
+            ..config
+        };
+        assert!(transform_with_config(&wasm, &config).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn externref_globals() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32)))
+                (import "env" "root" (global $root externref))
+                (global $cache (export "cache") (mut externref) (global.get $root))
+                (global $empty (mut externref) (ref.null extern))
+                (global $copy externref (global.get $root))
+                (func (export "run") (param i32)
+                    local.get 0
+                    call $log
+                    global.get $copy
+                    global.set $cache)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+            "#,
+        )?;
+        let inits = |module: &Module| {
+            module
+                .globals
+                .iter()
+                .filter(|global| global.ty == ValType::Externref)
+                .map(|global| match &global.kind {
+                    GlobalKind::Import(_) => "import",
+                    GlobalKind::Local(InitExpr::RefNull(_)) => "null",
+                    GlobalKind::Local(InitExpr::Global(_)) => "global",
+                    GlobalKind::Local(init) => panic!("unexpected initializer {init:?}"),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let transformed = transform(&wasm)?;
+        wasmparser::validate(&transformed)?;
+        let module = Module::from_buffer(&transformed)?;
+        assert_eq!(inits(&module), ["import", "global", "null", "global"]);
+        let log = module.imports.get_func("console", "log")?;
+        assert_eq!(signature(&module, log), (vec![ValType::Externref], vec![]));
+
+        // Only the mutable globals could be set to anything else, so the immutable copy is kept.
+        let config = Config {
+            null_externref_globals: true,
+            ..Default::default()
+        };
+        let transformed = transform_with_config(&wasm, &config)?;
+        wasmparser::validate(&transformed)?;
+        let module = Module::from_buffer(&transformed)?;
+        assert_eq!(inits(&module), ["import", "null", "null", "global"]);
+
+        // The globals would still need reference types.
+        let config = Config {
+            lower_to_i32: true,
+            ..Default::default()
+        };
+        assert!(transform_with_config(&wasm, &config).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn pack_args() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "dom" "insert" (func $insert (param i32 i32 i32)))
+                (func (export "run") (param i32 i32 i32)
+                    local.get 0
+                    local.get 1
+                    local.get 2
+                    call $insert)
+                (@custom "__extern_ref_data_dom_insert"
+                    "{\"name\":\"insert\",\"argIndicies\":[0,2],\"retIsExternRef\":false,\"packArgs\":true}")
+                (@custom "__extern_ref_data_run"
+                    "{\"name\":\"run\",\"argIndicies\":[0,2],\"retIsExternRef\":false,\"packArgs\":true}"))
+            "#,
+        )?;
+        let (transformed, report) = transform_with_report(&wasm, &Config::default())?;
+        wasmparser::validate(&transformed)?;
+        let module = Module::from_buffer(&transformed)?;
+
+        // The host sees the references after the other arguments.
+        let packed = (
+            vec![ValType::I32, ValType::Externref, ValType::Externref],
+            vec![],
+        );
+        let insert = module.imports.get_func("dom", "insert")?;
+        assert_eq!(signature(&module, insert), packed);
+        let run = exported_func(&module, "run");
+        assert_eq!(signature(&module, run), packed);
+        assert!(report.functions.iter().all(|function| function.pack_args));
+        assert_eq!(report.functions[0].arg_indices, [0, 2]);
+
+        // The shim passes its arguments to the host in the packed order, and the wrapper passes
+        // them back in the original order.
+        let local_gets = |func_id: FunctionId| {
+            let FunctionKind::Local(local) = &module.funcs.get(func_id).kind else {
+                panic!("{func_id:?} isn't a local function");
+            };
+            let instrs = &local.block(local.entry_block()).instrs;
+            let gets: Vec<_> = instrs
+                .iter()
+                .filter_map(|(instr, _)| match instr {
+                    Instr::LocalGet(get) => Some(get.local),
+                    _ => None,
+                })
+                .collect();
+            (local.args.clone(), gets)
+        };
+        let (args, gets) = local_gets(run);
+        assert_eq!(gets, [args[1], args[0], args[2]]);
+        let original = module
+            .funcs
+            .iter()
+            .find(|func| func.name.as_deref() == Some("run"))
+            .map(|func| func.id())
+            .expect("the original function is named after the export");
+        let FunctionKind::Local(original) = &module.funcs.get(original).kind else {
+            panic!("run isn't a local function");
+        };
+        let mut shims = HashSet::new();
+        walrus::ir::dfs_in_order(&mut Calls(&mut shims), original, original.entry_block());
+        let shim = *shims.iter().next().expect("run calls the shim of insert");
+        let (args, gets) = local_gets(shim);
+        assert_eq!(gets, [args[1], args[0], args[2]]);
+
+        Ok(())
+    }
+
+    struct Calls<'a>(&'a mut HashSet<FunctionId>);
+
+    impl<'instr> walrus::ir::Visitor<'instr> for Calls<'_> {
+        fn visit_call(&mut self, call: &walrus::ir::Call) {
+            self.0.insert(call.func);
+        }
+    }
+
+    struct CountGrows<'a>(&'a mut usize);
+
+    impl<'instr> walrus::ir::Visitor<'instr> for CountGrows<'_> {
+        fn visit_table_grow(&mut self, _: &walrus::ir::TableGrow) {
+            *self.0 += 1;
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::{bail, Result};
+use walrus::{ir::BinaryOp, ir::Value, FunctionBuilder, InitExpr, Module, ValType};
+
+/// The export handing out handles when references are lowered to `i32` handles.
+pub(crate) const ALLOC: &str = "__externref_alloc";
+
+/// Adds the export a host without reference types calls to get the handle of a new reference.
+///
+/// Handles are handed out in order starting at `1`, as `0` is the null handle, the same as the
+/// slots of the externref table. The host stores the reference under the handle itself. Handing
+/// out more than `maximum` handles traps.
+pub(crate) fn create_alloc(module: &mut Module, maximum: Option<u32>) -> Result<()> {
+    if module.exports.iter().any(|export| export.name == ALLOC) {
+        bail!("can't export {ALLOC}, another export has that name");
+    }
+
+    let next = module
+        .globals
+        .add_local(ValType::I32, true, InitExpr::Value(Value::I32(1)));
+    module.globals.get_mut(next).name = Some("__externref_next".into());
+
+    let mut builder = FunctionBuilder::new(&mut module.types, &[], &[ValType::I32]);
+    builder.name(ALLOC.into());
+    let mut body = builder.func_body();
+
+    // The last handle is left unused, so handing out every `i32` traps rather than wrapping.
+    let limit = maximum.map_or(-1, |maximum| maximum as i32);
+    body.global_get(next)
+        .i32_const(limit)
+        .binop(BinaryOp::I32GeU)
+        .if_else(
+            None,
+            |then| {
+                then.unreachable();
+            },
+            |_| {},
+        );
+    body.global_get(next)
+        .global_get(next)
+        .i32_const(1)
+        .binop(BinaryOp::I32Add)
+        .global_set(next);
+
+    let alloc = builder.finish(Vec::new(), &mut module.funcs);
+    module.exports.add(ALLOC, alloc);
+    Ok(())
+}
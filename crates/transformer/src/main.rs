@@ -1,3 +1,311 @@
-fn main() {
-    println!("Hello, world!");
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use externref_transformer::{Config, Proposal, TableStrategy};
+
+/// Transforms a WASM module built with the `externref` crate to use real `externref`s.
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// The WASM module to transform, in the text format when it ends in `.wat`.
+    #[arg(required = true)]
+    input: Option<PathBuf>,
+    /// Where to write the transformed module, defaults to overwriting the input. `-` writes it to
+    /// stdout, in which case `--json` prints the report to stderr.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Parses the input as the text format whatever its extension.
+    #[arg(long)]
+    wat: bool,
+    /// Writes the transformed module in the text format, which is the default when the output
+    /// ends in `.wat`.
+    #[arg(long)]
+    emit_wat: bool,
+    /// A value for a `${NAME}` placeholder in import module names, given as `NAME=VALUE`.
+    #[arg(long = "substitute", value_name = "NAME=VALUE", value_parser = parse_substitution)]
+    substitutions: Vec<(String, String)>,
+    /// An import the host doesn't provide, given as `module.name`. Optional imports are replaced
+    /// with a stub that traps.
+    #[arg(long = "missing-import", value_name = "MODULE.NAME")]
+    missing_imports: Vec<String>,
+    /// A function to leave untouched while still removing its data section, given as
+    /// `module.name` for imports and by its name for exports and callbacks.
+    #[arg(long, value_name = "NAME")]
+    skip: Vec<String>,
+    /// The number of threads to use, defaults to one per core.
+    #[arg(long, default_value_t = 0)]
+    threads: usize,
+    /// A JSON file with the signatures of the functions the host provides to check the rewritten
+    /// imports against.
+    #[arg(long, value_name = "FILE")]
+    host_signatures: Option<PathBuf>,
+    /// The proposal to encode reference types for, `reference-types` or `function-references`.
+    #[arg(long, default_value = "reference-types", value_parser = parse_proposal)]
+    proposal: Proposal,
+    /// Removes the `name` section from the transformed module.
+    #[arg(long)]
+    strip_names: bool,
+    /// Sorts imports and exports and removes custom sections like `producers`, so builds of the
+    /// same source produce identical bytes that diff cleanly.
+    #[arg(long)]
+    normalize: bool,
+    /// Prints a JSON report of the rewritten functions, removed sections and any warnings to
+    /// stdout instead of printing the warnings to stderr. The report's shape is documented on
+    /// `externref_transformer::Report`.
+    #[arg(long)]
+    json: bool,
+    /// The number of slots the externref table starts with.
+    #[arg(long, default_value_t = 1)]
+    table_initial: u32,
+    /// The most slots the externref table can grow to, unbounded by default.
+    #[arg(long)]
+    table_maximum: Option<u32>,
+    /// How references are split between externref tables, `single` or `per-module` for a table
+    /// per import module.
+    #[arg(long, default_value = "single", value_parser = parse_table_strategy)]
+    table_strategy: TableStrategy,
+    /// Exports the externref table under a name, `externref_table` if none is given. With
+    /// `--table-strategy per-module` each import module's table is exported as `NAME_MODULE`.
+    #[arg(long, value_name = "NAME", num_args = 0..=1, default_missing_value = "externref_table")]
+    export_table: Option<String>,
+    /// Exports the module's memory under a name, `memory` if none is given.
+    #[arg(long, value_name = "NAME", num_args = 0..=1, default_missing_value = "memory")]
+    export_memory: Option<String>,
+    /// Calls `__externref.trace(name_ptr, name_len)` with the name of every rewritten import
+    /// before calling it. Every traced call makes an extra call to the host, so this is only meant
+    /// for debugging.
+    #[arg(long)]
+    trace_imports: bool,
+    /// Traps when a null reference is passed or returned where the function data doesn't mark it
+    /// as nullable. Every check is an extra call, so this is only meant for debugging.
+    #[arg(long)]
+    check_nulls: bool,
+    /// Keeps references as `i32` handles for runtimes without reference types, exporting
+    /// `__externref_alloc` to hand out handles the host stores references under.
+    #[arg(long = "lower-to-i32")]
+    lower_to_i32: bool,
+    /// Copies the externref table's helpers into their callers instead of calling them, trading
+    /// module size for fewer calls.
+    #[arg(long)]
+    inline_accessors: bool,
+    /// Initializes the module's mutable `externref` globals to null instead of their initializers.
+    #[arg(long)]
+    null_externref_globals: bool,
+    /// Fails without writing the module when the transform has any warnings, e.g. a data section
+    /// that doesn't describe any import or export.
+    #[arg(long)]
+    fail_on_warning: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Prints how many `externref`s a transformed module's imports and exports take and return.
+    Stats {
+        /// The transformed WASM module.
+        input: PathBuf,
+        /// Prints the stats as JSON, the shape is documented on `externref_transformer::Stats`.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Prints the signatures transforming a module would change, without writing anything.
+    Preview {
+        /// The WASM module to preview the transform of.
+        input: PathBuf,
+        /// Prints the changes as JSON, the shape is documented on
+        /// `externref_transformer::SignatureDiff`.
+        #[arg(long)]
+        json: bool,
+        /// Only prints the functions tagged with this group by the macro's `group` option, can be
+        /// given multiple times.
+        #[arg(long = "group", value_name = "NAME")]
+        groups: Vec<String>,
+    },
+    /// Prints the JSON Schema of the function data in `__extern_ref_data_*` sections.
+    Schema,
+}
+
+fn parse_substitution(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected NAME=VALUE, found {value:?}"))
+}
+
+fn parse_proposal(value: &str) -> Result<Proposal, String> {
+    match value {
+        "reference-types" => Ok(Proposal::ReferenceTypes),
+        "function-references" => Ok(Proposal::FunctionReferences),
+        _ => Err(format!(
+            "expected reference-types or function-references, found {value:?}"
+        )),
+    }
+}
+
+fn parse_table_strategy(value: &str) -> Result<TableStrategy, String> {
+    match value {
+        "single" => Ok(TableStrategy::Single),
+        "per-module" => Ok(TableStrategy::PerModule),
+        _ => Err(format!("expected single or per-module, found {value:?}")),
+    }
+}
+
+/// Whether a path names a module in the text format.
+fn is_wat(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "wat")
+}
+
+/// Reads a module, parsing it from the text format when `wat` is set or the path ends in `.wat`.
+fn read_module(path: &Path, wat: bool) -> Result<Vec<u8>> {
+    if wat || is_wat(path) {
+        // The parser also accepts binary modules, so `--wat` can't break a `.wasm` input.
+        return wat::parse_file(path)
+            .with_context(|| format!("failed to parse {}", path.display()));
+    }
+    std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+fn stats(input: &Path, json: bool) -> Result<()> {
+    let wasm = read_module(input, false)?;
+    let stats = externref_transformer::stats(&wasm)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        println!("imports: {}", stats.imports);
+        println!("exports: {}", stats.exports);
+        println!("externref params: {}", stats.params);
+        println!("externref returns: {}", stats.returns);
+        println!("table size: {}", stats.table_size);
+    }
+
+    Ok(())
+}
+
+fn preview(input: &Path, json: bool, groups: &[String]) -> Result<()> {
+    let wasm = read_module(input, false)?;
+    let mut diffs = externref_transformer::preview(&wasm)?;
+    if !groups.is_empty() {
+        diffs.retain(|diff| {
+            diff.function
+                .group
+                .as_ref()
+                .is_some_and(|group| groups.contains(group))
+        });
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diffs)?);
+    } else {
+        for diff in diffs {
+            println!("{diff}");
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+
+    match &args.command {
+        Some(Command::Stats { input, json }) => return stats(input, *json),
+        Some(Command::Preview {
+            input,
+            json,
+            groups,
+        }) => return preview(input, *json, groups),
+        Some(Command::Schema) => {
+            let schema = externref_metadata::json_schema();
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+            return Ok(());
+        }
+        None => {}
+    }
+    // Clap requires the input when there's no subcommand.
+    let input = args.input.as_ref().expect("missing input");
+
+    let wasm = read_module(input, args.wat)?;
+    let host_signatures = args
+        .host_signatures
+        .as_ref()
+        .map(|path| -> Result<_> {
+            let json = std::fs::read(path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            serde_json::from_slice(&json)
+                .with_context(|| format!("failed to parse host signatures in {}", path.display()))
+        })
+        .transpose()?;
+
+    let config = Config {
+        substitutions: args.substitutions.into_iter().collect(),
+        missing_imports: args.missing_imports.into_iter().collect(),
+        skip: args.skip.into_iter().collect(),
+        threads: args.threads,
+        host_signatures,
+        proposal: args.proposal,
+        strip_names: args.strip_names,
+        table_initial: args.table_initial,
+        table_maximum: args.table_maximum,
+        table_strategy: args.table_strategy,
+        export_table: args.export_table,
+        export_memory: args.export_memory,
+        trace_imports: args.trace_imports,
+        check_nulls: args.check_nulls,
+        lower_to_i32: args.lower_to_i32,
+        inline_accessors: args.inline_accessors,
+        null_externref_globals: args.null_externref_globals,
+        normalize: args.normalize,
+    };
+    let (transformed, report) = externref_transformer::transform_with_report(&wasm, &config)?;
+    if args.fail_on_warning && !report.warnings.is_empty() {
+        for warning in &report.warnings {
+            eprintln!("warning: {warning}");
+        }
+        anyhow::bail!(
+            "the transform had {} warnings, and --fail-on-warning is set",
+            report.warnings.len()
+        );
+    }
+
+    // Writing the module to stdout leaves stderr for the report, so the output can be piped.
+    let output = args.output.as_ref().unwrap_or(input);
+    let to_stdout = output.as_os_str() == "-";
+    let transformed = if args.emit_wat || is_wat(output) {
+        wasmprinter::print_bytes(&transformed)
+            .context("failed to print the transformed module")?
+            .into_bytes()
+    } else {
+        transformed
+    };
+    if to_stdout {
+        std::io::stdout()
+            .write_all(&transformed)
+            .context("failed to write to stdout")?;
+    } else {
+        std::fs::write(output, transformed)
+            .with_context(|| format!("failed to write {}", output.display()))?;
+    }
+
+    if args.json && to_stdout {
+        eprintln!("{}", serde_json::to_string_pretty(&report)?);
+    } else if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        for warning in &report.warnings {
+            eprintln!("warning: {warning}");
+        }
+    }
+
+    Ok(())
 }
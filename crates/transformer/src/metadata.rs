@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use externref_metadata::{data_section_name, FunctionData, MANIFEST_SECTION, SECTION_PREFIX};
+use walrus::{IdsToIndices, Module};
+
+use crate::reader;
+
+/// The externref data taken out of a module.
+#[derive(Debug)]
+pub(crate) struct Sections {
+    /// The decoded function data keyed by the name of the data section describing it.
+    ///
+    /// Functions described in the manifest are keyed by the data section that would have described
+    /// them, so both forms can be matched the same way.
+    pub functions: HashMap<String, FunctionData>,
+    /// The name of every section that was removed, in the order they appeared in the module.
+    pub removed: Vec<String>,
+}
+
+/// Removes every externref data section and the manifest from the module, returning the decoded
+/// function data.
+pub(crate) fn take_sections(module: &mut Module) -> Result<Sections> {
+    let ids: Vec<_> = module
+        .customs
+        .iter()
+        .filter(|(_, section)| reader::is_data_section(section.name()))
+        .map(|(id, _)| id)
+        .collect();
+
+    let mut sections = HashMap::new();
+    let mut removed = Vec::new();
+    for (index, id) in ids.into_iter().enumerate() {
+        // Sections are deleted by id, removing them by name is a linear scan over every section.
+        let Some(section) = module.customs.delete(id) else {
+            continue;
+        };
+        let name = section.name().to_string();
+        let data = section.data(&IdsToIndices::default());
+        removed.push(name.clone());
+
+        for data in reader::decode_section(&name, index, &data)? {
+            log::debug!("found data section {name}: {data:?}");
+
+            let name = match (name.as_str(), &data.module) {
+                (MANIFEST_SECTION, Some(module)) => import_section_name(module, &data.name),
+                (MANIFEST_SECTION, None) => export_section_name(&data.name),
+                _ => ungrouped_section_name(&name, data.group.as_deref()),
+            };
+
+            // Section names join the module and function name with `_`, so different functions
+            // can end up with the same section, e.g. `a.b_c` and `a_b.c`. Only one of them could
+            // be rewritten, so this is an error rather than letting the last one win.
+            if let Some(existing) = sections.get(&name) {
+                if conflicts(existing, &data) {
+                    bail!(
+                        "externref data section {name} describes both {} and {}, rename one of \
+                         them so their section names don't collide",
+                        existing.name,
+                        data.name
+                    );
+                }
+            }
+            sections.insert(name, data);
+        }
+    }
+
+    log::info!("found {} externref data sections", sections.len());
+    Ok(Sections {
+        functions: sections,
+        removed,
+    })
+}
+
+/// If two records describing the same section would rewrite the function differently.
+///
+/// The same import can be declared by multiple crates, so records that only differ in details that
+/// don't affect the rewrite, like argument names, aren't a conflict.
+fn conflicts(a: &FunctionData, b: &FunctionData) -> bool {
+    a.name != b.name
+        || a.arg_indicies != b.arg_indicies
+        || a.ret_is_extern_ref != b.ret_is_extern_ref
+        || a.ret_result != b.ret_result
+        || a.intrinsic != b.intrinsic
+}
+
+/// The name a grouped section would have without its group, which is the name the import or
+/// export it describes is matched by.
+fn ungrouped_section_name(section: &str, group: Option<&str>) -> String {
+    let rest = group.and_then(|group| {
+        section
+            .strip_prefix(SECTION_PREFIX)?
+            .strip_prefix(group)?
+            .strip_prefix('@')
+    });
+    match rest {
+        Some(rest) => format!("{SECTION_PREFIX}{rest}"),
+        None => section.to_string(),
+    }
+}
+
+/// The name of the data section describing an imported function.
+pub(crate) fn import_section_name(module: &str, name: &str) -> String {
+    data_section_name(Some(module), name)
+}
+
+/// The name of the data section describing an exported function.
+pub(crate) fn export_section_name(name: &str) -> String {
+    data_section_name(None, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use externref_metadata::{group_section_name, FunctionData, Intrinsic, Lowering, ResultRefs};
+    use proptest::prelude::*;
+    use walrus::{Module, RawCustomSection};
+
+    use super::{export_section_name, import_section_name, take_sections};
+
+    fn function_data() -> impl Strategy<Value = FunctionData> {
+        // Strategies are only implemented for tuples of up to 12 elements.
+        let fields = (
+            "\\PC*",
+            prop::collection::vec(0..64usize, 0..8),
+            any::<bool>(),
+            any::<bool>(),
+            prop::collection::vec(prop::option::of("\\PC*"), 0..8),
+            prop::collection::vec("\\PC*", 0..4),
+            prop::option::of("\\PC*"),
+            prop::option::of((any::<bool>(), any::<bool>())),
+            any::<bool>(),
+            prop::collection::btree_map(0..64usize, "\\PC*", 0..4),
+            prop::option::of("\\PC*"),
+            prop::option::of(0..16usize),
+        );
+        let intrinsic = prop_oneof![
+            Just(Intrinsic::RefNull),
+            Just(Intrinsic::RefIsNull),
+            Just(Intrinsic::Transformed)
+        ];
+        let lowering = prop_oneof![Just(Lowering::Direct), Just(Lowering::Table)];
+        (
+            fields,
+            prop::option::of("\\PC*"),
+            prop::option::of(intrinsic),
+            any::<bool>(),
+            prop::collection::vec(0..64usize, 0..8),
+            any::<bool>(),
+            any::<bool>(),
+            lowering,
+            any::<bool>(),
+        )
+            .prop_map(
+                |(
+                    (
+                        name,
+                        arg_indicies,
+                        ret_is_extern_ref,
+                        optional,
+                        arg_names,
+                        aliases,
+                        module,
+                        ret_result,
+                        callback,
+                        arg_types,
+                        export_ref,
+                        param_count,
+                    ),
+                    group,
+                    intrinsic,
+                    constructor,
+                    nullable_args,
+                    ret_nullable,
+                    reentrant,
+                    lowering,
+                    pack_args,
+                )| {
+                    FunctionData {
+                        name,
+                        arg_indicies,
+                        ret_is_extern_ref,
+                        optional,
+                        arg_names,
+                        aliases,
+                        module,
+                        ret_result: ret_result.map(|(ok, err)| ResultRefs { ok, err }),
+                        callback,
+                        arg_types,
+                        export_ref,
+                        param_count,
+                        group,
+                        intrinsic,
+                        constructor,
+                        nullable_args,
+                        ret_nullable,
+                        reentrant,
+                        lowering,
+                        pack_args,
+                    }
+                },
+            )
+    }
+
+    #[test]
+    fn invalid_section_error() {
+        let mut module = Module::default();
+        module.customs.add(RawCustomSection {
+            name: export_section_name("broken"),
+            data: br#"{"name":"broken","argIndicies":"#.to_vec(),
+        });
+
+        let error = format!("{:#}", take_sections(&mut module).unwrap_err());
+        assert!(error.contains("__extern_ref_data_broken"), "{error}");
+        assert!(error.contains(r#"{\"name\":\"broken\""#), "{error}");
+    }
+
+    #[test]
+    fn colliding_sections() {
+        // An export named `a_b_c` and the import `a.b_c` share a section name.
+        let mut module = Module::default();
+        module.customs.add(RawCustomSection {
+            name: export_section_name("a_b_c"),
+            data: br#"{"name":"a_b_c","argIndicies":[0],"retIsExternRef":false}"#.to_vec(),
+        });
+        module.customs.add(RawCustomSection {
+            name: import_section_name("a", "b_c"),
+            data: br#"{"name":"b_c","argIndicies":[],"retIsExternRef":true}"#.to_vec(),
+        });
+
+        let error = take_sections(&mut module).unwrap_err().to_string();
+        assert!(error.contains("__extern_ref_data_a_b_c"), "{error}");
+
+        // The same import declared by multiple crates isn't a collision.
+        let mut module = Module::default();
+        for arg in ["message", "value"] {
+            module.customs.add(RawCustomSection {
+                name: import_section_name("console", "log"),
+                data: format!(
+                    r#"{{"name":"log","argIndicies":[0],"retIsExternRef":false,"argNames":["{arg}"]}}"#
+                )
+                .into_bytes(),
+            });
+        }
+        assert_eq!(take_sections(&mut module).unwrap().functions.len(), 1);
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(data in function_data()) {
+            let bytes = serde_json::to_vec(&data).unwrap();
+            let decoded: FunctionData = serde_json::from_slice(&bytes).unwrap();
+            prop_assert_eq!(&decoded, &data);
+
+            // The transformer reads the same bytes out of a custom section, matching a grouped
+            // section by the name it would have without the group.
+            let name = export_section_name(&data.name);
+            let section = match &data.group {
+                Some(group) => group_section_name(group, None, &data.name),
+                None => name.clone(),
+            };
+            let mut module = Module::default();
+            module.customs.add(RawCustomSection { name: section.clone(), data: bytes });
+
+            let sections = take_sections(&mut module).unwrap();
+            prop_assert_eq!(sections.functions.get(&name), Some(&data));
+            prop_assert_eq!(sections.removed, [section]);
+            prop_assert_eq!(module.customs.iter().count(), 0);
+        }
+
+        #[test]
+        fn mixed_encodings(a in function_data(), b in function_data()) {
+            // The linker can concatenate a JSON record with a binary one from another crate.
+            let name = export_section_name("mixed");
+            let mut bytes = serde_json::to_vec(&a).unwrap();
+            bytes.extend(b.to_binary());
+            bytes.extend(serde_json::to_vec(&a).unwrap());
+            let mut module = Module::default();
+            module.customs.add(RawCustomSection { name: name.clone(), data: bytes });
+
+            // Both records describe the same section, so the last one wins unless they conflict.
+            match take_sections(&mut module) {
+                Ok(sections) => prop_assert_eq!(sections.functions.get(&name), Some(&a)),
+                Err(error) => prop_assert!(error.to_string().contains("describes both")),
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+
+use walrus::{
+    ir::{dfs_pre_order_mut, Call, RefFunc, VisitorMut},
+    ExportItem, FunctionId, FunctionKind, GlobalKind, ImportKind, InitExpr, Module,
+};
+
+/// Puts the functions, imports and exports of a module in a canonical order and removes every
+/// custom section that isn't needed to run it, so builds of the same source produce identical
+/// bytes.
+///
+/// Local functions are ordered by their names, imports by their module and name, and exports by
+/// their name. The `name` section is kept, as it's derived from the source.
+pub(crate) fn normalize(module: &mut Module) {
+    sort_functions(module);
+    sort_imports(module);
+    sort_exports(module);
+
+    let customs: Vec<_> = module.customs.iter().map(|(id, _)| id).collect();
+    for id in customs {
+        module.customs.delete(id);
+    }
+    module.producers.clear();
+}
+
+/// Local functions of the same size are written in the order of their ids, which depends on the
+/// order the linker and the rewrites added them in. The functions are moved between the ids so
+/// the ids follow their names, and every reference to them is updated. Unnamed functions keep
+/// their order after the named ones.
+fn sort_functions(module: &mut Module) {
+    let ids: Vec<FunctionId> = module.funcs.iter_local().map(|(id, _)| id).collect();
+    let mut sorted = ids.clone();
+    sorted.sort_by_key(|id| {
+        let name = module.funcs.get(*id).name.clone();
+        (name.is_none(), name)
+    });
+    if sorted == ids {
+        return;
+    }
+
+    // The function at each sorted position moves to the id at the same position.
+    let remap: HashMap<FunctionId, FunctionId> = sorted.iter().copied().zip(ids.clone()).collect();
+    let moved: Vec<_> = sorted
+        .iter()
+        .map(|id| {
+            let func = module.funcs.get_mut(*id);
+            let ty = func.ty();
+            let kind = std::mem::replace(&mut func.kind, FunctionKind::Uninitialized(ty));
+            (kind, func.name.take())
+        })
+        .collect();
+    for (id, (kind, name)) in ids.iter().zip(moved) {
+        let func = module.funcs.get_mut(*id);
+        func.kind = kind;
+        func.name = name;
+    }
+
+    let mut visitor = RemapFunctions(&remap);
+    for (_, func) in module.funcs.iter_local_mut() {
+        let entry = func.entry_block();
+        dfs_pre_order_mut(&mut visitor, func, entry);
+    }
+    let remap = |id: &mut FunctionId| *id = remap.get(id).copied().unwrap_or(*id);
+    for export in module.exports.iter_mut() {
+        if let ExportItem::Function(func) = &mut export.item {
+            remap(func);
+        }
+    }
+    for element in module.elements.iter_mut() {
+        element.members.iter_mut().flatten().for_each(remap);
+    }
+    let globals: Vec<_> = module.globals.iter().map(|global| global.id()).collect();
+    for id in globals {
+        if let GlobalKind::Local(InitExpr::RefFunc(func)) = &mut module.globals.get_mut(id).kind {
+            remap(func);
+        }
+    }
+    if let Some(start) = &mut module.start {
+        remap(start);
+    }
+}
+
+/// Points every call and `ref.func` at the id its function moved to.
+///
+/// The instructions are remapped directly rather than through `visit_function_id_mut`, as the
+/// traversal visits the fields of each instruction twice and would move the ids twice.
+struct RemapFunctions<'a>(&'a HashMap<FunctionId, FunctionId>);
+
+impl RemapFunctions<'_> {
+    fn remap(&self, function: &mut FunctionId) {
+        if let Some(moved) = self.0.get(function) {
+            *function = *moved;
+        }
+    }
+}
+
+impl VisitorMut for RemapFunctions<'_> {
+    fn visit_call_mut(&mut self, instr: &mut Call) {
+        self.remap(&mut instr.func);
+    }
+
+    fn visit_ref_func_mut(&mut self, instr: &mut RefFunc) {
+        self.remap(&mut instr.func);
+    }
+}
+
+/// Sections are written in the order their items were added, so the imports are added again in
+/// order, and every item pointing at its import is updated.
+fn sort_imports(module: &mut Module) {
+    let mut imports: Vec<_> = module
+        .imports
+        .iter()
+        .map(|import| {
+            (
+                import.module.clone(),
+                import.name.clone(),
+                import.kind.clone(),
+                import.id(),
+            )
+        })
+        .collect();
+    imports.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+
+    for (import_module, name, kind, id) in imports {
+        module.imports.delete(id);
+        let import = module.imports.add(&import_module, &name, kind.clone());
+        match kind {
+            ImportKind::Function(func) => {
+                if let FunctionKind::Import(imported) = &mut module.funcs.get_mut(func).kind {
+                    imported.import = import;
+                }
+            }
+            ImportKind::Table(table) => module.tables.get_mut(table).import = Some(import),
+            ImportKind::Memory(memory) => module.memories.get_mut(memory).import = Some(import),
+            ImportKind::Global(global) => {
+                module.globals.get_mut(global).kind = GlobalKind::Import(import);
+            }
+        }
+    }
+}
+
+fn sort_exports(module: &mut Module) {
+    let mut exports: Vec<(String, ExportItem, _)> = module
+        .exports
+        .iter()
+        .map(|export| (export.name.clone(), export.item, export.id()))
+        .collect();
+    exports.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (name, item, id) in exports {
+        module.exports.delete(id);
+        module.exports.add(&name, item);
+    }
+}
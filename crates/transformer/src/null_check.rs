@@ -0,0 +1,43 @@
+use walrus::{FunctionBuilder, FunctionId, InstrSeqBuilder, Module, ValType};
+
+/// Traps when a reference that the function data doesn't allow to be `null` is, see
+/// [Config::check_nulls](crate::Config::check_nulls).
+pub(crate) struct NullCheck {
+    /// `(externref) -> externref`, traps on `null` and returns any other reference.
+    func: FunctionId,
+}
+
+impl NullCheck {
+    /// Adds the function checking a reference to the module.
+    pub fn create(module: &mut Module) -> Self {
+        let mut builder = FunctionBuilder::new(
+            &mut module.types,
+            &[ValType::Externref],
+            &[ValType::Externref],
+        );
+        builder.name("externref null check".into());
+        let reference = module.locals.add(ValType::Externref);
+
+        builder
+            .func_body()
+            .local_get(reference)
+            .ref_is_null()
+            .if_else(
+                None,
+                |then| {
+                    then.unreachable();
+                },
+                |_| {},
+            )
+            .local_get(reference);
+
+        Self {
+            func: builder.finish(vec![reference], &mut module.funcs),
+        }
+    }
+
+    /// Emits a check of the reference on top of the stack, which is left on the stack.
+    pub fn call(&self, body: &mut InstrSeqBuilder) {
+        body.call(self.func);
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use walrus::Module;
+
+/// Replaces the `${NAME}` placeholders in every import module name with their values.
+pub(crate) fn substitute_imports(
+    module: &mut Module,
+    substitutions: &HashMap<String, String>,
+) -> Result<()> {
+    for import in module.imports.iter_mut() {
+        let substituted = substitute(&import.module, substitutions)
+            .with_context(|| format!("invalid module name for import {}", import.name))?;
+
+        if substituted != import.module {
+            log::debug!(
+                "substituted import module {} with {substituted}",
+                import.module
+            );
+            import.module = substituted;
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the `${NAME}` placeholders in a string with their values.
+pub(crate) fn substitute(value: &str, substitutions: &HashMap<String, String>) -> Result<String> {
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        output.push_str(&rest[..start]);
+
+        let Some(len) = rest[start..].find('}') else {
+            bail!("unterminated placeholder in {value:?}");
+        };
+        let name = &rest[start + 2..start + len];
+        let Some(substitution) = substitutions.get(name) else {
+            bail!("no value provided for placeholder ${{{name}}} in {value:?}");
+        };
+
+        output.push_str(substitution);
+        rest = &rest[start + len + 1..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::substitute;
+
+    #[test]
+    fn substitute_placeholders() {
+        let substitutions = HashMap::from([
+            ("HOST".to_string(), "browserEnv".to_string()),
+            ("VERSION".to_string(), "v2".to_string()),
+        ]);
+
+        assert_eq!(substitute("console", &substitutions).unwrap(), "console");
+        assert_eq!(substitute("${HOST}", &substitutions).unwrap(), "browserEnv");
+        assert_eq!(
+            substitute("${HOST}_dom_${VERSION}", &substitutions).unwrap(),
+            "browserEnv_dom_v2"
+        );
+
+        assert!(substitute("${MISSING}", &substitutions).is_err());
+        assert!(substitute("${HOST", &substitutions).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::{bail, Context, Result};
+use externref_metadata::FunctionData;
+use rayon::prelude::*;
+use walrus::{ExportId, ExportItem, FunctionId, ImportId, ImportKind, Module};
+
+use crate::{
+    refcount::Hook,
+    shim::{self, Step},
+    signature::ExternRefSignature,
+    Config,
+};
+
+/// An import or export that was matched with a data section.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Target {
+    /// An imported function.
+    Import(ImportId),
+    /// An exported function.
+    Export(ExportId),
+    /// A function referenced from an element segment.
+    Element(FunctionId),
+}
+
+impl Target {
+    /// The function that is imported or exported.
+    pub fn func_id(self, module: &Module) -> Result<FunctionId> {
+        match self {
+            Target::Import(import_id) => match module.imports.get(import_id).kind {
+                ImportKind::Function(func_id) => Ok(func_id),
+                _ => bail!("{} is not a function", self.describe(module)),
+            },
+            Target::Export(export_id) => match module.exports.get(export_id).item {
+                ExportItem::Function(func_id) => Ok(func_id),
+                _ => bail!("{} is not a function", self.describe(module)),
+            },
+            Target::Element(func_id) => Ok(func_id),
+        }
+    }
+
+    /// Describes the target for errors and logs, e.g. `import console.log`.
+    pub fn describe(self, module: &Module) -> String {
+        match self {
+            Target::Import(import_id) => {
+                let import = module.imports.get(import_id);
+                format!("import {}.{}", import.module, import.name)
+            }
+            Target::Export(export_id) => format!("export {}", module.exports.get(export_id).name),
+            Target::Element(func_id) => {
+                let name = module
+                    .funcs
+                    .get(func_id)
+                    .name
+                    .as_deref()
+                    .unwrap_or_default();
+                format!("indirect function {name}")
+            }
+        }
+    }
+}
+
+/// A function whose signature will be rewritten.
+pub(crate) struct Rewrite {
+    /// The import or export being rewritten.
+    pub target: Target,
+    /// The signature it's rewritten to.
+    pub signature: ExternRefSignature,
+    /// The body of the shim or wrapper taking its place.
+    pub steps: Vec<Step>,
+}
+
+/// Computes the rewrite of every target, along with the body of the function converting between
+/// its handles and references.
+///
+/// Targets are independent of each other, so they're planned on a thread pool with
+/// [Config::threads] threads. The rewrites are returned in the same order as the targets, so
+/// applying them is deterministic regardless of the number of threads.
+pub(crate) fn plan(
+    module: &Module,
+    targets: &[(Target, &FunctionData)],
+    config: &Config,
+) -> Result<Vec<Rewrite>> {
+    let plan_one = |(target, data): &(Target, &FunctionData)| -> Result<Rewrite> {
+        let ty = module
+            .types
+            .get(module.funcs.get(target.func_id(module)?).ty());
+        let mut signature = ExternRefSignature::new(ty.params(), ty.results(), data)
+            .with_context(|| format!("invalid signature for {}", target.describe(module)))?;
+        let steps = match target {
+            Target::Import(import_id) => match Hook::of(module.imports.get(*import_id)) {
+                Some(hook) => hook.plan(&mut signature)?,
+                None => shim::plan_import(&signature, config.trace_imports, config.check_nulls),
+            },
+            Target::Export(_) | Target::Element(_) => {
+                shim::plan_export(&signature, config.check_nulls)
+            }
+        };
+
+        Ok(Rewrite {
+            target: *target,
+            signature,
+            steps,
+        })
+    };
+
+    if config.threads == 1 {
+        return targets.iter().map(plan_one).collect();
+    }
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.threads)
+        .build()
+        .context("failed to create the transform thread pool")?;
+    pool.install(|| targets.par_iter().map(plan_one).collect())
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use walrus::{Module, ValType};
+
+use crate::{report::RewrittenFunction, signature::ExternRefSignature, Config};
+
+/// How a transform changes the signature of a function, see [preview].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureDiff {
+    /// The function whose signature changes.
+    #[serde(flatten)]
+    pub function: RewrittenFunction,
+    /// The parameters before the transform, written as in the text format, e.g. `i32`.
+    pub old_params: Vec<String>,
+    /// The results before the transform.
+    pub old_results: Vec<String>,
+    /// The parameters after the transform, with `externref` in place of the handles.
+    pub new_params: Vec<String>,
+    /// The results after the transform.
+    pub new_results: Vec<String>,
+}
+
+impl SignatureDiff {
+    pub(crate) fn new(function: RewrittenFunction, signature: &ExternRefSignature) -> Self {
+        let names = |types: &[ValType]| types.iter().map(ToString::to_string).collect();
+        Self {
+            function,
+            old_params: names(&signature.original_params),
+            old_results: names(&signature.original_results),
+            new_params: names(&signature.params),
+            new_results: names(&signature.results),
+        }
+    }
+}
+
+/// Formats the diff as the function followed by its old and new signature, e.g.:
+///
+/// ```text
+/// import console.log
+/// - (i32, i32) -> i32
+/// + (externref, i32) -> externref
+/// ```
+impl fmt::Display for SignatureDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let function = &self.function;
+        match &function.module {
+            Some(module) => writeln!(f, "{} {module}.{}", function.kind, function.name)?,
+            None => writeln!(f, "{} {}", function.kind, function.name)?,
+        }
+        writeln!(f, "- {}", signature(&self.old_params, &self.old_results))?;
+        write!(f, "+ {}", signature(&self.new_params, &self.new_results))
+    }
+}
+
+fn signature(params: &[String], results: &[String]) -> String {
+    let params = params.join(", ");
+    match results {
+        [] => format!("({params})"),
+        [result] => format!("({params}) -> {result}"),
+        results => format!("({params}) -> ({})", results.join(", ")),
+    }
+}
+
+/// Lists the signatures a transform with the default [Config] would change, without writing the
+/// transformed module anywhere.
+///
+/// The module is transformed in memory, so this fails whenever the transform would.
+pub fn preview(wasm: &[u8]) -> Result<Vec<SignatureDiff>> {
+    let mut module = Module::from_buffer(wasm).context("failed to parse wasm module")?;
+    let (_, diffs) = crate::transform_module_inner(&mut module, &Config::default())?;
+    Ok(diffs)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::preview;
+    use crate::RewriteKind;
+
+    #[test]
+    fn import_diff() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32 i32) (result i32)))
+                (func (export "run") (param i32) (result i32)
+                    local.get 0
+                    i32.const 1
+                    call $log)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+        let diffs = preview(&wasm)?;
+
+        assert_eq!(diffs.len(), 1);
+        let diff = &diffs[0];
+        assert_eq!(diff.function.name, "log");
+        assert_eq!(diff.function.module.as_deref(), Some("console"));
+        assert_eq!(diff.function.kind, RewriteKind::Import);
+        assert_eq!(diff.old_params, ["i32", "i32"]);
+        assert_eq!(diff.old_results, ["i32"]);
+        assert_eq!(diff.new_params, ["externref", "i32"]);
+        assert_eq!(diff.new_results, ["externref"]);
+        assert_eq!(
+            diff.to_string(),
+            "import console.log\n- (i32, i32) -> i32\n+ (externref, i32) -> externref"
+        );
+
+        // A module without data sections has nothing to change.
+        assert!(preview(&wat::parse_str("(module)")?)?.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use anyhow::{bail, Context, Result};
+use wasmparser::{
+    BinaryReader, CompositeInnerType, RefType, TableInit, TableSectionReader, TypeSectionReader,
+    ValType,
+};
+
+/// The id of the type section.
+const TYPE_SECTION: u8 = 1;
+/// The id of the table section.
+const TABLE_SECTION: u8 = 4;
+
+/// Rewrites the type and table sections of an emitted module so every reference type uses the
+/// typed reference encoding of the `function-references` proposal, e.g. `(ref null extern)`
+/// instead of the `externref` shorthand. Every other section is copied as is.
+pub(crate) fn encode_typed_references(wasm: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = BinaryReader::new(wasm, 0);
+    let header = reader.read_bytes(8).context("missing wasm header")?;
+
+    let mut out = header.to_vec();
+    while !reader.eof() {
+        let id = reader.read_u8()?;
+        let size = reader.read_var_u32()?;
+        let offset = reader.original_position();
+        let contents = reader.read_bytes(size as usize)?;
+
+        let contents = match id {
+            TYPE_SECTION => encode_types(BinaryReader::new(contents, offset))?,
+            TABLE_SECTION => encode_tables(BinaryReader::new(contents, offset))?,
+            _ => contents.to_vec(),
+        };
+        out.push(id);
+        write_u32(&mut out, contents.len() as u32);
+        out.extend(contents);
+    }
+
+    Ok(out)
+}
+
+fn encode_types(reader: BinaryReader) -> Result<Vec<u8>> {
+    let types = TypeSectionReader::new(reader)?;
+
+    let mut out = Vec::new();
+    write_u32(&mut out, types.count());
+    for group in types {
+        let group = group?;
+        if group.is_explicit_rec_group() {
+            bail!("recursive type groups aren't supported");
+        }
+
+        for ty in group.types() {
+            let CompositeInnerType::Func(func) = &ty.composite_type.inner else {
+                bail!("only function types are supported, found {ty}");
+            };
+
+            out.push(0x60);
+            for types in [func.params(), func.results()] {
+                write_u32(&mut out, types.len() as u32);
+                for ty in types {
+                    encode_val_type(&mut out, *ty)?;
+                }
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+fn encode_tables(reader: BinaryReader) -> Result<Vec<u8>> {
+    let tables = TableSectionReader::new(reader)?;
+
+    let mut out = Vec::new();
+    write_u32(&mut out, tables.count());
+    for table in tables {
+        let table = table?;
+        if !matches!(table.init, TableInit::RefNull) || table.ty.table64 || table.ty.shared {
+            bail!("only tables with a 32-bit index and no initializer are supported");
+        }
+
+        encode_ref_type(&mut out, table.ty.element_type)?;
+        match table.ty.maximum {
+            Some(maximum) => {
+                out.push(0x01);
+                write_u32(&mut out, table.ty.initial as u32);
+                write_u32(&mut out, maximum as u32);
+            }
+            None => {
+                out.push(0x00);
+                write_u32(&mut out, table.ty.initial as u32);
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+fn encode_val_type(out: &mut Vec<u8>, ty: ValType) -> Result<()> {
+    match ty {
+        ValType::I32 => out.push(0x7f),
+        ValType::I64 => out.push(0x7e),
+        ValType::F32 => out.push(0x7d),
+        ValType::F64 => out.push(0x7c),
+        ValType::V128 => out.push(0x7b),
+        ValType::Ref(ty) => encode_ref_type(out, ty)?,
+    }
+
+    Ok(())
+}
+
+/// Encodes a nullable reference as `(ref null <heap type>)`.
+fn encode_ref_type(out: &mut Vec<u8>, ty: RefType) -> Result<()> {
+    let heap_type = match ty {
+        RefType::EXTERNREF => 0x6f,
+        RefType::FUNCREF => 0x70,
+        ty => bail!("unsupported reference type {ty}"),
+    };
+    out.extend([0x63, heap_type]);
+
+    Ok(())
+}
+
+/// Writes an unsigned LEB128 integer.
+fn write_u32(out: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
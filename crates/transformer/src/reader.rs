@@ -0,0 +1,165 @@
+use anyhow::{Context, Result};
+use externref_metadata::{
+    FunctionData, BINARY_MAGIC, GZIP_MAGIC, MANIFEST_SECTION, SECTION_PREFIX,
+};
+use wasmparser::{Parser, Payload};
+
+/// Reads the function data of every externref data section and the manifest in a module, in the
+/// order they appear, without transforming it.
+///
+/// This only parses the module's custom sections with `wasmparser`, so it's available without the
+/// `transform` feature for tools that only inspect modules, e.g. to generate glue code. Data
+/// compressed with gzip is read with the `compression` feature. Records in
+/// the manifest have [FunctionData::module] set for imports, while the module of a function in its
+/// own data section is only part of the section's name.
+///
+/// ```rust
+/// # fn main() -> anyhow::Result<()> {
+/// let wasm = wat::parse_str(r#"
+///     (module
+///         (@custom "__extern_ref_data_console_log"
+///             "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+/// "#)?;
+///
+/// let functions = externref_transformer::read_metadata(&wasm)?;
+/// assert_eq!(functions.len(), 1);
+/// assert_eq!(functions[0].name, "log");
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_metadata(wasm: &[u8]) -> Result<Vec<FunctionData>> {
+    let mut functions = Vec::new();
+    let mut index = 0;
+    for payload in Parser::new(0).parse_all(wasm) {
+        let Payload::CustomSection(section) = payload.context("failed to parse wasm module")?
+        else {
+            continue;
+        };
+        if !is_data_section(section.name()) {
+            continue;
+        }
+
+        functions.extend(decode_section(section.name(), index, section.data())?);
+        index += 1;
+    }
+    Ok(functions)
+}
+
+/// If a custom section holds externref function data.
+pub(crate) fn is_data_section(name: &str) -> bool {
+    name.starts_with(SECTION_PREFIX) || name == MANIFEST_SECTION
+}
+
+/// Decodes every record in a section. The `index` of the section among the data sections is only
+/// used to tell sections with the same name apart in errors.
+///
+/// The linker concatenates sections that share a name, so a single section can hold the data of
+/// the same function declared in multiple crates, in any encoding. A compressed member holds a
+/// single JSON or binary record, so its contents are never decompressed again.
+pub(crate) fn decode_section(name: &str, index: usize, data: &[u8]) -> Result<Vec<FunctionData>> {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    loop {
+        offset += data[offset..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_whitespace())
+            .count();
+        if data[offset..].starts_with(&GZIP_MAGIC) {
+            let context = || {
+                format!(
+                    "invalid compressed externref data in section {name} (#{index}) at byte \
+                     {offset}"
+                )
+            };
+            let (decompressed, len) = decompress(&data[offset..]).with_context(context)?;
+            records.push(decode_compressed(&decompressed).with_context(context)?);
+            offset += len;
+            continue;
+        }
+
+        // A manifest holds an array of records, which the linker concatenates with every other
+        // crate's.
+        if data[offset..].starts_with(b"[") {
+            let mut stream = serde_json::Deserializer::from_slice(&data[offset..])
+                .into_iter::<Vec<FunctionData>>();
+            let array = stream.next().expect("the data isn't empty");
+            records.extend(array.with_context(|| {
+                format!(
+                    "invalid externref data in section {name} (#{index}) at byte {offset}: {}",
+                    preview(&data[offset..])
+                )
+            })?);
+            offset += stream.byte_offset();
+            continue;
+        }
+
+        let Some(record) = decode_record(&data[offset..]) else {
+            break;
+        };
+        let (record, len) = record.with_context(|| {
+            format!(
+                "invalid externref data in section {name} (#{index}) at byte {offset}: {}",
+                preview(&data[offset..])
+            )
+        })?;
+        records.push(record);
+        offset += len;
+    }
+    Ok(records)
+}
+
+/// Decodes the record at the start of some section data, returning it and its length, or `None`
+/// when there's no data left.
+fn decode_record(data: &[u8]) -> Option<Result<(FunctionData, usize)>> {
+    if data.is_empty() {
+        return None;
+    }
+    if data.starts_with(&BINARY_MAGIC) {
+        return Some(FunctionData::from_binary(data).map_err(Into::into));
+    }
+
+    let mut stream = serde_json::Deserializer::from_slice(data).into_iter::<FunctionData>();
+    let record = stream.next()?;
+    Some(
+        record
+            .map(|record| (record, stream.byte_offset()))
+            .map_err(Into::into),
+    )
+}
+
+/// Decodes the single record a gzip member decompressed to.
+fn decode_compressed(data: &[u8]) -> Result<FunctionData> {
+    let data = data.trim_ascii_start();
+    if data.starts_with(&GZIP_MAGIC) {
+        anyhow::bail!("the record is compressed twice");
+    }
+    let (record, len) = decode_record(data).context("the member is empty")??;
+    if !data[len..].trim_ascii().is_empty() {
+        anyhow::bail!("the member holds more than one record");
+    }
+    Ok(record)
+}
+
+/// Decompresses the gzip member at the start of some section data, returning it and its length.
+#[cfg(feature = "compression")]
+fn decompress(data: &[u8]) -> Result<(Vec<u8>, usize)> {
+    Ok(externref_metadata::decompress(data)?)
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress(_: &[u8]) -> Result<(Vec<u8>, usize)> {
+    anyhow::bail!("reading compressed data needs the `compression` feature of the transformer")
+}
+
+/// A printable preview of the start of some invalid section data.
+fn preview(data: &[u8]) -> String {
+    const MAX_LEN: usize = 64;
+
+    let text = String::from_utf8_lossy(&data[..data.len().min(MAX_LEN)]);
+    let text = text.trim_start();
+    if data.len() > MAX_LEN {
+        format!("{text:?}...")
+    } else {
+        format!("{text:?}")
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::{bail, Result};
+use walrus::Import;
+
+use crate::{shim::Step, signature::ExternRefSignature};
+
+/// The module the `refcount` feature of the `externref` crate imports its hooks from.
+const HOOK_MODULE: &str = "__externref";
+
+/// A hook the `refcount` feature of the `externref` crate calls on clones and drops, whose shim
+/// manages the table around the call to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Hook {
+    /// `clone(handle) -> handle`, tells the host about the clone and stores it in a slot of its
+    /// own, so dropping either reference leaves the other in place.
+    Clone,
+    /// `drop(handle)`, clears the reference's slot so it can be handed out again and tells the
+    /// host about the drop.
+    Drop,
+}
+
+impl Hook {
+    /// The hook an import is, if any.
+    pub fn of(import: &Import) -> Option<Self> {
+        match (import.module.as_str(), import.name.as_str()) {
+            (HOOK_MODULE, "clone") => Some(Self::Clone),
+            (HOOK_MODULE, "drop") => Some(Self::Drop),
+            _ => None,
+        }
+    }
+
+    /// Plans the shim of the hook's import. The host only receives the reference, so the clone the
+    /// shim returns is removed from the host's signature.
+    pub fn plan(self, signature: &mut ExternRefSignature) -> Result<Vec<Step>> {
+        let name = match self {
+            Self::Clone => "clone",
+            Self::Drop => "drop",
+        };
+        let index_type = match signature.arg_index_types.as_slice() {
+            [Some(index_type)] => *index_type,
+            _ => bail!("{HOOK_MODULE}.{name} must take a single reference"),
+        };
+        let load = [Step::Arg(0), Step::HandleToIndex(index_type), Step::Get];
+
+        match self {
+            Self::Clone => {
+                let Some(clone_type) = signature.ret_index_type.take() else {
+                    bail!(
+                        "{HOOK_MODULE}.{name} must return the cloned reference, the externref \
+                         crate may be older than the transformer"
+                    );
+                };
+                signature.results.clear();
+
+                Ok([
+                    &load[..],
+                    &[Step::Call],
+                    &load,
+                    &[Step::Alloc, Step::IndexToHandle(clone_type)],
+                ]
+                .concat())
+            }
+            Self::Drop => {
+                if !signature.results.is_empty() {
+                    bail!("{HOOK_MODULE}.{name} can't return anything");
+                }
+                // The reference is loaded before its slot is cleared, and the host is told last.
+                Ok([
+                    &load[..],
+                    &[Step::Arg(0), Step::HandleToIndex(index_type), Step::Free],
+                    &[Step::Call],
+                ]
+                .concat())
+            }
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use externref_metadata::{FunctionData, Lowering};
+use serde::Serialize;
+use walrus::Module;
+
+use crate::{placeholders, plan::Target};
+
+/// A summary of what a transform changed, for build tools that want to inspect the result.
+///
+/// This is what the CLI prints with `--json`, e.g.:
+///
+/// ```json
+/// {
+///   "functions": [
+///     { "name": "log", "module": "console", "kind": "import", "argIndices": [0] },
+///     { "name": "identity", "kind": "export", "argIndices": [0] }
+///   ],
+///   "sectionsStripped": ["__extern_ref_data_console_log", "__extern_ref_data_identity"],
+///   "tableCreated": true,
+///   "warnings": []
+/// }
+/// ```
+///
+/// Fields are only ever added, so scripts should ignore ones they don't know about.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    /// Every function that was rewritten, in the order they were rewritten, followed by the ones
+    /// lowered through the table.
+    pub functions: Vec<RewrittenFunction>,
+    /// The custom sections describing the functions that were removed from the module.
+    pub sections_stripped: Vec<String>,
+    /// If the externref table was added, which only happens when a function was rewritten or
+    /// lowered through the table.
+    pub table_created: bool,
+    /// Problems that didn't fail the transform, e.g. data sections that didn't match anything.
+    ///
+    /// Callers that want these to be errors, like the CLI's `--fail-on-warning`, can check that
+    /// this is empty before using the transformed module.
+    pub warnings: Vec<String>,
+}
+
+/// A function in a [Report].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RewrittenFunction {
+    /// The name of the import or export, or the function's name for indirect functions.
+    pub name: String,
+    /// The module an import is imported from, after placeholder substitution.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    /// How the function is exposed to the host.
+    pub kind: RewriteKind,
+    /// The arguments that were changed to `externref`s.
+    pub arg_indices: Vec<usize>,
+    /// The group the function was tagged with by the macro's `group` option, for tools that only
+    /// handle some feature areas.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// If the import was marked as a host constructor by the macro's `constructor` option, so glue
+    /// code should call it with `new`.
+    #[serde(skip_serializing_if = "is_false")]
+    pub constructor: bool,
+    /// If the function was marked as reentrant by the macro's `host_callback` option, so the host
+    /// may call back into the module while it runs.
+    #[serde(skip_serializing_if = "is_false")]
+    pub reentrant: bool,
+    /// How the function's references are passed, chosen by the macro's `lowering` option. With
+    /// [Lowering::Table] the signature wasn't changed and the handles index the exported table.
+    #[serde(skip_serializing_if = "is_direct")]
+    pub lowering: Lowering,
+    /// If the `externref` arguments were moved after the others by the macro's `pack_args`
+    /// option, so glue code can pass them as a single array. [RewrittenFunction::arg_indices]
+    /// still holds their positions in the untransformed signature.
+    #[serde(skip_serializing_if = "is_false")]
+    pub pack_args: bool,
+}
+
+/// How a [RewrittenFunction] is exposed to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RewriteKind {
+    /// An imported function.
+    Import,
+    /// An exported function.
+    Export,
+    /// A function called through a function table.
+    Element,
+    /// An `export_ref!` intrinsic that was replaced with a reference to an export.
+    ExportRef,
+}
+
+/// Formats the kind the way diagnostics describe functions, e.g. `import`.
+impl std::fmt::Display for RewriteKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RewriteKind::Import => "import",
+            RewriteKind::Export => "export",
+            RewriteKind::Element => "indirect function",
+            RewriteKind::ExportRef => "export reference",
+        })
+    }
+}
+
+impl RewrittenFunction {
+    /// Describes a target before it's rewritten.
+    pub(crate) fn new(
+        module: &Module,
+        target: Target,
+        data: &FunctionData,
+        substitutions: &HashMap<String, String>,
+    ) -> Result<Self> {
+        let (name, import_module, kind) = match target {
+            Target::Import(import_id) => {
+                let import = module.imports.get(import_id);
+                let import_module = placeholders::substitute(&import.module, substitutions)?;
+                (
+                    import.name.clone(),
+                    Some(import_module),
+                    RewriteKind::Import,
+                )
+            }
+            Target::Export(export_id) => (
+                module.exports.get(export_id).name.clone(),
+                None,
+                RewriteKind::Export,
+            ),
+            Target::Element(func_id) => (
+                module.funcs.get(func_id).name.clone().unwrap_or_default(),
+                None,
+                RewriteKind::Element,
+            ),
+        };
+
+        Ok(Self {
+            name,
+            module: import_module,
+            kind,
+            arg_indices: data.arg_indicies.clone(),
+            group: data.group.clone(),
+            constructor: data.constructor,
+            reentrant: data.reentrant,
+            lowering: data.lowering,
+            pack_args: data.pack_args,
+        })
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn is_direct(value: &Lowering) -> bool {
+    *value == Lowering::Direct
+}
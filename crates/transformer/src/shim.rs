@@ -0,0 +1,207 @@
+use externref_metadata::ResultRefs;
+use walrus::{ir::UnaryOp, FunctionId, ImportId, InstrSeqBuilder, LocalId, ModuleLocals, ValType};
+
+use crate::{
+    null_check::NullCheck,
+    signature::ExternRefSignature,
+    table::{ExternRefTable, IndexType},
+    trace::Tracer,
+};
+
+/// An instruction of a shim converting between handles and `externref`s.
+///
+/// Shims are planned as steps without touching the module, so every function's shim can be
+/// planned on its own thread, and the steps are emitted into the module afterwards in a fixed
+/// order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Step {
+    /// Pushes the shim's parameter at this position.
+    Arg(usize),
+    /// Calls the host's trace function with the name of the import.
+    Trace,
+    /// Traps if the reference on the stack is `null`.
+    CheckNull,
+    /// Converts a handle on the stack into a table index.
+    HandleToIndex(IndexType),
+    /// Converts a table index on the stack into a handle.
+    IndexToHandle(IndexType),
+    /// Loads the reference at the table index on the stack.
+    Get,
+    /// Stores the reference on the stack in the table, leaving its index.
+    Alloc,
+    /// Clears the slot at the table index on the stack so it can be handed out again.
+    Free,
+    /// Calls the function the shim wraps.
+    Call,
+    /// Loads the reference of the active variant of a returned `Result`.
+    LoadResult(ResultRefs, IndexType),
+}
+
+/// Plans the body of an import's shim, which has the untransformed signature and calls the
+/// import with the `externref` signature.
+pub(crate) fn plan_import(
+    signature: &ExternRefSignature,
+    trace: bool,
+    check_nulls: bool,
+) -> Vec<Step> {
+    let mut steps = Vec::new();
+    if trace {
+        steps.push(Step::Trace);
+    }
+    // The host may take the arguments in a different order when its references are packed.
+    for &index in &signature.order {
+        steps.push(Step::Arg(index));
+        if let Some(index_type) = signature.arg_index_types[index] {
+            steps.extend([Step::HandleToIndex(index_type), Step::Get]);
+            if check_nulls && !signature.nullable_args[index] {
+                steps.push(Step::CheckNull);
+            }
+        }
+    }
+
+    steps.push(Step::Call);
+
+    if let Some(index_type) = signature.ret_index_type {
+        if check_nulls && !signature.ret_nullable {
+            steps.push(Step::CheckNull);
+        }
+        steps.extend([Step::Alloc, Step::IndexToHandle(index_type)]);
+    }
+
+    steps
+}
+
+/// Plans the body of an export's wrapper, which has the `externref` signature and calls the
+/// function with the untransformed signature.
+pub(crate) fn plan_export(signature: &ExternRefSignature, check_nulls: bool) -> Vec<Step> {
+    // The original function takes its arguments in their original order, even when the host
+    // passes the references packed at the end.
+    let mut positions = vec![0; signature.order.len()];
+    for (position, index) in signature.order.iter().enumerate() {
+        positions[*index] = position;
+    }
+
+    let mut steps = Vec::new();
+    for ((position, index_type), nullable) in positions
+        .into_iter()
+        .zip(&signature.arg_index_types)
+        .zip(&signature.nullable_args)
+    {
+        steps.push(Step::Arg(position));
+        if let Some(index_type) = index_type {
+            if check_nulls && !nullable {
+                steps.push(Step::CheckNull);
+            }
+            steps.extend([Step::Alloc, Step::IndexToHandle(*index_type)]);
+        }
+    }
+
+    steps.push(Step::Call);
+
+    if let Some(index_type) = signature.ret_index_type {
+        steps.extend([Step::HandleToIndex(index_type), Step::Get]);
+        if check_nulls && !signature.ret_nullable {
+            steps.push(Step::CheckNull);
+        }
+    }
+
+    if let Some((refs, index_type)) = signature.ret_result {
+        steps.push(Step::LoadResult(refs, index_type));
+    }
+
+    steps
+}
+
+/// The functions the steps of a shim call.
+pub(crate) struct Callees<'a> {
+    /// The table references are stored in.
+    pub table: &'a ExternRefTable,
+    /// The function the shim wraps.
+    pub func: FunctionId,
+    /// The trace function and the import being traced.
+    pub tracer: Option<(&'a Tracer, ImportId)>,
+    /// The null check, without which [Step::CheckNull] is skipped.
+    pub null_check: Option<&'a NullCheck>,
+}
+
+/// Emits the planned steps of a shim into its body.
+pub(crate) fn emit(
+    steps: &[Step],
+    body: &mut InstrSeqBuilder,
+    locals: &mut ModuleLocals,
+    args: &[LocalId],
+    callees: &Callees,
+) {
+    for step in steps {
+        match *step {
+            Step::Arg(position) => {
+                body.local_get(args[position]);
+            }
+            Step::Trace => {
+                if let Some((tracer, import_id)) = callees.tracer {
+                    tracer.call(body, import_id);
+                }
+            }
+            Step::CheckNull => {
+                if let Some(null_check) = callees.null_check {
+                    null_check.call(body);
+                }
+            }
+            Step::HandleToIndex(index_type) => index_type.handle_to_index(body),
+            Step::IndexToHandle(index_type) => index_type.index_to_handle(body),
+            Step::Get => {
+                body.call(callees.table.get);
+            }
+            Step::Alloc => {
+                body.call(callees.table.alloc);
+            }
+            Step::Free => match callees.table.free {
+                Some(free) => {
+                    body.call(free);
+                }
+                None => {
+                    body.drop();
+                }
+            },
+            Step::Call => {
+                body.call(callees.func);
+            }
+            Step::LoadResult(refs, index_type) => {
+                load_result(body, locals, callees.table, refs, index_type)
+            }
+        }
+    }
+}
+
+/// Replaces the handle of a returned `Result` with its reference, leaving the tag beneath it.
+fn load_result(
+    body: &mut InstrSeqBuilder,
+    locals: &mut ModuleLocals,
+    table: &ExternRefTable,
+    refs: ResultRefs,
+    index_type: IndexType,
+) {
+    let tag = locals.add(ValType::I32);
+    let handle = locals.add(index_type.val_type());
+    // The tag is left on the stack beneath the `externref`.
+    body.local_set(handle).local_tee(tag);
+
+    // The handle is only loaded from the table when the active variant is an `externref`.
+    let load = |then: &mut InstrSeqBuilder| {
+        then.local_get(handle);
+        index_type.handle_to_index(then);
+        then.call(table.get);
+    };
+    match (refs.ok, refs.err) {
+        (true, true) => load(body),
+        (ok, _) => {
+            body.local_get(tag);
+            if ok {
+                body.unop(UnaryOp::I32Eqz);
+            }
+            body.if_else(ValType::Externref, load, |otherwise| {
+                otherwise.ref_null(ValType::Externref);
+            });
+        }
+    }
+}
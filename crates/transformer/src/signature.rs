@@ -0,0 +1,119 @@
+use anyhow::{bail, Result};
+use externref_metadata::{FunctionData, ResultRefs};
+use walrus::ValType;
+
+use crate::table::IndexType;
+
+/// The signature of a function once its handles have been replaced with `externref`s.
+pub(crate) struct ExternRefSignature {
+    /// The untransformed parameter types.
+    pub original_params: Vec<ValType>,
+    /// The untransformed result types.
+    pub original_results: Vec<ValType>,
+    /// The transformed parameter types.
+    pub params: Vec<ValType>,
+    /// The index of the untransformed parameter each transformed parameter takes the place of,
+    /// which only differs from its position when the `externref`s are packed at the end.
+    pub order: Vec<usize>,
+    /// The transformed result types.
+    pub results: Vec<ValType>,
+    /// The handle type of each parameter that was replaced with an `externref`.
+    pub arg_index_types: Vec<Option<IndexType>>,
+    /// The handle type of the return value if it was replaced with an `externref`.
+    pub ret_index_type: Option<IndexType>,
+    /// If each parameter may be `null`, which only matters for the replaced parameters.
+    pub nullable_args: Vec<bool>,
+    /// If the returned `externref` may be `null`.
+    pub ret_nullable: bool,
+    /// The `externref` variants and handle type of a returned `Result`, which is lowered to an
+    /// `i32` tag followed by the handle of the active variant.
+    pub ret_result: Option<(ResultRefs, IndexType)>,
+}
+
+impl ExternRefSignature {
+    /// Computes the transformed signature of a function from its untransformed signature.
+    pub fn new(params: &[ValType], results: &[ValType], data: &FunctionData) -> Result<Self> {
+        let mut arg_index_types = vec![None; params.len()];
+        for &index in &data.arg_indicies {
+            let Some(ty) = params.get(index) else {
+                bail!(
+                    "externref argument {index} is out of range for a function with {} parameters",
+                    params.len()
+                );
+            };
+            arg_index_types[index] = Some(IndexType::from_val_type(*ty)?);
+        }
+
+        let mut nullable_args = vec![false; params.len()];
+        for &index in &data.nullable_args {
+            if arg_index_types.get(index).copied().flatten().is_none() {
+                bail!("nullable argument {index} isn't an externref argument");
+            }
+            nullable_args[index] = true;
+        }
+
+        let ret_index_type = match (data.ret_is_extern_ref, results) {
+            (false, _) => None,
+            (true, [ty]) => Some(IndexType::from_val_type(*ty)?),
+            (true, _) => bail!("an externref return requires exactly one result"),
+        };
+
+        let ret_result = match (data.ret_result, results) {
+            (None, _) => None,
+            (Some(_), _) if data.ret_is_extern_ref => {
+                bail!("a function can't return both an externref and a result")
+            }
+            (Some(refs), [ValType::I32, ty]) => Some((refs, IndexType::from_val_type(*ty)?)),
+            (Some(_), _) => {
+                bail!("a result return requires an i32 tag followed by the handle as results")
+            }
+        };
+
+        // Packed references follow the other parameters, in the order of `arg_indicies`.
+        let order: Vec<usize> = if data.pack_args {
+            (0..params.len())
+                .filter(|index| arg_index_types[*index].is_none())
+                .chain(data.arg_indicies.iter().copied())
+                .collect()
+        } else {
+            (0..params.len()).collect()
+        };
+        let original_params = params.to_vec();
+        let params = order
+            .iter()
+            .map(|index| match arg_index_types[*index] {
+                Some(_) => ValType::Externref,
+                None => params[*index],
+            })
+            .collect();
+        let original_results = results.to_vec();
+        let results = match (ret_index_type, ret_result) {
+            (Some(_), _) => vec![ValType::Externref],
+            (None, Some(_)) => vec![ValType::I32, ValType::Externref],
+            (None, None) => results.to_vec(),
+        };
+
+        Ok(Self {
+            original_params,
+            original_results,
+            params,
+            order,
+            results,
+            arg_index_types,
+            ret_index_type,
+            nullable_args,
+            ret_nullable: data.ret_nullable,
+            ret_result,
+        })
+    }
+
+    /// If any of the replaced parameters or the replaced result can't be `null`.
+    pub fn has_non_null_refs(&self) -> bool {
+        let args = self
+            .arg_index_types
+            .iter()
+            .zip(&self.nullable_args)
+            .any(|(index_type, nullable)| index_type.is_some() && !nullable);
+        args || (self.ret_index_type.is_some() && !self.ret_nullable)
+    }
+}
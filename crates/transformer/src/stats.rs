@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use walrus::{ExportItem, FunctionId, ImportKind, Module, ValType};
+
+/// Counts of the `externref`s a module's imports and exports take and return, for auditing a
+/// transformed module.
+///
+/// The counts come from the module's signatures, so an untransformed module, where references
+/// are still handles, has none.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    /// The imported functions taking or returning an `externref`.
+    pub imports: usize,
+    /// The exported functions taking or returning an `externref`.
+    pub exports: usize,
+    /// The `externref` parameters of those imports and exports combined.
+    pub params: usize,
+    /// The imports and exports returning an `externref`.
+    pub returns: usize,
+    /// The initial number of slots in the module's `externref` tables.
+    pub table_size: u32,
+}
+
+/// Counts the `externref`s in the signatures of a module's imports and exports.
+pub fn stats(wasm: &[u8]) -> Result<Stats> {
+    let module = Module::from_buffer(wasm).context("failed to parse wasm module")?;
+    let mut stats = Stats::default();
+
+    let imports = module
+        .imports
+        .iter()
+        .filter_map(|import| match import.kind {
+            ImportKind::Function(func_id) => Some(func_id),
+            _ => None,
+        });
+    for func_id in imports {
+        if count_refs(&module, func_id, &mut stats) {
+            stats.imports += 1;
+        }
+    }
+
+    let exports = module
+        .exports
+        .iter()
+        .filter_map(|export| match export.item {
+            ExportItem::Function(func_id) => Some(func_id),
+            _ => None,
+        });
+    for func_id in exports {
+        if count_refs(&module, func_id, &mut stats) {
+            stats.exports += 1;
+        }
+    }
+
+    stats.table_size = module
+        .tables
+        .iter()
+        .filter(|table| table.element_ty == ValType::Externref)
+        .map(|table| table.initial)
+        .sum();
+
+    Ok(stats)
+}
+
+/// Adds the `externref` parameters and return of a function to the stats, returning if it has
+/// any.
+fn count_refs(module: &Module, func_id: FunctionId, stats: &mut Stats) -> bool {
+    let ty = module.types.get(module.funcs.get(func_id).ty());
+    let params = ty
+        .params()
+        .iter()
+        .filter(|ty| **ty == ValType::Externref)
+        .count();
+    let returns = ty.results().contains(&ValType::Externref);
+
+    stats.params += params;
+    stats.returns += usize::from(returns);
+    params > 0 || returns
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::{stats, Stats};
+    use crate::{transform_with_config, Config, TableStrategy};
+
+    #[test]
+    fn fixture_stats() -> Result<()> {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "console" "log" (func $log (param i32 i32)))
+                (import "dom" "create" (func $create (result i32)))
+                (import "env" "now" (func $now (result f64)))
+                (func (export "identity") (param i32) (result i32)
+                    local.get 0)
+                (func (export "run") (param i32)
+                    local.get 0
+                    local.get 0
+                    call $log)
+                (@custom "__extern_ref_data_console_log"
+                    "{\"name\":\"log\",\"argIndicies\":[0,1],\"retIsExternRef\":false}")
+                (@custom "__extern_ref_data_dom_create"
+                    "{\"name\":\"create\",\"argIndicies\":[],\"retIsExternRef\":true}")
+                (@custom "__extern_ref_data_identity"
+                    "{\"name\":\"identity\",\"argIndicies\":[0],\"retIsExternRef\":true}"))
+            "#,
+        )?;
+
+        // The handles aren't references until the module is transformed.
+        assert_eq!(stats(&wasm)?, Stats::default());
+
+        let config = Config {
+            table_initial: 8,
+            ..Default::default()
+        };
+        let transformed = transform_with_config(&wasm, &config)?;
+        assert_eq!(
+            stats(&transformed)?,
+            Stats {
+                imports: 2,
+                exports: 1,
+                params: 3,
+                returns: 2,
+                table_size: 8,
+            }
+        );
+
+        // The size of every externref table is counted.
+        let config = Config {
+            table_initial: 8,
+            table_strategy: TableStrategy::PerModule,
+            ..Default::default()
+        };
+        let transformed = transform_with_config(&wasm, &config)?;
+        assert_eq!(stats(&transformed)?.table_size, 24);
+
+        Ok(())
+    }
+}
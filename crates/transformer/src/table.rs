@@ -0,0 +1,704 @@
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::{bail, Result};
+use walrus::{
+    ir::{BinaryOp, LoadKind, MemArg, StoreKind, UnaryOp, Value},
+    FunctionBuilder, FunctionId, GlobalId, InitExpr, InstrSeqBuilder, LocalId, MemoryId, Module,
+    TableId, ValType,
+};
+
+use crate::config::TableStrategy;
+
+/// The number of low bits of a handle holding the slot when there is a table per import module.
+/// The remaining bits hold the index of the table, so a handle can be passed to any import.
+const SLOT_BITS: u32 = 24;
+
+/// The most slots a table can have when there is a table per import module.
+const MAX_SLOTS: u32 = 1 << SLOT_BITS;
+
+/// The most tables there can be when there is a table per import module.
+const MAX_TABLES: usize = 1 << (32 - SLOT_BITS);
+
+/// The size of a page of linear memory.
+const PAGE_SIZE: i32 = 1 << 16;
+
+/// The number of freed slots a page of a free list holds, after the link to the page beneath it.
+const FREE_PAGE_SLOTS: i32 = (PAGE_SIZE - 4) / 4;
+
+/// The type Rust uses for an `ExternRef` handle, which follows the pointer width of the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IndexType {
+    /// A `usize` on `wasm32`.
+    I32,
+    /// A `usize` on `wasm64`.
+    I64,
+}
+
+impl IndexType {
+    /// Determines the index type from the type a handle has in the untransformed signature.
+    pub fn from_val_type(ty: ValType) -> Result<Self> {
+        match ty {
+            ValType::I32 => Ok(Self::I32),
+            ValType::I64 => Ok(Self::I64),
+            ty => bail!("expected an i32 or i64 externref handle, found {ty}"),
+        }
+    }
+
+    /// The value type of a handle of this type.
+    pub fn val_type(self) -> ValType {
+        match self {
+            Self::I32 => ValType::I32,
+            Self::I64 => ValType::I64,
+        }
+    }
+
+    /// Converts a table index on the stack into a handle of this type.
+    pub fn index_to_handle(self, body: &mut InstrSeqBuilder) {
+        if self == Self::I64 {
+            body.unop(UnaryOp::I64ExtendUI32);
+        }
+    }
+
+    /// Converts a handle of this type on the stack into a table index.
+    pub fn handle_to_index(self, body: &mut InstrSeqBuilder) {
+        if self == Self::I64 {
+            body.unop(UnaryOp::I32WrapI64);
+        }
+    }
+}
+
+/// The table holding every `externref` the module has been given, along with the helper
+/// functions used to move references in and out of it.
+///
+/// Slot `0` is never written to, so it always holds `ref.null extern`.
+pub(crate) struct ExternRefTable {
+    /// The table the references are stored in.
+    pub table: TableId,
+    /// `(externref) -> i32`, stores a reference and returns its index.
+    pub alloc: FunctionId,
+    /// `(i32) -> externref`, loads the reference at an index.
+    pub get: FunctionId,
+    /// `(i32) -> ()`, clears the slot at an index so `alloc` hands it out again, when the table
+    /// recycles its slots.
+    pub free: Option<FunctionId>,
+}
+
+impl ExternRefTable {
+    /// Adds the table and its helper functions to the module.
+    ///
+    /// The table starts with `initial` slots, or one for the reserved null slot if that's larger,
+    /// and can grow to `maximum` slots. With `recycle`, freed slots are handed out before the table
+    /// is grown.
+    pub fn create(
+        module: &mut Module,
+        initial: u32,
+        maximum: Option<u32>,
+        recycle: bool,
+    ) -> Result<Self> {
+        let name = "__externref_table";
+        let id = create_table(module, name, initial, maximum)?;
+        log::debug!("created externref table {id:?}");
+
+        let free_list = recycle.then(|| FreeList::create(module, name));
+        Ok(Self {
+            table: id,
+            alloc: create_alloc(module, id, free_list.as_ref()),
+            get: create_get(module, id),
+            free: free_list.map(|free_list| create_free(module, id, &free_list)),
+        })
+    }
+}
+
+/// The slots of a table that were freed and can be handed out again.
+///
+/// The slots are kept on a stack in pages of linear memory that are grown on demand. The first
+/// word of each page links to the page beneath it. Pages emptied by popping are kept as spares for
+/// later pushes, so the pages only ever cover the most slots that were free at once.
+struct FreeList {
+    /// `(i32) -> ()`, pushes a freed slot.
+    push: FunctionId,
+    /// `() -> i32`, pops a freed slot, or returns `0` when there is none.
+    pop: FunctionId,
+}
+
+impl FreeList {
+    /// Adds the free list of the table named `name` to the module, adding a memory for it if the
+    /// module doesn't have one.
+    fn create(module: &mut Module, name: &str) -> Self {
+        let memory = module.memories.iter().next().map(|memory| memory.id());
+        let memory = memory.unwrap_or_else(|| module.memories.add_local(false, 0, None));
+        let mut global = |value| {
+            module
+                .globals
+                .add_local(ValType::I32, true, InitExpr::Value(Value::I32(value)))
+        };
+        // The address of the top page, the number of slots on it and the address of the first
+        // spare page, `-1` standing for no page.
+        let (top, len, spare) = (global(-1), global(0), global(-1));
+
+        Self {
+            push: create_free_list_push(module, name, memory, top, len, spare),
+            pop: create_free_list_pop(module, name, memory, top, len, spare),
+        }
+    }
+}
+
+/// The link to the next page, at the start of a page.
+const LINK: MemArg = MemArg {
+    align: 4,
+    offset: 0,
+};
+
+/// A slot on a page, after the link.
+const SLOT: MemArg = MemArg {
+    align: 4,
+    offset: 4,
+};
+
+const I32_LOAD: LoadKind = LoadKind::I32 { atomic: false };
+const I32_STORE: StoreKind = StoreKind::I32 { atomic: false };
+
+/// Creates the function pushing a slot on a free list. The slot is dropped rather than recycled
+/// when a page is needed and memory can't be grown.
+fn create_free_list_push(
+    module: &mut Module,
+    name: &str,
+    memory: MemoryId,
+    top: GlobalId,
+    len: GlobalId,
+    spare: GlobalId,
+) -> FunctionId {
+    let mut builder = FunctionBuilder::new(&mut module.types, &[ValType::I32], &[]);
+    builder.name(format!("{name}_free_list_push"));
+
+    let slot = module.locals.add(ValType::I32);
+    let page = module.locals.add(ValType::I32);
+
+    let mut body = builder.func_body();
+    // A page is needed for the first slot and once the top page is full.
+    body.global_get(top)
+        .i32_const(-1)
+        .binop(BinaryOp::I32Eq)
+        .global_get(len)
+        .i32_const(FREE_PAGE_SLOTS)
+        .binop(BinaryOp::I32Eq)
+        .binop(BinaryOp::I32Or)
+        .if_else(
+            None,
+            |then| {
+                then.global_get(spare)
+                    .i32_const(-1)
+                    .binop(BinaryOp::I32Ne)
+                    .if_else(
+                        None,
+                        |reuse| {
+                            reuse
+                                .global_get(spare)
+                                .local_tee(page)
+                                .load(memory, I32_LOAD, LINK)
+                                .global_set(spare);
+                        },
+                        |grow| {
+                            grow.i32_const(1)
+                                .memory_grow(memory)
+                                .local_tee(page)
+                                .i32_const(-1)
+                                .binop(BinaryOp::I32Eq)
+                                .if_else(
+                                    None,
+                                    |full| {
+                                        full.return_();
+                                    },
+                                    |_| {},
+                                )
+                                .local_get(page)
+                                .i32_const(PAGE_SIZE)
+                                .binop(BinaryOp::I32Mul)
+                                .local_set(page);
+                        },
+                    )
+                    .local_get(page)
+                    .global_get(top)
+                    .store(memory, I32_STORE, LINK)
+                    .local_get(page)
+                    .global_set(top)
+                    .i32_const(0)
+                    .global_set(len);
+            },
+            |_| {},
+        );
+    body.global_get(top)
+        .global_get(len)
+        .i32_const(4)
+        .binop(BinaryOp::I32Mul)
+        .binop(BinaryOp::I32Add)
+        .local_get(slot)
+        .store(memory, I32_STORE, SLOT)
+        .global_get(len)
+        .i32_const(1)
+        .binop(BinaryOp::I32Add)
+        .global_set(len);
+
+    builder.finish(vec![slot], &mut module.funcs)
+}
+
+/// Creates the function popping a slot off a free list.
+fn create_free_list_pop(
+    module: &mut Module,
+    name: &str,
+    memory: MemoryId,
+    top: GlobalId,
+    len: GlobalId,
+    spare: GlobalId,
+) -> FunctionId {
+    let mut builder = FunctionBuilder::new(&mut module.types, &[], &[ValType::I32]);
+    builder.name(format!("{name}_free_list_pop"));
+
+    let below = module.locals.add(ValType::I32);
+
+    builder
+        .func_body()
+        .global_get(top)
+        .i32_const(-1)
+        .binop(BinaryOp::I32Eq)
+        .if_else(
+            ValType::I32,
+            |empty| {
+                empty.i32_const(0);
+            },
+            |pages| {
+                // An empty top page gives way to the page beneath it, if there is one, and becomes a
+                // spare.
+                pages
+                    .global_get(len)
+                    .unop(UnaryOp::I32Eqz)
+                    .global_get(top)
+                    .load(memory, I32_LOAD, LINK)
+                    .local_tee(below)
+                    .i32_const(-1)
+                    .binop(BinaryOp::I32Ne)
+                    .binop(BinaryOp::I32And)
+                    .if_else(
+                        None,
+                        |down| {
+                            down.global_get(top)
+                                .global_get(spare)
+                                .store(memory, I32_STORE, LINK)
+                                .global_get(top)
+                                .global_set(spare)
+                                .local_get(below)
+                                .global_set(top)
+                                .i32_const(FREE_PAGE_SLOTS)
+                                .global_set(len);
+                        },
+                        |_| {},
+                    )
+                    .global_get(len)
+                    .unop(UnaryOp::I32Eqz)
+                    .if_else(
+                        ValType::I32,
+                        |none| {
+                            none.i32_const(0);
+                        },
+                        |pop| {
+                            pop.global_get(len)
+                                .i32_const(1)
+                                .binop(BinaryOp::I32Sub)
+                                .global_set(len)
+                                .global_get(top)
+                                .global_get(len)
+                                .i32_const(4)
+                                .binop(BinaryOp::I32Mul)
+                                .binop(BinaryOp::I32Add)
+                                .load(memory, I32_LOAD, SLOT);
+                        },
+                    );
+            },
+        );
+
+    builder.finish(vec![], &mut module.funcs)
+}
+
+/// Creates the function clearing a slot of the table and pushing it on its free list. The null
+/// slot is left alone.
+fn create_free(module: &mut Module, table: TableId, free_list: &FreeList) -> FunctionId {
+    let mut builder = FunctionBuilder::new(&mut module.types, &[ValType::I32], &[]);
+    let name = module.tables.get(table).name.clone().unwrap_or_default();
+    builder.name(format!("{name}_free"));
+
+    let slot = module.locals.add(ValType::I32);
+    builder.func_body().local_get(slot).if_else(
+        None,
+        |then| {
+            then.local_get(slot)
+                .ref_null(ValType::Externref)
+                .table_set(table)
+                .local_get(slot)
+                .call(free_list.push);
+        },
+        |_| {},
+    );
+
+    builder.finish(vec![slot], &mut module.funcs)
+}
+
+/// The externref tables of a module and the functions moving references in and out of them.
+pub(crate) struct ExternRefTables {
+    /// The table exports, callbacks and export references store references in, and every import
+    /// with [TableStrategy::Single].
+    default: ExternRefTable,
+    /// The table of each import module with [TableStrategy::PerModule], keyed by the module's name
+    /// after placeholder substitution.
+    modules: HashMap<String, ExternRefTable>,
+    /// Every function added to move references in and out of the tables.
+    accessors: Vec<FunctionId>,
+}
+
+impl ExternRefTables {
+    /// Adds the tables for a strategy and their helper functions to the module, with a table for
+    /// each of `import_modules` with [TableStrategy::PerModule].
+    ///
+    /// Every table starts with `initial` slots and can grow to `maximum` slots. With `recycle`,
+    /// every table hands out its freed slots before growing.
+    pub fn create<'a>(
+        module: &mut Module,
+        strategy: TableStrategy,
+        initial: u32,
+        maximum: Option<u32>,
+        import_modules: impl IntoIterator<Item = &'a str>,
+        recycle: bool,
+    ) -> Result<Self> {
+        if strategy == TableStrategy::Single {
+            let default = ExternRefTable::create(module, initial, maximum, recycle)?;
+            return Ok(Self {
+                accessors: vec![default.alloc, default.get],
+                default,
+                modules: HashMap::new(),
+            });
+        }
+
+        let import_modules: BTreeSet<_> = import_modules.into_iter().collect();
+        if import_modules.len() >= MAX_TABLES {
+            bail!(
+                "a table per import module supports at most {} import modules, found {}",
+                MAX_TABLES - 1,
+                import_modules.len()
+            );
+        }
+        if initial > MAX_SLOTS {
+            bail!("a table per import module supports at most {MAX_SLOTS} slots per table");
+        }
+        let maximum = Some(maximum.map_or(MAX_SLOTS, |maximum| maximum.min(MAX_SLOTS)));
+
+        // The default table has index `0`, so its handles are the same as with a single table.
+        let mut tables = vec![create_table(module, "__externref_table", initial, maximum)?];
+        for name in &import_modules {
+            let name = format!("__externref_table_{name}");
+            tables.push(create_table(module, &name, initial, maximum)?);
+        }
+        log::debug!("created {} externref tables", tables.len());
+
+        let get = create_tagged_get(module, &tables);
+        let mut accessors = vec![get];
+        let mut frees = Vec::new();
+        let allocs: Vec<_> = tables
+            .iter()
+            .enumerate()
+            .map(|(tag, table)| {
+                let name = module.tables.get(*table).name.clone().unwrap_or_default();
+                let free_list = recycle.then(|| FreeList::create(module, &name));
+                let (alloc, alloc_slot) =
+                    create_tagged_alloc(module, *table, tag as u32, free_list.as_ref());
+                accessors.extend([alloc, alloc_slot]);
+                if let Some(free_list) = free_list {
+                    frees.push(create_free(module, *table, &free_list));
+                }
+                alloc
+            })
+            .collect();
+        let mut allocs = allocs.into_iter();
+        let free = recycle.then(|| create_tagged_free(module, &frees));
+
+        let default = ExternRefTable {
+            table: tables[0],
+            alloc: allocs.next().expect("the default table is always created"),
+            get,
+            free,
+        };
+        let modules = import_modules
+            .into_iter()
+            .zip(tables[1..].iter().zip(allocs))
+            .map(|(name, (table, alloc))| {
+                let table = ExternRefTable {
+                    table: *table,
+                    alloc,
+                    get,
+                    free,
+                };
+                (name.to_string(), table)
+            })
+            .collect();
+        Ok(Self {
+            default,
+            modules,
+            accessors,
+        })
+    }
+
+    /// The table references returned by an import from `import_module` are stored in.
+    pub fn import(&self, import_module: &str) -> &ExternRefTable {
+        self.modules.get(import_module).unwrap_or(&self.default)
+    }
+
+    /// The table references given to exports, callbacks and export references are stored in.
+    pub fn default(&self) -> &ExternRefTable {
+        &self.default
+    }
+
+    /// The table of each import module with [TableStrategy::PerModule], sorted by the module's name.
+    pub fn modules(&self) -> Vec<(&str, &ExternRefTable)> {
+        let mut modules: Vec<_> = self
+            .modules
+            .iter()
+            .map(|(name, table)| (name.as_str(), table))
+            .collect();
+        modules.sort_by_key(|(name, _)| *name);
+        modules
+    }
+
+    /// Every function added to move references in and out of the tables.
+    pub fn accessors(&self) -> &[FunctionId] {
+        &self.accessors
+    }
+}
+
+/// Adds an externref table to the module.
+fn create_table(
+    module: &mut Module,
+    name: &str,
+    initial: u32,
+    maximum: Option<u32>,
+) -> Result<TableId> {
+    let initial = initial.max(1);
+    if let Some(maximum) = maximum.filter(|maximum| *maximum < initial) {
+        bail!(
+            "the externref table can't have a maximum of {maximum} slots, it needs at least \
+             {initial}"
+        );
+    }
+
+    let id = module
+        .tables
+        .add_local(initial, maximum, ValType::Externref);
+    module.tables.get_mut(id).name = Some(name.into());
+    Ok(id)
+}
+
+/// Creates the function storing a reference in the table. A slot is taken from the free list if
+/// there is one, otherwise the table is grown by a slot. Null references aren't stored and are
+/// mapped to the reserved null slot instead.
+fn create_alloc(module: &mut Module, table: TableId, free_list: Option<&FreeList>) -> FunctionId {
+    let mut builder =
+        FunctionBuilder::new(&mut module.types, &[ValType::Externref], &[ValType::I32]);
+    builder.name("__externref_table_alloc".into());
+
+    let reference = module.locals.add(ValType::Externref);
+    let index = module.locals.add(ValType::I32);
+
+    let grow = |grow: &mut InstrSeqBuilder| {
+        grow.local_get(reference)
+            .i32_const(1)
+            .table_grow(table)
+            .local_tee(index)
+            // `table.grow` returns -1 when the table can't be grown any further.
+            .i32_const(-1)
+            .binop(BinaryOp::I32Eq)
+            .if_else(
+                None,
+                |then| {
+                    then.unreachable();
+                },
+                |_| {},
+            );
+    };
+
+    builder
+        .func_body()
+        .local_get(reference)
+        .ref_is_null()
+        .if_else(
+            ValType::I32,
+            |then| {
+                then.i32_const(0);
+            },
+            |otherwise| {
+                match free_list {
+                    Some(free_list) => {
+                        otherwise.call(free_list.pop).local_tee(index).if_else(
+                            None,
+                            |reuse| {
+                                reuse.local_get(index).local_get(reference).table_set(table);
+                            },
+                            grow,
+                        );
+                    }
+                    None => grow(otherwise),
+                }
+                otherwise.local_get(index);
+            },
+        );
+
+    builder.finish(vec![reference], &mut module.funcs)
+}
+
+/// Creates the function loading a reference from the table.
+fn create_get(module: &mut Module, table: TableId) -> FunctionId {
+    let mut builder =
+        FunctionBuilder::new(&mut module.types, &[ValType::I32], &[ValType::Externref]);
+    builder.name("__externref_table_get".into());
+
+    let index = module.locals.add(ValType::I32);
+    builder.func_body().local_get(index).table_get(table);
+
+    builder.finish(vec![index], &mut module.funcs)
+}
+
+/// Creates the function storing a reference in one of several tables, returning the slot tagged
+/// with the index of the table.
+///
+/// The function calls the table's own alloc, which is returned after it.
+fn create_tagged_alloc(
+    module: &mut Module,
+    table: TableId,
+    tag: u32,
+    free_list: Option<&FreeList>,
+) -> (FunctionId, FunctionId) {
+    let alloc = create_alloc(module, table, free_list);
+    let name = module.tables.get(table).name.clone().unwrap_or_default();
+    module.funcs.get_mut(alloc).name = Some(format!("{name}_alloc_slot"));
+
+    let mut builder =
+        FunctionBuilder::new(&mut module.types, &[ValType::Externref], &[ValType::I32]);
+    builder.name(format!("{name}_alloc"));
+
+    let reference = module.locals.add(ValType::Externref);
+    let index = module.locals.add(ValType::I32);
+
+    // The null slot of every table maps to the same null handle.
+    builder
+        .func_body()
+        .local_get(reference)
+        .call(alloc)
+        .local_tee(index)
+        .if_else(
+            ValType::I32,
+            |then| {
+                then.local_get(index)
+                    .i32_const((tag << SLOT_BITS) as i32)
+                    .binop(BinaryOp::I32Or);
+            },
+            |otherwise| {
+                otherwise.i32_const(0);
+            },
+        );
+
+    (builder.finish(vec![reference], &mut module.funcs), alloc)
+}
+
+/// Creates the function loading a reference from the table its tagged handle points into.
+fn create_tagged_get(module: &mut Module, tables: &[TableId]) -> FunctionId {
+    let mut builder =
+        FunctionBuilder::new(&mut module.types, &[ValType::I32], &[ValType::Externref]);
+    builder.name("__externref_table_get".into());
+
+    let index = module.locals.add(ValType::I32);
+    let tag = module.locals.add(ValType::I32);
+    let slot = module.locals.add(ValType::I32);
+
+    let mut body = builder.func_body();
+    body.local_get(index)
+        .i32_const(SLOT_BITS as i32)
+        .binop(BinaryOp::I32ShrU)
+        .local_set(tag)
+        .local_get(index)
+        .i32_const((MAX_SLOTS - 1) as i32)
+        .binop(BinaryOp::I32And)
+        .local_set(slot);
+    get_from_tables(&mut body, tables, 0, tag, slot);
+
+    builder.finish(vec![index], &mut module.funcs)
+}
+
+/// Creates the function freeing a slot of the table its tagged handle points into, with the free
+/// function of each table in the order of their tags.
+fn create_tagged_free(module: &mut Module, frees: &[FunctionId]) -> FunctionId {
+    let mut builder = FunctionBuilder::new(&mut module.types, &[ValType::I32], &[]);
+    builder.name("__externref_table_free".into());
+
+    let index = module.locals.add(ValType::I32);
+    let tag = module.locals.add(ValType::I32);
+    let slot = module.locals.add(ValType::I32);
+
+    let mut body = builder.func_body();
+    body.local_get(index)
+        .i32_const(SLOT_BITS as i32)
+        .binop(BinaryOp::I32ShrU)
+        .local_set(tag)
+        .local_get(index)
+        .i32_const((MAX_SLOTS - 1) as i32)
+        .binop(BinaryOp::I32And)
+        .local_set(slot);
+    free_in_tables(&mut body, frees, 0, tag, slot);
+
+    builder.finish(vec![index], &mut module.funcs)
+}
+
+/// Frees the slot in the table matching the tag, trapping when no table does.
+fn free_in_tables(
+    body: &mut InstrSeqBuilder,
+    frees: &[FunctionId],
+    first_tag: u32,
+    tag: LocalId,
+    slot: LocalId,
+) {
+    let Some((free, rest)) = frees.split_first() else {
+        body.unreachable();
+        return;
+    };
+
+    body.local_get(tag)
+        .i32_const(first_tag as i32)
+        .binop(BinaryOp::I32Eq)
+        .if_else(
+            None,
+            |then| {
+                then.local_get(slot).call(*free);
+            },
+            |otherwise| free_in_tables(otherwise, rest, first_tag + 1, tag, slot),
+        );
+}
+
+/// Loads the slot from the table matching the tag, trapping when no table does.
+fn get_from_tables(
+    body: &mut InstrSeqBuilder,
+    tables: &[TableId],
+    first_tag: u32,
+    tag: LocalId,
+    slot: LocalId,
+) {
+    let Some((table, rest)) = tables.split_first() else {
+        body.unreachable();
+        return;
+    };
+
+    body.local_get(tag)
+        .i32_const(first_tag as i32)
+        .binop(BinaryOp::I32Eq)
+        .if_else(
+            ValType::Externref,
+            |then| {
+                then.local_get(slot).table_get(*table);
+            },
+            |otherwise| get_from_tables(otherwise, rest, first_tag + 1, tag, slot),
+        );
+}
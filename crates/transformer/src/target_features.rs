@@ -0,0 +1,58 @@
+use anyhow::{bail, Context, Result};
+use walrus::{IdsToIndices, Module};
+use wasmparser::BinaryReader;
+
+/// The custom section the linker lists the target features the module was compiled with in.
+const TARGET_FEATURES_SECTION: &str = "target_features";
+
+/// The feature the rewritten signatures and the externref table need.
+const REFERENCE_TYPES: &str = "reference-types";
+
+/// Checks that the module's `target_features` section allows `reference-types`.
+///
+/// This fails with the flags to build the module with when the feature is explicitly disallowed.
+/// When the section only doesn't list it, e.g. for the default output of older rustc versions,
+/// the module itself doesn't use any reference types, so the transform goes ahead and this
+/// returns a warning that the output needs a runtime supporting them. Modules without the section,
+/// e.g. ones written by hand, are assumed to support it.
+pub(crate) fn check_reference_types(module: &Module) -> Result<Option<String>> {
+    let Some((_, section)) = module
+        .customs
+        .iter()
+        .find(|(_, section)| section.name() == TARGET_FEATURES_SECTION)
+    else {
+        return Ok(None);
+    };
+
+    let data = section.data(&IdsToIndices::default());
+    let features = parse(&data).context("invalid target_features section")?;
+    let prefix = features
+        .iter()
+        .find(|(_, name)| name == REFERENCE_TYPES)
+        .map(|(prefix, _)| *prefix);
+    match prefix {
+        Some(b'-') => bail!(
+            "the module was compiled with the {REFERENCE_TYPES} target feature disallowed, which \
+             the transformed module needs for `externref`s. Build it with `RUSTFLAGS=\"-C \
+             target-feature=+{REFERENCE_TYPES}\"`, or `-m{REFERENCE_TYPES}` for clang, or lower \
+             the references to i32 handles with `--lower-to-i32` for runtimes without it"
+        ),
+        Some(_) => Ok(None),
+        None => Ok(Some(format!(
+            "the module's target features don't include {REFERENCE_TYPES}, which the transformed \
+             module needs for `externref`s, so it only runs where the runtime supports them"
+        ))),
+    }
+}
+
+/// Reads the prefix, `+` used, `-` disallowed or `=` required, and name of every feature.
+fn parse(data: &[u8]) -> Result<Vec<(u8, String)>> {
+    let mut reader = BinaryReader::new(data, 0);
+    let count = reader.read_var_u32()?;
+    let mut features = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let prefix = reader.read_u8()?;
+        features.push((prefix, reader.read_string()?.to_string()));
+    }
+    Ok(features)
+}
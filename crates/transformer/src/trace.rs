@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use walrus::{
+    ActiveData, ActiveDataLocation, DataKind, FunctionId, ImportId, InstrSeqBuilder, Module,
+    ValType,
+};
+
+/// The module and name of the host function called with the name of every traced import.
+const TRACE: (&str, &str) = ("__externref", "trace");
+
+/// The size of a wasm page in bytes.
+const PAGE_SIZE: u32 = 64 * 1024;
+
+/// Calls the host's trace function with the name of an import before the import is called.
+pub(crate) struct Tracer {
+    /// The imported trace function taking a pointer to a name and its length.
+    func: FunctionId,
+    /// The address and length of the name of each traced import.
+    names: HashMap<ImportId, (u32, u32)>,
+}
+
+impl Tracer {
+    /// Imports the trace function and stores the names of the imports in the module's memory.
+    ///
+    /// The names are placed in pages added after the memory's initial pages. The stack and static
+    /// data are laid out by the linker within the initial pages, and allocators get more memory
+    /// with `memory.grow`, which starts after the added pages.
+    pub fn create(module: &mut Module, imports: &[(ImportId, String)]) -> Result<Self> {
+        let Some(memory) = module.memories.iter_mut().next() else {
+            bail!("tracing imports requires a memory to store their names in");
+        };
+        if memory.import.is_some() {
+            bail!("tracing imports requires a memory defined by the module, not an imported one");
+        }
+
+        let mut data = Vec::new();
+        let mut names = HashMap::new();
+        let base = memory.initial * PAGE_SIZE;
+        for (import_id, name) in imports {
+            names.insert(*import_id, (base + data.len() as u32, name.len() as u32));
+            data.extend_from_slice(name.as_bytes());
+        }
+
+        let pages = (data.len() as u32).div_ceil(PAGE_SIZE);
+        let initial = memory.initial + pages;
+        if memory.maximum.is_some_and(|maximum| initial > maximum) {
+            bail!("tracing imports requires {pages} more memory pages than the memory's maximum");
+        }
+        memory.initial = initial;
+
+        let memory = memory.id();
+        module.data.add(
+            DataKind::Active(ActiveData {
+                memory,
+                location: ActiveDataLocation::Absolute(base),
+            }),
+            data,
+        );
+
+        let (module_name, name) = TRACE;
+        let ty = module.types.add(&[ValType::I32, ValType::I32], &[]);
+        let (func, _) = module.add_import_func(module_name, name, ty);
+        module.funcs.get_mut(func).name = Some(format!("{module_name}.{name}"));
+
+        log::debug!("tracing {} imports", names.len());
+        Ok(Self { func, names })
+    }
+
+    /// Emits a call to the trace function with the name of an import, if it's traced.
+    pub fn call(&self, body: &mut InstrSeqBuilder, import_id: ImportId) {
+        if let Some((address, len)) = self.names.get(&import_id) {
+            body.i32_const(*address as i32)
+                .i32_const(*len as i32)
+                .call(self.func);
+        }
+    }
+}
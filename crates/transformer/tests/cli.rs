@@ -0,0 +1,267 @@
+//! Runs the `externref-cli` binary the way shell pipelines use it.
+#![cfg(feature = "transform")]
+
+use std::process::Command;
+
+use anyhow::Result;
+
+#[test]
+fn output_to_stdout() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+            (import "console" "log" (func $log (param i32)))
+            (func (export "run") (param i32)
+                local.get 0
+                call $log)
+            (@custom "__extern_ref_data_console_log"
+                "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+        "#,
+    )?;
+    let input = std::env::temp_dir().join(format!("externref-cli-{}.wasm", std::process::id()));
+    std::fs::write(&input, &wasm)?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_externref-cli"))
+        .arg(&input)
+        .args(["-o", "-", "--json"])
+        .output()?;
+    let written = std::fs::read(&input)?;
+    std::fs::remove_file(&input)?;
+
+    assert!(output.status.success(), "{output:?}");
+    // Only the module is written to stdout, and the input is left alone.
+    assert_eq!(output.stdout, externref_transformer::transform(&wasm)?);
+    assert_eq!(written, wasm);
+
+    // The report moves to stderr so it doesn't corrupt the module.
+    let report: serde_json::Value = serde_json::from_slice(&output.stderr)?;
+    assert_eq!(report["tableCreated"], true);
+
+    Ok(())
+}
+
+#[test]
+fn preview_groups() -> Result<()> {
+    let wasm = wat::parse_str(
+        r#"
+        (module
+            (import "console" "log" (func $log (param i32)))
+            (func (export "run") (param i32)
+                local.get 0
+                call $log)
+            (@custom "__extern_ref_data_debug@console_log"
+                "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false,\"group\":\"debug\"}")
+            (@custom "__extern_ref_data_dom@run"
+                "{\"name\":\"run\",\"argIndicies\":[0],\"retIsExternRef\":false,\"group\":\"dom\"}"))
+        "#,
+    )?;
+    let input =
+        std::env::temp_dir().join(format!("externref-cli-groups-{}.wasm", std::process::id()));
+    std::fs::write(&input, &wasm)?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_externref-cli"))
+        .args(["preview", "--json", "--group", "dom"])
+        .arg(&input)
+        .output()?;
+    std::fs::remove_file(&input)?;
+
+    assert!(output.status.success(), "{output:?}");
+    let diffs: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(
+        diffs,
+        serde_json::json!([{
+            "name": "run",
+            "kind": "export",
+            "argIndices": [0],
+            "group": "dom",
+            "oldParams": ["i32"],
+            "oldResults": [],
+            "newParams": ["externref"],
+            "newResults": [],
+        }])
+    );
+
+    Ok(())
+}
+
+#[test]
+fn fail_on_warning() -> Result<()> {
+    // The section describes an import the module doesn't have.
+    let wasm = wat::parse_str(
+        r#"
+        (module
+            (import "console" "log" (func $log (param i32)))
+            (func (export "run") (param i32)
+                local.get 0
+                call $log)
+            (@custom "__extern_ref_data_console_log"
+                "{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}")
+            (@custom "__extern_ref_data_console_warn"
+                "{\"name\":\"warn\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+        "#,
+    )?;
+    let input = std::env::temp_dir().join(format!(
+        "externref-cli-warnings-{}.wasm",
+        std::process::id()
+    ));
+    let run = |args: &[&str]| -> Result<std::process::Output> {
+        std::fs::write(&input, &wasm)?;
+        Ok(Command::new(env!("CARGO_BIN_EXE_externref-cli"))
+            .arg(&input)
+            .args(args)
+            .output()?)
+    };
+
+    let output = run(&["--fail-on-warning"])?;
+    let written = std::fs::read(&input)?;
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("__extern_ref_data_console_warn doesn't describe any import or export"),
+        "{stderr}"
+    );
+    // The module isn't written when the transform fails.
+    assert_eq!(written, wasm);
+
+    let output = run(&[])?;
+    let written = std::fs::read(&input)?;
+    std::fs::remove_file(&input)?;
+    assert!(output.status.success(), "{output:?}");
+    assert_ne!(written, wasm);
+
+    Ok(())
+}
+
+#[test]
+fn normalize() -> Result<()> {
+    // Two builds of the same source, where the linker ordered the imports and exports differently
+    // and the builds were produced by different tool versions.
+    let build = |first: &str, second: &str, producer: &str| {
+        wat::parse_str(format!(
+            r#"
+            (module
+                (import "console" "{first}" (func ${first} (param i32)))
+                (import "console" "{second}" (func ${second} (param i32)))
+                (func $log_all (param i32)
+                    local.get 0
+                    call $log)
+                (func $error_all (param i32)
+                    local.get 0
+                    call $error)
+                (export "{first}_all" (func ${first}_all))
+                (export "{second}_all" (func ${second}_all))
+                (@custom "__extern_ref_data_console_log"
+                    "{{\"name\":\"log\",\"argIndicies\":[0],\"retIsExternRef\":false}}")
+                (@custom "__extern_ref_data_console_error"
+                    "{{\"name\":\"error\",\"argIndicies\":[0],\"retIsExternRef\":false}}")
+                (@custom "build-id" "{producer}"))
+            "#
+        ))
+    };
+
+    let mut outputs = Vec::new();
+    for (index, wasm) in [
+        build("log", "error", "1.0")?,
+        build("log", "error", "1.0")?,
+        build("error", "log", "2.0")?,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let input = std::env::temp_dir().join(format!(
+            "externref-cli-normalize-{}-{index}.wasm",
+            std::process::id()
+        ));
+        std::fs::write(&input, &wasm)?;
+        let output = Command::new(env!("CARGO_BIN_EXE_externref-cli"))
+            .arg(&input)
+            .args(["-o", "-", "--normalize"])
+            .output()?;
+        std::fs::remove_file(&input)?;
+
+        assert!(output.status.success(), "{output:?}");
+        outputs.push(output.stdout);
+    }
+    assert_eq!(outputs[0], outputs[1]);
+    assert_eq!(outputs[0], outputs[2]);
+    wasmparser::validate(&outputs[0])?;
+
+    // Only the name section is left.
+    let mut imports = Vec::new();
+    for payload in wasmparser::Parser::new(0).parse_all(&outputs[0]) {
+        match payload? {
+            wasmparser::Payload::CustomSection(section) => assert_eq!(section.name(), "name"),
+            wasmparser::Payload::ImportSection(section) => {
+                for import in section.into_imports() {
+                    imports.push(import?.name.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(imports, ["error", "log"]);
+
+    Ok(())
+}
+
+#[test]
+fn schema() -> Result<()> {
+    let output = Command::new(env!("CARGO_BIN_EXE_externref-cli"))
+        .arg("schema")
+        .output()?;
+    assert!(output.status.success(), "{output:?}");
+
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(schema, externref_metadata::json_schema());
+    assert!(schema["properties"]["argIndicies"].is_object());
+
+    Ok(())
+}
+
+#[test]
+fn wat_input() -> Result<()> {
+    let input = std::env::temp_dir().join(format!("externref-cli-{}.wat", std::process::id()));
+    std::fs::write(
+        &input,
+        r#"
+        (module
+            (import "console" "log" (func $log (param i32 i32)))
+            (func (export "run") (param i32)
+                i32.const 1
+                local.get 0
+                call $log)
+            (@custom "__extern_ref_data_console_log"
+                "{\"name\":\"log\",\"argIndicies\":[1],\"retIsExternRef\":false}"))
+        "#,
+    )?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_externref-cli"))
+        .arg(&input)
+        .args(["-o", "-", "--emit-wat"])
+        .output()?;
+    // Overwriting a `.wat` input keeps it in the text format.
+    let status = Command::new(env!("CARGO_BIN_EXE_externref-cli"))
+        .arg(&input)
+        .status()?;
+    let written = std::fs::read_to_string(&input)?;
+    std::fs::remove_file(&input)?;
+
+    assert!(output.status.success(), "{output:?}");
+    assert!(status.success());
+    let text = String::from_utf8(output.stdout)?;
+    // The printed import refers to its signature by the index of its type.
+    let import = text
+        .lines()
+        .find(|line| line.contains(r#"(import "console" "log""#))
+        .expect("missing import");
+    let ty = import
+        .split("(type ")
+        .nth(1)
+        .and_then(|rest| rest.split(')').next())
+        .expect("missing type");
+    let signature = format!("(type (;{ty};) (func (param i32 externref)))");
+    assert!(text.contains(&signature), "{text}");
+    assert_eq!(written, text);
+
+    Ok(())
+}
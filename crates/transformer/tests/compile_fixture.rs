@@ -0,0 +1,218 @@
+//! Compiles the crate in `tests/fixture` to wasm and transforms it, checking that the macro and
+//! transformer agree end to end. The test is skipped when the `wasm32-unknown-unknown` target isn't
+//! installed.
+#![cfg(feature = "transform")]
+
+use std::{collections::HashMap, env, path::Path, process::Command};
+
+use anyhow::{bail, Context, Result};
+use wasmparser::{ExternalKind, FuncType, Parser, Payload, TypeRef, ValType};
+
+const TARGET: &str = "wasm32-unknown-unknown";
+
+/// The functions, imports, exports and custom sections of a module.
+#[derive(Default)]
+struct Summary {
+    types: Vec<FuncType>,
+    /// The type of every function, starting with the imported ones.
+    funcs: Vec<u32>,
+    imports: HashMap<String, u32>,
+    exports: HashMap<String, u32>,
+    customs: Vec<String>,
+}
+
+impl Summary {
+    fn parse(wasm: &[u8]) -> Result<Self> {
+        let mut summary = Self::default();
+        for payload in Parser::new(0).parse_all(wasm) {
+            match payload? {
+                Payload::TypeSection(reader) => {
+                    for ty in reader.into_iter_err_on_gc_types() {
+                        summary.types.push(ty?);
+                    }
+                }
+                Payload::ImportSection(reader) => {
+                    for import in reader.into_imports() {
+                        let import = import?;
+                        if let TypeRef::Func(ty) = import.ty {
+                            let index = summary.funcs.len() as u32;
+                            summary
+                                .imports
+                                .insert(format!("{}.{}", import.module, import.name), index);
+                            summary.funcs.push(ty);
+                        }
+                    }
+                }
+                Payload::FunctionSection(reader) => {
+                    for ty in reader {
+                        summary.funcs.push(ty?);
+                    }
+                }
+                Payload::ExportSection(reader) => {
+                    for export in reader {
+                        let export = export?;
+                        if export.kind == ExternalKind::Func {
+                            summary
+                                .exports
+                                .insert(export.name.to_string(), export.index);
+                        }
+                    }
+                }
+                Payload::CustomSection(reader) => summary.customs.push(reader.name().to_string()),
+                _ => {}
+            }
+        }
+
+        Ok(summary)
+    }
+
+    fn signature(&self, func: u32) -> (&[ValType], &[ValType]) {
+        let ty = &self.types[self.funcs[func as usize] as usize];
+        (ty.params(), ty.results())
+    }
+}
+
+fn target_installed() -> bool {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    match Command::new(rustc).args(["--print", "sysroot"]).output() {
+        Ok(output) if output.status.success() => {
+            let sysroot = String::from_utf8_lossy(&output.stdout);
+            Path::new(sysroot.trim())
+                .join("lib/rustlib")
+                .join(TARGET)
+                .exists()
+        }
+        _ => false,
+    }
+}
+
+/// Builds the fixture crate with some of its features, in a target directory of their own.
+fn build_fixture(features: &[&str]) -> Result<Vec<u8>> {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixture/Cargo.toml");
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(match features {
+        [] => "fixture".to_string(),
+        features => format!("fixture-{}", features.join("-")),
+    });
+
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let status = Command::new(cargo)
+        .args(["build", "--release", "--target", TARGET, "--manifest-path"])
+        .arg(&manifest)
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .arg("--features")
+        .arg(features.join(","))
+        .status()
+        .context("failed to run cargo")?;
+    if !status.success() {
+        bail!("failed to build the fixture crate: {status}");
+    }
+
+    let wasm = target_dir
+        .join(TARGET)
+        .join("release/externref_fixture.wasm");
+    std::fs::read(&wasm).with_context(|| format!("failed to read {}", wasm.display()))
+}
+
+/// Checks that the intrinsics `ExternRef::null` calls were replaced rather than left for the host.
+fn assert_intrinsics_replaced(summary: &Summary) {
+    let intrinsics: Vec<_> = summary
+        .imports
+        .keys()
+        .filter(|name| name.starts_with("__externref_intrinsic."))
+        .collect();
+    assert!(intrinsics.is_empty(), "{intrinsics:?}");
+}
+
+#[test]
+fn compile_fixture() -> Result<()> {
+    if !target_installed() {
+        eprintln!("skipping, the {TARGET} target isn't installed");
+        return Ok(());
+    }
+
+    let wasm = build_fixture(&[])?;
+    let before = Summary::parse(&wasm)?;
+    assert!(before
+        .customs
+        .iter()
+        .any(|name| name == "__extern_ref_data_console_log"));
+    assert!(before
+        .customs
+        .iter()
+        .any(|name| name == "__extern_ref_data_logTwice"));
+
+    let transformed = externref_transformer::transform(&wasm)?;
+    wasmparser::validate(&transformed)?;
+    let after = Summary::parse(&transformed)?;
+
+    let log = after.imports["console.log"];
+    assert_eq!(
+        after.signature(log),
+        (&[ValType::EXTERNREF, ValType::I32][..], &[][..])
+    );
+
+    let log_twice = after.exports["logTwice"];
+    assert_eq!(
+        after.signature(log_twice),
+        (&[ValType::EXTERNREF][..], &[ValType::EXTERNREF][..])
+    );
+
+    assert_intrinsics_replaced(&after);
+    assert!(
+        !after
+            .customs
+            .iter()
+            .any(|name| name.starts_with("__extern_ref_")),
+        "{:?}",
+        after.customs
+    );
+
+    Ok(())
+}
+
+#[test]
+fn compile_fixture_manifest() -> Result<()> {
+    if !target_installed() {
+        eprintln!("skipping, the {TARGET} target isn't installed");
+        return Ok(());
+    }
+
+    // Every function is described by the array in a single section.
+    let wasm = build_fixture(&["manifest"])?;
+    let before = Summary::parse(&wasm)?;
+    let sections: Vec<_> = before
+        .customs
+        .iter()
+        .filter(|name| name.starts_with("__extern_ref_"))
+        .collect();
+    assert_eq!(sections, ["__extern_ref_manifest"]);
+
+    let functions = externref_transformer::read_metadata(&wasm)?;
+    let names: Vec<_> = functions
+        .iter()
+        .map(|data| (data.module.as_deref(), data.name.as_str()))
+        .collect();
+    for name in [
+        (Some("console"), "log"),
+        (None, "logTwice"),
+        (Some("__externref_intrinsic"), "ref.null"),
+    ] {
+        assert!(names.contains(&name), "{names:?}");
+    }
+
+    let transformed = externref_transformer::transform(&wasm)?;
+    wasmparser::validate(&transformed)?;
+    let after = Summary::parse(&transformed)?;
+    assert_eq!(
+        after.signature(after.imports["console.log"]),
+        (&[ValType::EXTERNREF, ValType::I32][..], &[][..])
+    );
+    assert_eq!(
+        after.signature(after.exports["logTwice"]),
+        (&[ValType::EXTERNREF][..], &[ValType::EXTERNREF][..])
+    );
+    assert_intrinsics_replaced(&after);
+
+    Ok(())
+}
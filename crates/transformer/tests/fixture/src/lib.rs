@@ -0,0 +1,25 @@
+use externref::{externref, ExternRef};
+
+#[externref(name = "console")]
+extern "C" {
+    #[externref(name = "log")]
+    fn console_log(message: ExternRef, times: u32);
+}
+
+#[externref(name = "logTwice")]
+#[export_name = "logTwice"]
+pub extern "C" fn log_twice(message: ExternRef) -> ExternRef {
+    // SAFETY: The transformer gives the host the reference the handle refers to.
+    unsafe { console_log(message, 2) };
+    message
+}
+
+#[externref(name = "nullRef")]
+#[export_name = "nullRef"]
+pub extern "C" fn null_ref() -> ExternRef {
+    // The intrinsics are described by the `externref` crate rather than this one.
+    ExternRef::null()
+}
+
+// Writes the manifest with the `manifest` feature, and expands to nothing without it.
+externref::manifest!();
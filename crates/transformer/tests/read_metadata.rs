@@ -0,0 +1,145 @@
+//! Reads the function data of a module with only the `wasmparser` based reader, so this also runs
+//! with `--no-default-features`.
+
+use std::collections::BTreeMap;
+
+use externref_metadata::{FunctionData, Lowering};
+
+fn function(name: &str, module: Option<&str>) -> FunctionData {
+    FunctionData {
+        name: name.into(),
+        arg_indicies: vec![0],
+        ret_is_extern_ref: false,
+        optional: false,
+        arg_names: Vec::new(),
+        aliases: Vec::new(),
+        module: module.map(Into::into),
+        ret_result: None,
+        callback: false,
+        arg_types: BTreeMap::new(),
+        export_ref: None,
+        param_count: None,
+        group: None,
+        intrinsic: None,
+        constructor: false,
+        nullable_args: Vec::new(),
+        ret_nullable: false,
+        reentrant: false,
+        lowering: Lowering::Direct,
+        pack_args: false,
+    }
+}
+
+/// Escapes bytes so they can be embedded in a wat string.
+fn escape(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("\\{byte:02x}")).collect()
+}
+
+#[test]
+fn read_metadata() {
+    let log = function("log", None);
+    let mut append = function("append", Some("dom"));
+    append.group = Some("dom".into());
+    let mut remove = function("remove", Some("dom"));
+    remove.ret_is_extern_ref = true;
+    let mut get = function("get", None);
+    get.arg_indicies = Vec::new();
+    get.ret_is_extern_ref = true;
+
+    // The manifest of each crate is an array, which the linker concatenates.
+    let manifest = [
+        serde_json::to_vec(&[&remove]).unwrap(),
+        serde_json::to_vec(&[&get]).unwrap(),
+    ]
+    .concat();
+    let wasm = wat::parse_str(format!(
+        r#"
+        (module
+            (@custom "producers" "")
+            (@custom "__extern_ref_data_console_log" "{log}")
+            (@custom "__extern_ref_data_dom@dom_append" "{append}")
+            (@custom "__extern_ref_manifest" "{manifest}"))
+        "#,
+        log = escape(&serde_json::to_vec(&log).unwrap()),
+        append = escape(&append.to_binary()),
+        manifest = escape(&manifest),
+    ))
+    .unwrap();
+
+    assert_eq!(
+        externref_transformer::read_metadata(&wasm).unwrap(),
+        vec![log, append, remove, get]
+    );
+}
+
+#[test]
+fn read_invalid_metadata() {
+    let wasm = wat::parse_str(r#"(module (@custom "__extern_ref_data_console_log" "{\"name\""))"#)
+        .unwrap();
+
+    let error = externref_transformer::read_metadata(&wasm).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "invalid externref data in section __extern_ref_data_console_log (#0) at byte 0: \
+         \"{\\\"name\\\"\""
+    );
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn read_compressed_metadata() {
+    let log = function("log", None);
+    let mut warn = function("warn", None);
+    warn.ret_is_extern_ref = true;
+
+    // Compressed records can share a section with uncompressed ones.
+    let section = [
+        externref_metadata::compress(&serde_json::to_vec(&log).unwrap()),
+        serde_json::to_vec(&warn).unwrap(),
+        externref_metadata::compress(&warn.to_binary()),
+    ]
+    .concat();
+    let wasm = wat::parse_str(format!(
+        r#"(module (@custom "__extern_ref_data_console_log" "{}"))"#,
+        escape(&section)
+    ))
+    .unwrap();
+
+    assert_eq!(
+        externref_transformer::read_metadata(&wasm).unwrap(),
+        vec![log.clone(), warn.clone(), warn.clone()]
+    );
+
+    // A member holds a single record, which is never decompressed again.
+    let nested = externref_metadata::compress(&externref_metadata::compress(&log.to_binary()));
+    let two = externref_metadata::compress(&[log.to_binary(), warn.to_binary()].concat());
+    let huge =
+        externref_metadata::compress(&vec![b' '; externref_metadata::MAX_DECOMPRESSED_LEN + 1]);
+    for (data, message) in [
+        (nested, "the record is compressed twice"),
+        (two, "the member holds more than one record"),
+        (huge, "decompressed data is larger than 1048576 bytes"),
+    ] {
+        let wasm = wat::parse_str(format!(
+            r#"(module (@custom "__extern_ref_data_console_log" "{}"))"#,
+            escape(&data)
+        ))
+        .unwrap();
+        let error = externref_transformer::read_metadata(&wasm).unwrap_err();
+        assert_eq!(format!("{:#}", error.root_cause()), message);
+    }
+
+    // A truncated member can't be decompressed.
+    let wasm = wat::parse_str(format!(
+        r#"(module (@custom "__extern_ref_data_console_log" "{}"))"#,
+        escape(&section[..8])
+    ))
+    .unwrap();
+    let error = externref_transformer::read_metadata(&wasm).unwrap_err();
+    assert!(
+        error.to_string().starts_with(
+            "invalid compressed externref data in section __extern_ref_data_console_log (#0)"
+        ),
+        "{error}"
+    );
+}
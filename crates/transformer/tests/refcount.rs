@@ -0,0 +1,117 @@
+//! Runs a module using the `refcount` hooks in Node.js, checking that dropped references free
+//! their slots for the next references. The test is skipped when `node` isn't installed.
+#![cfg(feature = "transform")]
+
+use std::process::Command;
+
+use anyhow::{bail, Result};
+use externref_transformer::{transform_with_config, Config};
+use serde_json::json;
+
+/// Stores references passed from JavaScript, then releases and clones them by their slots.
+const SCRIPT: &str = r#"
+const fs = require("fs");
+const events = [];
+const imports = {
+    __externref: {
+        clone: (reference) => events.push(["clone", reference]),
+        drop: (reference) => events.push(["drop", reference]),
+    },
+};
+WebAssembly.instantiate(fs.readFileSync(process.argv[1]), imports).then(({ instance }) => {
+    const { store, release, duplicate, externref_table: table } = instance.exports;
+    const slots = {};
+    slots.a = store("a");
+    slots.b = store("b");
+    release(slots.a);
+    slots.c = store("c");
+    slots.d = duplicate(slots.c);
+    release(slots.b);
+    release(slots.d);
+    slots.e = store("e");
+    slots.f = store("f");
+    slots.g = store("g");
+    const table_slots = [...Array(table.length).keys()].map((slot) => table.get(slot));
+
+    // Enough references to fill several pages of the free list, released and stored again.
+    const lengths = [];
+    for (let round = 0; round < 3; round++) {
+        const many = Array.from({ length: 40000 }, (_, i) => store(i));
+        many.forEach(release);
+        lengths.push(table.length);
+    }
+    console.log(JSON.stringify({ slots, table: table_slots, events: events.slice(0, 4), lengths }));
+});
+"#;
+
+fn node_installed() -> bool {
+    Command::new("node")
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+#[test]
+fn dropped_slots_are_reused() -> Result<()> {
+    if !node_installed() {
+        eprintln!("skipping, node isn't installed");
+        return Ok(());
+    }
+
+    // What the `refcount` feature of the `externref` crate compiles to, with exports handing the
+    // slots of references to the host.
+    let wasm = wat::parse_str(
+        r#"
+        (module
+            (import "__externref" "clone" (func $clone (param i32) (result i32)))
+            (import "__externref" "drop" (func $drop (param i32)))
+            (memory 1)
+            (func (export "store") (param i32) (result i32)
+                local.get 0)
+            (func (export "release") (param i32)
+                local.get 0
+                call $drop)
+            (func (export "duplicate") (param i32) (result i32)
+                local.get 0
+                call $clone)
+            (@custom "__extern_ref_data___externref_clone"
+                "{\"name\":\"clone\",\"argIndicies\":[0],\"retIsExternRef\":true}")
+            (@custom "__extern_ref_data___externref_drop"
+                "{\"name\":\"drop\",\"argIndicies\":[0],\"retIsExternRef\":false}")
+            (@custom "__extern_ref_data_store"
+                "{\"name\":\"store\",\"argIndicies\":[0],\"retIsExternRef\":false}"))
+        "#,
+    )?;
+    let config = Config {
+        export_table: Some("externref_table".into()),
+        ..Default::default()
+    };
+    let transformed = transform_with_config(&wasm, &config)?;
+
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("refcount.wasm");
+    std::fs::write(&path, transformed)?;
+    let output = Command::new("node")
+        .args(["-e", SCRIPT])
+        .arg(&path)
+        .output()?;
+    if !output.status.success() {
+        bail!("node failed: {}", String::from_utf8_lossy(&output.stderr));
+    }
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+
+    assert_eq!(
+        result,
+        json!({
+            // `c` takes the slot `a` freed, and a clone gets a slot of its own. The slots freed
+            // last are handed out first, before the table grows again.
+            "slots": { "a": 1, "b": 2, "c": 1, "d": 3, "e": 3, "f": 2, "g": 4 },
+            "table": [null, "c", "f", "e", "g"],
+            "events": [["drop", "a"], ["clone", "c"], ["drop", "b"], ["drop", "c"]],
+            // The first round grows the table past the 5 slots already there, the others reuse
+            // the slots it freed.
+            "lengths": [40005, 40005, 40005],
+        })
+    );
+
+    Ok(())
+}